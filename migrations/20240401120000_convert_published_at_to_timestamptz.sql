@@ -0,0 +1,4 @@
+-- published_at was stored as the text rendering of now(), cast it back
+ALTER TABLE newsletter_issues
+    ALTER COLUMN published_at TYPE timestamptz
+    USING published_at::timestamptz;
@@ -0,0 +1,3 @@
+-- Issues published before this migration have no known author
+ALTER TABLE newsletter_issues
+    ADD COLUMN author_id uuid NULL REFERENCES users(user_id);
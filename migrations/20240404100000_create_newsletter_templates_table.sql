@@ -0,0 +1,13 @@
+CREATE TABLE newsletter_templates (
+    template_id uuid NOT NULL,
+    name TEXT NOT NULL UNIQUE,
+    header_html TEXT NOT NULL,
+    footer_html TEXT NOT NULL,
+    css TEXT NOT NULL,
+    created_at timestamptz NOT NULL,
+    updated_at timestamptz NOT NULL,
+    PRIMARY KEY(template_id)
+);
+
+ALTER TABLE newsletter_issues
+    ADD COLUMN template_id uuid NULL REFERENCES newsletter_templates(template_id);
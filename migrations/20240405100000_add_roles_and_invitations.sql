@@ -0,0 +1,20 @@
+-- Every account that existed before roles did was a full administrator
+ALTER TABLE users ADD COLUMN role TEXT NULL;
+UPDATE users SET role = 'owner' WHERE role IS NULL;
+ALTER TABLE users ALTER COLUMN role SET NOT NULL;
+ALTER TABLE users ADD CONSTRAINT users_role_check
+    CHECK (role IN ('owner', 'editor', 'viewer'));
+
+ALTER TABLE users ADD COLUMN email TEXT NULL UNIQUE;
+
+CREATE TABLE user_invitations (
+    invitation_id uuid NOT NULL,
+    email TEXT NOT NULL,
+    role TEXT NOT NULL CHECK (role IN ('owner', 'editor', 'viewer')),
+    invited_by uuid NOT NULL REFERENCES users(user_id),
+    created_at timestamptz NOT NULL,
+    expires_at timestamptz NOT NULL,
+    accepted_at timestamptz NULL,
+    accepted_user_id uuid NULL REFERENCES users(user_id),
+    PRIMARY KEY(invitation_id)
+);
@@ -0,0 +1,12 @@
+-- The seeded `admin` account ships with a publicly known password.
+-- Drop it wherever it was never used; deployments that did use it are
+-- refused by the startup check until its password is changed.
+DELETE FROM users u
+WHERE u.password_hash = '$argon2id$v=19$m=15000,t=2,p=1$OEx/rcq+3ts//WUDzGNl2g$Am8UFBA4w5NJEmAtquGvBmAlu92q/VQcaoL5AyJPfc8'
+    AND NOT EXISTS (SELECT 1 FROM idempotency i WHERE i.user_id = u.user_id)
+    AND NOT EXISTS (SELECT 1 FROM newsletter_issues n WHERE n.author_id = u.user_id)
+    AND NOT EXISTS (SELECT 1 FROM api_tokens t WHERE t.user_id = u.user_id)
+    AND NOT EXISTS (
+        SELECT 1 FROM user_invitations v
+        WHERE v.invited_by = u.user_id OR v.accepted_user_id = u.user_id
+    );
@@ -0,0 +1,19 @@
+-- Session data lives in Redis, keyed by an id we never get to see. Every
+-- login registers its own session here so that it can be revoked later.
+CREATE TABLE user_sessions (
+    session_id uuid NOT NULL,
+    user_id uuid NOT NULL REFERENCES users(user_id),
+    created_at timestamptz NOT NULL,
+    revoked_at timestamptz NULL,
+    PRIMARY KEY(session_id)
+);
+CREATE INDEX user_sessions_user_id_idx ON user_sessions (user_id);
+
+CREATE TABLE password_reset_tokens (
+    token_hash TEXT NOT NULL,
+    user_id uuid NOT NULL REFERENCES users(user_id),
+    created_at timestamptz NOT NULL,
+    expires_at timestamptz NOT NULL,
+    used_at timestamptz NULL,
+    PRIMARY KEY(token_hash)
+);
@@ -0,0 +1,14 @@
+-- A secret is stored as soon as enrollment starts, but is only enforced
+-- at login once the user proved they can generate codes from it.
+ALTER TABLE users ADD COLUMN totp_secret TEXT NULL;
+ALTER TABLE users ADD COLUMN totp_enabled_at timestamptz NULL;
+-- The last accepted time step, so a code cannot be replayed
+ALTER TABLE users ADD COLUMN totp_last_used_step BIGINT NULL;
+
+CREATE TABLE totp_recovery_codes (
+    user_id uuid NOT NULL REFERENCES users(user_id),
+    code_hash TEXT NOT NULL,
+    created_at timestamptz NOT NULL,
+    used_at timestamptz NULL,
+    PRIMARY KEY(user_id, code_hash)
+);
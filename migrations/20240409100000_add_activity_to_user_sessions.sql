@@ -0,0 +1,6 @@
+-- Lets users see where they are logged in before revoking a session
+ALTER TABLE user_sessions ADD COLUMN last_seen_at timestamptz NULL;
+ALTER TABLE user_sessions ADD COLUMN ip_address TEXT NULL;
+ALTER TABLE user_sessions ADD COLUMN user_agent TEXT NULL;
+UPDATE user_sessions SET last_seen_at = created_at;
+ALTER TABLE user_sessions ALTER COLUMN last_seen_at SET NOT NULL;
@@ -0,0 +1,26 @@
+-- A durable record of who did what, which tracing logs do not give us
+CREATE TABLE audit_events (
+    event_id uuid NOT NULL,
+    occurred_at timestamptz NOT NULL,
+    -- No foreign key: the record has to outlive the accounts it mentions
+    actor_user_id uuid NULL,
+    action TEXT NOT NULL,
+    target TEXT NULL,
+    ip_address TEXT NULL,
+    PRIMARY KEY(event_id)
+);
+CREATE INDEX audit_events_occurred_at_idx ON audit_events (occurred_at);
+
+-- Rows can be added, but never changed or removed
+CREATE FUNCTION reject_audit_event_changes() RETURNS trigger AS $$
+BEGIN
+    RAISE EXCEPTION 'audit_events is append-only';
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER audit_events_are_append_only
+    BEFORE UPDATE OR DELETE ON audit_events
+    FOR EACH ROW EXECUTE FUNCTION reject_audit_event_changes();
+CREATE TRIGGER audit_events_are_not_truncated
+    BEFORE TRUNCATE ON audit_events
+    FOR EACH STATEMENT EXECUTE FUNCTION reject_audit_event_changes();
@@ -0,0 +1,3 @@
+-- Unknown for subscribers who confirmed before it was recorded
+ALTER TABLE subscriptions ADD COLUMN confirmed_at timestamptz NULL;
+CREATE INDEX subscriptions_subscribed_at_idx ON subscriptions (subscribed_at);
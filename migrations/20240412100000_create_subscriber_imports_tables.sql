@@ -0,0 +1,25 @@
+-- One row per uploaded file
+CREATE TABLE subscriber_imports (
+    import_id uuid NOT NULL,
+    created_by uuid NULL REFERENCES users(user_id),
+    created_at timestamptz NOT NULL,
+    file_name TEXT NOT NULL,
+    -- 'confirmed' or 'send_confirmation'
+    mode TEXT NOT NULL,
+    -- Where the consent of confirmed imports comes from, in the admin's words
+    consent_attestation TEXT NULL,
+    finished_at timestamptz NULL,
+    PRIMARY KEY(import_id)
+);
+
+-- One row per record of the file after its header, for the downloadable report
+CREATE TABLE subscriber_import_rows (
+    import_id uuid NOT NULL REFERENCES subscriber_imports(import_id),
+    row_number INTEGER NOT NULL,
+    email TEXT NOT NULL,
+    name TEXT NOT NULL,
+    -- 'accepted', 'skipped' or 'invalid'
+    outcome TEXT NOT NULL,
+    detail TEXT NOT NULL,
+    PRIMARY KEY(import_id, row_number)
+);
@@ -0,0 +1,22 @@
+-- Addresses that must not be added back, e.g. after an erasure request. Only
+-- a keyed hash of each address is kept, so the list holds no personal data.
+CREATE TABLE suppression_list (
+    email_hash TEXT NOT NULL,
+    reason TEXT NOT NULL,
+    created_at timestamptz NOT NULL,
+    PRIMARY KEY(email_hash)
+);
+
+-- Access and erasure requests, waiting for the owner of the address to
+-- follow the link we emailed them
+CREATE TABLE data_requests (
+    token_hash TEXT NOT NULL,
+    email TEXT NOT NULL,
+    -- 'access' or 'erasure'
+    kind TEXT NOT NULL,
+    created_at timestamptz NOT NULL,
+    expires_at timestamptz NOT NULL,
+    used_at timestamptz NULL,
+    PRIMARY KEY(token_hash)
+);
+CREATE INDEX data_requests_email_idx ON data_requests (email);
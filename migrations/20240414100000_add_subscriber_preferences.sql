@@ -0,0 +1,15 @@
+-- What each subscriber chose to receive
+ALTER TABLE subscriptions ADD COLUMN frequency TEXT NOT NULL DEFAULT 'every_issue';
+ALTER TABLE subscriptions ADD COLUMN muted_topics TEXT[] NOT NULL DEFAULT '{}';
+-- Issues without a topic go to everyone
+ALTER TABLE newsletter_issues ADD COLUMN topic TEXT NULL;
+
+-- An address change takes effect once the new address confirms it
+CREATE TABLE email_changes(
+    token_hash TEXT NOT NULL,
+    subscriber_id uuid NOT NULL REFERENCES subscriptions (id) ON DELETE CASCADE,
+    new_email TEXT NOT NULL,
+    created_at timestamptz NOT NULL,
+    expires_at timestamptz NOT NULL,
+    PRIMARY KEY (token_hash)
+);
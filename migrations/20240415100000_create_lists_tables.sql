@@ -0,0 +1,48 @@
+-- Each publication has its own list, which people join and confirm separately
+CREATE TABLE lists (
+    list_id uuid NOT NULL,
+    -- What `POST /subscriptions` names the list by
+    slug TEXT NOT NULL UNIQUE,
+    name TEXT NOT NULL,
+    created_at timestamptz NOT NULL,
+    PRIMARY KEY (list_id)
+);
+
+-- `subscriptions.status` is about the address, e.g. whether it is suppressed;
+-- the status here is about one list: 'pending_confirmation', 'confirmed'
+-- or 'unsubscribed'.
+CREATE TABLE list_memberships (
+    list_id uuid NOT NULL REFERENCES lists (list_id),
+    subscriber_id uuid NOT NULL REFERENCES subscriptions (id) ON DELETE CASCADE,
+    status TEXT NOT NULL,
+    subscribed_at timestamptz NOT NULL,
+    confirmed_at timestamptz NULL,
+    PRIMARY KEY (list_id, subscriber_id)
+);
+CREATE INDEX list_memberships_subscriber_id_idx ON list_memberships (subscriber_id);
+
+CREATE TABLE newsletter_issue_lists (
+    newsletter_issue_id uuid NOT NULL REFERENCES newsletter_issues (newsletter_issue_id),
+    list_id uuid NOT NULL REFERENCES lists (list_id),
+    PRIMARY KEY (newsletter_issue_id, list_id)
+);
+
+-- A confirmation link confirms the list it was sent for
+ALTER TABLE subscription_tokens ADD COLUMN list_id uuid NULL REFERENCES lists (list_id);
+
+-- Everything so far went out to a single list
+INSERT INTO lists (list_id, slug, name, created_at)
+VALUES (gen_random_uuid(), 'newsletter', 'Newsletter', now());
+INSERT INTO list_memberships (list_id, subscriber_id, status, subscribed_at, confirmed_at)
+SELECT
+    l.list_id,
+    s.id,
+    CASE WHEN s.status = 'suppressed' THEN 'unsubscribed' ELSE s.status END,
+    s.subscribed_at,
+    s.confirmed_at
+FROM subscriptions s, lists l;
+INSERT INTO newsletter_issue_lists (newsletter_issue_id, list_id)
+SELECT i.newsletter_issue_id, l.list_id
+FROM newsletter_issues i, lists l;
+UPDATE subscription_tokens SET list_id = (SELECT list_id FROM lists);
+ALTER TABLE subscription_tokens ALTER COLUMN list_id SET NOT NULL;
@@ -0,0 +1,25 @@
+-- Labels admins put on subscribers, e.g. to send an issue to some of them
+CREATE TABLE tags (
+    tag_id uuid NOT NULL,
+    name TEXT NOT NULL UNIQUE,
+    created_at timestamptz NOT NULL,
+    PRIMARY KEY (tag_id)
+);
+
+CREATE TABLE subscriber_tags (
+    subscriber_id uuid NOT NULL REFERENCES subscriptions (id) ON DELETE CASCADE,
+    tag_id uuid NOT NULL REFERENCES tags (tag_id) ON DELETE CASCADE,
+    tagged_at timestamptz NOT NULL,
+    PRIMARY KEY (subscriber_id, tag_id)
+);
+CREATE INDEX subscriber_tags_tag_id_idx ON subscriber_tags (tag_id);
+
+-- Which of its lists' subscribers an issue goes to. Every condition that is
+-- set has to hold; an issue without any goes to all of them.
+ALTER TABLE newsletter_issues
+    ADD COLUMN segment_joined_since DATE NULL,
+    ADD COLUMN segment_tag_id uuid NULL REFERENCES tags (tag_id),
+    ADD COLUMN segment_engaged_within_days INT NULL;
+
+-- The engagement condition looks up a subscriber's recent events
+CREATE INDEX audit_events_target_occurred_at_idx ON audit_events (target, occurred_at);
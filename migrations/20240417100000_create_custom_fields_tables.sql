@@ -0,0 +1,21 @@
+-- Extra details admins collect from people who sign up
+CREATE TABLE custom_fields (
+    field_id uuid NOT NULL,
+    -- The form field at sign-up and the variable in newsletter content
+    key TEXT NOT NULL UNIQUE,
+    label TEXT NOT NULL,
+    -- 'text', 'select' or 'boolean'
+    kind TEXT NOT NULL,
+    -- The choices of a 'select' field
+    options TEXT[] NOT NULL DEFAULT '{}',
+    is_required BOOLEAN NOT NULL,
+    created_at timestamptz NOT NULL,
+    PRIMARY KEY (field_id)
+);
+
+CREATE TABLE subscriber_field_values (
+    subscriber_id uuid NOT NULL REFERENCES subscriptions (id) ON DELETE CASCADE,
+    field_id uuid NOT NULL REFERENCES custom_fields (field_id) ON DELETE CASCADE,
+    value TEXT NOT NULL,
+    PRIMARY KEY (subscriber_id, field_id)
+);
@@ -0,0 +1,91 @@
+-- Addresses are stored normalized from now on: trimmed, lower-cased and
+-- with international domains in punycode. Punycode cannot be computed
+-- here, so existing addresses on such domains must be fixed by hand first.
+DO $$
+BEGIN
+    IF EXISTS (SELECT 1 FROM subscriptions WHERE email ~ '@[^@]*[^\x01-\x7F][^@]*$')
+        OR EXISTS (SELECT 1 FROM issue_delivery_queue WHERE subscriber_email ~ '@[^@]*[^\x01-\x7F][^@]*$')
+        OR EXISTS (SELECT 1 FROM issue_delivery_log WHERE subscriber_email ~ '@[^@]*[^\x01-\x7F][^@]*$')
+    THEN
+        RAISE EXCEPTION 'Some addresses have an international domain: store them in punycode first.';
+    END IF;
+END
+$$;
+
+CREATE FUNCTION pg_temp.normalized_email(email TEXT) RETURNS TEXT AS $$
+    SELECT lower(btrim(email, E' \t\n\r\f'))
+$$ LANGUAGE SQL IMMUTABLE;
+
+-- Subscriptions that differ only by case or surrounding whitespace belong
+-- to one mailbox, so they are merged: into the suppressed one if there is
+-- one, otherwise into the latest sign-up, which says best what the owner
+-- wants now. The others' lists, tags, fields and links move to it.
+CREATE TEMPORARY TABLE merged_subscriptions AS
+SELECT id, survivor_id
+FROM (
+    SELECT
+        id,
+        first_value(id) OVER (
+            PARTITION BY pg_temp.normalized_email(email)
+            ORDER BY status = 'suppressed' DESC, subscribed_at DESC, id
+        ) AS survivor_id
+    FROM subscriptions
+) s
+WHERE id <> survivor_id;
+
+INSERT INTO list_memberships (list_id, subscriber_id, status, subscribed_at, confirmed_at)
+SELECT l.list_id, m.survivor_id, l.status, l.subscribed_at, l.confirmed_at
+FROM list_memberships l
+JOIN merged_subscriptions m ON m.id = l.subscriber_id
+ON CONFLICT (list_id, subscriber_id) DO NOTHING;
+
+INSERT INTO subscriber_tags (subscriber_id, tag_id, tagged_at)
+SELECT m.survivor_id, t.tag_id, t.tagged_at
+FROM subscriber_tags t
+JOIN merged_subscriptions m ON m.id = t.subscriber_id
+ON CONFLICT (subscriber_id, tag_id) DO NOTHING;
+
+INSERT INTO subscriber_field_values (subscriber_id, field_id, value)
+SELECT m.survivor_id, v.field_id, v.value
+FROM subscriber_field_values v
+JOIN merged_subscriptions m ON m.id = v.subscriber_id
+ON CONFLICT (subscriber_id, field_id) DO NOTHING;
+
+-- Links already sent out keep working
+UPDATE subscription_tokens t
+SET subscriber_id = m.survivor_id
+FROM merged_subscriptions m
+WHERE t.subscriber_id = m.id;
+
+UPDATE email_changes c
+SET subscriber_id = m.survivor_id
+FROM merged_subscriptions m
+WHERE c.subscriber_id = m.id;
+
+DELETE FROM subscriptions WHERE id IN (SELECT id FROM merged_subscriptions);
+DROP TABLE merged_subscriptions;
+
+UPDATE subscriptions
+SET email = pg_temp.normalized_email(email)
+WHERE email <> pg_temp.normalized_email(email);
+
+-- Deliveries follow the addresses, keeping one per issue and mailbox
+DELETE FROM issue_delivery_queue q
+USING issue_delivery_queue o
+WHERE o.newsletter_issue_id = q.newsletter_issue_id
+    AND pg_temp.normalized_email(o.subscriber_email) = pg_temp.normalized_email(q.subscriber_email)
+    AND o.subscriber_email < q.subscriber_email;
+UPDATE issue_delivery_queue
+SET subscriber_email = pg_temp.normalized_email(subscriber_email)
+WHERE subscriber_email <> pg_temp.normalized_email(subscriber_email);
+
+DELETE FROM issue_delivery_log l
+USING issue_delivery_log o
+WHERE o.newsletter_issue_id = l.newsletter_issue_id
+    AND pg_temp.normalized_email(o.subscriber_email) = pg_temp.normalized_email(l.subscriber_email)
+    AND o.subscriber_email < l.subscriber_email;
+UPDATE issue_delivery_log
+SET subscriber_email = pg_temp.normalized_email(subscriber_email)
+WHERE subscriber_email <> pg_temp.normalized_email(subscriber_email);
+
+CREATE UNIQUE INDEX subscriptions_lower_email_key ON subscriptions (lower(email));
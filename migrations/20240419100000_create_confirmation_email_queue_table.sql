@@ -0,0 +1,8 @@
+-- Confirmation emails owed to imported subscribers. The background worker
+-- sends them, so an upload does not wait on the email API for every row.
+CREATE TABLE confirmation_email_queue (
+    subscription_token TEXT NOT NULL
+        REFERENCES subscription_tokens (subscription_token) ON DELETE CASCADE,
+    enqueued_at timestamptz NOT NULL,
+    PRIMARY KEY (subscription_token)
+);
@@ -0,0 +1,14 @@
+-- When the subscriber last did something themselves: confirmed their
+-- address or changed their preferences. Segments pick engaged readers by it.
+ALTER TABLE subscriptions ADD COLUMN last_engaged_at timestamptz NULL;
+
+UPDATE subscriptions s
+SET last_engaged_at = e.occurred_at
+FROM (
+    SELECT target, max(occurred_at) AS occurred_at
+    FROM audit_events
+    WHERE actor_user_id IS NULL
+        AND action IN ('subscriber_confirmed', 'subscriber_updated')
+    GROUP BY target
+) e
+WHERE e.target = s.id::TEXT;
@@ -0,0 +1,208 @@
+use actix_web::HttpRequest;
+use crate::client_ip::client_ip;
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::{Executor, PgPool, Postgres};
+use uuid::Uuid;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AuditAction {
+    Login,
+    LoginFailed,
+    Logout,
+    PasswordChanged,
+    PasswordReset,
+    NewsletterPublished,
+    SubscriberConfirmed,
+    SubscriberUnsubscribed,
+    SubscriberSuppressed,
+    SubscriberUpdated,
+    SubscriberDeleted,
+    SubscribersImported,
+    SubscriberDataAccessed,
+    SubscriberErased,
+}
+
+impl AuditAction {
+    pub const ALL: [AuditAction; 14] = [
+        AuditAction::Login,
+        AuditAction::LoginFailed,
+        AuditAction::Logout,
+        AuditAction::PasswordChanged,
+        AuditAction::PasswordReset,
+        AuditAction::NewsletterPublished,
+        AuditAction::SubscriberConfirmed,
+        AuditAction::SubscriberUnsubscribed,
+        AuditAction::SubscriberSuppressed,
+        AuditAction::SubscriberUpdated,
+        AuditAction::SubscriberDeleted,
+        AuditAction::SubscribersImported,
+        AuditAction::SubscriberDataAccessed,
+        AuditAction::SubscriberErased,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AuditAction::Login => "login",
+            AuditAction::LoginFailed => "login_failed",
+            AuditAction::Logout => "logout",
+            AuditAction::PasswordChanged => "password_changed",
+            AuditAction::PasswordReset => "password_reset",
+            AuditAction::NewsletterPublished => "newsletter_published",
+            AuditAction::SubscriberConfirmed => "subscriber_confirmed",
+            AuditAction::SubscriberUnsubscribed => "subscriber_unsubscribed",
+            AuditAction::SubscriberSuppressed => "subscriber_suppressed",
+            AuditAction::SubscriberUpdated => "subscriber_updated",
+            AuditAction::SubscriberDeleted => "subscriber_deleted",
+            AuditAction::SubscribersImported => "subscribers_imported",
+            AuditAction::SubscriberDataAccessed => "subscriber_data_accessed",
+            AuditAction::SubscriberErased => "subscriber_erased",
+        }
+    }
+}
+
+impl std::fmt::Display for AuditAction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.as_str().fmt(f)
+    }
+}
+
+impl TryFrom<String> for AuditAction {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        Self::ALL
+            .into_iter()
+            .find(|action| action.as_str() == s)
+            .ok_or_else(|| format!("{} is not a known audit action.", s))
+    }
+}
+
+// What happened, before it is stored
+pub struct NewAuditEvent {
+    pub actor: Option<Uuid>,
+    pub action: AuditAction,
+    pub target: Option<String>,
+    pub ip_address: Option<String>,
+}
+
+impl NewAuditEvent {
+    pub fn new(actor: Option<Uuid>, action: AuditAction, request: &HttpRequest) -> Self {
+        Self {
+            actor,
+            action,
+            target: None,
+            ip_address: client_ip(request).map(|ip| ip.to_string()),
+        }
+    }
+
+    // Something a subscriber did themselves. Their IP address is not kept:
+    // the log cannot be changed, so it would outlive an erasure.
+    pub fn by_subscriber(action: AuditAction) -> Self {
+        Self {
+            actor: None,
+            action,
+            target: None,
+            ip_address: None,
+        }
+    }
+
+    pub fn target(mut self, target: impl ToString) -> Self {
+        self.target = Some(target.to_string());
+        self
+    }
+}
+
+#[derive(serde::Serialize)]
+pub struct AuditEvent {
+    pub event_id: Uuid,
+    pub occurred_at: DateTime<Utc>,
+    pub actor_user_id: Option<Uuid>,
+    pub actor_username: Option<String>,
+    pub action: String,
+    pub target: Option<String>,
+    pub ip_address: Option<String>,
+}
+
+// Pass the transaction that makes the change, so the change and its record
+// are committed together.
+#[tracing::instrument(name = "Record audit event", skip(executor, event), fields(action=%event.action))]
+pub async fn record_audit_event<'c, E>(executor: E, event: NewAuditEvent) -> Result<(), anyhow::Error>
+where
+    E: Executor<'c, Database = Postgres>,
+{
+    sqlx::query!(
+        r#"
+        INSERT INTO audit_events (event_id, occurred_at, actor_user_id, action, target, ip_address)
+        VALUES ($1, now(), $2, $3, $4, $5)
+        "#,
+        Uuid::new_v4(),
+        event.actor,
+        event.action.as_str(),
+        event.target,
+        event.ip_address,
+    )
+        .execute(executor)
+        .await
+        .context("Failed to record an audit event.")?;
+    Ok(())
+}
+
+// Every field is optional: an empty filter matches every event
+#[derive(Debug, Default)]
+pub struct AuditFilter {
+    pub action: Option<AuditAction>,
+    pub actor_username: Option<String>,
+    pub target: Option<String>,
+    pub since: Option<DateTime<Utc>>,
+    pub until: Option<DateTime<Utc>>,
+}
+
+// Most recent first
+#[tracing::instrument(name = "Get audit events", skip(pool))]
+pub async fn get_audit_events(
+    pool: &PgPool,
+    filter: &AuditFilter,
+    limit: Option<i64>,
+) -> Result<Vec<AuditEvent>, anyhow::Error> {
+    let events = sqlx::query_as!(
+        AuditEvent,
+        r#"
+        SELECT e.event_id, e.occurred_at, e.actor_user_id, u.username AS "actor_username?",
+            e.action, e.target, e.ip_address
+        FROM audit_events e
+        LEFT JOIN users u ON u.user_id = e.actor_user_id
+        WHERE ($1::TEXT IS NULL OR e.action = $1)
+            AND ($2::TEXT IS NULL OR u.username = $2)
+            AND ($3::TEXT IS NULL OR e.target = $3)
+            AND ($4::TIMESTAMPTZ IS NULL OR e.occurred_at >= $4)
+            AND ($5::TIMESTAMPTZ IS NULL OR e.occurred_at < $5)
+        ORDER BY e.occurred_at DESC
+        LIMIT $6
+        "#,
+        filter.action.map(|action| action.as_str()),
+        filter.actor_username,
+        filter.target,
+        filter.since,
+        filter.until,
+        limit,
+    )
+        .fetch_all(pool)
+        .await
+        .context("Failed to retrieve audit events.")?;
+    Ok(events)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::AuditAction;
+    use claims::assert_err;
+
+    #[test]
+    fn actions_round_trip_through_their_names() {
+        for action in AuditAction::ALL {
+            assert_eq!(AuditAction::try_from(action.as_str().to_string()), Ok(action));
+        }
+        assert_err!(AuditAction::try_from("deleted_everything".to_string()));
+    }
+}
@@ -0,0 +1,160 @@
+use crate::authentication::AuthError;
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use rand::distributions::Alphanumeric;
+use rand::{thread_rng, Rng};
+use secrecy::{ExposeSecret, Secret};
+use sha2::{Digest, Sha256};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+const TOKEN_PREFIX: &str = "z2p_";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ApiTokenScope {
+    PublishNewsletters,
+}
+
+impl ApiTokenScope {
+    pub const ALL: [ApiTokenScope; 1] = [ApiTokenScope::PublishNewsletters];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ApiTokenScope::PublishNewsletters => "newsletters:publish",
+        }
+    }
+}
+
+impl TryFrom<String> for ApiTokenScope {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        match s.as_str() {
+            "newsletters:publish" => Ok(Self::PublishNewsletters),
+            other => Err(format!("{} is not a supported API token scope.", other)),
+        }
+    }
+}
+
+pub struct ApiToken {
+    pub token_id: Uuid,
+    pub name: String,
+    pub scopes: Vec<String>,
+    pub created_at: DateTime<Utc>,
+    pub last_used_at: Option<DateTime<Utc>>,
+    pub revoked_at: Option<DateTime<Utc>>,
+}
+
+// Tokens carry enough entropy that a fast hash is enough to keep them
+// useless if the table leaks, unlike user-chosen passwords.
+fn hash_api_token(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
+
+fn generate_api_token() -> Secret<String> {
+    let mut rng = thread_rng();
+    let token: String = std::iter::repeat_with(|| rng.sample(Alphanumeric))
+        .map(char::from)
+        .take(40)
+        .collect();
+    Secret::new(format!("{}{}", TOKEN_PREFIX, token))
+}
+
+#[tracing::instrument(name = "Create API token", skip(pool))]
+pub async fn create_api_token(
+    pool: &PgPool,
+    user_id: Uuid,
+    name: &str,
+    scopes: &[ApiTokenScope],
+) -> Result<Secret<String>, anyhow::Error> {
+    let token = generate_api_token();
+    let scopes: Vec<String> = scopes.iter().map(|s| s.as_str().to_owned()).collect();
+    sqlx::query!(
+        r#"
+        INSERT INTO api_tokens (token_id, user_id, name, scopes, token_hash, created_at)
+        VALUES ($1, $2, $3, $4, $5, now())
+        "#,
+        Uuid::new_v4(),
+        user_id,
+        name,
+        &scopes,
+        hash_api_token(token.expose_secret()),
+    )
+        .execute(pool)
+        .await
+        .context("Failed to store the new API token.")?;
+    Ok(token)
+}
+
+#[tracing::instrument(name = "List API tokens", skip(pool))]
+pub async fn get_api_tokens(
+    pool: &PgPool,
+    user_id: Uuid,
+) -> Result<Vec<ApiToken>, anyhow::Error> {
+    let tokens = sqlx::query_as!(
+        ApiToken,
+        r#"
+        SELECT token_id, name, scopes, created_at, last_used_at, revoked_at
+        FROM api_tokens
+        WHERE user_id = $1
+        ORDER BY created_at DESC
+        "#,
+        user_id,
+    )
+        .fetch_all(pool)
+        .await
+        .context("Failed to retrieve API tokens.")?;
+    Ok(tokens)
+}
+
+// Returns false if the token does not exist, belongs to another user or
+// was already revoked.
+#[tracing::instrument(name = "Revoke API token", skip(pool))]
+pub async fn revoke_api_token(
+    pool: &PgPool,
+    user_id: Uuid,
+    token_id: Uuid,
+) -> Result<bool, anyhow::Error> {
+    let result = sqlx::query!(
+        r#"
+        UPDATE api_tokens
+        SET revoked_at = now()
+        WHERE token_id = $1 AND user_id = $2 AND revoked_at IS NULL
+        "#,
+        token_id,
+        user_id,
+    )
+        .execute(pool)
+        .await
+        .context("Failed to revoke API token.")?;
+    Ok(result.rows_affected() > 0)
+}
+
+#[tracing::instrument(name = "Validate API token", skip(token, pool))]
+pub async fn validate_api_token(
+    token: Secret<String>,
+    required_scope: ApiTokenScope,
+    pool: &PgPool,
+) -> Result<Uuid, AuthError> {
+    let row = sqlx::query!(
+        r#"
+        UPDATE api_tokens
+        SET last_used_at = now()
+        WHERE token_hash = $1 AND revoked_at IS NULL
+        RETURNING user_id, scopes
+        "#,
+        hash_api_token(token.expose_secret()),
+    )
+        .fetch_optional(pool)
+        .await
+        .context("Failed to perform a query to validate the API token.")?
+        .ok_or_else(|| anyhow::anyhow!("Unknown or revoked API token."))
+        .map_err(AuthError::InvalidCredentials)?;
+    if !row.scopes.iter().any(|s| s == required_scope.as_str()) {
+        return Err(AuthError::InvalidCredentials(anyhow::anyhow!(
+            "The API token is missing the '{}' scope.",
+            required_scope.as_str()
+        )));
+    }
+    Ok(row.user_id)
+}
@@ -0,0 +1,114 @@
+use crate::configuration::Environment;
+use anyhow::Context;
+use rand::distributions::Alphanumeric;
+use rand::{thread_rng, Rng};
+use secrecy::{ExposeSecret, Secret};
+use sha2::{Digest, Sha256};
+use sqlx::PgPool;
+
+// The hash of the well-known password the early seed migration shipped with
+pub const SEEDED_ADMIN_PASSWORD_HASH: &str = "$argon2id$v=19$m=15000,t=2,p=1$\
+    OEx/rcq+3ts//WUDzGNl2g$Am8UFBA4w5NJEmAtquGvBmAlu92q/VQcaoL5AyJPfc8";
+
+// Refuse to serve production traffic while an account can still be logged
+// into with the seeded password.
+#[tracing::instrument(name = "Check for the seeded admin password", skip(pool))]
+pub async fn check_seeded_admin_password(
+    pool: &PgPool,
+    environment: Environment,
+) -> Result<(), anyhow::Error> {
+    let usernames: Vec<String> = sqlx::query!(
+        "SELECT username FROM users WHERE password_hash = $1",
+        SEEDED_ADMIN_PASSWORD_HASH,
+    )
+        .fetch_all(pool)
+        .await
+        .context("Failed to look for accounts using the seeded password.")?
+        .into_iter()
+        .map(|r| r.username)
+        .collect();
+    if usernames.is_empty() {
+        return Ok(());
+    }
+    if environment == Environment::Production {
+        anyhow::bail!(
+            "The account(s) {} still use the seeded default password. \
+            Change their password before running in production.",
+            usernames.join(", ")
+        );
+    }
+    tracing::warn!(
+        "The account(s) {} still use the seeded default password.",
+        usernames.join(", ")
+    );
+    Ok(())
+}
+
+// A one-time token that lets whoever can read the server logs create the
+// first owner account.
+#[derive(Clone)]
+pub struct SetupToken(Secret<String>);
+
+impl SetupToken {
+    fn generate() -> Self {
+        let mut rng = thread_rng();
+        let token: String = std::iter::repeat_with(|| rng.sample(Alphanumeric))
+            .map(char::from)
+            .take(32)
+            .collect();
+        Self(Secret::new(token))
+    }
+
+    pub fn expose(&self) -> &Secret<String> {
+        &self.0
+    }
+
+    // Compare digests so the check does not leak a matching prefix
+    pub fn verify(&self, candidate: &str) -> bool {
+        Sha256::digest(candidate.as_bytes()) == Sha256::digest(self.0.expose_secret().as_bytes())
+    }
+}
+
+#[tracing::instrument(name = "Check whether any user exists", skip(executor))]
+pub async fn users_exist<'c, E>(executor: E) -> Result<bool, anyhow::Error>
+where
+    E: sqlx::Executor<'c, Database = sqlx::Postgres>,
+{
+    let exists = sqlx::query!(r#"SELECT EXISTS(SELECT 1 FROM users) AS "exists!""#)
+        .fetch_one(executor)
+        .await
+        .context("Failed to check whether any user exists.")?
+        .exists;
+    Ok(exists)
+}
+
+// Only issued while there is nobody who could log in
+pub async fn issue_setup_token(
+    pool: &PgPool,
+    base_url: &str,
+) -> Result<Option<SetupToken>, anyhow::Error> {
+    if users_exist(pool).await? {
+        return Ok(None);
+    }
+    let token = SetupToken::generate();
+    tracing::warn!(
+        "No user account exists yet. Create the first owner at {}/setup \
+        with the one-time setup token {}",
+        base_url,
+        token.expose().expose_secret()
+    );
+    Ok(Some(token))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SetupToken;
+
+    #[test]
+    fn only_the_issued_setup_token_verifies() {
+        let token = SetupToken::generate();
+        assert!(token.verify(secrecy::ExposeSecret::expose_secret(token.expose())));
+        assert!(!token.verify("not-the-token"));
+        assert!(!token.verify(""));
+    }
+}
@@ -0,0 +1,193 @@
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::error::InternalError;
+use actix_web::http::header::CONTENT_TYPE;
+use actix_web::http::Method;
+use actix_web::dev::Payload;
+use actix_web::{web, FromRequest, HttpMessage, HttpResponse};
+use futures_util::StreamExt;
+use actix_web_lab::middleware::Next;
+use sha2::{Digest, Sha256};
+use crate::session_state::TypedSession;
+use crate::utils::e500;
+
+pub const CSRF_TOKEN_FIELD: &str = "csrf_token";
+// For submissions that are not url-encoded forms, e.g. scripts
+pub const CSRF_TOKEN_HEADER: &str = "X-CSRF-Token";
+
+// The hidden input every admin form has to carry
+pub fn csrf_field(token: &str) -> String {
+    format!(
+        r#"<input type="hidden" name="{}" value="{}">"#,
+        CSRF_TOKEN_FIELD,
+        htmlescape::encode_minimal(token)
+    )
+}
+
+// How much of a multipart body is read ahead to find the token. The field
+// comes first, so it is well within the limit.
+const MAX_LEADING_FIELD_BYTES: usize = 8 * 1024;
+
+// Refuses any state-changing request that does not carry the session's
+// CSRF token, either in the `csrf_token` form field or in a header.
+// Multipart forms, whose body is left for the handler to stream, must send
+// the field first.
+pub async fn reject_forged_requests(
+    mut req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    if matches!(*req.method(), Method::GET | Method::HEAD | Method::OPTIONS) {
+        return next.call(req).await;
+    }
+    let session = {
+        let (http_request, payload) = req.parts_mut();
+        TypedSession::from_request(http_request, payload).await
+    }?;
+    let expected = session.get_csrf_token().map_err(e500)?;
+    let submitted = match req.headers().get(CSRF_TOKEN_HEADER) {
+        Some(value) => value.to_str().ok().map(str::to_owned),
+        None if is_url_encoded_form(&req) => {
+            let body = req.extract::<web::Bytes>().await?;
+            let token = find_form_token(&body);
+            // Put the body back for the handler's own extractors
+            let (_, mut payload) = actix_http::h1::Payload::create(true);
+            payload.unread_data(body);
+            req.set_payload(payload.into());
+            token
+        }
+        None => match multipart_boundary(&req) {
+            Some(boundary) => {
+                let mut payload = req.take_payload();
+                let mut leading = Vec::new();
+                while leading.len() < MAX_LEADING_FIELD_BYTES
+                    && !has_second_part(&leading, &boundary)
+                {
+                    match payload.next().await {
+                        Some(chunk) => leading.extend_from_slice(&chunk?),
+                        None => break,
+                    }
+                }
+                let token = find_leading_multipart_token(&leading, &boundary);
+                // Put what was read back in front of the rest of the body
+                let leading = web::Bytes::from(leading);
+                let body = futures_util::stream::once(async move { Ok(leading) }).chain(payload);
+                req.set_payload(Payload::Stream { payload: Box::pin(body) });
+                token
+            }
+            None => None,
+        },
+    };
+    match (expected, submitted) {
+        (Some(expected), Some(submitted)) if tokens_match(&expected, &submitted) => {
+            next.call(req).await
+        }
+        _ => {
+            let response = HttpResponse::Forbidden()
+                .body("This form has expired - please reload the page and try again.");
+            let e = anyhow::anyhow!("The request did not carry a valid CSRF token.");
+            Err(InternalError::from_response(e, response).into())
+        }
+    }
+}
+
+fn is_url_encoded_form(req: &ServiceRequest) -> bool {
+    req.headers()
+        .get(CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("application/x-www-form-urlencoded"))
+}
+
+fn multipart_boundary(req: &ServiceRequest) -> Option<String> {
+    let mime = req.mime_type().ok()??;
+    if mime.essence_str() != "multipart/form-data" {
+        return None;
+    }
+    mime.get_param("boundary").map(|boundary| boundary.to_string())
+}
+
+fn has_second_part(body: &[u8], boundary: &str) -> bool {
+    let delimiter = format!("\r\n--{}", boundary);
+    body.windows(delimiter.len())
+        .any(|window| window == delimiter.as_bytes())
+}
+
+// The value of the first part of a multipart body, if that part is the
+// `csrf_token` field
+fn find_leading_multipart_token(body: &[u8], boundary: &str) -> Option<String> {
+    let body = std::str::from_utf8(body).ok()?;
+    let first_part = body.strip_prefix(&format!("--{}\r\n", boundary))?;
+    let (first_part, _) = first_part.split_once(&format!("\r\n--{}", boundary))?;
+    let (headers, value) = first_part.split_once("\r\n\r\n")?;
+    let is_token_field = headers.lines().any(|header| {
+        let header = header.to_ascii_lowercase();
+        header.starts_with("content-disposition:")
+            && header.contains(&format!("name=\"{}\"", CSRF_TOKEN_FIELD))
+    });
+    is_token_field.then(|| value.to_string())
+}
+
+fn find_form_token(body: &[u8]) -> Option<String> {
+    let body = std::str::from_utf8(body).ok()?;
+    let pairs = web::Query::<Vec<(String, String)>>::from_query(body).ok()?;
+    pairs
+        .into_inner()
+        .into_iter()
+        .find(|(key, _)| key == CSRF_TOKEN_FIELD)
+        .map(|(_, value)| value)
+}
+
+// Compares digests, so the time taken says nothing about the expected token
+fn tokens_match(expected: &str, submitted: &str) -> bool {
+    Sha256::digest(expected.as_bytes()) == Sha256::digest(submitted.as_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{csrf_field, find_form_token, find_leading_multipart_token};
+    use claims::{assert_none, assert_some_eq};
+
+    #[test]
+    fn the_token_is_found_in_the_first_part_of_a_multipart_body() {
+        let body = "--XyZ\r\n\
+            Content-Disposition: form-data; name=\"csrf_token\"\r\n\r\n\
+            abc123\r\n\
+            --XyZ\r\n\
+            Content-Disposition: form-data; name=\"file\"; filename=\"a.csv\"\r\n\r\n\
+            email,name\r\n\
+            --XyZ--\r\n";
+        assert_some_eq!(find_leading_multipart_token(body.as_bytes(), "XyZ"), "abc123");
+    }
+
+    #[test]
+    fn a_token_after_the_first_part_of_a_multipart_body_is_not_looked_for() {
+        let body = "--XyZ\r\n\
+            Content-Disposition: form-data; name=\"mode\"\r\n\r\n\
+            confirmed\r\n\
+            --XyZ\r\n\
+            Content-Disposition: form-data; name=\"csrf_token\"\r\n\r\n\
+            abc123\r\n\
+            --XyZ--\r\n";
+        assert_none!(find_leading_multipart_token(body.as_bytes(), "XyZ"));
+        assert_none!(find_leading_multipart_token(b"--XyZ\r\nunterminated", "XyZ"));
+    }
+
+    #[test]
+    fn the_token_is_found_among_other_fields() {
+        let body = b"name=Some+Name&scope=publish&csrf_token=abc123&scope=read";
+        assert_some_eq!(find_form_token(body), "abc123");
+    }
+
+    #[test]
+    fn forms_without_a_token_have_none() {
+        assert_none!(find_form_token(b"name=Some+Name"));
+        assert_none!(find_form_token(&[0xff, 0xfe]));
+    }
+
+    #[test]
+    fn the_hidden_field_escapes_the_token() {
+        assert_eq!(
+            csrf_field(r#"a"b"#),
+            r#"<input type="hidden" name="csrf_token" value="a&quot;b">"#
+        );
+    }
+}
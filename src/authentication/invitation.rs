@@ -0,0 +1,55 @@
+use anyhow::Context;
+use hmac::{Hmac, Mac};
+use secrecy::{ExposeSecret, Secret};
+use sha2::Sha256;
+use uuid::Uuid;
+
+// How long an invite link stays valid
+pub const INVITATION_TTL_DAYS: i64 = 7;
+
+fn invitation_mac(invitation_id: &Uuid, secret: &Secret<String>) -> Hmac<Sha256> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.expose_secret().as_bytes())
+        .expect("HMAC can take a key of any size");
+    // Prefix the message so the tag cannot be replayed for another purpose
+    mac.update(b"invitation:");
+    mac.update(invitation_id.as_bytes());
+    mac
+}
+
+// The tag that signs an invite link
+pub fn invitation_tag(invitation_id: &Uuid, secret: &Secret<String>) -> String {
+    hex::encode(invitation_mac(invitation_id, secret).finalize().into_bytes())
+}
+
+pub fn verify_invitation_tag(
+    invitation_id: &Uuid,
+    tag: &str,
+    secret: &Secret<String>,
+) -> Result<(), anyhow::Error> {
+    let tag = hex::decode(tag).context("The invitation tag is not valid hex.")?;
+    invitation_mac(invitation_id, secret)
+        .verify_slice(&tag)
+        .context("The invitation tag does not match.")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{invitation_tag, verify_invitation_tag};
+    use claims::{assert_err, assert_ok};
+    use secrecy::Secret;
+    use uuid::Uuid;
+
+    #[test]
+    fn a_tag_verifies_for_its_own_invitation_only() {
+        let secret = Secret::new("secret".to_string());
+        let invitation_id = Uuid::new_v4();
+        let tag = invitation_tag(&invitation_id, &secret);
+        assert_ok!(verify_invitation_tag(&invitation_id, &tag, &secret));
+        assert_err!(verify_invitation_tag(&Uuid::new_v4(), &tag, &secret));
+        assert_err!(verify_invitation_tag(
+            &invitation_id,
+            &tag,
+            &Secret::new("another-secret".to_string())
+        ));
+    }
+}
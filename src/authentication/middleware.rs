@@ -8,6 +8,10 @@ use crate::utils::{e500, see_other};
 use uuid::Uuid;
 use std::ops::Deref;
 use actix_web::HttpMessage;
+use actix_web::{web, HttpResponse};
+use anyhow::Context;
+use sqlx::PgPool;
+use crate::authentication::{touch_session, Role};
 
 
 
@@ -19,12 +23,42 @@ pub async fn reject_anonymous_users(
         let (http_request, payload) = req.parts_mut();
         TypedSession::from_request(http_request, payload).await
     }?;
-    match session.get_user_id().map_err(e500)? {
-        Some(user_id) => {
+    let pool = req
+        .app_data::<web::Data<PgPool>>()
+        .context("The connection pool is missing from the application data.")
+        .map_err(e500)?
+        .clone();
+    let user_id = match (
+        session.get_user_id().map_err(e500)?,
+        session.get_session_id().map_err(e500)?,
+    ) {
+        (Some(user_id), Some(session_id)) => {
+            // The session may have been revoked, e.g. by a password reset
+            if touch_session(&pool, session_id, user_id).await.map_err(e500)? {
+                Some(user_id)
+            } else {
+                None
+            }
+        }
+        _ => None,
+    };
+    let role = match user_id {
+        Some(user_id) => get_user_role(&pool, user_id)
+            .await
+            .map_err(e500)?
+            .map(|role| (user_id, role)),
+        None => None,
+    };
+    match role {
+        Some((user_id, role)) => {
             req.extensions_mut().insert(UserId(user_id));
+            req.extensions_mut().insert(role);
             next.call(req).await
         }
         None => {
+            // The session may have been revoked, or the account deleted,
+            // since the session was created
+            session.log_out();
             let response = see_other("/login");
             let e = anyhow::anyhow!("The user has not been logged in.");
             Err(InternalError::from_response(e, response).into())
@@ -32,6 +66,58 @@ pub async fn reject_anonymous_users(
     }
 }
 
+// Must be registered inside `reject_anonymous_users`, which resolves the role.
+pub async fn reject_viewers(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    require_role(req, next, Role::Editor).await
+}
+
+pub async fn reject_non_owners(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    require_role(req, next, Role::Owner).await
+}
+
+async fn require_role(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+    minimum: Role,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    let role = req.extensions().get::<Role>().copied();
+    match role {
+        Some(role) if role >= minimum => next.call(req).await,
+        _ => {
+            let response = HttpResponse::Forbidden()
+                .body("You do not have permission to access this page.");
+            let e = anyhow::anyhow!("The user's role is below {}.", minimum);
+            Err(InternalError::from_response(e, response).into())
+        }
+    }
+}
+
+#[tracing::instrument(name = "Get user role", skip(pool))]
+pub async fn get_user_role(
+    pool: &PgPool,
+    user_id: Uuid,
+) -> Result<Option<Role>, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"
+        SELECT role
+        FROM users
+        WHERE user_id = $1
+        "#,
+        user_id,
+    )
+        .fetch_optional(pool)
+        .await
+        .context("Failed to perform a query to retrieve a user's role.")?;
+    row.map(|r| Role::try_from(r.role).map_err(|e| anyhow::anyhow!(e)))
+        .transpose()
+}
+
 
 #[derive(Copy, Clone, Debug)]
 pub struct UserId(Uuid);
@@ -42,6 +128,12 @@ impl std::fmt::Display for UserId {
     }
 }
 
+impl From<Uuid> for UserId {
+    fn from(user_id: Uuid) -> Self {
+        Self(user_id)
+    }
+}
+
 impl Deref for UserId {
     type Target = Uuid;
 
@@ -1,9 +1,42 @@
+mod api_token;
+mod bootstrap;
+mod csrf;
+mod invitation;
 mod middleware;
 mod password;
+mod password_reset;
+mod role;
+mod throttling;
+mod totp;
+mod user_session;
 
+pub use api_token::{
+    create_api_token, get_api_tokens, revoke_api_token,
+    validate_api_token, ApiToken, ApiTokenScope,
+};
 pub use password::{
-    change_password, validate_credentials,
-    AuthError, Credentials,
+    change_password, compute_password_hash, validate_credentials,
+    AuthError, Credentials, PasswordHashing,
 };
-pub use middleware::reject_anonymous_users;
+pub use middleware::{get_user_role, reject_anonymous_users, reject_non_owners, reject_viewers};
 pub use middleware::UserId;
+pub use role::Role;
+pub use csrf::{csrf_field, reject_forged_requests, CSRF_TOKEN_FIELD, CSRF_TOKEN_HEADER};
+pub use invitation::{invitation_tag, verify_invitation_tag, INVITATION_TTL_DAYS};
+pub use bootstrap::{
+    check_seeded_admin_password, issue_setup_token, users_exist,
+    SetupToken, SEEDED_ADMIN_PASSWORD_HASH,
+};
+pub use password_reset::{
+    check_password_reset_token, consume_password_reset_token,
+    create_password_reset_token, PASSWORD_RESET_TTL_MINUTES,
+};
+pub use user_session::{
+    get_active_sessions, register_session, revoke_all_sessions, revoke_other_sessions,
+    revoke_session, touch_session, SessionOrigin, UserSession,
+};
+pub use totp::{
+    disable_totp, enable_totp, get_totp_settings, is_totp_enabled, otpauth_uri,
+    start_totp_enrollment, totp_code, verify_second_factor, verify_totp_code,
+};
+pub use throttling::{lockout_message, LoginAttemptSource, LoginThrottle};
@@ -1,9 +1,12 @@
 use crate::telemetry::spawn_blocking_with_tracing;
-use anyhow::{Context, Ok};
+use crate::authentication::revoke_other_sessions;
+use crate::audit::{record_audit_event, NewAuditEvent};
+use anyhow::Context;
 use secrecy::{Secret, ExposeSecret};
 use argon2::{Argon2, PasswordHash, PasswordVerifier, Algorithm, Params, PasswordHasher, Version};
 use sqlx::PgPool;
 use argon2::password_hash::SaltString;
+use crate::configuration::PasswordHashingSettings;
 
 #[derive(thiserror::Error, Debug)]
 pub enum AuthError {
@@ -18,24 +21,77 @@ pub struct Credentials {
     pub password: Secret<String>,
 }
 
+// The algorithm and cost that new password hashes are computed with
+#[derive(Clone)]
+pub struct PasswordHashing {
+    algorithm: Algorithm,
+    params: Params,
+    // Verified against when the username is unknown, so that a missing
+    // account costs as much as a wrong password.
+    dummy_hash: Secret<String>,
+}
+
+impl PasswordHashing {
+    pub fn new(settings: &PasswordHashingSettings) -> Result<Self, anyhow::Error> {
+        let algorithm = Algorithm::new(&settings.algorithm)
+            .map_err(|e| anyhow::anyhow!("Unsupported password hashing algorithm: {}", e))?;
+        let params = Params::new(
+            settings.memory_cost_kib,
+            settings.iterations,
+            settings.parallelism,
+            None,
+        )
+            .map_err(|e| anyhow::anyhow!("Invalid password hashing parameters: {}", e))?;
+        let mut hashing = Self {
+            algorithm,
+            params,
+            dummy_hash: Secret::new(String::new()),
+        };
+        hashing.dummy_hash = compute_password_hash(
+            Secret::new(uuid::Uuid::new_v4().to_string()),
+            &hashing,
+        )?;
+        Ok(hashing)
+    }
+
+    fn argon2(&self) -> Argon2<'static> {
+        Argon2::new(self.algorithm, Version::V0x13, self.params.clone())
+    }
+
+    // Whether a hash was computed with anything weaker than the target
+    fn needs_rehash(&self, hash: &PasswordHash) -> bool {
+        if Algorithm::new(hash.algorithm.as_str()).ok() != Some(self.algorithm) {
+            return true;
+        }
+        if hash.version != Some(Version::V0x13.into()) {
+            return true;
+        }
+        match Params::try_from(hash) {
+            Ok(params) => {
+                params.m_cost() < self.params.m_cost()
+                    || params.t_cost() < self.params.t_cost()
+                    || params.p_cost() < self.params.p_cost()
+            }
+            Err(_) => true,
+        }
+    }
+}
+
 #[tracing::instrument(
     name = "Validate credentials",
-    skip(credentials, pool)
+    skip(credentials, pool, hashing)
 )]
 
 pub async fn validate_credentials(
         credentials: Credentials,
         pool: &PgPool,
+        hashing: &PasswordHashing,
     ) -> Result<uuid::Uuid, AuthError> {
         let mut user_id = None;
-        let mut expected_password_hash = Secret::new(
-            "$argon2id$v=15000,t=2,p=1$\
-            gZiV/MigPc22ElAH/Jh1Hw$\
-            CWOrkoo7oJBQ/iyh7uJ0LO2aLEfrHwTWllSAxT0zRno".to_string()
-        );
+        let mut expected_password_hash = hashing.dummy_hash.clone();
         if let Some((stored_user_id, stored_password_hash)) = get_stored_credentials(
             &credentials.username,
-            &pool
+            pool
         )
             .await?
         {
@@ -43,26 +99,45 @@ pub async fn validate_credentials(
             expected_password_hash = stored_password_hash;
         }
 
-        spawn_blocking_with_tracing(move || {
-                verify_password_hash(expected_password_hash, credentials.password)
+        let current_password_hash = expected_password_hash.clone();
+        let hashing = hashing.clone();
+        let upgraded_password_hash = spawn_blocking_with_tracing(move || {
+                verify_password_hash(expected_password_hash, credentials.password, &hashing)
         })
         .await
         .context("Invalid password.")??;
 
-    user_id
+    let user_id = user_id
         .ok_or_else(|| anyhow::anyhow!("Unknown username."))
-        .map_err(AuthError::InvalidCredentials)
+        .map_err(AuthError::InvalidCredentials)?;
+    if let Some(upgraded_password_hash) = upgraded_password_hash {
+        // The login itself succeeded, so a failed upgrade is only logged
+        if let Err(e) = upgrade_password_hash(
+            user_id,
+            &current_password_hash,
+            &upgraded_password_hash,
+            pool,
+        )
+            .await
+        {
+            tracing::warn!(error.cause_chain = ?e, "Failed to upgrade a password hash");
+        }
+    }
+    Ok(user_id)
 }
 
 
+// On success, returns a new hash of the password if the stored one is
+// weaker than the current target.
 #[tracing::instrument(
     name = "Verify password hash",
-    skip(expected_password_hash, password_candidate)
+    skip(expected_password_hash, password_candidate, hashing)
 )]
 fn verify_password_hash(
     expected_password_hash: Secret<String>,
     password_candidate: Secret<String>,
-) -> Result<(), AuthError> {
+    hashing: &PasswordHashing,
+) -> Result<Option<Secret<String>>, AuthError> {
     let expected_password_hash = PasswordHash::new(
         expected_password_hash.expose_secret()
     )
@@ -74,7 +149,37 @@ fn verify_password_hash(
             &expected_password_hash
         )
         .context("Invalid password.")
-        .map_err(AuthError::InvalidCredentials)
+        .map_err(AuthError::InvalidCredentials)?;
+
+    if !hashing.needs_rehash(&expected_password_hash) {
+        return Ok(None);
+    }
+    Ok(Some(compute_password_hash(password_candidate, hashing)?))
+}
+
+// Only replaces the hash that was verified, in case the password was
+// changed in the meantime.
+#[tracing::instrument(name = "Upgrade password hash", skip_all, fields(user_id=%user_id))]
+async fn upgrade_password_hash(
+    user_id: uuid::Uuid,
+    current_password_hash: &Secret<String>,
+    upgraded_password_hash: &Secret<String>,
+    pool: &PgPool,
+) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        r#"
+        UPDATE users
+        SET password_hash = $1
+        WHERE user_id = $2 AND password_hash = $3
+        "#,
+        upgraded_password_hash.expose_secret(),
+        user_id,
+        current_password_hash.expose_secret(),
+    )
+        .execute(pool)
+        .await
+        .context("Failed to store the upgraded password hash.")?;
+    Ok(())
 }
 
 #[tracing::instrument(name = "Get stored credentials", skip(username, pool))]
@@ -99,17 +204,26 @@ async fn get_stored_credentials(
 }
 
 
-#[tracing::instrument(name = "Change password", skip(password, pool))]
+// Everyone else logged in as the user is logged out, since they may only
+// have known the old password.
+#[tracing::instrument(name = "Change password", skip(password, audit_event, pool, hashing))]
+// The audit event is recorded with the change, so neither is kept without
+// the other
 pub async fn change_password(
     user_id: uuid::Uuid,
+    current_session_id: uuid::Uuid,
     password: Secret<String>,
+    audit_event: NewAuditEvent,
     pool: &PgPool,
+    hashing: &PasswordHashing,
 ) -> Result<(), anyhow::Error> {
+    let hashing = hashing.clone();
     let password_hash = spawn_blocking_with_tracing(
-        move || compute_password_hash(password)
+        move || compute_password_hash(password, &hashing)
     )
     .await?
     .context("Failed to hash password.")?;
+    let mut transaction = pool.begin().await.context("Failed to start a transaction.")?;
     sqlx::query!(
         r#"
         UPDATE users
@@ -119,22 +233,78 @@ pub async fn change_password(
         password_hash.expose_secret(),
         user_id
     )
-        .execute(pool)
+        .execute(&mut *transaction)
         .await
         .context("Failed to change user's password in the database.")?;
+    revoke_other_sessions(&mut *transaction, user_id, current_session_id).await?;
+    record_audit_event(&mut *transaction, audit_event).await?;
+    transaction.commit().await.context("Failed to commit the password change.")?;
     Ok(())
 }
 
-fn compute_password_hash(
+pub fn compute_password_hash(
     password: Secret<String>,
+    hashing: &PasswordHashing,
 ) ->  Result<Secret<String>, anyhow::Error> {
     let salt = SaltString::generate(& mut rand::thread_rng());
-    let password_hash = Argon2::new(
-        Algorithm::Argon2id,
-        Version::V0x13,
-        Params::new(15000, 2, 1, None).unwrap(),
-    )
+    let password_hash = hashing
+    .argon2()
     .hash_password(password.expose_secret().as_bytes(), &salt)?
     .to_string();
     Ok(Secret::new(password_hash))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{compute_password_hash, PasswordHashing};
+    use crate::configuration::PasswordHashingSettings;
+    use argon2::PasswordHash;
+    use secrecy::{ExposeSecret, Secret};
+
+    fn hashing(algorithm: &str, memory_cost_kib: u32, iterations: u32) -> PasswordHashing {
+        PasswordHashing::new(&PasswordHashingSettings {
+            algorithm: algorithm.into(),
+            memory_cost_kib,
+            iterations,
+            parallelism: 1,
+        })
+        .unwrap()
+    }
+
+    fn needs_rehash(from: &PasswordHashing, to: &PasswordHashing) -> bool {
+        let hash = compute_password_hash(Secret::new("password".into()), from).unwrap();
+        to.needs_rehash(&PasswordHash::new(hash.expose_secret()).unwrap())
+    }
+
+    #[test]
+    fn hashes_matching_the_target_are_kept() {
+        assert!(!needs_rehash(&hashing("argon2id", 1024, 1), &hashing("argon2id", 1024, 1)));
+    }
+
+    #[test]
+    fn hashes_above_the_target_are_kept() {
+        assert!(!needs_rehash(&hashing("argon2id", 2048, 2), &hashing("argon2id", 1024, 1)));
+    }
+
+    #[test]
+    fn cheaper_hashes_are_upgraded() {
+        assert!(needs_rehash(&hashing("argon2id", 1024, 1), &hashing("argon2id", 2048, 1)));
+        assert!(needs_rehash(&hashing("argon2id", 1024, 1), &hashing("argon2id", 1024, 2)));
+    }
+
+    #[test]
+    fn hashes_from_another_algorithm_are_upgraded() {
+        assert!(needs_rehash(&hashing("argon2d", 1024, 1), &hashing("argon2id", 1024, 1)));
+    }
+
+    #[test]
+    fn unknown_algorithms_are_rejected() {
+        assert!(PasswordHashing::new(&PasswordHashingSettings {
+            algorithm: "md5".into(),
+            memory_cost_kib: 1024,
+            iterations: 1,
+            parallelism: 1,
+        })
+        .is_err());
+    }
+}
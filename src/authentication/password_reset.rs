@@ -0,0 +1,86 @@
+use anyhow::Context;
+use chrono::{Duration, Utc};
+use rand::distributions::Alphanumeric;
+use rand::{thread_rng, Rng};
+use secrecy::{ExposeSecret, Secret};
+use sha2::{Digest, Sha256};
+use sqlx::{Executor, PgPool, Postgres};
+use uuid::Uuid;
+
+// Reset links are only good for a short while
+pub const PASSWORD_RESET_TTL_MINUTES: i64 = 30;
+
+fn hash_reset_token(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
+
+#[tracing::instrument(name = "Create password reset token", skip(pool))]
+pub async fn create_password_reset_token(
+    pool: &PgPool,
+    user_id: Uuid,
+) -> Result<Secret<String>, anyhow::Error> {
+    let token: String = {
+        let mut rng = thread_rng();
+        std::iter::repeat_with(|| rng.sample(Alphanumeric))
+            .map(char::from)
+            .take(40)
+            .collect()
+    };
+    sqlx::query!(
+        r#"
+        INSERT INTO password_reset_tokens (token_hash, user_id, created_at, expires_at)
+        VALUES ($1, $2, now(), $3)
+        "#,
+        hash_reset_token(&token),
+        user_id,
+        Utc::now() + Duration::minutes(PASSWORD_RESET_TTL_MINUTES),
+    )
+        .execute(pool)
+        .await
+        .context("Failed to store the password reset token.")?;
+    Ok(Secret::new(token))
+}
+
+// Returns the user the token was issued for, if it is still usable
+#[tracing::instrument(name = "Check password reset token", skip(token, pool))]
+pub async fn check_password_reset_token(
+    pool: &PgPool,
+    token: &Secret<String>,
+) -> Result<Option<Uuid>, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"
+        SELECT user_id
+        FROM password_reset_tokens
+        WHERE token_hash = $1 AND used_at IS NULL AND expires_at > now()
+        "#,
+        hash_reset_token(token.expose_secret()),
+    )
+        .fetch_optional(pool)
+        .await
+        .context("Failed to look up the password reset token.")?;
+    Ok(row.map(|r| r.user_id))
+}
+
+// Marks the token as used, so it only ever resets one password
+#[tracing::instrument(name = "Consume password reset token", skip(token, executor))]
+pub async fn consume_password_reset_token<'c, E>(
+    executor: E,
+    token: &Secret<String>,
+) -> Result<Option<Uuid>, anyhow::Error>
+where
+    E: Executor<'c, Database = Postgres>,
+{
+    let row = sqlx::query!(
+        r#"
+        UPDATE password_reset_tokens
+        SET used_at = now()
+        WHERE token_hash = $1 AND used_at IS NULL AND expires_at > now()
+        RETURNING user_id
+        "#,
+        hash_reset_token(token.expose_secret()),
+    )
+        .fetch_optional(executor)
+        .await
+        .context("Failed to consume the password reset token.")?;
+    Ok(row.map(|r| r.user_id))
+}
@@ -0,0 +1,75 @@
+// Roles are ordered from the least to the most privileged
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Role {
+    Viewer,
+    Editor,
+    Owner,
+}
+
+impl Role {
+    pub const ALL: [Role; 3] = [Role::Owner, Role::Editor, Role::Viewer];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Role::Viewer => "viewer",
+            Role::Editor => "editor",
+            Role::Owner => "owner",
+        }
+    }
+
+    pub fn can_publish(&self) -> bool {
+        *self >= Role::Editor
+    }
+
+    pub fn can_manage_users(&self) -> bool {
+        *self == Role::Owner
+    }
+}
+
+impl std::fmt::Display for Role {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.as_str().fmt(f)
+    }
+}
+
+impl TryFrom<String> for Role {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        match s.as_str() {
+            "viewer" => Ok(Self::Viewer),
+            "editor" => Ok(Self::Editor),
+            "owner" => Ok(Self::Owner),
+            other => Err(format!(
+                "{} is not a supported role. \
+                Use either 'owner', 'editor' or 'viewer'.",
+                other
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Role;
+    use claims::assert_err;
+
+    #[test]
+    fn only_owners_can_manage_users() {
+        assert!(Role::Owner.can_manage_users());
+        assert!(!Role::Editor.can_manage_users());
+        assert!(!Role::Viewer.can_manage_users());
+    }
+
+    #[test]
+    fn viewers_cannot_publish() {
+        assert!(Role::Owner.can_publish());
+        assert!(Role::Editor.can_publish());
+        assert!(!Role::Viewer.can_publish());
+    }
+
+    #[test]
+    fn unknown_roles_are_rejected() {
+        assert_err!(Role::try_from("admin".to_string()));
+    }
+}
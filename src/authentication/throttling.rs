@@ -0,0 +1,159 @@
+use crate::configuration::LoginThrottlingSettings;
+use anyhow::Context;
+use redis::aio::ConnectionManager;
+use secrecy::{ExposeSecret, Secret};
+use sha2::{Digest, Sha256};
+use std::net::IpAddr;
+
+// What a failed login is counted against
+#[derive(Debug, Clone, Copy)]
+pub enum LoginAttemptSource<'a> {
+    Username(&'a str),
+    Ip(IpAddr),
+}
+
+// Counts failed logins in the Redis instance that already stores sessions,
+// so every replica of the application shares the same counters.
+#[derive(Clone)]
+pub struct LoginThrottle {
+    connection: ConnectionManager,
+    settings: LoginThrottlingSettings,
+}
+
+impl LoginThrottle {
+    pub async fn new(
+        redis_uri: &Secret<String>,
+        settings: LoginThrottlingSettings,
+    ) -> Result<Self, anyhow::Error> {
+        let client = redis::Client::open(redis_uri.expose_secret().as_str())
+            .context("Failed to parse the Redis URI.")?;
+        let connection = ConnectionManager::new(client)
+            .await
+            .context("Failed to connect to Redis.")?;
+        Ok(Self { connection, settings })
+    }
+
+    fn max_failures(&self, source: LoginAttemptSource) -> u32 {
+        match source {
+            LoginAttemptSource::Username(_) => self.settings.max_failures_per_username,
+            LoginAttemptSource::Ip(_) => self.settings.max_failures_per_ip,
+        }
+    }
+
+    // Usernames are hashed to keep attacker-chosen strings out of key names
+    fn key(&self, kind: &str, source: LoginAttemptSource) -> String {
+        let subject = match source {
+            LoginAttemptSource::Username(username) => {
+                format!("user:{}", hex::encode(Sha256::digest(username.as_bytes())))
+            }
+            LoginAttemptSource::Ip(ip) => format!("ip:{}", ip),
+        };
+        format!("{}:{}:{}", self.settings.key_prefix, kind, subject)
+    }
+
+    // Returns how many seconds are left on the longest lockout, if any
+    #[tracing::instrument(name = "Check login lockout", skip(self))]
+    pub async fn locked_out_for(
+        &self,
+        sources: &[LoginAttemptSource<'_>],
+    ) -> Result<Option<u64>, anyhow::Error> {
+        let mut connection = self.connection.clone();
+        let mut locked_out_for = None;
+        for source in sources {
+            let ttl: i64 = redis::cmd("TTL")
+                .arg(self.key("lockout", *source))
+                .query_async(&mut connection)
+                .await
+                .context("Failed to read a login lockout from Redis.")?;
+            if ttl > 0 {
+                locked_out_for = locked_out_for.max(Some(ttl as u64));
+            }
+        }
+        Ok(locked_out_for)
+    }
+
+    // Counts a failure and starts a lockout for any source that reached
+    // its limit within the window.
+    #[tracing::instrument(name = "Record failed login", skip(self))]
+    pub async fn record_failure(
+        &self,
+        sources: &[LoginAttemptSource<'_>],
+    ) -> Result<(), anyhow::Error> {
+        let mut connection = self.connection.clone();
+        for source in sources {
+            let failures_key = self.key("failures", *source);
+            let (n_failures,): (u32,) = redis::pipe()
+                .atomic()
+                .cmd("SET")
+                .arg(&failures_key)
+                .arg(0)
+                .arg("EX")
+                .arg(self.settings.window_seconds)
+                .arg("NX")
+                .ignore()
+                .cmd("INCR")
+                .arg(&failures_key)
+                .query_async(&mut connection)
+                .await
+                .context("Failed to count a failed login in Redis.")?;
+            if n_failures >= self.max_failures(*source) {
+                tracing::warn!(?source, "Too many failed logins, locking out");
+                redis::pipe()
+                    .atomic()
+                    .cmd("SET")
+                    .arg(self.key("lockout", *source))
+                    .arg(1)
+                    .arg("EX")
+                    .arg(self.settings.lockout_seconds)
+                    .ignore()
+                    .cmd("DEL")
+                    .arg(&failures_key)
+                    .ignore()
+                    .query_async::<_, ()>(&mut connection)
+                    .await
+                    .context("Failed to store a login lockout in Redis.")?;
+            }
+        }
+        Ok(())
+    }
+
+    // A successful login clears the count for the account, but not for the
+    // IP address: one valid account must not reset a credential-stuffing run.
+    #[tracing::instrument(name = "Record successful login", skip(self))]
+    pub async fn record_success(&self, username: &str) -> Result<(), anyhow::Error> {
+        let mut connection = self.connection.clone();
+        redis::cmd("DEL")
+            .arg(self.key("failures", LoginAttemptSource::Username(username)))
+            .query_async::<_, ()>(&mut connection)
+            .await
+            .context("Failed to clear failed logins in Redis.")?;
+        Ok(())
+    }
+}
+
+// Rounded up, so "0 minutes" is never shown
+pub fn lockout_message(seconds: u64) -> String {
+    let minutes = seconds.div_ceil(60);
+    format!(
+        "Too many failed login attempts. Try again in {} minute{}.",
+        minutes,
+        if minutes == 1 { "" } else { "s" }
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::lockout_message;
+
+    #[test]
+    fn lockouts_are_shown_in_whole_minutes() {
+        assert_eq!(
+            lockout_message(1),
+            "Too many failed login attempts. Try again in 1 minute."
+        );
+        assert_eq!(
+            lockout_message(900),
+            "Too many failed login attempts. Try again in 15 minutes."
+        );
+    }
+}
@@ -0,0 +1,328 @@
+use anyhow::Context;
+use hmac::{Hmac, Mac};
+use rand::distributions::Alphanumeric;
+use rand::{thread_rng, Rng, RngCore};
+use secrecy::{ExposeSecret, Secret};
+use sha1::Sha1;
+use sha2::{Digest, Sha256};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+// RFC 6238 defaults, which is what every authenticator app expects
+const TOTP_STEP_SECONDS: i64 = 30;
+const TOTP_DIGITS: u32 = 6;
+// Accept the previous and next code too, to absorb clock drift
+const TOTP_ALLOWED_DRIFT_STEPS: i64 = 1;
+const N_RECOVERY_CODES: usize = 10;
+const BASE32: base32::Alphabet = base32::Alphabet::RFC4648 { padding: false };
+
+pub fn generate_totp_secret() -> Secret<String> {
+    let mut key = [0u8; 20];
+    thread_rng().fill_bytes(&mut key);
+    Secret::new(base32::encode(BASE32, &key))
+}
+
+// RFC 4226 HOTP, with the dynamic truncation of section 5.3
+fn hotp(key: &[u8], counter: u64) -> u32 {
+    let mut mac = Hmac::<Sha1>::new_from_slice(key).expect("HMAC can take a key of any size");
+    mac.update(&counter.to_be_bytes());
+    let digest = mac.finalize().into_bytes();
+    let offset = (digest[19] & 0x0f) as usize;
+    let binary = u32::from_be_bytes([
+        digest[offset] & 0x7f,
+        digest[offset + 1],
+        digest[offset + 2],
+        digest[offset + 3],
+    ]);
+    binary % 10u32.pow(TOTP_DIGITS)
+}
+
+fn decode_secret(secret: &Secret<String>) -> Result<Vec<u8>, anyhow::Error> {
+    base32::decode(BASE32, secret.expose_secret())
+        .context("The TOTP secret is not valid base32.")
+}
+
+// The code an authenticator app shows at `unix_time`
+pub fn totp_code(secret: &Secret<String>, unix_time: i64) -> Result<String, anyhow::Error> {
+    let key = decode_secret(secret)?;
+    let step = (unix_time / TOTP_STEP_SECONDS) as u64;
+    Ok(format!("{:0width$}", hotp(&key, step), width = TOTP_DIGITS as usize))
+}
+
+// Returns the time step the code belongs to, if it is valid at `unix_time`
+pub fn verify_totp_code(
+    secret: &Secret<String>,
+    code: &str,
+    unix_time: i64,
+) -> Result<Option<i64>, anyhow::Error> {
+    let key = decode_secret(secret)?;
+    let code = code.trim();
+    if code.len() != TOTP_DIGITS as usize {
+        return Ok(None);
+    }
+    let current_step = unix_time / TOTP_STEP_SECONDS;
+    for step in current_step - TOTP_ALLOWED_DRIFT_STEPS..=current_step + TOTP_ALLOWED_DRIFT_STEPS {
+        let expected = format!("{:0width$}", hotp(&key, step as u64), width = TOTP_DIGITS as usize);
+        if expected == code {
+            return Ok(Some(step));
+        }
+    }
+    Ok(None)
+}
+
+pub fn otpauth_uri(secret: &Secret<String>, username: &str, issuer: &str) -> String {
+    format!(
+        "otpauth://totp/{issuer}:{username}?secret={secret}&issuer={issuer}&algorithm=SHA1&digits={digits}&period={period}",
+        issuer = urlencoding::encode(issuer),
+        username = urlencoding::encode(username),
+        secret = secret.expose_secret(),
+        digits = TOTP_DIGITS,
+        period = TOTP_STEP_SECONDS,
+    )
+}
+
+// Recovery codes are random enough for a fast hash, like API tokens.
+// Dashes and case are ignored so codes can be typed back comfortably.
+fn hash_recovery_code(code: &str) -> String {
+    let normalized: String = code
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .map(|c| c.to_ascii_lowercase())
+        .collect();
+    hex::encode(Sha256::digest(normalized.as_bytes()))
+}
+
+fn generate_recovery_codes() -> Vec<String> {
+    let mut rng = thread_rng();
+    (0..N_RECOVERY_CODES)
+        .map(|_| {
+            let code: String = std::iter::repeat_with(|| rng.sample(Alphanumeric))
+                .map(|c| char::from(c).to_ascii_lowercase())
+                .take(10)
+                .collect();
+            format!("{}-{}", &code[..5], &code[5..])
+        })
+        .collect()
+}
+
+pub struct TotpSettings {
+    pub secret: Secret<String>,
+    pub enabled: bool,
+}
+
+#[tracing::instrument(name = "Get TOTP settings", skip(pool))]
+pub async fn get_totp_settings(
+    pool: &PgPool,
+    user_id: Uuid,
+) -> Result<Option<TotpSettings>, anyhow::Error> {
+    let row = sqlx::query!(
+        "SELECT totp_secret, totp_enabled_at FROM users WHERE user_id = $1",
+        user_id,
+    )
+        .fetch_optional(pool)
+        .await
+        .context("Failed to retrieve the TOTP settings.")?;
+    Ok(row.and_then(|r| {
+        r.totp_secret.map(|secret| TotpSettings {
+            secret: Secret::new(secret),
+            enabled: r.totp_enabled_at.is_some(),
+        })
+    }))
+}
+
+pub async fn is_totp_enabled(pool: &PgPool, user_id: Uuid) -> Result<bool, anyhow::Error> {
+    Ok(get_totp_settings(pool, user_id)
+        .await?
+        .map(|settings| settings.enabled)
+        .unwrap_or(false))
+}
+
+// Replaces any enrollment that was started but never confirmed
+#[tracing::instrument(name = "Start TOTP enrollment", skip(pool))]
+pub async fn start_totp_enrollment(pool: &PgPool, user_id: Uuid) -> Result<(), anyhow::Error> {
+    let secret = generate_totp_secret();
+    sqlx::query!(
+        r#"
+        UPDATE users
+        SET totp_secret = $1, totp_last_used_step = NULL
+        WHERE user_id = $2 AND totp_enabled_at IS NULL
+        "#,
+        secret.expose_secret(),
+        user_id,
+    )
+        .execute(pool)
+        .await
+        .context("Failed to store the TOTP secret.")?;
+    Ok(())
+}
+
+// Returns the recovery codes, which are only ever shown this once
+#[tracing::instrument(name = "Enable TOTP", skip(pool))]
+pub async fn enable_totp(
+    pool: &PgPool,
+    user_id: Uuid,
+    step: i64,
+) -> Result<Vec<String>, anyhow::Error> {
+    let recovery_codes = generate_recovery_codes();
+    let mut transaction = pool.begin().await.context("Failed to start a transaction.")?;
+    sqlx::query!(
+        r#"
+        UPDATE users
+        SET totp_enabled_at = now(), totp_last_used_step = $1
+        WHERE user_id = $2
+        "#,
+        step,
+        user_id,
+    )
+        .execute(&mut *transaction)
+        .await
+        .context("Failed to enable TOTP.")?;
+    sqlx::query!("DELETE FROM totp_recovery_codes WHERE user_id = $1", user_id)
+        .execute(&mut *transaction)
+        .await
+        .context("Failed to delete old recovery codes.")?;
+    for code in &recovery_codes {
+        sqlx::query!(
+            r#"
+            INSERT INTO totp_recovery_codes (user_id, code_hash, created_at)
+            VALUES ($1, $2, now())
+            "#,
+            user_id,
+            hash_recovery_code(code),
+        )
+            .execute(&mut *transaction)
+            .await
+            .context("Failed to store a recovery code.")?;
+    }
+    transaction.commit().await.context("Failed to commit TOTP enrollment.")?;
+    Ok(recovery_codes)
+}
+
+#[tracing::instrument(name = "Disable TOTP", skip(pool))]
+pub async fn disable_totp(pool: &PgPool, user_id: Uuid) -> Result<(), anyhow::Error> {
+    let mut transaction = pool.begin().await.context("Failed to start a transaction.")?;
+    sqlx::query!(
+        r#"
+        UPDATE users
+        SET totp_secret = NULL, totp_enabled_at = NULL, totp_last_used_step = NULL
+        WHERE user_id = $1
+        "#,
+        user_id,
+    )
+        .execute(&mut *transaction)
+        .await
+        .context("Failed to disable TOTP.")?;
+    sqlx::query!("DELETE FROM totp_recovery_codes WHERE user_id = $1", user_id)
+        .execute(&mut *transaction)
+        .await
+        .context("Failed to delete recovery codes.")?;
+    transaction.commit().await.context("Failed to commit disabling TOTP.")?;
+    Ok(())
+}
+
+// Accepts either a current TOTP code or an unused recovery code. Either one
+// is spent on success, so it cannot be replayed.
+#[tracing::instrument(name = "Verify second factor", skip(pool, code))]
+pub async fn verify_second_factor(
+    pool: &PgPool,
+    user_id: Uuid,
+    code: &str,
+) -> Result<bool, anyhow::Error> {
+    let mut transaction = pool.begin().await.context("Failed to start a transaction.")?;
+    let row = sqlx::query!(
+        r#"
+        SELECT totp_secret, totp_last_used_step
+        FROM users
+        WHERE user_id = $1 AND totp_enabled_at IS NOT NULL
+        FOR UPDATE
+        "#,
+        user_id,
+    )
+        .fetch_optional(&mut *transaction)
+        .await
+        .context("Failed to retrieve the TOTP secret.")?;
+    let (secret, last_used_step) = match row {
+        Some(r) => (
+            Secret::new(r.totp_secret.context("TOTP is enabled without a secret.")?),
+            r.totp_last_used_step,
+        ),
+        None => return Ok(false),
+    };
+    let now = chrono::Utc::now().timestamp();
+    if let Some(step) = verify_totp_code(&secret, code, now)? {
+        if last_used_step.is_some_and(|last| step <= last) {
+            return Ok(false);
+        }
+        sqlx::query!(
+            "UPDATE users SET totp_last_used_step = $1 WHERE user_id = $2",
+            step,
+            user_id,
+        )
+            .execute(&mut *transaction)
+            .await
+            .context("Failed to record the TOTP step.")?;
+        transaction.commit().await.context("Failed to commit the TOTP step.")?;
+        return Ok(true);
+    }
+    let result = sqlx::query!(
+        r#"
+        UPDATE totp_recovery_codes
+        SET used_at = now()
+        WHERE user_id = $1 AND code_hash = $2 AND used_at IS NULL
+        "#,
+        user_id,
+        hash_recovery_code(code),
+    )
+        .execute(&mut *transaction)
+        .await
+        .context("Failed to spend a recovery code.")?;
+    transaction.commit().await.context("Failed to commit the recovery code.")?;
+    Ok(result.rows_affected() > 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{hash_recovery_code, hotp, otpauth_uri, totp_code, verify_totp_code, BASE32};
+    use claims::{assert_none, assert_some_eq};
+    use secrecy::Secret;
+
+    // The SHA1 seed from RFC 6238, appendix B
+    fn rfc_secret() -> Secret<String> {
+        Secret::new(base32::encode(BASE32, b"12345678901234567890"))
+    }
+
+    #[test]
+    fn hotp_matches_the_rfc_4226_test_vectors() {
+        let expected = [755224, 287082, 359152, 969429, 338314];
+        for (counter, code) in expected.iter().enumerate() {
+            assert_eq!(hotp(b"12345678901234567890", counter as u64), *code);
+        }
+    }
+
+    #[test]
+    fn totp_matches_the_rfc_6238_test_vectors() {
+        assert_eq!(totp_code(&rfc_secret(), 59).unwrap(), "287082");
+        assert_eq!(totp_code(&rfc_secret(), 1111111109).unwrap(), "081804");
+        assert_eq!(totp_code(&rfc_secret(), 1234567890).unwrap(), "005924");
+    }
+
+    #[test]
+    fn codes_from_adjacent_steps_are_accepted() {
+        let secret = rfc_secret();
+        let code = totp_code(&secret, 1111111109).unwrap();
+        assert_some_eq!(verify_totp_code(&secret, &code, 1111111109 + 30).unwrap(), 37037036);
+        assert_none!(verify_totp_code(&secret, &code, 1111111109 + 90).unwrap());
+        assert_none!(verify_totp_code(&secret, "12345", 1111111109).unwrap());
+    }
+
+    #[test]
+    fn recovery_codes_ignore_dashes_and_case() {
+        assert_eq!(hash_recovery_code("abcde-12345"), hash_recovery_code("ABCDE12345"));
+    }
+
+    #[test]
+    fn the_otpauth_uri_carries_the_secret_and_issuer() {
+        let uri = otpauth_uri(&Secret::new("ABC".into()), "ada lovelace", "zero2prod");
+        assert!(uri.starts_with("otpauth://totp/zero2prod:ada%20lovelace?secret=ABC&issuer=zero2prod"));
+    }
+}
@@ -0,0 +1,180 @@
+use crate::client_ip::client_ip;
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::{Executor, PgPool, Postgres};
+use uuid::Uuid;
+
+// Activity is only written back this often, to spare a write per request
+const LAST_SEEN_RESOLUTION_SECONDS: i64 = 60;
+const MAX_USER_AGENT_CHARS: usize = 512;
+
+// Where a login came from, as shown on the sessions page
+#[derive(Debug, Default)]
+pub struct SessionOrigin {
+    pub ip_address: Option<String>,
+    pub user_agent: Option<String>,
+}
+
+impl SessionOrigin {
+    pub fn from_request(request: &actix_web::HttpRequest) -> Self {
+        let ip_address = client_ip(request).map(|ip| ip.to_string());
+        let user_agent = request
+            .headers()
+            .get(actix_web::http::header::USER_AGENT)
+            .and_then(|value| value.to_str().ok())
+            .map(|value| value.chars().take(MAX_USER_AGENT_CHARS).collect());
+        Self { ip_address, user_agent }
+    }
+}
+
+pub struct UserSession {
+    pub session_id: Uuid,
+    pub created_at: DateTime<Utc>,
+    pub last_seen_at: DateTime<Utc>,
+    pub ip_address: Option<String>,
+    pub user_agent: Option<String>,
+}
+
+#[tracing::instrument(name = "Register user session", skip(pool))]
+pub async fn register_session(
+    pool: &PgPool,
+    user_id: Uuid,
+    origin: &SessionOrigin,
+) -> Result<Uuid, anyhow::Error> {
+    let session_id = Uuid::new_v4();
+    sqlx::query!(
+        r#"
+        INSERT INTO user_sessions (session_id, user_id, created_at, last_seen_at, ip_address, user_agent)
+        VALUES ($1, $2, now(), now(), $3, $4)
+        "#,
+        session_id,
+        user_id,
+        origin.ip_address,
+        origin.user_agent,
+    )
+        .execute(pool)
+        .await
+        .context("Failed to register the user session.")?;
+    Ok(session_id)
+}
+
+// Checks the session has not been revoked and records that it was seen
+#[tracing::instrument(name = "Check user session", skip(pool))]
+pub async fn touch_session(
+    pool: &PgPool,
+    session_id: Uuid,
+    user_id: Uuid,
+) -> Result<bool, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"
+        SELECT last_seen_at
+        FROM user_sessions
+        WHERE session_id = $1 AND user_id = $2 AND revoked_at IS NULL
+        "#,
+        session_id,
+        user_id,
+    )
+        .fetch_optional(pool)
+        .await
+        .context("Failed to look up the user session.")?;
+    let last_seen_at = match row {
+        Some(r) => r.last_seen_at,
+        None => return Ok(false),
+    };
+    if (Utc::now() - last_seen_at).num_seconds() >= LAST_SEEN_RESOLUTION_SECONDS {
+        sqlx::query!(
+            "UPDATE user_sessions SET last_seen_at = now() WHERE session_id = $1",
+            session_id,
+        )
+            .execute(pool)
+            .await
+            .context("Failed to record user session activity.")?;
+    }
+    Ok(true)
+}
+
+// Most recently used first
+#[tracing::instrument(name = "Get active user sessions", skip(pool))]
+pub async fn get_active_sessions(
+    pool: &PgPool,
+    user_id: Uuid,
+) -> Result<Vec<UserSession>, anyhow::Error> {
+    let sessions = sqlx::query_as!(
+        UserSession,
+        r#"
+        SELECT session_id, created_at, last_seen_at, ip_address, user_agent
+        FROM user_sessions
+        WHERE user_id = $1 AND revoked_at IS NULL
+        ORDER BY last_seen_at DESC
+        "#,
+        user_id,
+    )
+        .fetch_all(pool)
+        .await
+        .context("Failed to retrieve the user's sessions.")?;
+    Ok(sessions)
+}
+
+// Returns false if the session does not belong to the user or was
+// already revoked.
+#[tracing::instrument(name = "Revoke user session", skip(pool))]
+pub async fn revoke_session(
+    pool: &PgPool,
+    user_id: Uuid,
+    session_id: Uuid,
+) -> Result<bool, anyhow::Error> {
+    let result = sqlx::query!(
+        r#"
+        UPDATE user_sessions
+        SET revoked_at = now()
+        WHERE session_id = $1 AND user_id = $2 AND revoked_at IS NULL
+        "#,
+        session_id,
+        user_id,
+    )
+        .execute(pool)
+        .await
+        .context("Failed to revoke the user session.")?;
+    Ok(result.rows_affected() > 0)
+}
+
+// Log the user out everywhere
+#[tracing::instrument(name = "Revoke all user sessions", skip(executor))]
+pub async fn revoke_all_sessions<'c, E>(executor: E, user_id: Uuid) -> Result<u64, anyhow::Error>
+where
+    E: Executor<'c, Database = Postgres>,
+{
+    let result = sqlx::query!(
+        "UPDATE user_sessions SET revoked_at = now() WHERE user_id = $1 AND revoked_at IS NULL",
+        user_id,
+    )
+        .execute(executor)
+        .await
+        .context("Failed to revoke the user's sessions.")?;
+    Ok(result.rows_affected())
+}
+
+// Log the user out everywhere but in `current_session_id`
+#[tracing::instrument(name = "Revoke other user sessions", skip(executor))]
+pub async fn revoke_other_sessions<'c, E>(
+    executor: E,
+    user_id: Uuid,
+    current_session_id: Uuid,
+) -> Result<u64, anyhow::Error>
+where
+    E: Executor<'c, Database = Postgres>,
+{
+    let result = sqlx::query!(
+        r#"
+        UPDATE user_sessions
+        SET revoked_at = now()
+        WHERE user_id = $1 AND session_id <> $2 AND revoked_at IS NULL
+        "#,
+        user_id,
+        current_session_id,
+    )
+        .execute(executor)
+        .await
+        .context("Failed to revoke the user's other sessions.")?;
+    Ok(result.rows_affected())
+}
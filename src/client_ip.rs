@@ -0,0 +1,88 @@
+use actix_web::{web, HttpRequest};
+use std::net::IpAddr;
+
+// The reverse proxies in front of the application. Their
+// `X-Forwarded-For` header is believed; anyone else's is ignored, since
+// clients can send whatever they like.
+#[derive(Clone, Debug, Default)]
+pub struct TrustedProxies(pub Vec<IpAddr>);
+
+// The address a request came from, as used for rate limits, sessions and
+// the audit log. Without a trusted proxy in front, that is the peer.
+pub fn client_ip(request: &HttpRequest) -> Option<IpAddr> {
+    let peer = request.peer_addr()?.ip();
+    let trusted = match request.app_data::<web::Data<TrustedProxies>>() {
+        Some(trusted) => trusted.get_ref(),
+        None => return Some(peer),
+    };
+    let forwarded_for: Vec<&str> = request
+        .headers()
+        .get_all("X-Forwarded-For")
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .collect();
+    Some(resolve_client_ip(peer, &forwarded_for, trusted))
+}
+
+// Proxies append the address they received the request from, so the list
+// is read from the right. The first address that is not a trusted proxy is
+// the client; whatever is left of it could have been made up.
+fn resolve_client_ip(peer: IpAddr, forwarded_for: &[&str], trusted: &TrustedProxies) -> IpAddr {
+    let mut client = peer;
+    for hop in forwarded_for.iter().rev() {
+        if !trusted.0.contains(&client) {
+            break;
+        }
+        match hop.trim().parse() {
+            Ok(ip) => client = ip,
+            Err(_) => break,
+        }
+    }
+    client
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{resolve_client_ip, TrustedProxies};
+    use std::net::IpAddr;
+
+    fn ip(s: &str) -> IpAddr {
+        s.parse().unwrap()
+    }
+
+    fn proxies() -> TrustedProxies {
+        TrustedProxies(vec![ip("10.0.0.1"), ip("10.0.0.2")])
+    }
+
+    #[test]
+    fn the_header_is_ignored_when_the_peer_is_not_a_trusted_proxy() {
+        assert_eq!(
+            resolve_client_ip(ip("198.51.100.1"), &["203.0.113.7"], &proxies()),
+            ip("198.51.100.1")
+        );
+    }
+
+    #[test]
+    fn the_address_added_by_a_trusted_proxy_is_the_client() {
+        assert_eq!(
+            resolve_client_ip(ip("10.0.0.1"), &["203.0.113.7"], &proxies()),
+            ip("203.0.113.7")
+        );
+    }
+
+    #[test]
+    fn addresses_made_up_by_the_client_are_skipped() {
+        assert_eq!(
+            resolve_client_ip(ip("10.0.0.1"), &["1.2.3.4", " 203.0.113.7", "10.0.0.2"], &proxies()),
+            ip("203.0.113.7")
+        );
+    }
+
+    #[test]
+    fn an_unparseable_hop_stops_the_search() {
+        assert_eq!(
+            resolve_client_ip(ip("10.0.0.1"), &["203.0.113.7", "unknown"], &proxies()),
+            ip("10.0.0.1")
+        );
+    }
+}
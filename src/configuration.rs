@@ -1,3 +1,4 @@
+use actix_web::cookie::SameSite;
 use secrecy::Secret;
 use secrecy::ExposeSecret;
 use sqlx::ConnectOptions;
@@ -13,7 +14,14 @@ pub struct Settings {
     pub database: DatabaseSettings,
     pub application: ApplicationSettings,
     pub email_client: EmailClientSettings,
-    pub redis_uri: Secret<String>
+    pub redis_uri: Secret<String>,
+    pub login_throttling: LoginThrottlingSettings,
+    pub subscribe_protection: SubscribeProtectionSettings,
+    pub email_policy: EmailPolicySettings,
+    pub password_hashing: PasswordHashingSettings,
+    pub cookies: CookieSettings,
+    pub preferences: PreferenceSettings,
+    pub environment: Environment,
 }
 
 #[derive(serde::Deserialize, Clone, Debug)]
@@ -34,6 +42,146 @@ pub struct ApplicationSettings {
     pub host: String,
     pub base_url: String,
     pub hmac_secret: Secret<String>,
+    // Keys the suppression list, and nothing else
+    pub suppression_secret: Secret<String>,
+    // Reverse proxies whose `X-Forwarded-For` header names the client
+    #[serde(default)]
+    pub trusted_proxies: Vec<std::net::IpAddr>,
+}
+
+// Failed logins are counted per username and per IP address. Once either
+// count reaches its maximum within the window, further attempts are refused
+// until the lockout expires.
+#[derive(serde::Deserialize, Clone, Debug)]
+pub struct LoginThrottlingSettings {
+    pub key_prefix: String,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub max_failures_per_username: u32,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub max_failures_per_ip: u32,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub window_seconds: u64,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub lockout_seconds: u64,
+}
+
+// Sign-up attempts are counted per IP address and per address to confirm.
+// Attempts beyond either maximum within the window are refused.
+#[derive(serde::Deserialize, Clone, Debug)]
+pub struct SubscribeProtectionSettings {
+    pub key_prefix: String,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub max_attempts_per_ip: u32,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub max_attempts_per_address: u32,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub window_seconds: u64,
+    // How long the sign-up form must stay open before it is sent. 0 turns
+    // the check off.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub min_fill_seconds: u64,
+    #[serde(default)]
+    pub challenge: Option<ChallengeSettings>,
+}
+
+// A "siteverify" endpoint to check the challenge answered on the sign-up form
+#[derive(serde::Deserialize, Clone, Debug)]
+pub struct ChallengeSettings {
+    pub provider: ChallengeProvider,
+    // Public, unlike the secret: the widget on the form is set up with it
+    pub site_key: String,
+    pub verify_url: String,
+    pub secret: Secret<String>,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub timeout_milliseconds: u64,
+}
+
+impl ChallengeSettings {
+    pub fn timeout(&self) -> std::time::Duration {
+        std::time::Duration::from_millis(self.timeout_milliseconds)
+    }
+}
+
+// Whose widget the sign-up form embeds
+#[derive(serde::Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ChallengeProvider {
+    Turnstile,
+    Hcaptcha,
+    Recaptcha,
+}
+
+// What subscribers' addresses are checked for, besides their syntax
+#[derive(serde::Deserialize, Clone, Debug)]
+pub struct EmailPolicySettings {
+    pub reject_disposable_domains: bool,
+    // A newer copy of the disposable domain list, one domain per line. The
+    // list bundled with the application is used when unset.
+    #[serde(default)]
+    pub disposable_domains_path: Option<String>,
+    pub role_addresses: RoleAddressPolicy,
+}
+
+// Role addresses, like `postmaster@`, reach a team or a machine
+#[derive(serde::Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum RoleAddressPolicy {
+    Allow,
+    // Accepted, and pointed out to admins
+    Flag,
+    Reject,
+}
+
+// The target for password hashes. Raising it upgrades existing hashes the
+// next time their owner logs in.
+#[derive(serde::Deserialize, Clone, Debug)]
+pub struct PasswordHashingSettings {
+    pub algorithm: String,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub memory_cost_kib: u32,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub iterations: u32,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub parallelism: u32,
+}
+
+// Browsers leave out cookies marked `strict` on requests started by another
+// site, which keeps forged form submissions logged out.
+#[derive(serde::Deserialize, Clone, Debug)]
+pub struct CookieSettings {
+    pub session_same_site: SameSitePolicy,
+    pub flash_same_site: SameSitePolicy,
+}
+
+// Topics an issue can be published under. Subscribers can mute any of them
+// from their preferences page.
+#[derive(serde::Deserialize, Clone, Debug)]
+pub struct PreferenceSettings {
+    pub topics: Vec<String>,
+}
+
+impl PreferenceSettings {
+    pub fn is_topic(&self, topic: &str) -> bool {
+        self.topics.iter().any(|t| t == topic)
+    }
+}
+
+#[derive(serde::Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SameSitePolicy {
+    Strict,
+    Lax,
+    None,
+}
+
+impl From<SameSitePolicy> for SameSite {
+    fn from(policy: SameSitePolicy) -> Self {
+        match policy {
+            SameSitePolicy::Strict => SameSite::Strict,
+            SameSitePolicy::Lax => SameSite::Lax,
+            SameSitePolicy::None => SameSite::None,
+        }
+    }
 }
 
 #[derive(serde::Deserialize, Clone, Debug)]
@@ -67,12 +215,15 @@ pub fn get_configuration() -> Result<Settings, config::ConfigError> {
             .prefix_separator("_")
             .separator("__")
         )
+        .set_override("environment", environment.as_str())?
         .build()?;
     
     // convert into our settings struct
     settings.try_deserialize::<Settings>()
 }
 
+#[derive(serde::Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
 pub enum Environment {
     Local,
     Production,
@@ -112,7 +263,7 @@ impl DatabaseSettings {
         PgConnectOptions::new()
             .host(&self.host)
             .username(&self.username)
-            .password(&self.password.expose_secret())
+            .password(self.password.expose_secret())
             .port(self.port)
             .ssl_mode(ssl_mode)
     }
@@ -0,0 +1,101 @@
+use anyhow::Context;
+use sqlx::{Executor, Postgres, Transaction};
+use uuid::Uuid;
+use crate::domain::{CustomField, CustomFieldKind};
+
+pub struct FieldValue {
+    pub key: String,
+    pub label: String,
+    pub value: String,
+}
+
+#[tracing::instrument(name = "Get custom fields", skip(executor))]
+pub async fn get_custom_fields<'c, E>(executor: E) -> Result<Vec<CustomField>, anyhow::Error>
+where
+    E: Executor<'c, Database = Postgres>,
+{
+    sqlx::query!(
+        r#"
+        SELECT field_id, key, label, kind, options, is_required
+        FROM custom_fields
+        ORDER BY created_at, field_id
+        "#,
+    )
+        .fetch_all(executor)
+        .await
+        .context("Failed to retrieve the custom fields.")?
+        .into_iter()
+        .map(|r| {
+            Ok(CustomField {
+                field_id: r.field_id,
+                key: r.key,
+                label: r.label,
+                kind: CustomFieldKind::try_from(r.kind).map_err(anyhow::Error::msg)?,
+                options: r.options,
+                is_required: r.is_required,
+            })
+        })
+        .collect()
+}
+
+// The keys of the custom fields, which newsletter content can use as variables
+pub async fn get_field_keys<'c, E>(executor: E) -> Result<Vec<String>, anyhow::Error>
+where
+    E: Executor<'c, Database = Postgres>,
+{
+    let keys = get_custom_fields(executor)
+        .await?
+        .into_iter()
+        .map(|field| field.key)
+        .collect();
+    Ok(keys)
+}
+
+#[tracing::instrument(name = "Get subscriber field values", skip(executor))]
+pub async fn get_field_values<'c, E>(
+    executor: E,
+    subscriber_id: Uuid,
+) -> Result<Vec<FieldValue>, anyhow::Error>
+where
+    E: Executor<'c, Database = Postgres>,
+{
+    let values = sqlx::query_as!(
+        FieldValue,
+        r#"
+        SELECT f.key, f.label, v.value
+        FROM subscriber_field_values v
+        JOIN custom_fields f ON f.field_id = v.field_id
+        WHERE v.subscriber_id = $1
+        ORDER BY f.created_at, f.field_id
+        "#,
+        subscriber_id,
+    )
+        .fetch_all(executor)
+        .await
+        .context("Failed to retrieve the subscriber's field values.")?;
+    Ok(values)
+}
+
+// Fields left out keep whatever value they had
+#[tracing::instrument(name = "Store subscriber field values", skip(transaction, values))]
+pub async fn store_field_values(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    values: &[(Uuid, String)],
+) -> Result<(), anyhow::Error> {
+    let (field_ids, values): (Vec<Uuid>, Vec<String>) = values.iter().cloned().unzip();
+    sqlx::query!(
+        r#"
+        INSERT INTO subscriber_field_values (subscriber_id, field_id, value)
+        SELECT $1, * FROM UNNEST($2::uuid[], $3::TEXT[])
+        ON CONFLICT (subscriber_id, field_id) DO UPDATE SET value = EXCLUDED.value
+        "#,
+        subscriber_id,
+        &field_ids,
+        &values,
+    )
+        .execute(&mut **transaction)
+        .await
+        .context("Failed to store the subscriber's field values.")?;
+    Ok(())
+}
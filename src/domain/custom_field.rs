@@ -0,0 +1,193 @@
+use unicode_segmentation::UnicodeSegmentation;
+use uuid::Uuid;
+use crate::domain::SUBSCRIBER_VARIABLES;
+
+const MAX_VALUE_LENGTH: usize = 256;
+const MAX_KEY_LENGTH: usize = 32;
+// Keys double as export columns, content variables and sign-up form fields,
+// so they cannot take the name of any of them
+const RESERVED_KEYS: [&str; 9] = [
+    "id",
+    "email",
+    "subscribed_at",
+    "status",
+    "confirmed_at",
+    "list",
+    "website",
+    "form_token",
+    "challenge_response",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CustomFieldKind {
+    Text,
+    Select,
+    Boolean,
+}
+
+impl CustomFieldKind {
+    pub const ALL: [CustomFieldKind; 3] = [
+        CustomFieldKind::Text,
+        CustomFieldKind::Select,
+        CustomFieldKind::Boolean,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CustomFieldKind::Text => "text",
+            CustomFieldKind::Select => "select",
+            CustomFieldKind::Boolean => "boolean",
+        }
+    }
+
+    pub fn description(&self) -> &'static str {
+        match self {
+            CustomFieldKind::Text => "Free text",
+            CustomFieldKind::Select => "One of a few options",
+            CustomFieldKind::Boolean => "Yes or no",
+        }
+    }
+}
+
+impl std::fmt::Display for CustomFieldKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.as_str().fmt(f)
+    }
+}
+
+impl TryFrom<String> for CustomFieldKind {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        Self::ALL
+            .into_iter()
+            .find(|kind| kind.as_str() == s)
+            .ok_or_else(|| format!("{} is not a kind of field.", s))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct CustomField {
+    pub field_id: Uuid,
+    pub key: String,
+    pub label: String,
+    pub kind: CustomFieldKind,
+    pub options: Vec<String>,
+    pub is_required: bool,
+}
+
+impl CustomField {
+    pub fn parse_key(key: &str) -> Result<String, String> {
+        let key = key.trim();
+        let is_valid = key.len() <= MAX_KEY_LENGTH
+            && key.starts_with(|c: char| c.is_ascii_lowercase())
+            && key.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_');
+        if !is_valid {
+            return Err(format!(
+                "Field keys are up to {} lowercase letters, digits and underscores, \
+                starting with a letter.",
+                MAX_KEY_LENGTH
+            ));
+        }
+        if RESERVED_KEYS.contains(&key) || SUBSCRIBER_VARIABLES.contains(&key) {
+            return Err(format!("{} is reserved.", key));
+        }
+        Ok(key.to_string())
+    }
+
+    // The value to store for what was submitted, if any. Unticked boolean
+    // fields are not submitted at all, and are stored as "false".
+    pub fn parse_value(&self, submitted: Option<&str>) -> Result<Option<String>, String> {
+        let submitted = submitted.map(str::trim).filter(|v| !v.is_empty());
+        let value = match (self.kind, submitted) {
+            (CustomFieldKind::Boolean, submitted) => {
+                let is_true = match submitted {
+                    None | Some("false" | "off" | "no" | "0") => false,
+                    Some("true" | "on" | "yes" | "1") => true,
+                    Some(_) => return Err(format!("{} must be yes or no.", self.label)),
+                };
+                if self.is_required && !is_true {
+                    return Err(format!("{} must be ticked.", self.label));
+                }
+                Some(is_true.to_string())
+            }
+            (_, None) if self.is_required => {
+                return Err(format!("{} is required.", self.label));
+            }
+            (_, None) => None,
+            (CustomFieldKind::Text, Some(value)) => {
+                if value.graphemes(true).count() > MAX_VALUE_LENGTH {
+                    return Err(format!(
+                        "{} is longer than {} characters.",
+                        self.label, MAX_VALUE_LENGTH
+                    ));
+                }
+                Some(value.to_string())
+            }
+            (CustomFieldKind::Select, Some(value)) => {
+                if !self.options.iter().any(|option| option == value) {
+                    return Err(format!("{} is not one of the options for {}.", value, self.label));
+                }
+                Some(value.to_string())
+            }
+        };
+        Ok(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::domain::{CustomField, CustomFieldKind};
+    use claims::{assert_err, assert_ok_eq};
+
+    fn field(kind: CustomFieldKind, is_required: bool) -> CustomField {
+        CustomField {
+            field_id: uuid::Uuid::new_v4(),
+            key: "field".into(),
+            label: "Field".into(),
+            kind,
+            options: vec!["Small".into(), "Large".into()],
+            is_required,
+        }
+    }
+
+    #[test]
+    fn keys_must_be_usable_as_variables() {
+        assert_ok_eq!(CustomField::parse_key(" company "), "company".to_string());
+        assert_ok_eq!(CustomField::parse_key("t_shirt_size2"), "t_shirt_size2".to_string());
+        assert_err!(CustomField::parse_key("Company"));
+        assert_err!(CustomField::parse_key("2nd"));
+        assert_err!(CustomField::parse_key("t-shirt"));
+        assert_err!(CustomField::parse_key("name"));
+        assert_err!(CustomField::parse_key("email"));
+    }
+
+    #[test]
+    fn required_fields_must_be_filled_in() {
+        assert_err!(field(CustomFieldKind::Text, true).parse_value(None));
+        assert_err!(field(CustomFieldKind::Text, true).parse_value(Some("  ")));
+        assert_err!(field(CustomFieldKind::Boolean, true).parse_value(None));
+        assert_ok_eq!(field(CustomFieldKind::Text, false).parse_value(None), None);
+    }
+
+    #[test]
+    fn select_fields_only_take_their_options() {
+        let field = field(CustomFieldKind::Select, false);
+        assert_ok_eq!(field.parse_value(Some("Large")), Some("Large".to_string()));
+        assert_err!(field.parse_value(Some("Medium")));
+    }
+
+    #[test]
+    fn boolean_fields_are_stored_as_true_or_false() {
+        let field = field(CustomFieldKind::Boolean, false);
+        assert_ok_eq!(field.parse_value(Some("on")), Some("true".to_string()));
+        assert_ok_eq!(field.parse_value(None), Some("false".to_string()));
+        assert_err!(field.parse_value(Some("maybe")));
+    }
+
+    #[test]
+    fn long_text_is_rejected() {
+        let field = field(CustomFieldKind::Text, false);
+        assert_err!(field.parse_value(Some(&"a".repeat(257))));
+    }
+}
@@ -0,0 +1,63 @@
+// How often a subscriber is willing to hear from us. Issues published while
+// a subscriber's last one is more recent than that are not sent to them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeliveryFrequency {
+    EveryIssue,
+    Weekly,
+    Monthly,
+}
+
+impl DeliveryFrequency {
+    pub const ALL: [DeliveryFrequency; 3] = [
+        DeliveryFrequency::EveryIssue,
+        DeliveryFrequency::Weekly,
+        DeliveryFrequency::Monthly,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DeliveryFrequency::EveryIssue => "every_issue",
+            DeliveryFrequency::Weekly => "weekly",
+            DeliveryFrequency::Monthly => "monthly",
+        }
+    }
+
+    pub fn description(&self) -> &'static str {
+        match self {
+            DeliveryFrequency::EveryIssue => "Every issue",
+            DeliveryFrequency::Weekly => "At most one issue a week",
+            DeliveryFrequency::Monthly => "At most one issue a month",
+        }
+    }
+}
+
+impl std::fmt::Display for DeliveryFrequency {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.as_str().fmt(f)
+    }
+}
+
+impl TryFrom<String> for DeliveryFrequency {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        Self::ALL
+            .into_iter()
+            .find(|frequency| frequency.as_str() == s)
+            .ok_or_else(|| format!("{} is not a delivery frequency.", s))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::domain::DeliveryFrequency;
+    use claims::assert_err;
+
+    #[test]
+    fn frequencies_round_trip_through_their_names() {
+        for frequency in DeliveryFrequency::ALL {
+            assert_eq!(DeliveryFrequency::try_from(frequency.as_str().to_string()), Ok(frequency));
+        }
+        assert_err!(DeliveryFrequency::try_from("daily".to_string()));
+    }
+}
@@ -0,0 +1,118 @@
+use anyhow::Context;
+use std::collections::HashSet;
+use crate::configuration::{EmailPolicySettings, RoleAddressPolicy};
+use crate::domain::SubscriberEmail;
+
+const BUNDLED_DISPOSABLE_DOMAINS: &str = include_str!("disposable_domains.txt");
+
+// What a subscriber's address is held to, on top of being well-formed
+#[derive(Debug, Clone)]
+pub struct EmailPolicy {
+    reject_disposable_domains: bool,
+    disposable_domains: HashSet<String>,
+    role_addresses: RoleAddressPolicy,
+}
+
+impl EmailPolicy {
+    pub fn new(settings: &EmailPolicySettings) -> Result<Self, anyhow::Error> {
+        let disposable_domains = match &settings.disposable_domains_path {
+            Some(path) => parse_domain_list(
+                &std::fs::read_to_string(path)
+                    .with_context(|| format!("Failed to read the disposable domains in {}.", path))?,
+            ),
+            None => parse_domain_list(BUNDLED_DISPOSABLE_DOMAINS),
+        };
+        Ok(Self {
+            reject_disposable_domains: settings.reject_disposable_domains,
+            disposable_domains,
+            role_addresses: settings.role_addresses,
+        })
+    }
+
+    fn is_disposable(&self, email: &SubscriberEmail) -> bool {
+        // `mail.yopmail.com` is as disposable as `yopmail.com`
+        let mut domain = email.domain();
+        loop {
+            if self.disposable_domains.contains(domain) {
+                return true;
+            }
+            match domain.split_once('.') {
+                Some((_, parent)) => domain = parent,
+                None => return false,
+            }
+        }
+    }
+
+    // Meant for addresses people sign up with. Flagged role addresses are
+    // let through.
+    pub fn check(&self, email: &SubscriberEmail) -> Result<(), String> {
+        if self.reject_disposable_domains && self.is_disposable(email) {
+            return Err(format!(
+                "Addresses at {} are disposable. Please use a permanent address.",
+                email.domain(),
+            ));
+        }
+        if self.role_addresses == RoleAddressPolicy::Reject && email.is_role_address() {
+            return Err(format!(
+                "{} is usually read by a team rather than a person. Please use your own address.",
+                email,
+            ));
+        }
+        Ok(())
+    }
+
+    // Whether admins should be told that the address reaches a team or a
+    // machine rather than a person
+    pub fn flags(&self, email: &SubscriberEmail) -> bool {
+        self.role_addresses == RoleAddressPolicy::Flag && email.is_role_address()
+    }
+}
+
+// One domain per line. Blank lines and `#` comments are skipped.
+fn parse_domain_list(list: &str) -> HashSet<String> {
+    list.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(str::to_lowercase)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::EmailPolicy;
+    use crate::configuration::{EmailPolicySettings, RoleAddressPolicy};
+    use crate::domain::SubscriberEmail;
+    use claims::{assert_err, assert_ok};
+
+    fn policy(role_addresses: RoleAddressPolicy) -> EmailPolicy {
+        EmailPolicy::new(&EmailPolicySettings {
+            reject_disposable_domains: true,
+            disposable_domains_path: None,
+            role_addresses,
+        })
+        .unwrap()
+    }
+
+    fn email(s: &str) -> SubscriberEmail {
+        SubscriberEmail::parse(s.to_string()).unwrap()
+    }
+
+    #[test]
+    fn disposable_domains_and_their_subdomains_are_rejected() {
+        let policy = policy(RoleAddressPolicy::Allow);
+        assert_err!(policy.check(&email("ursula@mailinator.com")));
+        assert_err!(policy.check(&email("ursula@Mail.YOPmail.com")));
+        assert_ok!(policy.check(&email("ursula@example.com")));
+        assert_ok!(policy.check(&email("ursula@notmailinator.com")));
+    }
+
+    #[test]
+    fn role_addresses_are_rejected_or_flagged_as_configured() {
+        let postmaster = email("postmaster@example.com");
+        assert_err!(policy(RoleAddressPolicy::Reject).check(&postmaster));
+        assert_ok!(policy(RoleAddressPolicy::Flag).check(&postmaster));
+        assert!(policy(RoleAddressPolicy::Flag).flags(&postmaster));
+        assert!(!policy(RoleAddressPolicy::Allow).flags(&postmaster));
+        assert!(!policy(RoleAddressPolicy::Flag).flags(&email("ursula@example.com")));
+    }
+}
@@ -1,7 +1,24 @@
 mod subscriber_name;
 mod subscriber_email;
 mod new_subscriber;
+mod newsletter_template;
+mod new_password;
+mod subscription_status;
+mod delivery_frequency;
+mod segment;
+mod custom_field;
+mod email_policy;
 
 pub use subscriber_name::SubscriberName;
-pub use subscriber_email::SubscriberEmail;
+pub use subscriber_email::{normalize_email, SubscriberEmail};
 pub use new_subscriber::NewSubscriber;
+pub use new_password::NewPassword;
+pub use subscription_status::SubscriptionStatus;
+pub use delivery_frequency::DeliveryFrequency;
+pub use segment::Segment;
+pub use custom_field::{CustomField, CustomFieldKind};
+pub use email_policy::EmailPolicy;
+pub use newsletter_template::{
+    known_variables, render_variables, uses_any_variable, validate_variables,
+    NewsletterContent, NewsletterTemplate, SUBSCRIBER_VARIABLES,
+};
@@ -0,0 +1,103 @@
+use secrecy::{ExposeSecret, Secret};
+
+const MIN_CHARS: usize = 8;
+const MAX_CHARS: usize = 64;
+// zxcvbn scores go from 0 to 4: 3 is "safely unguessable" for online attacks
+const MIN_STRENGTH_SCORE: u8 = 3;
+// The most common passwords that are long enough to pass the length check
+const COMMON_PASSWORDS: &str = include_str!("common_passwords.txt");
+
+// A password someone wants to start using, checked against our policy
+#[derive(Debug)]
+pub struct NewPassword(Secret<String>);
+
+impl NewPassword {
+    // `user_inputs` are things like the username, which make a password
+    // easier to guess for anyone who knows them.
+    pub fn parse(s: Secret<String>, user_inputs: &[&str]) -> Result<NewPassword, String> {
+        let password = s.expose_secret();
+
+        // count characters rather than bytes, so non-ASCII passwords are
+        // held to the same bounds
+        let n_chars = password.chars().count();
+        if !(MIN_CHARS..=MAX_CHARS).contains(&n_chars) {
+            return Err(format!(
+                "Your password must be between {} and {} characters long.",
+                MIN_CHARS, MAX_CHARS
+            ));
+        }
+
+        let lowercase = password.to_lowercase();
+        if COMMON_PASSWORDS.lines().any(|common| common == lowercase) {
+            return Err("This password is too common - please choose another one.".into());
+        }
+
+        let estimate = zxcvbn::zxcvbn(password, user_inputs)
+            .map_err(|_| "Your password could not be checked.".to_string())?;
+        if estimate.score() < MIN_STRENGTH_SCORE {
+            let mut message = String::from("This password is too easy to guess.");
+            if let Some(feedback) = estimate.feedback() {
+                if let Some(warning) = feedback.warning() {
+                    message.push_str(&format!(" {}", warning));
+                }
+                for suggestion in feedback.suggestions() {
+                    message.push_str(&format!(" {}", suggestion));
+                }
+            }
+            return Err(message);
+        }
+        Ok(Self(s))
+    }
+}
+
+impl From<NewPassword> for Secret<String> {
+    fn from(password: NewPassword) -> Self {
+        password.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::domain::NewPassword;
+    use claims::{assert_err, assert_ok};
+    use secrecy::Secret;
+
+    fn parse(s: &str) -> Result<NewPassword, String> {
+        NewPassword::parse(Secret::new(s.to_string()), &["ada"])
+    }
+
+    #[test]
+    fn a_strong_password_is_valid() {
+        assert_ok!(parse("correct horse battery staple"));
+    }
+
+    #[test]
+    fn a_password_shorter_than_8_characters_is_rejected() {
+        assert_err!(parse("x7#Qp!z"));
+    }
+
+    #[test]
+    fn a_password_longer_than_64_characters_is_rejected() {
+        assert_err!(parse(&"x7#Qp!z".repeat(10)));
+    }
+
+    #[test]
+    fn length_is_counted_in_characters_not_bytes() {
+        // 7 characters, but 14 bytes
+        assert_err!(parse("ëëëëëëë"));
+    }
+
+    #[test]
+    fn common_passwords_are_rejected() {
+        assert_eq!(
+            parse("Password1").err().unwrap(),
+            "This password is too common - please choose another one."
+        );
+    }
+
+    #[test]
+    fn weak_passwords_are_rejected() {
+        assert_err!(parse("aaaaaaaaaaaa"));
+        assert_err!(parse("ada12345678"));
+    }
+}
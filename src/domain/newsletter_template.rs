@@ -0,0 +1,251 @@
+use std::collections::HashMap;
+
+// Variables the delivery worker knows how to fill in for every subscriber,
+// on top of the custom fields'
+pub const SUBSCRIBER_VARIABLES: [&str; 3] = ["name", "unsubscribe_url", "preferences_url"];
+
+// Everything content can refer to, given the keys of the custom fields
+pub fn known_variables(field_keys: &[String]) -> Vec<&str> {
+    SUBSCRIBER_VARIABLES
+        .into_iter()
+        .chain(field_keys.iter().map(String::as_str))
+        .collect()
+}
+
+#[derive(Debug, Clone)]
+pub struct NewsletterTemplate {
+    pub header_html: String,
+    pub footer_html: String,
+    pub css: String,
+}
+
+impl NewsletterTemplate {
+    pub fn parse(
+        header_html: String,
+        footer_html: String,
+        css: String,
+        variables: &[&str],
+    ) -> Result<Self, String> {
+        validate_variables(&header_html, variables)?;
+        validate_variables(&footer_html, variables)?;
+        if css.contains("</style") {
+            return Err("The template CSS cannot close its own <style> element.".into());
+        }
+        Ok(Self { header_html, footer_html, css })
+    }
+
+    // Lay the issue body out between the template's header and footer
+    pub fn render(&self, title: &str, body_html: &str) -> String {
+        format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+<meta http-equiv="content-type" content="text/html; charset=utf-8">
+<title>{}</title>
+<style>{}</style>
+</head>
+<body>
+{}
+{}
+{}
+</body>
+</html>"#,
+            htmlescape::encode_minimal(title),
+            self.css,
+            self.header_html,
+            body_html,
+            self.footer_html,
+        )
+    }
+}
+
+// A newsletter issue whose content only refers to variables we can fill in
+pub struct NewsletterContent {
+    pub title: String,
+    pub text_content: String,
+    pub html_content: String,
+}
+
+impl NewsletterContent {
+    pub fn parse(
+        title: String,
+        text_content: String,
+        html_content: String,
+        template: Option<&NewsletterTemplate>,
+        variables: &[&str],
+    ) -> Result<Self, String> {
+        if title.trim().is_empty() {
+            return Err("The issue title cannot be empty.".into());
+        }
+        validate_variables(&title, variables)?;
+        validate_variables(&text_content, variables)?;
+        validate_variables(&html_content, variables)?;
+        let html_content = match template {
+            Some(template) => template.render(&title, &html_content),
+            None => html_content,
+        };
+        Ok(Self { title, text_content, html_content })
+    }
+}
+
+// Split `content` into literal text and `{{ variable }}` names.
+fn tokenize(content: &str) -> Result<Vec<Segment<'_>>, String> {
+    let mut segments = Vec::new();
+    let mut rest = content;
+    while let Some(start) = rest.find("{{") {
+        segments.push(Segment::Text(&rest[..start]));
+        let after_open = &rest[start + 2..];
+        let end = after_open
+            .find("}}")
+            .ok_or_else(|| "A '{{' is never closed with '}}'.".to_string())?;
+        segments.push(Segment::Variable(after_open[..end].trim()));
+        rest = &after_open[end + 2..];
+    }
+    segments.push(Segment::Text(rest));
+    Ok(segments)
+}
+
+enum Segment<'a> {
+    Text(&'a str),
+    Variable(&'a str),
+}
+
+pub fn validate_variables(content: &str, known_variables: &[&str]) -> Result<(), String> {
+    for segment in tokenize(content)? {
+        if let Segment::Variable(name) = segment {
+            if !known_variables.contains(&name) {
+                return Err(format!(
+                    "{{{{{}}}}} is not a known variable. Use one of: {}.",
+                    name,
+                    known_variables.join(", ")
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
+// Fill in every variable, leaving unknown ones untouched. Values are
+// HTML-escaped when `escape_html` is set.
+pub fn render_variables(
+    content: &str,
+    values: &HashMap<String, String>,
+    escape_html: bool,
+) -> String {
+    let segments = match tokenize(content) {
+        Ok(segments) => segments,
+        Err(_) => return content.to_string(),
+    };
+    let mut rendered = String::with_capacity(content.len());
+    for segment in segments {
+        match segment {
+            Segment::Text(text) => rendered.push_str(text),
+            Segment::Variable(name) => match values.get(name) {
+                Some(value) if escape_html => {
+                    rendered.push_str(&htmlescape::encode_minimal(value))
+                }
+                Some(value) => rendered.push_str(value),
+                None => {
+                    rendered.push_str("{{");
+                    rendered.push_str(name);
+                    rendered.push_str("}}");
+                }
+            },
+        }
+    }
+    rendered
+}
+
+// Whether `content` fills in any of `names`
+pub fn uses_any_variable(content: &str, names: &[&str]) -> bool {
+    tokenize(content)
+        .map(|segments| {
+            segments.iter().any(|segment| matches!(segment, Segment::Variable(name) if names.contains(name)))
+        })
+        .unwrap_or(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        known_variables, render_variables, uses_any_variable, validate_variables,
+        NewsletterContent, NewsletterTemplate, SUBSCRIBER_VARIABLES,
+    };
+    use claims::{assert_err, assert_ok};
+    use std::collections::HashMap;
+
+    const KNOWN: [&str; 2] = ["name", "unsubscribe_url"];
+
+    #[test]
+    fn known_variables_are_accepted() {
+        assert_ok!(validate_variables("Hi {{name}}, bye {{ unsubscribe_url }}", &KNOWN));
+    }
+
+    #[test]
+    fn unknown_variables_are_rejected() {
+        assert_err!(validate_variables("Hi {{nmae}}", &KNOWN));
+    }
+
+    #[test]
+    fn unclosed_variables_are_rejected() {
+        assert_err!(validate_variables("Hi {{name", &KNOWN));
+    }
+
+    #[test]
+    fn custom_field_keys_are_variables_too() {
+        let keys = vec!["company".to_string()];
+        assert_ok!(validate_variables("{{name}} at {{company}}", &known_variables(&keys)));
+    }
+
+    #[test]
+    fn variables_are_substituted_and_escaped() {
+        let values = HashMap::from([("name".to_string(), "Tom & Jerry".to_string())]);
+        assert_eq!(
+            render_variables("<p>Hi {{ name }}</p>", &values, true),
+            "<p>Hi Tom &amp; Jerry</p>"
+        );
+        assert_eq!(
+            render_variables("Hi {{name}}", &values, false),
+            "Hi Tom & Jerry"
+        );
+    }
+
+    #[test]
+    fn variables_are_found_with_or_without_spaces() {
+        let names = ["preferences_url", "unsubscribe_url"];
+        assert!(uses_any_variable("<a href=\"{{ unsubscribe_url }}\">", &names));
+        assert!(uses_any_variable("Manage: {{preferences_url}}", &names));
+        assert!(!uses_any_variable("Hello {{name}}, unsubscribe_url", &names));
+    }
+
+    #[test]
+    fn templates_with_unknown_variables_are_rejected() {
+        assert_err!(NewsletterTemplate::parse(
+            "<h1>{{brand}}</h1>".into(),
+            "".into(),
+            "".into(),
+            &SUBSCRIBER_VARIABLES,
+        ));
+    }
+
+    #[test]
+    fn issue_content_is_rendered_into_the_template() {
+        let template = NewsletterTemplate::parse(
+            "<header>Our brand</header>".into(),
+            r#"<footer><a href="{{unsubscribe_url}}">Unsubscribe</a></footer>"#.into(),
+            "p { color: red; }".into(),
+            &SUBSCRIBER_VARIABLES,
+        )
+        .unwrap();
+        let content = NewsletterContent::parse(
+            "Issue #1".into(),
+            "Plain".into(),
+            "<p>Hello {{name}}</p>".into(),
+            Some(&template),
+            &SUBSCRIBER_VARIABLES,
+        )
+        .unwrap();
+        assert!(content.html_content.contains("<header>Our brand</header>\n<p>Hello {{name}}</p>"));
+        assert!(content.html_content.contains("<style>p { color: red; }</style>"));
+    }
+}
@@ -0,0 +1,100 @@
+use chrono::NaiveDate;
+use uuid::Uuid;
+
+// Narrows an issue down to some of the subscribers on its lists. Every
+// condition that is set has to hold; the default segment is everyone.
+#[derive(Debug, Clone, Default, PartialEq, Eq, serde::Deserialize)]
+pub struct Segment {
+    pub joined_since: Option<NaiveDate>,
+    pub tag_id: Option<Uuid>,
+    // Subscribers count as engaged when they confirmed their address or
+    // changed their preferences, as kept in `subscriptions.last_engaged_at`
+    pub engaged_within_days: Option<i32>,
+}
+
+impl Segment {
+    pub const MAX_ENGAGEMENT_DAYS: i32 = 3650;
+
+    // Blank fields leave their condition out
+    pub fn parse(
+        joined_since: &str,
+        tag_id: &str,
+        engaged_within_days: &str,
+    ) -> Result<Self, String> {
+        let joined_since = match joined_since.trim() {
+            "" => None,
+            day => Some(
+                NaiveDate::parse_from_str(day, "%Y-%m-%d")
+                    .map_err(|_| format!("{} is not a date in the YYYY-MM-DD format.", day))?,
+            ),
+        };
+        let tag_id = match tag_id.trim() {
+            "" => None,
+            id => Some(Uuid::parse_str(id).map_err(|_| format!("{} is not a tag.", id))?),
+        };
+        let engaged_within_days = match engaged_within_days.trim() {
+            "" => None,
+            days => Some(
+                days.parse()
+                    .map_err(|_| format!("{} is not a number of days.", days))?,
+            ),
+        };
+        Self {
+            joined_since,
+            tag_id,
+            engaged_within_days,
+        }
+        .validate()
+    }
+
+    pub fn validate(self) -> Result<Self, String> {
+        if let Some(days) = self.engaged_within_days {
+            if !(1..=Self::MAX_ENGAGEMENT_DAYS).contains(&days) {
+                return Err(format!(
+                    "Engagement is looked up over 1 to {} days.",
+                    Self::MAX_ENGAGEMENT_DAYS
+                ));
+            }
+        }
+        Ok(self)
+    }
+
+    pub fn is_everyone(&self) -> bool {
+        self == &Self::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::domain::Segment;
+    use chrono::NaiveDate;
+    use claims::{assert_err, assert_ok_eq};
+
+    #[test]
+    fn blank_fields_select_everyone() {
+        let segment = Segment::parse("", " ", "").unwrap();
+        assert!(segment.is_everyone());
+    }
+
+    #[test]
+    fn every_condition_is_parsed() {
+        let tag_id = uuid::Uuid::new_v4();
+        assert_ok_eq!(
+            Segment::parse("2024-04-01", &tag_id.to_string(), "30"),
+            Segment {
+                joined_since: NaiveDate::from_ymd_opt(2024, 4, 1),
+                tag_id: Some(tag_id),
+                engaged_within_days: Some(30),
+            }
+        );
+    }
+
+    #[test]
+    fn invalid_conditions_are_rejected() {
+        assert_err!(Segment::parse("01/04/2024", "", ""));
+        assert_err!(Segment::parse("", "not-a-tag", ""));
+        assert_err!(Segment::parse("", "", "soon"));
+        assert_err!(Segment::parse("", "", "0"));
+        assert_err!(Segment::parse("", "", "100000"));
+    }
+}
@@ -1,16 +1,63 @@
 use validator::validate_email;
 
+// Local parts that reach a team or a machine rather than a person
+const ROLE_LOCAL_PARTS: [&str; 16] = [
+    "abuse",
+    "admin",
+    "administrator",
+    "billing",
+    "hostmaster",
+    "info",
+    "mailer-daemon",
+    "no-reply",
+    "noreply",
+    "postmaster",
+    "root",
+    "sales",
+    "security",
+    "support",
+    "webmaster",
+    "www",
+];
+
 #[derive(Debug, Clone)]
 pub struct SubscriberEmail(String);
 
 impl SubscriberEmail {
+    // Addresses are normalized first, so that one mailbox is always spelled
+    // the same way and a unique column catches it twice.
     pub fn parse(s: String) -> Result<SubscriberEmail, String> {
-        if validate_email(&s) {
-            Ok(Self(s))
-        } else {
-            Err(format!("{} is not a valid subscriber email.", s))
+        match normalize(&s) {
+            Some(email) if validate_email(&email) => Ok(Self(email)),
+            _ => Err(format!("{} is not a valid subscriber email.", s)),
         }
     }
+
+    pub fn domain(&self) -> &str {
+        self.0.rsplit_once('@').map(|(_, domain)| domain).unwrap_or_default()
+    }
+
+    // `postmaster+news@` is as much a role address as `postmaster@`
+    pub fn is_role_address(&self) -> bool {
+        let local_part = self.0.rsplit_once('@').map(|(local, _)| local).unwrap_or_default();
+        let mailbox = local_part.split('+').next().unwrap_or_default();
+        ROLE_LOCAL_PARTS.contains(&mailbox)
+    }
+}
+
+// The spelling `parse` would store, for text that is not necessarily a
+// valid address: whatever is looked up by address has to agree with it
+pub fn normalize_email(s: &str) -> String {
+    normalize(s).unwrap_or_else(|| s.trim().to_lowercase())
+}
+
+// Trims the address, turns an international domain into punycode and lower-
+// cases all of it. Local parts are case-sensitive on paper only: no provider
+// tells `Ursula@` from `ursula@`, and the suppression list ignores case too.
+fn normalize(s: &str) -> Option<String> {
+    let (local_part, domain) = s.trim().rsplit_once('@')?;
+    let domain = idna::domain_to_ascii(domain).ok()?;
+    Some(format!("{}@{}", local_part.to_lowercase(), domain))
 }
 
 impl std::fmt::Display for SubscriberEmail {
@@ -65,4 +112,26 @@ mod tests {
         let email = "@domain.com".to_string();
         assert_err!(SubscriberEmail::parse(email));
     }
+
+    #[test]
+    fn emails_are_trimmed_and_lower_cased() {
+        let email = SubscriberEmail::parse("  Ursula.Le.Guin@Example.COM ".to_string()).unwrap();
+        assert_eq!(email.as_ref(), "ursula.le.guin@example.com");
+    }
+
+    #[test]
+    fn international_domains_are_stored_as_punycode() {
+        let email = SubscriberEmail::parse("ursula@Bücher.example".to_string()).unwrap();
+        assert_eq!(email.as_ref(), "ursula@xn--bcher-kva.example");
+        assert_eq!(email.domain(), "xn--bcher-kva.example");
+    }
+
+    #[test]
+    fn role_addresses_are_recognized_with_or_without_a_tag() {
+        let is_role = |email: &str| SubscriberEmail::parse(email.to_string()).unwrap().is_role_address();
+        assert!(is_role("postmaster@example.com"));
+        assert!(is_role("Abuse+reports@example.com"));
+        assert!(!is_role("ursula@example.com"));
+        assert!(!is_role("postmasters@example.com"));
+    }
 }
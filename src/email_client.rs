@@ -40,7 +40,7 @@ impl EmailClient {
             let request_body = SendEmailRequest {
                 from: self.sender.as_ref(),
                 to: recipient.as_ref(),
-                subject: subject,
+                subject,
                 html_body: html_content,
                 text_body: text_content,
             };
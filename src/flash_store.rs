@@ -0,0 +1,59 @@
+use actix_web::cookie::{Cookie, Key, SameSite};
+use actix_web::dev::ResponseHead;
+use actix_web::http::header::{HeaderValue, SET_COOKIE};
+use actix_web::HttpRequest;
+use actix_web_flash_messages::storage::{
+    CookieMessageStore, FlashMessageStore, LoadError, StoreError,
+};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+
+const FLASH_COOKIE_NAME: &str = "_flash";
+
+// `CookieMessageStore` always marks its cookie `SameSite=Lax`: this rewrites
+// the attribute on the way out, so it follows our configuration.
+pub struct FlashCookieStore {
+    inner: CookieMessageStore,
+    same_site: SameSite,
+}
+
+impl FlashCookieStore {
+    pub fn new(signing_key: Key, same_site: SameSite) -> Self {
+        let inner = CookieMessageStore::builder(signing_key)
+            .cookie_name(FLASH_COOKIE_NAME.into())
+            .build();
+        Self { inner, same_site }
+    }
+}
+
+impl FlashMessageStore for FlashCookieStore {
+    fn load(&self, request: &HttpRequest) -> Result<Vec<FlashMessage>, LoadError> {
+        self.inner.load(request)
+    }
+
+    fn store(
+        &self,
+        messages: &[FlashMessage],
+        request: HttpRequest,
+        response_head: &mut ResponseHead,
+    ) -> Result<(), StoreError> {
+        self.inner.store(messages, request, response_head)?;
+        let set_cookies: Vec<HeaderValue> = response_head
+            .headers_mut()
+            .remove(SET_COOKIE)
+            .collect();
+        for value in set_cookies {
+            let value = match value.to_str().ok().and_then(|v| Cookie::parse(v).ok()) {
+                Some(mut cookie) if cookie.name() == FLASH_COOKIE_NAME => {
+                    cookie.set_same_site(self.same_site);
+                    HeaderValue::from_str(&cookie.to_string())
+                        .context("Failed to encode the flash message cookie.")
+                        .map_err(StoreError::GenericError)?
+                }
+                _ => value,
+            };
+            response_head.headers_mut().append(SET_COOKIE, value);
+        }
+        Ok(())
+    }
+}
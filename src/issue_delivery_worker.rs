@@ -2,7 +2,11 @@ use crate::email_client::EmailClient;
 use sqlx::{PgPool, Postgres, Transaction};
 use tracing::{field::display, Span};
 use uuid::Uuid;
-use crate::domain::SubscriberEmail;
+use crate::domain::{
+    render_variables, uses_any_variable, NewSubscriber, SubscriberEmail, SubscriberName,
+};
+use crate::routes::send_comfirmation_email;
+use std::collections::HashMap;
 use std::time::Duration;
 use crate::{configuration::Settings, startup::get_connection_pool};
 
@@ -11,13 +15,31 @@ pub enum ExecutionOutcome {
     EmptyQueue,
 }
 
+// What happened to a single queued delivery, kept in `issue_delivery_log`
+#[derive(Debug, Clone, Copy)]
+pub enum DeliveryOutcome {
+    Delivered,
+    Failed,
+    Skipped,
+}
+
+impl DeliveryOutcome {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DeliveryOutcome::Delivered => "delivered",
+            DeliveryOutcome::Failed => "failed",
+            DeliveryOutcome::Skipped => "skipped",
+        }
+    }
+}
+
 pub async fn run_worker_until_stopped(
     configuration: Settings,
 ) -> Result<(), anyhow::Error> {
     let connection_pool = get_connection_pool(&configuration.database);
 
     let email_client = configuration.email_client.client();
-    worker_loop(connection_pool, email_client).await
+    worker_loop(connection_pool, email_client, configuration.application.base_url).await
 }
 
 
@@ -33,26 +55,34 @@ pub async fn run_worker_until_stopped(
 pub async fn try_execute_task(
     pool: &PgPool,
     email_client: &EmailClient,
+    base_url: &str,
 ) -> Result<ExecutionOutcome, anyhow::Error> {
     let task = dequeue_task(pool).await?;
     if task.is_none() {
         return Ok(ExecutionOutcome::EmptyQueue);
     }
-    let (transaction, issue_id, email) = task.unwrap(); 
+    let (mut transaction, issue_id, email) = task.unwrap(); 
     {
     Span::current()
-        .record("newsletter_issue_id", &display(issue_id))
-        .record("subscriber_email", &display(&email));
+        .record("newsletter_issue_id", display(issue_id))
+        .record("subscriber_email", display(&email));
     
-        match SubscriberEmail::parse(email.clone()) {
+        let outcome = match SubscriberEmail::parse(email.clone()) {
         Ok(email) => {
             let issue = get_issue(pool, issue_id).await?;
+            let variables = get_subscriber_variables(pool, issue_id, &email, base_url).await?;
+            let (html_content, text_content) = add_preferences_footer(
+                render_variables(&issue.html_content, &variables, true),
+                render_variables(&issue.text_content, &variables, false),
+                &issue,
+                variables.get("preferences_url").map(String::as_str),
+            );
             if let Err(e) = email_client
                 .send_email(
                     &email,
-                    &issue.title,
-                    &issue.html_content,
-                    &issue.text_content,
+                    &render_variables(&issue.title, &variables, false),
+                    &html_content,
+                    &text_content,
                 )
                     .await
             {
@@ -62,6 +92,9 @@ pub async fn try_execute_task(
                     "Failed to deliber issue to a confirmed subscriber. \
                     Skipping.",
                 );
+                DeliveryOutcome::Failed
+            } else {
+                DeliveryOutcome::Delivered
             }
         }
         Err(e) => {
@@ -71,23 +104,31 @@ pub async fn try_execute_task(
                     "Skipping confirmed subscriber. \
                     Their stored contact details are invalid.",
                 );
+                DeliveryOutcome::Skipped
             }
-        }
+        };
+        record_delivery_outcome(&mut transaction, issue_id, &email, outcome).await?;
         delete_task(transaction, issue_id, &email).await?;
     }
     Ok(ExecutionOutcome::TaskCompleted)
 }
 
-async fn worker_loop(pool: PgPool, email_client: EmailClient) -> Result<(), anyhow::Error> {
+async fn worker_loop(
+    pool: PgPool,
+    email_client: EmailClient,
+    base_url: String,
+) -> Result<(), anyhow::Error> {
     loop {
-        match try_execute_task(&pool, &email_client).await {
-            Ok(ExecutionOutcome::EmptyQueue) => {
+        let issues = try_execute_task(&pool, &email_client, &base_url).await;
+        let confirmations = try_send_confirmation_email(&pool, &email_client, &base_url).await;
+        match (issues, confirmations) {
+            (Ok(ExecutionOutcome::EmptyQueue), Ok(ExecutionOutcome::EmptyQueue)) => {
                 tokio::time::sleep(Duration::from_secs(10)).await;
             }
-            Err(_) => {
+            (Err(_), _) | (_, Err(_)) => {
                 tokio::time::sleep(Duration::from_secs(1)).await;
             }
-            Ok(ExecutionOutcome::TaskCompleted) => {}
+            _ => {}
         }
     }
 }
@@ -97,6 +138,82 @@ async fn worker_loop(pool: PgPool, email_client: EmailClient) -> Result<(), anyh
 
 type PgTransaction = Transaction<'static, Postgres>;
 
+// The email goes out with the worker's next round, once the transaction
+// that stored the token has committed
+pub async fn enqueue_confirmation_email(
+    transaction: &mut PgTransaction,
+    subscription_token: &str,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO confirmation_email_queue (subscription_token, enqueued_at)
+        VALUES ($1, now())
+        "#,
+        subscription_token,
+    )
+        .execute(&mut **transaction)
+        .await?;
+    Ok(())
+}
+
+// Sends one queued confirmation email. Subscribers who have confirmed or
+// left the list since it was queued are not asked again.
+#[tracing::instrument(skip_all, err)]
+pub async fn try_send_confirmation_email(
+    pool: &PgPool,
+    email_client: &EmailClient,
+    base_url: &str,
+) -> Result<ExecutionOutcome, anyhow::Error> {
+    let mut transaction = pool.begin().await?;
+    let r = sqlx::query!(
+        r#"
+        SELECT
+            q.subscription_token,
+            s.email,
+            s.name,
+            (m.status = 'pending_confirmation' AND s.status <> 'unsubscribed'
+                AND s.status <> 'suppressed') AS "is_pending!"
+        FROM confirmation_email_queue q
+        JOIN subscription_tokens t USING (subscription_token)
+        JOIN subscriptions s ON s.id = t.subscriber_id
+        JOIN list_memberships m ON m.subscriber_id = t.subscriber_id AND m.list_id = t.list_id
+        FOR UPDATE OF q
+        SKIP LOCKED
+        LIMIT 1
+        "#,
+    )
+        .fetch_optional(&mut *transaction)
+        .await?;
+    let Some(r) = r else {
+        return Ok(ExecutionOutcome::EmptyQueue);
+    };
+    if r.is_pending {
+        match (SubscriberEmail::parse(r.email), SubscriberName::parse(r.name)) {
+            (Ok(email), Ok(name)) => {
+                let new_subscriber = NewSubscriber { email, name };
+                if let Err(e) =
+                    send_comfirmation_email(email_client, new_subscriber, base_url, &r.subscription_token).await
+                {
+                    tracing::error!(
+                        error.cause_chain = ?e,
+                        error.message = %e,
+                        "Failed to send a queued confirmation email. Skipping.",
+                    );
+                }
+            }
+            _ => tracing::error!("Skipping a queued confirmation email. The stored contact details are invalid."),
+        }
+    }
+    sqlx::query!(
+        "DELETE FROM confirmation_email_queue WHERE subscription_token = $1",
+        r.subscription_token,
+    )
+        .execute(&mut *transaction)
+        .await?;
+    transaction.commit().await?;
+    Ok(ExecutionOutcome::TaskCompleted)
+}
+
 #[tracing::instrument(skip_all)]
 async fn dequeue_task(
     pool: &PgPool,
@@ -146,6 +263,34 @@ async fn delete_task(
     Ok(())
 }
 
+#[tracing::instrument(skip_all)]
+async fn record_delivery_outcome(
+    transaction: &mut PgTransaction,
+    issue_id: Uuid,
+    email: &str,
+    outcome: DeliveryOutcome,
+) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO issue_delivery_log (
+            newsletter_issue_id,
+            subscriber_email,
+            outcome,
+            attempted_at
+        )
+        VALUES ($1, $2, $3, now())
+        ON CONFLICT (newsletter_issue_id, subscriber_email)
+        DO UPDATE SET outcome = EXCLUDED.outcome, attempted_at = EXCLUDED.attempted_at
+        "#,
+        issue_id,
+        email,
+        outcome.as_str()
+    )
+        .execute(&mut **transaction)
+        .await?;
+    Ok(())
+}
+
 // Struct to cast newsletters to
 struct NewsletterIssue {
     title: String,
@@ -170,3 +315,92 @@ async fn get_issue(pool: &PgPool, issue_id: Uuid) -> Result<NewsletterIssue, any
         .await?;
     Ok(issue)
 }
+
+// Per-subscriber values for the variables newsletter content can use. The
+// links use the token of a list the issue went out to, so unsubscribing
+// leaves that list. Custom fields the subscriber left blank come out empty.
+#[tracing::instrument(skip_all)]
+// Every issue tells its reader how to change or end their subscription.
+// Content that already links to the preferences or unsubscribe page is
+// left as it was.
+fn add_preferences_footer(
+    mut html_content: String,
+    mut text_content: String,
+    issue: &NewsletterIssue,
+    preferences_url: Option<&str>,
+) -> (String, String) {
+    const LINKS: [&str; 2] = ["preferences_url", "unsubscribe_url"];
+    let preferences_url = match preferences_url {
+        Some(url) => url,
+        None => return (html_content, text_content),
+    };
+    if !uses_any_variable(&issue.html_content, &LINKS) {
+        let footer = format!(
+            "<p><a href=\"{}\">Manage your subscription or unsubscribe</a></p>",
+            htmlescape::encode_minimal(preferences_url),
+        );
+        // Inside the body when the issue was laid out in a template
+        match html_content.rfind("</body>") {
+            Some(end) => html_content.insert_str(end, &format!("{}\n", footer)),
+            None => html_content.push_str(&footer),
+        }
+    }
+    if !uses_any_variable(&issue.text_content, &LINKS) {
+        text_content.push_str(&format!(
+            "\n\n--\nManage your subscription or unsubscribe: {}",
+            preferences_url,
+        ));
+    }
+    (html_content, text_content)
+}
+
+async fn get_subscriber_variables(
+    pool: &PgPool,
+    issue_id: Uuid,
+    email: &SubscriberEmail,
+    base_url: &str,
+) -> Result<HashMap<String, String>, anyhow::Error> {
+    let r = sqlx::query!(
+        r#"
+        SELECT s.id, s.name, t.subscription_token AS "subscription_token?"
+        FROM subscriptions s
+        LEFT JOIN subscription_tokens t ON t.subscriber_id = s.id
+            AND t.list_id IN (
+                SELECT list_id FROM newsletter_issue_lists WHERE newsletter_issue_id = $2
+            )
+        WHERE s.email = $1
+        LIMIT 1
+        "#,
+        email.as_ref(),
+        issue_id,
+    )
+        .fetch_optional(pool)
+        .await?;
+    let mut variables = HashMap::new();
+    if let Some(r) = r {
+        variables.insert("name".to_string(), r.name);
+        if let Some(token) = r.subscription_token {
+            variables.insert(
+                "unsubscribe_url".to_string(),
+                format!("{}/subscriptions/unsubscribe?subscription_token={}", base_url, token),
+            );
+            variables.insert(
+                "preferences_url".to_string(),
+                format!("{}/preferences?subscription_token={}", base_url, token),
+            );
+        }
+        let fields = sqlx::query!(
+            r#"
+            SELECT f.key, COALESCE(v.value, '') AS "value!"
+            FROM custom_fields f
+            LEFT JOIN subscriber_field_values v
+                ON v.field_id = f.field_id AND v.subscriber_id = $1
+            "#,
+            r.id,
+        )
+            .fetch_all(pool)
+            .await?;
+        variables.extend(fields.into_iter().map(|f| (f.key, f.value)));
+    }
+    Ok(variables)
+}
@@ -1,7 +1,9 @@
 pub mod issue_delivery_worker;
 pub mod idempotency;
 pub mod utils;
+pub mod client_ip;
 pub mod session_state;
+pub mod flash_store;
 pub mod authentication;
 pub mod configuration;
 pub mod routes;
@@ -9,3 +11,9 @@ pub mod startup;
 pub mod telemetry;
 pub mod domain;
 pub mod email_client;
+pub mod audit;
+pub mod suppression_list;
+pub mod mailing_lists;
+pub mod tags;
+pub mod custom_fields;
+pub mod subscribe_protection;
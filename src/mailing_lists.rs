@@ -0,0 +1,127 @@
+use anyhow::Context;
+use sqlx::{Executor, PgPool, Postgres, Transaction};
+use uuid::Uuid;
+use crate::domain::SubscriptionStatus;
+
+// The list everyone was on before there were several. Sign-ups, imports and
+// issues that do not name a list use it.
+pub const DEFAULT_LIST_SLUG: &str = "newsletter";
+
+#[derive(Debug, Clone)]
+pub struct MailingList {
+    pub list_id: Uuid,
+    pub slug: String,
+    pub name: String,
+}
+
+pub struct ListSummary {
+    pub list_id: Uuid,
+    pub slug: String,
+    pub name: String,
+    pub n_confirmed: i64,
+    pub n_pending: i64,
+}
+
+pub struct ListMembership {
+    pub list_id: Uuid,
+    pub name: String,
+    pub status: String,
+}
+
+#[tracing::instrument(name = "Get mailing list", skip(executor))]
+pub async fn get_list_by_slug<'c, E>(executor: E, slug: &str) -> Result<Option<MailingList>, anyhow::Error>
+where
+    E: Executor<'c, Database = Postgres>,
+{
+    let list = sqlx::query_as!(
+        MailingList,
+        "SELECT list_id, slug, name FROM lists WHERE slug = $1",
+        slug,
+    )
+        .fetch_optional(executor)
+        .await
+        .context("Failed to retrieve a mailing list.")?;
+    Ok(list)
+}
+
+#[tracing::instrument(name = "Get mailing lists", skip(pool))]
+pub async fn get_lists(pool: &PgPool) -> Result<Vec<ListSummary>, anyhow::Error> {
+    let lists = sqlx::query_as!(
+        ListSummary,
+        r#"
+        SELECT
+            l.list_id,
+            l.slug,
+            l.name,
+            COUNT(*) FILTER (WHERE m.status = 'confirmed') AS "n_confirmed!",
+            COUNT(*) FILTER (WHERE m.status = 'pending_confirmation') AS "n_pending!"
+        FROM lists l
+        LEFT JOIN list_memberships m ON m.list_id = l.list_id
+        GROUP BY l.list_id
+        ORDER BY l.created_at
+        "#,
+    )
+        .fetch_all(pool)
+        .await
+        .context("Failed to retrieve the mailing lists.")?;
+    Ok(lists)
+}
+
+#[tracing::instrument(name = "Get list memberships", skip(pool))]
+pub async fn get_memberships(
+    pool: &PgPool,
+    subscriber_id: Uuid,
+) -> Result<Vec<ListMembership>, anyhow::Error> {
+    let memberships = sqlx::query_as!(
+        ListMembership,
+        r#"
+        SELECT l.list_id, l.name, m.status
+        FROM list_memberships m
+        JOIN lists l ON l.list_id = m.list_id
+        WHERE m.subscriber_id = $1
+        ORDER BY l.created_at
+        "#,
+        subscriber_id,
+    )
+        .fetch_all(pool)
+        .await
+        .context("Failed to retrieve the subscriber's lists.")?;
+    Ok(memberships)
+}
+
+// Returns the membership's status before the call, if there was one.
+// Confirmed memberships stay confirmed.
+#[tracing::instrument(name = "Join mailing list", skip(transaction))]
+pub async fn join_list(
+    transaction: &mut Transaction<'_, Postgres>,
+    list_id: Uuid,
+    subscriber_id: Uuid,
+    status: SubscriptionStatus,
+) -> Result<Option<String>, anyhow::Error> {
+    let previous = sqlx::query!(
+        "SELECT status FROM list_memberships WHERE list_id = $1 AND subscriber_id = $2 FOR UPDATE",
+        list_id,
+        subscriber_id,
+    )
+        .fetch_optional(&mut **transaction)
+        .await
+        .context("Failed to look up the list membership.")?
+        .map(|r| r.status);
+    sqlx::query!(
+        r#"
+        INSERT INTO list_memberships (list_id, subscriber_id, status, subscribed_at, confirmed_at)
+        VALUES ($1, $2, $3, now(), CASE WHEN $3 = 'confirmed' THEN now() END)
+        ON CONFLICT (list_id, subscriber_id) DO UPDATE
+        SET status = EXCLUDED.status,
+            confirmed_at = COALESCE(list_memberships.confirmed_at, EXCLUDED.confirmed_at)
+        WHERE list_memberships.status <> 'confirmed'
+        "#,
+        list_id,
+        subscriber_id,
+        status.as_str(),
+    )
+        .execute(&mut **transaction)
+        .await
+        .context("Failed to add the subscriber to the list.")?;
+    Ok(previous)
+}
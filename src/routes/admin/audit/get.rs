@@ -0,0 +1,132 @@
+use actix_web::http::header::{ContentDisposition, ContentType, DispositionParam, DispositionType};
+use actix_web::{web, HttpResponse};
+use sqlx::PgPool;
+use std::fmt::Write;
+use crate::audit::{get_audit_events, AuditAction, AuditFilter};
+use crate::utils::{e400, e500, parse_day};
+
+// How many events the page shows; the export has no limit
+const PAGE_SIZE: i64 = 100;
+
+// Empty fields come from the filter form when they are left blank
+#[derive(serde::Deserialize)]
+pub struct QueryParams {
+    #[serde(default)]
+    action: String,
+    #[serde(default)]
+    actor: String,
+    #[serde(default)]
+    since: String,
+    #[serde(default)]
+    until: String,
+}
+
+impl TryFrom<&QueryParams> for AuditFilter {
+    type Error = String;
+
+    fn try_from(params: &QueryParams) -> Result<Self, Self::Error> {
+        let action = match params.action.as_str() {
+            "" => None,
+            action => Some(AuditAction::try_from(action.to_string())?),
+        };
+        let actor_username = match params.actor.trim() {
+            "" => None,
+            actor => Some(actor.to_string()),
+        };
+        Ok(Self {
+            action,
+            actor_username,
+            target: None,
+            since: parse_day(&params.since, 0)?,
+            // `until` is inclusive, so it ends where the next day starts
+            until: parse_day(&params.until, 1)?,
+        })
+    }
+}
+
+pub async fn audit_log(
+    query: web::Query<QueryParams>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let params = query.into_inner();
+    let filter = AuditFilter::try_from(&params).map_err(e400)?;
+    let events = get_audit_events(&pool, &filter, Some(PAGE_SIZE))
+        .await
+        .map_err(e500)?;
+    let mut rows_html = String::new();
+    for event in &events {
+        writeln!(
+            rows_html,
+            "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+            event.occurred_at.format("%Y-%m-%d %H:%M:%S UTC"),
+            htmlescape::encode_minimal(event.actor_username.as_deref().unwrap_or("-")),
+            event.action,
+            htmlescape::encode_minimal(event.target.as_deref().unwrap_or("-")),
+            htmlescape::encode_minimal(event.ip_address.as_deref().unwrap_or("-")),
+        )
+        .unwrap();
+    }
+    let mut action_options = String::from(r#"<option value="">Any action</option>"#);
+    for action in AuditAction::ALL {
+        let selected = if action.as_str() == params.action { " selected" } else { "" };
+        write!(action_options, r#"<option value="{0}"{1}>{0}</option>"#, action.as_str(), selected).unwrap();
+    }
+    let export_query = filter_query(&params);
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+                r#"<!DOCTYPE html>
+                <html lang="en">
+                <head>
+                <meta http-equiv="content-type" content="text/html; charset=utf-8">
+                <title>Audit Log</title>
+                </head>
+                <body>
+                <form action="/admin/audit" method="get">
+                <select name="action">{action_options}</select>
+                <input type="text" placeholder="Username" name="actor" value="{actor}">
+                <label>From <input type="date" name="since" value="{since}"></label>
+                <label>To <input type="date" name="until" value="{until}"></label>
+                <button type="submit">Filter</button>
+                </form>
+                <p>The {PAGE_SIZE} most recent matching events are shown. <a href="/admin/audit/export?{export_query}">Export all as JSON</a></p>
+                <table>
+                <tr><th>Time</th><th>User</th><th>Action</th><th>Target</th><th>IP address</th></tr>
+                {rows_html}
+                </table>
+                <p><a href="/admin/dashboard">&lt;- Back</a></p>
+                </body>
+                </html>"#,
+                actor = htmlescape::encode_minimal(&params.actor),
+                since = htmlescape::encode_minimal(&params.since),
+                until = htmlescape::encode_minimal(&params.until),
+    )))
+}
+
+pub async fn export_audit_log(
+    query: web::Query<QueryParams>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let filter = AuditFilter::try_from(&query.into_inner()).map_err(e400)?;
+    let events = get_audit_events(&pool, &filter, None).await.map_err(e500)?;
+    Ok(HttpResponse::Ok()
+        .insert_header(ContentDisposition {
+            disposition: DispositionType::Attachment,
+            parameters: vec![DispositionParam::Filename("audit-events.json".into())],
+        })
+        .json(events))
+}
+
+// The filters again, to carry them over to the export link
+fn filter_query(params: &QueryParams) -> String {
+    [
+        ("action", &params.action),
+        ("actor", &params.actor),
+        ("since", &params.since),
+        ("until", &params.until),
+    ]
+    .iter()
+    .map(|(key, value)| format!("{}={}", key, urlencoding::encode(value)))
+    .collect::<Vec<_>>()
+    .join("&amp;")
+}
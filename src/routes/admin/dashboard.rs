@@ -6,12 +6,15 @@ use sqlx::PgPool;
 use crate::session_state::TypedSession;
 use actix_web::http::header::LOCATION;
 use crate::utils::e500;
+use crate::authentication::{csrf_field, Role};
 
 
 pub async fn admin_dashboard(
     session: TypedSession,
     pool: web::Data<PgPool>,
+    role: web::ReqData<Role>,
 ) -> Result<HttpResponse, actix_web::Error> {
+    let role = role.into_inner();
     let username = if let Some(user_id) = session
         .get_user_id()
         .map_err(e500)?
@@ -22,6 +25,7 @@ pub async fn admin_dashboard(
             .insert_header((LOCATION, "/login"))
             .finish());
             };
+    let csrf_field = csrf_field(&session.csrf_token().map_err(e500)?);
 
     Ok(HttpResponse::Ok()
         .content_type(ContentType::html())
@@ -33,12 +37,25 @@ pub async fn admin_dashboard(
 <title>Admin Dashboard</title>
 </head>
 <body>
-    <p>Welcome {username}!</p>
+    <p>Welcome {username}! You are signed in as {role}.</p>
     <p>Available actions:</p>
     <ol>
+        <li><a href="/admin/newsletters">Send a newsletter issue</a></li>
+        <li><a href="/admin/newsletters/history">Newsletter issues history</a></li>
+        <li><a href="/admin/subscribers">Subscribers</a></li>
+        <li><a href="/admin/lists">Mailing lists</a></li>
+        <li><a href="/admin/tags">Tags</a></li>
+        <li><a href="/admin/fields">Subscriber fields</a></li>
+        <li><a href="/admin/templates">Newsletter templates</a></li>
+        <li><a href="/admin/tokens">API tokens</a></li>
+        <li><a href="/admin/users">Users</a></li>
+        <li><a href="/admin/audit">Audit log</a></li>
         <li><a href="/admin/password">Change password</a></li>
+        <li><a href="/admin/totp">Two-factor authentication</a></li>
+        <li><a href="/admin/sessions">Sessions</a></li>
         <li>
             <form name="logoutForm" action="/admin/logout" method="post">
+                {csrf_field}
                 <input type="submit" value="logout">
             </form>
         </li>
@@ -0,0 +1,83 @@
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use sqlx::PgPool;
+use std::fmt::Write;
+use crate::authentication::csrf_field;
+use crate::custom_fields::get_custom_fields;
+use crate::domain::CustomFieldKind;
+use crate::session_state::TypedSession;
+use crate::utils::e500;
+
+pub async fn custom_fields_form(
+    flash_messages: IncomingFlashMessages,
+    pool: web::Data<PgPool>,
+    session: TypedSession,
+) -> Result<HttpResponse, actix_web::Error> {
+    let csrf_field = csrf_field(&session.csrf_token().map_err(e500)?);
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", htmlescape::encode_minimal(m.content())).unwrap();
+    }
+    let mut rows_html = String::new();
+    for field in get_custom_fields(pool.get_ref()).await.map_err(e500)? {
+        writeln!(
+            rows_html,
+            "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+            htmlescape::encode_minimal(&field.label),
+            field.key,
+            field.kind.description(),
+            htmlescape::encode_minimal(&field.options.join(", ")),
+            if field.is_required { "Required" } else { "Optional" },
+        )
+        .unwrap();
+    }
+    let mut kind_options = String::new();
+    for kind in CustomFieldKind::ALL {
+        write!(kind_options, r#"<option value="{}">{}</option>"#, kind, kind.description()).unwrap();
+    }
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+                r#"<!DOCTYPE html>
+                <html lang="en">
+                <head>
+                <meta http-equiv="content-type" content="text/html; charset=utf-8">
+                <title>Subscriber fields</title>
+                </head>
+                <body>
+                {msg_html}
+                <p>People signing up fill these in next to their name and email.
+                Newsletter content can use each one as a variable, e.g. {{{{company}}}}.</p>
+                <table>
+                <tr><th>Label</th><th>Key</th><th>Kind</th><th>Options</th><th></th></tr>
+                {rows_html}
+                </table>
+                <h2>New field</h2>
+                <form action="/admin/fields" method="post">
+                {csrf_field}
+                <label>Label
+                <input type="text" placeholder="e.g. Company" name="label">
+                </label>
+                <br>
+                <label>Key
+                <input type="text" placeholder="e.g. company" name="key">
+                </label>
+                <br>
+                <label>Kind
+                <select name="kind">{kind_options}</select>
+                </label>
+                <br>
+                <label>Options, one per line
+                <textarea name="options" rows="4" cols="30"></textarea>
+                </label>
+                <br>
+                <label><input type="checkbox" name="is_required" value="true"> Required</label>
+                <br>
+                <button type="submit">Create field</button>
+                </form>
+                <p><a href="/admin/dashboard">&lt;- Back</a></p>
+                </body>
+                </html>"#,
+    )))
+}
@@ -0,0 +1,5 @@
+mod get;
+mod post;
+
+pub use get::custom_fields_form;
+pub use post::create_custom_field;
@@ -0,0 +1,91 @@
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use sqlx::PgPool;
+use uuid::Uuid;
+use crate::domain::{CustomField, CustomFieldKind};
+use crate::utils::{e500, see_other};
+
+#[derive(serde::Deserialize)]
+pub struct FormData {
+    key: String,
+    label: String,
+    kind: String,
+    #[serde(default)]
+    options: String,
+    #[serde(default)]
+    is_required: bool,
+}
+
+impl FormData {
+    fn parse(self) -> Result<CustomField, String> {
+        let key = CustomField::parse_key(&self.key)?;
+        let label = self.label.trim().to_string();
+        if label.is_empty() {
+            return Err("Fields need a label.".into());
+        }
+        let kind = CustomFieldKind::try_from(self.kind)?;
+        let options: Vec<String> = self
+            .options
+            .lines()
+            .map(str::trim)
+            .filter(|option| !option.is_empty())
+            .map(String::from)
+            .collect();
+        match kind {
+            CustomFieldKind::Select if options.is_empty() => {
+                return Err("Select fields need at least one option.".into());
+            }
+            CustomFieldKind::Select => {}
+            _ if !options.is_empty() => {
+                return Err("Only select fields have options.".into());
+            }
+            _ => {}
+        }
+        Ok(CustomField {
+            field_id: Uuid::new_v4(),
+            key,
+            label,
+            kind,
+            options,
+            is_required: self.is_required,
+        })
+    }
+}
+
+#[tracing::instrument(name = "Create a custom field", skip(form, pool))]
+pub async fn create_custom_field(
+    form: web::Form<FormData>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let field = match form.0.parse() {
+        Ok(field) => field,
+        Err(e) => {
+            FlashMessage::error(e).send();
+            return Ok(see_other("/admin/fields"));
+        }
+    };
+    let result = sqlx::query!(
+        r#"
+        INSERT INTO custom_fields (field_id, key, label, kind, options, is_required, created_at)
+        VALUES ($1, $2, $3, $4, $5, $6, now())
+        ON CONFLICT (key) DO NOTHING
+        "#,
+        field.field_id,
+        field.key,
+        field.label,
+        field.kind.as_str(),
+        &field.options,
+        field.is_required,
+    )
+        .execute(pool.get_ref())
+        .await
+        .context("Failed to store the custom field.")
+        .map_err(e500)?;
+    if result.rows_affected() == 0 {
+        FlashMessage::error("A field with this key already exists.").send();
+    } else {
+        FlashMessage::info("The field has been created.").send();
+    }
+    Ok(see_other("/admin/fields"))
+}
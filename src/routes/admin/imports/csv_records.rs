@@ -0,0 +1,176 @@
+use csv_core::{ReadRecordResult, Reader};
+use std::str::Utf8Error;
+
+pub(crate) type CsvRecord = Result<Vec<String>, Utf8Error>;
+
+// Past these, the upload is refused rather than buffered: a quote that is
+// never closed would otherwise make the rest of the file a single record
+const MAX_RECORD_BYTES: usize = 64 * 1024;
+const MAX_RECORD_FIELDS: usize = 1024;
+
+#[derive(thiserror::Error, Debug)]
+#[error(
+    "A row of the file is too long: rows can have up to {} bytes and {} fields.",
+    MAX_RECORD_BYTES,
+    MAX_RECORD_FIELDS
+)]
+pub(crate) struct RecordTooLong;
+
+// Splits a CSV document that arrives in arbitrary chunks into records,
+// so an upload can be processed without holding all of it in memory.
+pub(crate) struct CsvRecords {
+    reader: Reader,
+    // The record being read: its fields back to back, and where each ends
+    fields: Vec<u8>,
+    ends: Vec<usize>,
+    n_bytes: usize,
+    n_fields: usize,
+}
+
+impl CsvRecords {
+    pub fn new() -> Self {
+        Self {
+            reader: Reader::new(),
+            fields: vec![0; 1024],
+            ends: vec![0; 16],
+            n_bytes: 0,
+            n_fields: 0,
+        }
+    }
+
+    // The records completed by `chunk`. What is left of an unfinished record
+    // is kept for the next chunk.
+    pub fn feed(&mut self, chunk: &[u8]) -> Result<Vec<CsvRecord>, RecordTooLong> {
+        let mut records = Vec::new();
+        // An empty input is how the reader is told the document has ended
+        if !chunk.is_empty() {
+            self.read(chunk, &mut records)?;
+        }
+        Ok(records)
+    }
+
+    // The last record, when the document does not end with a line break
+    pub fn finish(&mut self) -> Result<Vec<CsvRecord>, RecordTooLong> {
+        let mut records = Vec::new();
+        self.read(&[], &mut records)?;
+        Ok(records)
+    }
+
+    fn read(&mut self, mut input: &[u8], records: &mut Vec<CsvRecord>) -> Result<(), RecordTooLong> {
+        loop {
+            let (result, n_in, n_out, n_ends) = self.reader.read_record(
+                input,
+                &mut self.fields[self.n_bytes..],
+                &mut self.ends[self.n_fields..],
+            );
+            input = &input[n_in..];
+            self.n_bytes += n_out;
+            self.n_fields += n_ends;
+            match result {
+                ReadRecordResult::InputEmpty | ReadRecordResult::End => return Ok(()),
+                ReadRecordResult::OutputFull => {
+                    let n = self.fields.len();
+                    if n >= MAX_RECORD_BYTES {
+                        return Err(RecordTooLong);
+                    }
+                    self.fields.resize(n * 2, 0);
+                }
+                ReadRecordResult::OutputEndsFull => {
+                    let n = self.ends.len();
+                    if n >= MAX_RECORD_FIELDS {
+                        return Err(RecordTooLong);
+                    }
+                    self.ends.resize(n * 2, 0);
+                }
+                ReadRecordResult::Record => records.push(self.take_record()),
+            }
+        }
+    }
+
+    fn take_record(&mut self) -> CsvRecord {
+        let mut start = 0;
+        let record = self.ends[..self.n_fields]
+            .iter()
+            .map(|&end| {
+                let field = std::str::from_utf8(&self.fields[start..end]).map(str::to_owned);
+                start = end;
+                field
+            })
+            .collect();
+        self.n_bytes = 0;
+        self.n_fields = 0;
+        record
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::CsvRecords;
+    use claims::{assert_err, assert_ok};
+
+    fn fields(record: &[&str]) -> Vec<String> {
+        record.iter().map(|field| field.to_string()).collect()
+    }
+
+    #[test]
+    fn records_split_across_chunks_are_reassembled() {
+        let mut records = CsvRecords::new();
+        assert!(records.feed(b"email,na").unwrap().is_empty());
+        let complete = records.feed(b"me\nursula@example.com,Ursula\noctavia@exa").unwrap();
+        let last = records.feed(b"mple.com,Octavia\n").unwrap();
+        assert!(records.finish().unwrap().is_empty());
+
+        let complete: Vec<_> = complete.into_iter().map(Result::unwrap).collect();
+        assert_eq!(
+            complete,
+            vec![fields(&["email", "name"]), fields(&["ursula@example.com", "Ursula"])]
+        );
+        assert_eq!(last.into_iter().next().unwrap().unwrap(), fields(&["octavia@example.com", "Octavia"]));
+    }
+
+    #[test]
+    fn the_last_record_does_not_need_a_line_break() {
+        let mut records = CsvRecords::new();
+        assert!(records.feed(b"email,name\r\nursula@example.com,Ursula").unwrap().len() == 1);
+        let last = records.finish().unwrap();
+        assert_eq!(last.into_iter().next().unwrap().unwrap(), fields(&["ursula@example.com", "Ursula"]));
+    }
+
+    #[test]
+    fn quoted_fields_can_hold_commas_and_line_breaks() {
+        let mut records = CsvRecords::new();
+        let record = records
+            .feed(b"\"le guin, ursula\",\"two\nlines\"\n")
+            .unwrap()
+            .into_iter()
+            .next()
+            .unwrap()
+            .unwrap();
+        assert_eq!(record, fields(&["le guin, ursula", "two\nlines"]));
+    }
+
+    #[test]
+    fn long_records_are_read_in_full() {
+        let mut records = CsvRecords::new();
+        let long_name = "a".repeat(5000);
+        let line = format!("{},{}\n", long_name, ",".repeat(40));
+        let record = records.feed(line.as_bytes()).unwrap().into_iter().next().unwrap().unwrap();
+        assert_eq!(record[0], long_name);
+        assert_eq!(record.len(), 42);
+    }
+
+    #[test]
+    fn an_unterminated_quote_is_not_buffered_without_limit() {
+        let mut records = CsvRecords::new();
+        assert_ok!(records.feed(b"email,name\n\"ursula@example.com,Ursula\n"));
+        let rest_of_the_file = "octavia@example.com,Octavia\n".repeat(5000);
+        assert_err!(records.feed(rest_of_the_file.as_bytes()));
+    }
+
+    #[test]
+    fn records_that_are_not_utf8_are_errors() {
+        let mut records = CsvRecords::new();
+        let record = records.feed(b"\xff\xfe,name\n").unwrap().into_iter().next().unwrap();
+        assert_err!(record);
+    }
+}
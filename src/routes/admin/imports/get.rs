@@ -0,0 +1,272 @@
+use actix_web::http::header::{ContentDisposition, ContentType, DispositionParam, DispositionType};
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use std::fmt::Write;
+use uuid::Uuid;
+use crate::authentication::csrf_field;
+use crate::mailing_lists::get_lists;
+use crate::session_state::TypedSession;
+use crate::utils::e500;
+
+const TIME_FORMAT: &str = "%Y-%m-%d %H:%M:%S UTC";
+// Report rows fetched per query while the download streams
+const REPORT_BATCH_SIZE: i64 = 1000;
+
+struct ImportSummary {
+    import_id: Uuid,
+    created_at: DateTime<Utc>,
+    created_by: Option<String>,
+    file_name: String,
+    mode: String,
+    consent_attestation: Option<String>,
+    finished_at: Option<DateTime<Utc>>,
+    n_accepted: i64,
+    n_skipped: i64,
+    n_invalid: i64,
+}
+
+// Most recent first
+#[tracing::instrument(name = "Get subscriber imports", skip(pool))]
+async fn get_imports(
+    pool: &PgPool,
+    import_id: Option<Uuid>,
+) -> Result<Vec<ImportSummary>, anyhow::Error> {
+    let imports = sqlx::query_as!(
+        ImportSummary,
+        r#"
+        SELECT i.import_id, i.created_at, u.username AS "created_by?", i.file_name, i.mode,
+            i.consent_attestation, i.finished_at,
+            COUNT(*) FILTER (WHERE r.outcome = 'accepted') AS "n_accepted!",
+            COUNT(*) FILTER (WHERE r.outcome = 'skipped') AS "n_skipped!",
+            COUNT(*) FILTER (WHERE r.outcome = 'invalid') AS "n_invalid!"
+        FROM subscriber_imports i
+        LEFT JOIN users u ON u.user_id = i.created_by
+        LEFT JOIN subscriber_import_rows r ON r.import_id = i.import_id
+        WHERE $1::UUID IS NULL OR i.import_id = $1
+        GROUP BY i.import_id, u.username
+        ORDER BY i.created_at DESC
+        LIMIT 50
+        "#,
+        import_id,
+    )
+        .fetch_all(pool)
+        .await
+        .context("Failed to retrieve subscriber imports.")?;
+    Ok(imports)
+}
+
+fn page(title: &str, flash_messages: IncomingFlashMessages, body: &str) -> HttpResponse {
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", htmlescape::encode_minimal(m.content())).unwrap();
+    }
+    HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+                r#"<!DOCTYPE html>
+                <html lang="en">
+                <head>
+                <meta http-equiv="content-type" content="text/html; charset=utf-8">
+                <title>{title}</title>
+                </head>
+                <body>
+                {msg_html}
+                {body}
+                </body>
+                </html>"#,
+        ))
+}
+
+pub async fn imports_form(
+    flash_messages: IncomingFlashMessages,
+    pool: web::Data<PgPool>,
+    session: TypedSession,
+) -> Result<HttpResponse, actix_web::Error> {
+    // Multipart bodies are left to the handler, so the token must be the
+    // first field: the CSRF check only reads that far
+    let csrf_field = csrf_field(&session.csrf_token().map_err(e500)?);
+    let mut rows_html = String::new();
+    for import in get_imports(&pool, None).await.map_err(e500)? {
+        writeln!(
+            rows_html,
+            r#"<tr><td><a href="/admin/subscribers/imports/{}">{}</a></td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>"#,
+            import.import_id,
+            import.created_at.format(TIME_FORMAT),
+            htmlescape::encode_minimal(&import.file_name),
+            import.mode,
+            import.n_accepted,
+            import.n_skipped,
+            import.n_invalid,
+        )
+        .unwrap();
+    }
+    let mut list_options = String::new();
+    for list in get_lists(&pool).await.map_err(e500)? {
+        write!(
+            list_options,
+            r#"<option value="{}">{}</option>"#,
+            htmlescape::encode_minimal(&list.slug),
+            htmlescape::encode_minimal(&list.name),
+        )
+        .unwrap();
+    }
+    let body = format!(
+        r#"<p>The file must be a CSV whose first row names an <code>email</code> and a <code>name</code> column. Addresses that are already on the list, or that have unsubscribed, are skipped.</p>
+                <form action="/admin/subscribers/imports" method="post" enctype="multipart/form-data">
+                {csrf_field}
+                <label><input type="radio" name="mode" value="send_confirmation" checked> Send each contact a confirmation email</label>
+                <br>
+                <label><input type="radio" name="mode" value="confirmed"> Import as confirmed</label>
+                <br>
+                <label>If imported as confirmed, how did these contacts consent to receive the newsletter?<br>
+                <textarea name="consent_attestation" rows="3" cols="60"></textarea>
+                </label>
+                <br>
+                <label>List:<br>
+                <select name="list">{list_options}</select>
+                </label>
+                <br>
+                <label>File:<br>
+                <input type="file" name="file" accept=".csv,text/csv">
+                </label>
+                <br>
+                <button type="submit">Import</button>
+                </form>
+                <h3>Previous imports</h3>
+                <table>
+                <tr><th>Started at</th><th>File</th><th>Mode</th><th>Accepted</th><th>Skipped</th><th>Invalid</th></tr>
+                {rows_html}
+                </table>
+                <p><a href="/admin/subscribers">&lt;- Back</a></p>"#,
+    );
+    Ok(page("Import subscribers", flash_messages, &body))
+}
+
+pub async fn import_details(
+    import_id: web::Path<Uuid>,
+    flash_messages: IncomingFlashMessages,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let import_id = import_id.into_inner();
+    let import = match get_imports(&pool, Some(import_id)).await.map_err(e500)?.pop() {
+        Some(import) => import,
+        None => return Ok(HttpResponse::NotFound().finish()),
+    };
+    let body = format!(
+        r#"<h2>{file_name}</h2>
+                <p>Started at {created_at} by {created_by}.</p>
+                <p>{status}</p>
+                <p>Mode: {mode}</p>
+                <p>Consent: {consent_attestation}</p>
+                <table>
+                <tr><th>Accepted</th><th>Skipped</th><th>Invalid</th></tr>
+                <tr><td>{n_accepted}</td><td>{n_skipped}</td><td>{n_invalid}</td></tr>
+                </table>
+                <p><a href="/admin/subscribers/imports/{import_id}/report">Download the report of every row (CSV)</a></p>
+                <p><a href="/admin/subscribers/imports">&lt;- Back</a></p>"#,
+        file_name = htmlescape::encode_minimal(&import.file_name),
+        created_at = import.created_at.format(TIME_FORMAT),
+        created_by = htmlescape::encode_minimal(import.created_by.as_deref().unwrap_or("a removed user")),
+        status = match import.finished_at {
+            Some(finished_at) => format!("Finished at {}.", finished_at.format(TIME_FORMAT)),
+            None => "Not finished: the upload stopped before the end of the file.".into(),
+        },
+        mode = import.mode,
+        consent_attestation = htmlescape::encode_minimal(
+            import.consent_attestation.as_deref().unwrap_or("collected by confirmation email")
+        ),
+        n_accepted = import.n_accepted,
+        n_skipped = import.n_skipped,
+        n_invalid = import.n_invalid,
+    );
+    Ok(page("Subscriber import", flash_messages, &body))
+}
+
+struct ReportRow {
+    row_number: i32,
+    email: String,
+    name: String,
+    outcome: String,
+    detail: String,
+}
+
+// A batch at a time, so that large reports are never held in memory
+async fn get_report_rows(
+    pool: &PgPool,
+    import_id: Uuid,
+    after_row: i32,
+) -> Result<Vec<ReportRow>, sqlx::Error> {
+    sqlx::query_as!(
+        ReportRow,
+        r#"
+        SELECT row_number, email, name, outcome, detail
+        FROM subscriber_import_rows
+        WHERE import_id = $1 AND row_number > $2
+        ORDER BY row_number
+        LIMIT $3
+        "#,
+        import_id,
+        after_row,
+        REPORT_BATCH_SIZE,
+    )
+        .fetch_all(pool)
+        .await
+}
+
+fn write_report_rows(rows: &[ReportRow], with_header: bool) -> Result<web::Bytes, anyhow::Error> {
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    if with_header {
+        writer.write_record(["row", "email", "name", "outcome", "detail"])?;
+    }
+    for row in rows {
+        writer.write_record([
+            &row.row_number.to_string(),
+            &row.email,
+            &row.name,
+            &row.outcome,
+            &row.detail,
+        ])?;
+    }
+    Ok(writer.into_inner()?.into())
+}
+
+#[tracing::instrument(name = "Download an import report", skip(pool))]
+pub async fn import_report(
+    import_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let import_id = import_id.into_inner();
+    if get_imports(&pool, Some(import_id)).await.map_err(e500)?.is_empty() {
+        return Ok(HttpResponse::NotFound().finish());
+    }
+    let pool = pool.into_inner();
+    // The last row sent, or `None` once the report is complete
+    let report = futures_util::stream::try_unfold(Some(0), move |after_row| {
+        let pool = pool.clone();
+        async move {
+            let Some(after_row) = after_row else {
+                return Ok(None);
+            };
+            let rows = get_report_rows(&pool, import_id, after_row).await?;
+            let next = match rows.last() {
+                Some(last) if rows.len() as i64 == REPORT_BATCH_SIZE => Some(last.row_number),
+                _ => None,
+            };
+            let chunk = write_report_rows(&rows, after_row == 0)?;
+            Ok::<_, anyhow::Error>(Some((chunk, next)))
+        }
+    });
+    Ok(HttpResponse::Ok()
+        .content_type("text/csv; charset=utf-8")
+        .insert_header(ContentDisposition {
+            disposition: DispositionType::Attachment,
+            parameters: vec![DispositionParam::Filename(format!(
+                "import-{}.csv",
+                import_id
+            ))],
+        })
+        .streaming(report))
+}
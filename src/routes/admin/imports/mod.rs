@@ -0,0 +1,54 @@
+mod csv_records;
+mod get;
+mod post;
+
+pub use get::{import_details, import_report, imports_form};
+pub use post::import_subscribers;
+
+// What happens to the subscribers an import adds
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImportMode {
+    // The admin attests that the contacts have already consented
+    Confirmed,
+    SendConfirmation,
+}
+
+impl ImportMode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ImportMode::Confirmed => "confirmed",
+            ImportMode::SendConfirmation => "send_confirmation",
+        }
+    }
+}
+
+impl TryFrom<String> for ImportMode {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        match s.as_str() {
+            "confirmed" => Ok(ImportMode::Confirmed),
+            "send_confirmation" => Ok(ImportMode::SendConfirmation),
+            other => Err(format!("{} is not a known import mode.", other)),
+        }
+    }
+}
+
+// What happened to a single row, kept in `subscriber_import_rows`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RowOutcome {
+    Accepted,
+    // Valid, but already in the subscriber list
+    Skipped,
+    Invalid,
+}
+
+impl RowOutcome {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RowOutcome::Accepted => "accepted",
+            RowOutcome::Skipped => "skipped",
+            RowOutcome::Invalid => "invalid",
+        }
+    }
+}
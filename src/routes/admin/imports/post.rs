@@ -0,0 +1,432 @@
+use actix_multipart::{Field, Multipart};
+use actix_web::{web, HttpRequest, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use futures_util::TryStreamExt;
+use sqlx::PgPool;
+use uuid::Uuid;
+use super::csv_records::{CsvRecord, CsvRecords};
+use super::{ImportMode, RowOutcome};
+use crate::audit::{record_audit_event, AuditAction, NewAuditEvent};
+use crate::authentication::UserId;
+use crate::domain::{
+    EmailPolicy, NewSubscriber, SubscriberEmail, SubscriberName, SubscriptionStatus,
+};
+use crate::mailing_lists::{get_list_by_slug, join_list, DEFAULT_LIST_SLUG};
+use crate::issue_delivery_worker::enqueue_confirmation_email;
+use crate::routes::{generate_subscription_token, store_token};
+use crate::suppression_list::{is_email_suppressed, SuppressionKeys};
+use crate::utils::{e400, e500, see_other};
+
+// For the form fields that come before the file
+const MAX_OPTION_LENGTH: usize = 1024;
+
+#[derive(Default)]
+struct ImportOptions {
+    mode: Option<String>,
+    consent_attestation: String,
+    list: String,
+}
+
+#[derive(thiserror::Error, Debug)]
+enum ImportError {
+    // Shown to the admin as is
+    #[error("{0}")]
+    InvalidFile(String),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+// The file is imported while it is uploaded: each record is validated and
+// stored as soon as it has arrived. The browser sends the form fields in
+// page order, so the options come before the file.
+#[allow(clippy::too_many_arguments)]
+#[tracing::instrument(
+    name = "Import subscribers",
+    skip(form, pool, email_policy, suppression_keys, user_id, request),
+    fields(user_id=%*user_id)
+)]
+pub async fn import_subscribers(
+    mut form: Multipart,
+    pool: web::Data<PgPool>,
+    email_policy: web::Data<EmailPolicy>,
+    suppression_keys: web::Data<SuppressionKeys>,
+    user_id: web::ReqData<UserId>,
+    request: HttpRequest,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut options = ImportOptions::default();
+    while let Some(mut field) = form.try_next().await? {
+        match field.name() {
+            "mode" => options.mode = Some(read_option(&mut field).await?),
+            "consent_attestation" => options.consent_attestation = read_option(&mut field).await?,
+            "list" => options.list = read_option(&mut field).await?,
+            "file" => {
+                let mode = match check_options(&options) {
+                    Ok(mode) => mode,
+                    Err(e) => {
+                        discard_rest_of_file(&mut field).await;
+                        FlashMessage::error(e).send();
+                        return Ok(see_other("/admin/subscribers/imports"));
+                    }
+                };
+                let list_slug = match options.list.as_str() {
+                    "" => DEFAULT_LIST_SLUG,
+                    slug => slug,
+                };
+                let Some(list) = get_list_by_slug(pool.get_ref(), list_slug).await.map_err(e500)? else {
+                    discard_rest_of_file(&mut field).await;
+                    FlashMessage::error(format!("{} is not a known list.", list_slug)).send();
+                    return Ok(see_other("/admin/subscribers/imports"));
+                };
+                let file_name = field
+                    .content_disposition()
+                    .get_filename()
+                    .unwrap_or_default()
+                    .to_owned();
+                let mut importer = Importer {
+                    pool: &pool,
+                    email_policy: &email_policy,
+                    suppression_keys: &suppression_keys,
+                    user_id: **user_id,
+                    mode,
+                    list_id: list.list_id,
+                    consent_attestation: options.consent_attestation.trim(),
+                    file_name: &file_name,
+                    import: None,
+                    records: CsvRecords::new(),
+                    counts: RowCounts::default(),
+                };
+                return match importer.import(&mut field).await {
+                    Ok(import_id) => {
+                        let event = NewAuditEvent::new(
+                            Some(**user_id),
+                            AuditAction::SubscribersImported,
+                            &request,
+                        )
+                        .target(import_id);
+                        record_audit_event(pool.get_ref(), event).await.map_err(e500)?;
+                        let counts = &importer.counts;
+                        FlashMessage::info(format!(
+                            "The import has finished: {} row(s) accepted, {} skipped and {} invalid.",
+                            counts.accepted, counts.skipped, counts.invalid
+                        ))
+                        .send();
+                        Ok(see_other(&format!("/admin/subscribers/imports/{}", import_id)))
+                    }
+                    Err(ImportError::InvalidFile(e)) => {
+                        discard_rest_of_file(&mut field).await;
+                        FlashMessage::error(e).send();
+                        Ok(see_other("/admin/subscribers/imports"))
+                    }
+                    Err(ImportError::UnexpectedError(e)) => Err(e500(e)),
+                };
+            }
+            _ => {}
+        }
+    }
+    FlashMessage::error("Please choose a CSV file to import.").send();
+    Ok(see_other("/admin/subscribers/imports"))
+}
+
+// Reads what is left of the file without keeping any of it: a response
+// sent before the upload has been read can reach the browser as a reset
+// connection instead of the error. The file is the last field of the form.
+async fn discard_rest_of_file(file: &mut Field) {
+    while let Ok(Some(_)) = file.try_next().await {}
+}
+
+async fn read_option(field: &mut Field) -> Result<String, actix_web::Error> {
+    let mut value = Vec::new();
+    while let Some(chunk) = field.try_next().await? {
+        if value.len() + chunk.len() > MAX_OPTION_LENGTH {
+            return Err(e400(format!("The {} field is too long.", field.name())));
+        }
+        value.extend_from_slice(&chunk);
+    }
+    String::from_utf8(value).map_err(e400)
+}
+
+fn check_options(options: &ImportOptions) -> Result<ImportMode, String> {
+    let mode = match &options.mode {
+        Some(mode) => ImportMode::try_from(mode.clone())?,
+        None => {
+            return Err("Please choose whether imported subscribers are confirmed \
+                or sent a confirmation email."
+                .into())
+        }
+    };
+    if mode == ImportMode::Confirmed && options.consent_attestation.trim().is_empty() {
+        return Err("Please describe how the imported contacts gave their consent.".into());
+    }
+    Ok(mode)
+}
+
+// Where the `email` and `name` columns are, according to the header
+struct Columns {
+    email: usize,
+    name: usize,
+}
+
+struct Import {
+    import_id: Uuid,
+    columns: Columns,
+    n_rows: i32,
+}
+
+struct Importer<'a> {
+    pool: &'a PgPool,
+    email_policy: &'a EmailPolicy,
+    suppression_keys: &'a SuppressionKeys,
+    user_id: Uuid,
+    mode: ImportMode,
+    // Everyone imported joins this list
+    list_id: Uuid,
+    consent_attestation: &'a str,
+    file_name: &'a str,
+    // Started once the header has been read
+    import: Option<Import>,
+    records: CsvRecords,
+    counts: RowCounts,
+}
+
+#[derive(Default)]
+struct RowCounts {
+    accepted: u32,
+    skipped: u32,
+    invalid: u32,
+}
+
+impl Importer<'_> {
+    async fn import(&mut self, file: &mut Field) -> Result<Uuid, ImportError> {
+        while let Some(chunk) = file
+            .try_next()
+            .await
+            .map_err(|_| ImportError::InvalidFile("The upload was interrupted.".into()))?
+        {
+            let records = self
+                .records
+                .feed(&chunk)
+                .map_err(|e| ImportError::InvalidFile(e.to_string()))?;
+            for record in records {
+                self.import_record(record).await?;
+            }
+        }
+        let records = self
+            .records
+            .finish()
+            .map_err(|e| ImportError::InvalidFile(e.to_string()))?;
+        for record in records {
+            self.import_record(record).await?;
+        }
+        let import_id = match &self.import {
+            Some(import) => import.import_id,
+            None => return Err(ImportError::InvalidFile("The file is empty.".into())),
+        };
+        sqlx::query!(
+            r#"UPDATE subscriber_imports SET finished_at = now() WHERE import_id = $1"#,
+            import_id,
+        )
+            .execute(self.pool)
+            .await
+            .context("Failed to mark the import as finished.")?;
+        Ok(import_id)
+    }
+
+    async fn import_record(&mut self, record: CsvRecord) -> Result<(), ImportError> {
+        let Some(import) = &mut self.import else {
+            let columns = find_columns(record)?;
+            self.import = Some(self.start_import(columns).await?);
+            return Ok(());
+        };
+        import.n_rows += 1;
+        let row_number = import.n_rows;
+        let import_id = import.import_id;
+        let (email, name) = match &record {
+            Ok(fields) => (
+                fields.get(import.columns.email).cloned().unwrap_or_default(),
+                fields.get(import.columns.name).cloned().unwrap_or_default(),
+            ),
+            Err(_) => (String::new(), String::new()),
+        };
+        let (outcome, detail) = match record {
+            Err(_) => (RowOutcome::Invalid, "The row is not valid UTF-8.".to_string()),
+            Ok(_) => match parse_subscriber(email.clone(), name.clone(), self.email_policy) {
+                Err(e) => (RowOutcome::Invalid, e),
+                Ok(new_subscriber) => self.add_subscriber(new_subscriber).await?,
+            },
+        };
+        sqlx::query!(
+            r#"
+            INSERT INTO subscriber_import_rows (import_id, row_number, email, name, outcome, detail)
+            VALUES ($1, $2, $3, $4, $5, $6)
+            "#,
+            import_id,
+            row_number,
+            email,
+            name,
+            outcome.as_str(),
+            detail,
+        )
+            .execute(self.pool)
+            .await
+            .context("Failed to store the outcome of an imported row.")?;
+        match outcome {
+            RowOutcome::Accepted => self.counts.accepted += 1,
+            RowOutcome::Skipped => self.counts.skipped += 1,
+            RowOutcome::Invalid => self.counts.invalid += 1,
+        }
+        Ok(())
+    }
+
+    async fn start_import(&self, columns: Columns) -> Result<Import, anyhow::Error> {
+        let import_id = Uuid::new_v4();
+        let consent_attestation =
+            (self.mode == ImportMode::Confirmed).then_some(self.consent_attestation);
+        sqlx::query!(
+            r#"
+            INSERT INTO subscriber_imports
+                (import_id, created_by, created_at, file_name, mode, consent_attestation)
+            VALUES ($1, $2, now(), $3, $4, $5)
+            "#,
+            import_id,
+            self.user_id,
+            self.file_name,
+            self.mode.as_str(),
+            consent_attestation,
+        )
+            .execute(self.pool)
+            .await
+            .context("Failed to start an import.")?;
+        Ok(Import { import_id, columns, n_rows: 0 })
+    }
+
+    // Addresses we already have join the list, unless they are on it
+    // already, whatever their status there. An import must not resubscribe
+    // people who have left, so unsubscribed and suppressed subscribers are
+    // skipped, and so are the addresses whose owners had their data erased.
+    async fn add_subscriber(
+        &self,
+        new_subscriber: NewSubscriber,
+    ) -> Result<(RowOutcome, String), anyhow::Error> {
+        if is_email_suppressed(self.pool, new_subscriber.email.as_ref(), self.suppression_keys).await? {
+            return Ok((RowOutcome::Skipped, "This address is on the suppression list.".into()));
+        }
+        let status = match self.mode {
+            ImportMode::Confirmed => SubscriptionStatus::Confirmed,
+            ImportMode::SendConfirmation => SubscriptionStatus::PendingConfirmation,
+        };
+        let mut transaction = self.pool.begin().await.context("Failed to acquire a Postgres connection from the pool")?;
+        let existing = sqlx::query!(
+            r#"
+            SELECT
+                id,
+                status,
+                EXISTS (
+                    SELECT 1 FROM list_memberships WHERE list_id = $2 AND subscriber_id = id
+                ) AS "is_member!"
+            FROM subscriptions
+            WHERE email = $1
+            FOR UPDATE
+            "#,
+            new_subscriber.email.as_ref(),
+            self.list_id,
+        )
+            .fetch_optional(&mut *transaction)
+            .await
+            .context("Failed to look up an imported subscriber.")?;
+        let subscriber_id = match existing {
+            Some(s) if s.is_member => {
+                return Ok((RowOutcome::Skipped, "This email address is already on the list.".into()));
+            }
+            Some(s)
+                if s.status == SubscriptionStatus::Unsubscribed.as_str()
+                    || s.status == SubscriptionStatus::Suppressed.as_str() =>
+            {
+                return Ok((RowOutcome::Skipped, format!("This subscriber is {}.", s.status)));
+            }
+            Some(s) => {
+                if status == SubscriptionStatus::Confirmed {
+                    sqlx::query!(
+                        r#"
+                        UPDATE subscriptions
+                        SET status = 'confirmed', confirmed_at = COALESCE(confirmed_at, now())
+                        WHERE id = $1
+                        "#,
+                        s.id,
+                    )
+                        .execute(&mut *transaction)
+                        .await
+                        .context("Failed to confirm an imported subscriber.")?;
+                }
+                s.id
+            }
+            None => {
+                let subscriber_id = sqlx::query!(
+                    r#"
+                    INSERT INTO subscriptions (id, email, name, subscribed_at, status, confirmed_at)
+                    VALUES ($1, $2, $3, now(), $4, CASE WHEN $4 = 'confirmed' THEN now() END)
+                    ON CONFLICT (email) DO NOTHING
+                    RETURNING id
+                    "#,
+                    Uuid::new_v4(),
+                    new_subscriber.email.as_ref(),
+                    new_subscriber.name.as_ref(),
+                    status.as_str(),
+                )
+                    .fetch_optional(&mut *transaction)
+                    .await
+                    .context("Failed to insert an imported subscriber.")?
+                    .map(|r| r.id);
+                // Signed up while the row was being imported
+                let Some(subscriber_id) = subscriber_id else {
+                    return Ok((RowOutcome::Skipped, "This email address is already subscribed.".into()));
+                };
+                subscriber_id
+            }
+        };
+        join_list(&mut transaction, self.list_id, subscriber_id, status).await?;
+        // Confirmed subscribers need a token too: it is what their
+        // unsubscribe and preferences links carry
+        let subscription_token = generate_subscription_token();
+        store_token(&mut transaction, subscriber_id, self.list_id, &subscription_token)
+            .await
+            .context("Failed to store the token for an imported subscriber.")?;
+        if self.mode == ImportMode::Confirmed {
+            transaction.commit().await.context("Failed to commit SQL transaction to import a subscriber.")?;
+            return Ok((RowOutcome::Accepted, "Imported as confirmed.".into()));
+        }
+        enqueue_confirmation_email(&mut transaction, &subscription_token)
+            .await
+            .context("Failed to queue the confirmation email for an imported subscriber.")?;
+        transaction.commit().await.context("Failed to commit SQL transaction to import a subscriber.")?;
+        Ok((RowOutcome::Accepted, "A confirmation email will be sent.".into()))
+    }
+}
+
+fn find_columns(header: CsvRecord) -> Result<Columns, ImportError> {
+    let header = header
+        .map_err(|_| ImportError::InvalidFile("The file is not valid UTF-8.".into()))?;
+    let position = |column: &str| {
+        header
+            .iter()
+            .position(|field| field.trim().eq_ignore_ascii_case(column))
+    };
+    match (position("email"), position("name")) {
+        (Some(email), Some(name)) => Ok(Columns { email, name }),
+        _ => Err(ImportError::InvalidFile(
+            "The first row of the file must name an email and a name column.".into(),
+        )),
+    }
+}
+
+// The same rules as for signing up
+fn parse_subscriber(
+    email: String,
+    name: String,
+    email_policy: &EmailPolicy,
+) -> Result<NewSubscriber, String> {
+    let email = SubscriberEmail::parse(email)?;
+    email_policy.check(&email)?;
+    let name = SubscriberName::parse(name)?;
+    Ok(NewSubscriber { email, name })
+}
@@ -0,0 +1,63 @@
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use sqlx::PgPool;
+use std::fmt::Write;
+use crate::authentication::csrf_field;
+use crate::mailing_lists::get_lists;
+use crate::session_state::TypedSession;
+use crate::utils::e500;
+
+pub async fn mailing_lists_form(
+    flash_messages: IncomingFlashMessages,
+    pool: web::Data<PgPool>,
+    session: TypedSession,
+) -> Result<HttpResponse, actix_web::Error> {
+    let csrf_field = csrf_field(&session.csrf_token().map_err(e500)?);
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", htmlescape::encode_minimal(m.content())).unwrap();
+    }
+    let mut rows_html = String::new();
+    for list in get_lists(&pool).await.map_err(e500)? {
+        writeln!(
+            rows_html,
+            "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+            htmlescape::encode_minimal(&list.name),
+            htmlescape::encode_minimal(&list.slug),
+            list.n_confirmed,
+            list.n_pending,
+        )
+        .unwrap();
+    }
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+                r#"<!DOCTYPE html>
+                <html lang="en">
+                <head>
+                <meta http-equiv="content-type" content="text/html; charset=utf-8">
+                <title>Mailing lists</title>
+                </head>
+                <body>
+                {msg_html}
+                <table>
+                <tr><th>Name</th><th>Identifier</th><th>Confirmed</th><th>Pending</th></tr>
+                {rows_html}
+                </table>
+                <h2>New list</h2>
+                <form action="/admin/lists" method="post">
+                {csrf_field}
+                <label>Name
+                <input type="text" placeholder="Enter the list name" name="name">
+                </label>
+                <label>Identifier
+                <input type="text" placeholder="e.g. product-updates" name="slug">
+                </label>
+                <button type="submit">Create list</button>
+                </form>
+                <p><a href="/admin/dashboard">&lt;- Back</a></p>
+                </body>
+                </html>"#,
+    )))
+}
@@ -0,0 +1,5 @@
+mod get;
+mod post;
+
+pub use get::mailing_lists_form;
+pub use post::create_mailing_list;
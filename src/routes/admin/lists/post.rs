@@ -0,0 +1,70 @@
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use sqlx::PgPool;
+use uuid::Uuid;
+use crate::utils::{e500, see_other};
+
+#[derive(serde::Deserialize)]
+pub struct FormData {
+    name: String,
+    slug: String,
+}
+
+impl FormData {
+    fn parse(self) -> Result<(String, String), String> {
+        let name = self.name.trim().to_string();
+        if name.is_empty() {
+            return Err("Lists need a name.".into());
+        }
+        // The identifier ends up in sign-up forms, so it is kept URL-friendly
+        let slug = self.slug.trim().to_string();
+        let is_valid_slug = !slug.is_empty()
+            && slug.len() <= 64
+            && !slug.starts_with('-')
+            && !slug.ends_with('-')
+            && slug.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-');
+        if !is_valid_slug {
+            return Err(format!(
+                "{} is not a valid list identifier. \
+                Use lowercase letters, digits and dashes.",
+                slug,
+            ));
+        }
+        Ok((name, slug))
+    }
+}
+
+#[tracing::instrument(name = "Create a mailing list", skip(form, pool))]
+pub async fn create_mailing_list(
+    form: web::Form<FormData>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let (name, slug) = match form.0.parse() {
+        Ok(parsed) => parsed,
+        Err(e) => {
+            FlashMessage::error(e).send();
+            return Ok(see_other("/admin/lists"));
+        }
+    };
+    let result = sqlx::query!(
+        r#"
+        INSERT INTO lists (list_id, slug, name, created_at)
+        VALUES ($1, $2, $3, now())
+        ON CONFLICT (slug) DO NOTHING
+        "#,
+        Uuid::new_v4(),
+        slug,
+        name,
+    )
+        .execute(pool.get_ref())
+        .await
+        .context("Failed to store the mailing list.")
+        .map_err(e500)?;
+    if result.rows_affected() == 0 {
+        FlashMessage::error("A list with this identifier already exists.").send();
+    } else {
+        FlashMessage::info("The list has been created.").send();
+    }
+    Ok(see_other("/admin/lists"))
+}
@@ -1,16 +1,26 @@
+use crate::audit::{record_audit_event, AuditAction, NewAuditEvent};
+use crate::authentication::revoke_session;
 use crate::session_state::TypedSession;
 use crate::utils::{e500, see_other};
-use actix_web::HttpResponse;
+use actix_web::{web, HttpRequest, HttpResponse};
 use actix_web_flash_messages::FlashMessage;
+use sqlx::PgPool;
 
 pub async fn log_out(
     session: TypedSession,
+    pool: web::Data<PgPool>,
+    request: HttpRequest,
 ) -> Result<HttpResponse, actix_web::Error> {
-    if session.get_user_id().map_err(e500)?.is_none() {
-        Ok(see_other("/login"))
-    } else {
+    if let Some(user_id) = session.get_user_id().map_err(e500)? {
+        if let Some(session_id) = session.get_session_id().map_err(e500)? {
+            revoke_session(&pool, user_id, session_id).await.map_err(e500)?;
+        }
+        let event = NewAuditEvent::new(Some(user_id), AuditAction::Logout, &request);
+        record_audit_event(pool.get_ref(), event).await.map_err(e500)?;
         session.log_out();
         FlashMessage::info("You have successfully logged out.").send();
         Ok(see_other("/login"))
+    } else {
+        Ok(see_other("/login"))
     }
 }
@@ -1,9 +1,36 @@
+mod audit;
 mod dashboard;
+mod fields;
+mod imports;
+mod lists;
 mod password;
 mod logout;
 mod newsletter;
+mod sessions;
+mod subscribers;
+mod tags;
+mod tokens;
+mod templates;
+mod totp;
+mod users;
 
+pub use audit::*;
 pub use dashboard::admin_dashboard;
+pub use fields::*;
+pub use imports::{import_details, import_report, import_subscribers, imports_form};
+pub use lists::*;
+pub(crate) use dashboard::get_username;
 pub use password::*;
 pub use logout::log_out;
 pub use newsletter::*;
+pub use sessions::*;
+pub use subscribers::*;
+pub use tags::*;
+pub use tokens::*;
+pub use templates::*;
+pub use totp::*;
+pub use users::*;
+pub(crate) use newsletter::{
+    enqueue_delivery_tasks, insert_newsletter_issue, parse_topic, resolve_lists, resolve_segment,
+    prepare_newsletter_content, ContentError,
+};
@@ -1,16 +1,77 @@
 use actix_web::http::header::ContentType;
-use actix_web::HttpResponse;
+use actix_web::{web, HttpResponse};
 use actix_web_flash_messages::IncomingFlashMessages;
+use sqlx::PgPool;
 use std::fmt::Write;
+use crate::authentication::csrf_field;
+use crate::configuration::PreferenceSettings;
+use crate::custom_fields::get_field_keys;
+use crate::domain::{known_variables, Segment};
+use crate::mailing_lists::{get_lists, DEFAULT_LIST_SLUG};
+use crate::session_state::TypedSession;
+use crate::tags::get_tags;
+use crate::routes::admin::templates::get_newsletter_templates;
+use crate::utils::e500;
 
 pub async fn publish_newsletter_form(
     flash_messages: IncomingFlashMessages,
+    pool: web::Data<PgPool>,
+    preferences: web::Data<PreferenceSettings>,
+    session: TypedSession,
 ) -> Result<HttpResponse, actix_web::Error> {
     let mut msg_html = String::new();
     for m in flash_messages.iter() {
-        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+        writeln!(msg_html, "<p><i>{}</i></p>", htmlescape::encode_minimal(m.content())).unwrap();
     }
+    let mut template_options = String::from(r#"<option value="">No template</option>"#);
+    for template in get_newsletter_templates(&pool).await.map_err(e500)? {
+        write!(
+            template_options,
+            r#"<option value="{}">{}</option>"#,
+            template.template_id,
+            htmlescape::encode_minimal(&template.name),
+        )
+        .unwrap();
+    }
+    let mut topic_options = String::from(r#"<option value="">Everyone</option>"#);
+    for topic in &preferences.topics {
+        write!(
+            topic_options,
+            r#"<option value="{0}">{0}</option>"#,
+            htmlescape::encode_minimal(topic),
+        )
+        .unwrap();
+    }
+    let mut list_checkboxes = String::new();
+    for list in get_lists(&pool).await.map_err(e500)? {
+        writeln!(
+            list_checkboxes,
+            r#"<label><input type="checkbox" name="list_ids" value="{}"{}> {} ({} confirmed)</label><br>"#,
+            list.list_id,
+            if list.slug == DEFAULT_LIST_SLUG { " checked" } else { "" },
+            htmlescape::encode_minimal(&list.name),
+            list.n_confirmed,
+        )
+        .unwrap();
+    }
+    let mut tag_options = String::from(r#"<option value="">Any</option>"#);
+    for tag in get_tags(&pool).await.map_err(e500)? {
+        write!(
+            tag_options,
+            r#"<option value="{}">{}</option>"#,
+            tag.tag_id,
+            htmlescape::encode_minimal(&tag.name),
+        )
+        .unwrap();
+    }
+    let field_keys = get_field_keys(pool.get_ref()).await.map_err(e500)?;
+    let variables = known_variables(&field_keys)
+        .iter()
+        .map(|v| format!("{{{{{}}}}}", v))
+        .collect::<Vec<_>>()
+        .join(", ");
     let idempotency_key = uuid::Uuid::new_v4();
+    let csrf_field = csrf_field(&session.csrf_token().map_err(e500)?);
     Ok(HttpResponse::Ok()
         .content_type(ContentType::html())
         .body(format!(
@@ -23,6 +84,7 @@ pub async fn publish_newsletter_form(
                 <body>
                 {msg_html}
                 <form action="/admin/newsletters" method="post">
+                {csrf_field}
                 <label>Title:<br>
                 <input
                 type="text"
@@ -49,11 +111,39 @@ pub async fn publish_newsletter_form(
                 ></textarea>
                 </label>
                 <br>
+                <label>Template:<br>
+                <select name="template_id">{template_options}</select>
+                </label>
+                <br>
+                <label>Topic:<br>
+                <select name="topic">{topic_options}</select>
+                </label>
+                <fieldset>
+                <legend>Send to</legend>
+                {list_checkboxes}
+                </fieldset>
+                <fieldset>
+                <legend>Only subscribers who</legend>
+                <label>Joined on or after
+                <input type="date" name="joined_since">
+                </label>
+                <br>
+                <label>Are tagged
+                <select name="tag_id">{tag_options}</select>
+                </label>
+                <br>
+                <label>Did something themselves in the last
+                <input type="number" name="engaged_within_days" min="1" max="{max_engagement_days}"> days
+                </label>
+                </fieldset>
+                <p>Available variables: {variables}</p>
                 <input hidden type="text" name="idempotency_key" value="{idempotency_key}">
+                <button type="submit" formaction="/admin/newsletters/preview">Preview</button>
                 <button type="submit">Publish</button>
                 </form>
                 <p><a href="/admin/dashboard">&lt;- Back</a></p>
                 </body>
                 </html>"#,
+                max_engagement_days = Segment::MAX_ENGAGEMENT_DAYS,
     )))
 }
@@ -0,0 +1,124 @@
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use std::fmt::Write;
+use uuid::Uuid;
+use crate::utils::e500;
+
+struct IssueSummary {
+    newsletter_issue_id: Uuid,
+    title: String,
+    author_id: Option<Uuid>,
+    author_username: Option<String>,
+    published_at: DateTime<Utc>,
+    n_recipients: Option<i32>,
+    n_delivered: i64,
+    n_failed: i64,
+    n_skipped: i64,
+    n_pending: i64,
+}
+
+impl IssueSummary {
+    fn author(&self) -> String {
+        match (&self.author_id, &self.author_username) {
+            (Some(id), Some(username)) => format!(
+                "{} ({})",
+                htmlescape::encode_minimal(username),
+                id
+            ),
+            _ => "unknown".into(),
+        }
+    }
+
+    fn delivery_outcome(&self) -> String {
+        if self.n_pending > 0 {
+            format!("In progress ({} pending)", self.n_pending)
+        } else if self.n_failed == 0 && self.n_skipped == 0 {
+            format!("Delivered ({})", self.n_delivered)
+        } else {
+            format!(
+                "Completed ({} delivered, {} failed, {} skipped)",
+                self.n_delivered, self.n_failed, self.n_skipped
+            )
+        }
+    }
+}
+
+#[tracing::instrument(name = "Get newsletter issues history", skip(pool))]
+async fn get_issues_history(pool: &PgPool) -> Result<Vec<IssueSummary>, anyhow::Error> {
+    let issues = sqlx::query_as!(
+        IssueSummary,
+        r#"
+        SELECT
+            i.newsletter_issue_id,
+            i.title,
+            i.author_id,
+            u.username AS "author_username?",
+            i.published_at,
+            i.n_recipients,
+            COUNT(l.outcome) FILTER (WHERE l.outcome = 'delivered') AS "n_delivered!",
+            COUNT(l.outcome) FILTER (WHERE l.outcome = 'failed') AS "n_failed!",
+            COUNT(l.outcome) FILTER (WHERE l.outcome = 'skipped') AS "n_skipped!",
+            (
+                SELECT COUNT(*)
+                FROM issue_delivery_queue q
+                WHERE q.newsletter_issue_id = i.newsletter_issue_id
+            ) AS "n_pending!"
+        FROM newsletter_issues i
+        LEFT JOIN issue_delivery_log l
+            ON l.newsletter_issue_id = i.newsletter_issue_id
+        LEFT JOIN users u
+            ON u.user_id = i.author_id
+        GROUP BY i.newsletter_issue_id, u.user_id
+        ORDER BY i.published_at DESC
+        "#,
+    )
+        .fetch_all(pool)
+        .await
+        .context("Failed to retrieve the newsletter issues history.")?;
+    Ok(issues)
+}
+
+pub async fn newsletter_issues_history(
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let issues = get_issues_history(&pool).await.map_err(e500)?;
+    let mut rows_html = String::new();
+    for issue in &issues {
+        let n_recipients = issue
+            .n_recipients
+            .map(|n| n.to_string())
+            .unwrap_or_else(|| "unknown".into());
+        writeln!(
+            rows_html,
+            "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+            issue.newsletter_issue_id,
+            htmlescape::encode_minimal(&issue.title),
+            issue.author(),
+            issue.published_at.format("%Y-%m-%d %H:%M:%S UTC"),
+            n_recipients,
+            issue.delivery_outcome(),
+        )
+        .unwrap();
+    }
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+                r#"<!DOCTYPE html>
+                <html lang="en">
+                <head>
+                <meta http-equiv="content-type" content="text/html; charset=utf-8">
+                <title>Newsletter Issues</title>
+                </head>
+                <body>
+                <table>
+                <tr><th>Issue</th><th>Title</th><th>Author</th><th>Published at</th><th>Recipients</th><th>Delivery</th></tr>
+                {rows_html}
+                </table>
+                <p><a href="/admin/dashboard">&lt;- Back</a></p>
+                </body>
+                </html>"#,
+    )))
+}
@@ -1,5 +1,11 @@
 mod get;
+mod history;
 mod post;
 
 pub use get::publish_newsletter_form;
-pub use post::publish_newsletter;
+pub use history::newsletter_issues_history;
+pub use post::{preview_newsletter, publish_newsletter};
+pub(crate) use post::{
+    enqueue_delivery_tasks, insert_newsletter_issue, parse_topic, resolve_lists, resolve_segment,
+    prepare_newsletter_content, ContentError,
+};
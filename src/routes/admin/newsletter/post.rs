@@ -1,7 +1,10 @@
 use crate::authentication::UserId;
-use crate::utils::{e500, see_other};
+use crate::configuration::PreferenceSettings;
+use crate::mailing_lists::{get_list_by_slug, DEFAULT_LIST_SLUG};
+use crate::utils::{e500, form_values, see_other};
 use actix_web::web::ReqData;
-use actix_web::{web, HttpResponse};
+use actix_web::{web, HttpRequest, HttpResponse};
+use crate::audit::{record_audit_event, AuditAction, NewAuditEvent};
 use actix_web_flash_messages::FlashMessage;
 use anyhow::Context;
 use sqlx::PgPool;
@@ -10,37 +13,196 @@ use crate::idempotency::{try_processing, NextAction, IdempotencyKey, save_respon
 use sqlx::{Postgres, Transaction};
 use uuid::Uuid;
 use sqlx::Executor;
+use crate::custom_fields::get_field_keys;
+use crate::domain::{known_variables, NewsletterContent, Segment};
+use crate::authentication::csrf_field;
+use crate::session_state::TypedSession;
+use crate::tags::tag_exists;
+use actix_web::http::header::ContentType;
+use std::fmt::Write;
+use crate::routes::admin::templates::get_newsletter_template;
 
+#[derive(thiserror::Error, Debug)]
+pub(crate) enum ContentError {
+    #[error("{0}")]
+    ValidationError(String),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
 
+// Validate a draft issue and lay it out in its template, if it uses one
+pub(crate) async fn prepare_newsletter_content(
+    pool: &PgPool,
+    template_id: Option<Uuid>,
+    title: String,
+    text_content: String,
+    html_content: String,
+) -> Result<NewsletterContent, ContentError> {
+    let template = match template_id {
+        Some(template_id) => Some(
+            get_newsletter_template(pool, template_id)
+                .await?
+                .ok_or_else(|| {
+                    ContentError::ValidationError("The selected template does not exist.".into())
+                })?
+                .into(),
+        ),
+        None => None,
+    };
+    let field_keys = get_field_keys(pool).await?;
+    NewsletterContent::parse(
+        title,
+        text_content,
+        html_content,
+        template.as_ref(),
+        &known_variables(&field_keys),
+    )
+        .map_err(ContentError::ValidationError)
+}
+
+// The lists an issue goes out to. Issues that do not name one go to the
+// default list.
+pub(crate) async fn resolve_lists(
+    pool: &PgPool,
+    mut list_ids: Vec<Uuid>,
+) -> Result<Vec<Uuid>, ContentError> {
+    if list_ids.is_empty() {
+        let list = get_list_by_slug(pool, DEFAULT_LIST_SLUG)
+            .await?
+            .context("The default list is missing.")?;
+        return Ok(vec![list.list_id]);
+    }
+    list_ids.sort();
+    list_ids.dedup();
+    let n_known = sqlx::query!(
+        r#"SELECT COUNT(*) AS "n!" FROM lists WHERE list_id = ANY($1)"#,
+        &list_ids,
+    )
+        .fetch_one(pool)
+        .await
+        .context("Failed to look up the selected lists.")?
+        .n;
+    if n_known as usize != list_ids.len() {
+        return Err(ContentError::ValidationError(
+            "One of the selected lists does not exist.".into(),
+        ));
+    }
+    Ok(list_ids)
+}
+
+pub(crate) async fn resolve_segment(
+    pool: &PgPool,
+    segment: Segment,
+) -> Result<Segment, ContentError> {
+    let segment = segment.validate().map_err(ContentError::ValidationError)?;
+    if let Some(tag_id) = segment.tag_id {
+        if !tag_exists(pool, tag_id).await? {
+            return Err(ContentError::ValidationError(
+                "The selected tag does not exist.".into(),
+            ));
+        }
+    }
+    Ok(segment)
+}
+
+// An empty topic publishes the issue to every subscriber
+pub(crate) fn parse_topic(
+    preferences: &PreferenceSettings,
+    topic: Option<String>,
+) -> Result<Option<String>, String> {
+    match topic {
+        None => Ok(None),
+        Some(topic) if topic.is_empty() => Ok(None),
+        Some(topic) if preferences.is_topic(&topic) => Ok(Some(topic)),
+        Some(topic) => Err(format!("{} is not a known topic.", topic)),
+    }
+}
 
 #[tracing::instrument(skip_all)]
-async fn enqueue_delivery_tasks(
+pub(crate) async fn enqueue_delivery_tasks(
     transaction: &mut Transaction<'_, Postgres>,
     newsletter_issue_id: Uuid,
-) -> Result<(), sqlx::Error> {
+) -> Result<u64, sqlx::Error> {
     let query = sqlx::query!(
         r#"
         INSERT INTO issue_delivery_queue (
             newsletter_issue_id,
             subscriber_email
             )
-        SELECT $1, email
-        FROM subscriptions
-        WHERE status = 'confirmed'
+        SELECT $1, s.email
+        FROM subscriptions s
+        JOIN newsletter_issues i ON i.newsletter_issue_id = $1
+        WHERE s.status = 'confirmed'
+        -- Once per subscriber, however many of the issue's lists they are on
+        AND EXISTS (
+            SELECT 1
+            FROM list_memberships m
+            JOIN newsletter_issue_lists il ON il.list_id = m.list_id
+            WHERE il.newsletter_issue_id = $1
+            AND m.subscriber_id = s.id
+            AND m.status = 'confirmed'
+        )
+        AND (i.topic IS NULL OR NOT i.topic = ANY(s.muted_topics))
+        -- The issue's segment
+        AND (i.segment_joined_since IS NULL OR s.subscribed_at >= i.segment_joined_since)
+        AND (
+            i.segment_tag_id IS NULL
+            OR EXISTS (
+                SELECT 1 FROM subscriber_tags st
+                WHERE st.subscriber_id = s.id AND st.tag_id = i.segment_tag_id
+            )
+        )
+        AND (
+            i.segment_engaged_within_days IS NULL
+            OR s.last_engaged_at > now() - make_interval(days => i.segment_engaged_within_days)
+        )
+        -- Anyone who asked for fewer emails skips issues until their last one
+        -- is old enough, as the preferences page tells them
+        AND (
+            s.frequency = 'every_issue'
+            OR (
+                NOT EXISTS (
+                    SELECT 1 FROM issue_delivery_queue q
+                    WHERE q.subscriber_email = s.email
+                )
+                AND NOT EXISTS (
+                    SELECT 1 FROM issue_delivery_log l
+                    WHERE l.subscriber_email = s.email
+                    AND l.outcome = 'delivered'
+                    AND l.attempted_at > now() - CASE s.frequency
+                        WHEN 'weekly' THEN INTERVAL '7 days'
+                        ELSE INTERVAL '1 month'
+                    END
+                )
+            )
+        )
+        "#,
+        newsletter_issue_id,
+    );
+    let n_recipients = transaction.execute(query).await?.rows_affected();
+    let query = sqlx::query!(
+        r#"
+        UPDATE newsletter_issues
+        SET n_recipients = $2
+        WHERE newsletter_issue_id = $1
         "#,
         newsletter_issue_id,
+        n_recipients as i32,
     );
     transaction.execute(query).await?;
-    Ok(())
+    Ok(n_recipients)
 }
 
 
 #[tracing::instrument(skip_all)]
-async fn insert_newsletter_issue(
+pub(crate) async fn insert_newsletter_issue(
     transaction: &mut Transaction<'_, Postgres>,
-    title: &str,
-    text_content: &str,
-    html_content: &str,
+    author_id: &UserId,
+    template_id: Option<Uuid>,
+    topic: Option<&str>,
+    list_ids: &[Uuid],
+    segment: &Segment,
+    content: &NewsletterContent,
 ) -> Result<Uuid, sqlx::Error> {
     let newsletter_issue_id = Uuid::new_v4();
     let query = sqlx::query!(
@@ -50,14 +212,35 @@ async fn insert_newsletter_issue(
             title,
             text_content,
             html_content,
-            published_at
+            published_at,
+            author_id,
+            template_id,
+            topic,
+            segment_joined_since,
+            segment_tag_id,
+            segment_engaged_within_days
         )
-        VALUES ($1, $2, $3, $4, now())
+        VALUES ($1, $2, $3, $4, now(), $5, $6, $7, $8, $9, $10)
         "#,
         newsletter_issue_id,
-        title,
-        text_content,
-        html_content
+        content.title,
+        content.text_content,
+        content.html_content,
+        **author_id,
+        template_id,
+        topic,
+        segment.joined_since,
+        segment.tag_id,
+        segment.engaged_within_days,
+    );
+    transaction.execute(query).await?;
+    let query = sqlx::query!(
+        r#"
+        INSERT INTO newsletter_issue_lists (newsletter_issue_id, list_id)
+        SELECT $1, UNNEST($2::uuid[])
+        "#,
+        newsletter_issue_id,
+        list_ids,
     );
     transaction.execute(query).await?;
     Ok(newsletter_issue_id)
@@ -68,23 +251,105 @@ pub struct FormData {
     title: String,
     text_content: String,
     html_content: String,
+    #[serde(default)]
+    template_id: String,
+    #[serde(default)]
+    topic: String,
+    #[serde(default)]
+    joined_since: String,
+    #[serde(default)]
+    tag_id: String,
+    #[serde(default)]
+    engaged_within_days: String,
     idempotency_key: String,
 }
 
+// An issue as the publish form describes it, ready to be stored
+struct IssueDraft {
+    template_id: Option<Uuid>,
+    topic: Option<String>,
+    list_ids: Vec<Uuid>,
+    segment: Segment,
+    content: NewsletterContent,
+    idempotency_key: IdempotencyKey,
+}
+
+// A malformed form is an error; anything the editor can fix comes back as
+// the message to show them. The list checkboxes repeat their name, so the
+// body is parsed by hand.
+async fn parse_issue_form(
+    body: &[u8],
+    pool: &PgPool,
+    preferences: &PreferenceSettings,
+) -> Result<Result<IssueDraft, String>, actix_web::Error> {
+    let form: FormData = serde_urlencoded::from_bytes(body).map_err(e400)?;
+    let list_ids = form_values(body, "list_ids")
+        .iter()
+        .map(|id| Uuid::parse_str(id))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(e400)?;
+    let idempotency_key: IdempotencyKey = form.idempotency_key.try_into().map_err(e400)?;
+    let template_id = match form.template_id.as_str() {
+        "" => None,
+        id => Some(Uuid::parse_str(id).map_err(e400)?),
+    };
+    let topic = match parse_topic(preferences, Some(form.topic)) {
+        Ok(topic) => topic,
+        Err(e) => return Ok(Err(e)),
+    };
+    let segment = match Segment::parse(&form.joined_since, &form.tag_id, &form.engaged_within_days) {
+        Ok(segment) => segment,
+        Err(e) => return Ok(Err(e)),
+    };
+    let validated = async {
+        let content = prepare_newsletter_content(
+            pool,
+            template_id,
+            form.title,
+            form.text_content,
+            form.html_content,
+        )
+            .await?;
+        let list_ids = resolve_lists(pool, list_ids).await?;
+        let segment = resolve_segment(pool, segment).await?;
+        Ok::<_, ContentError>((content, list_ids, segment))
+    };
+    match validated.await {
+        Ok((content, list_ids, segment)) => Ok(Ok(IssueDraft {
+            template_id,
+            topic,
+            list_ids,
+            segment,
+            content,
+            idempotency_key,
+        })),
+        Err(ContentError::ValidationError(e)) => Ok(Err(e)),
+        Err(ContentError::UnexpectedError(e)) => Err(e500(e)),
+    }
+}
+
 #[tracing::instrument(
     name = "Publish a newsletter issue",
-    skip(form, pool, user_id),
-    fields(user_id=%*user_id)
+    skip(body, pool, preferences, user_id, request),
+    fields(user_id=%*user_id, newsletter_issue_id=tracing::field::Empty)
 )]
 pub async fn publish_newsletter(
-    form: web::Form<FormData>,
+    body: web::Bytes,
     user_id: ReqData<UserId>,
     pool: web::Data<PgPool>,
+    preferences: web::Data<PreferenceSettings>,
+    request: HttpRequest,
 ) -> Result<HttpResponse, actix_web::Error> {
     let user_id = user_id.into_inner();
-    let FormData { title, text_content, html_content, idempotency_key } = form.0;
-    let idempotency_key: IdempotencyKey = idempotency_key.try_into().map_err(e400)?;
-    let mut transaction = match try_processing(&pool, &idempotency_key, *user_id)
+    let draft = match parse_issue_form(&body, &pool, &preferences).await? {
+        Ok(draft) => draft,
+        Err(e) => {
+            FlashMessage::error(e).send();
+            return Ok(see_other("/admin/newsletters"));
+        }
+    };
+    let idempotency_key = &draft.idempotency_key;
+    let mut transaction = match try_processing(&pool, idempotency_key, *user_id)
         .await
         .map_err(e500)?
     {
@@ -94,23 +359,132 @@ pub async fn publish_newsletter(
             return Ok(saved_response);
         }
     };
-    let issue_id = insert_newsletter_issue(&mut transaction, &title, &text_content, &html_content)
+    let issue_id = insert_newsletter_issue(
+        &mut transaction,
+        &user_id,
+        draft.template_id,
+        draft.topic.as_deref(),
+        &draft.list_ids,
+        &draft.segment,
+        &draft.content,
+    )
         .await
         .context("Failed to store newsletter issue details.")
         .map_err(e500)?;
+    tracing::Span::current().record("newsletter_issue_id", tracing::field::display(&issue_id));
     enqueue_delivery_tasks(&mut transaction, issue_id)
         .await
         .context("Failed to enqueue delivery tasks.")
         .map_err(e500)?;
+    let event = NewAuditEvent::new(Some(*user_id), AuditAction::NewsletterPublished, &request)
+        .target(issue_id);
+    record_audit_event(&mut *transaction, event).await.map_err(e500)?;
 
     let response = see_other("/admin/newsletters");
-    let response = save_response(transaction, &idempotency_key, *user_id, response)
+    let response = save_response(transaction, idempotency_key, *user_id, response)
         .await
         .map_err(e500)?;
+    tracing::info!(author_id = %user_id, "Newsletter issue {} published", issue_id);
     success_message().send();
     Ok(response)
 }
 
+// Stores and enqueues the issue the way publishing would, then throws it
+// away, so that the count cannot drift from who actually gets it.
+#[tracing::instrument(skip_all)]
+async fn count_recipients(
+    pool: &PgPool,
+    user_id: &UserId,
+    draft: &IssueDraft,
+) -> Result<u64, anyhow::Error> {
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    let issue_id = insert_newsletter_issue(
+        &mut transaction,
+        user_id,
+        draft.template_id,
+        draft.topic.as_deref(),
+        &draft.list_ids,
+        &draft.segment,
+        &draft.content,
+    )
+        .await
+        .context("Failed to store the draft issue.")?;
+    let n_recipients = enqueue_delivery_tasks(&mut transaction, issue_id)
+        .await
+        .context("Failed to count the recipients.")?;
+    transaction
+        .rollback()
+        .await
+        .context("Failed to roll back the draft issue.")?;
+    Ok(n_recipients)
+}
+
+#[tracing::instrument(
+    name = "Preview a newsletter issue",
+    skip(body, pool, preferences, user_id, session),
+    fields(user_id=%*user_id)
+)]
+pub async fn preview_newsletter(
+    body: web::Bytes,
+    user_id: ReqData<UserId>,
+    pool: web::Data<PgPool>,
+    preferences: web::Data<PreferenceSettings>,
+    session: TypedSession,
+) -> Result<HttpResponse, actix_web::Error> {
+    let user_id = user_id.into_inner();
+    let draft = match parse_issue_form(&body, &pool, &preferences).await? {
+        Ok(draft) => draft,
+        Err(e) => {
+            FlashMessage::error(e).send();
+            return Ok(see_other("/admin/newsletters"));
+        }
+    };
+    let n_recipients = count_recipients(&pool, &user_id, &draft).await.map_err(e500)?;
+    // Sending from the preview submits the form exactly as it was filled in
+    let mut hidden_fields = String::new();
+    for (name, value) in serde_urlencoded::from_bytes::<Vec<(String, String)>>(&body).map_err(e400)? {
+        if name == "csrf_token" {
+            continue;
+        }
+        writeln!(
+            hidden_fields,
+            r#"<input type="hidden" name="{}" value="{}">"#,
+            htmlescape::encode_minimal(&name),
+            htmlescape::encode_minimal(&value),
+        )
+        .unwrap();
+    }
+    let csrf_field = csrf_field(&session.csrf_token().map_err(e500)?);
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+                r#"<!DOCTYPE html>
+                <html lang="en">
+                <head>
+                <meta http-equiv="content-type" content="text/html; charset=utf-8">
+                <title>Preview Newsletter Issue</title>
+                </head>
+                <body>
+                <h2>{title}</h2>
+                <p>This issue will go to {n_recipients} subscriber(s).</p>
+                <iframe sandbox srcdoc="{html_content}" width="600" height="400"></iframe>
+                <pre>{text_content}</pre>
+                <form action="/admin/newsletters" method="post">
+                {csrf_field}
+                {hidden_fields}
+                <button type="submit">Publish</button>
+                </form>
+                <p><a href="/admin/newsletters">&lt;- Back</a></p>
+                </body>
+                </html>"#,
+                title = htmlescape::encode_minimal(&draft.content.title),
+                html_content = htmlescape::encode_attribute(&draft.content.html_content),
+                text_content = htmlescape::encode_minimal(&draft.content.text_content),
+    )))
+}
 
 fn success_message() -> FlashMessage {
     FlashMessage::info("The newsletter issue has been accepted - emails will go out shortly.")
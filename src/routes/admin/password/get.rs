@@ -2,6 +2,7 @@ use actix_web::http::header::ContentType;
 use actix_web::HttpResponse;
 use crate::utils::{e500, see_other};
 use crate::session_state::TypedSession;
+use crate::authentication::csrf_field;
 use actix_web_flash_messages::IncomingFlashMessages;
 use std::fmt::Write;
 
@@ -12,10 +13,11 @@ pub async fn change_password_form(
     if session.get_user_id().map_err(e500)?.is_none() {
         return  Ok(see_other("/login"));
     };
+    let csrf_field = csrf_field(&session.csrf_token().map_err(e500)?);
 
 let mut msg_html = String::new();
 for m in flash_messages.iter() {
-    writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    writeln!(msg_html, "<p><i>{}</i></p>", htmlescape::encode_minimal(m.content())).unwrap();
     }
 
 Ok(HttpResponse::Ok()
@@ -30,6 +32,7 @@ Ok(HttpResponse::Ok()
             <body>
             {msg_html}
             <form action="/admin/password" method="post">
+            {csrf_field}
             <label>Current password
             <input
             type="password"
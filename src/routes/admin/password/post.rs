@@ -1,13 +1,14 @@
-use actix_web::{HttpResponse, web};
-use actix_web::error::InternalError;
+use actix_web::{HttpRequest, HttpResponse, web};
+use crate::audit::{AuditAction, NewAuditEvent};
 use secrecy::{Secret, ExposeSecret};
-use uuid::Uuid;
-use crate::{utils::{e500, see_other}, session_state::TypedSession};
+use crate::utils::{e500, see_other};
 use actix_web_flash_messages::FlashMessage;
 use crate::routes::admin::dashboard::get_username;
 use sqlx::PgPool;
-use crate::authentication::{validate_credentials, AuthError, Credentials};
+use crate::authentication::{validate_credentials, AuthError, Credentials, PasswordHashing};
 use crate::authentication::UserId;
+use crate::domain::NewPassword;
+use crate::session_state::TypedSession;
 
 #[derive(serde::Deserialize)]
 pub struct FormData {
@@ -21,28 +22,36 @@ pub async fn change_password(
     form: web::Form<FormData>,
     pool: web::Data<PgPool>,
     user_id: web::ReqData<UserId>,
+    hashing: web::Data<PasswordHashing>,
+    session: TypedSession,
+    request: HttpRequest,
 ) -> Result<HttpResponse, actix_web::Error> {
     let user_id = user_id.into_inner();
-    if form.new_password.expose_secret().len() < 8 && form.new_password.expose_secret().len() > 64 {
-        FlashMessage::error(
-            "You entered a password with too few characters or too many - it needs more than 8 but less than 64.",
-        )
-            .send();
-        return Ok(see_other("/admin/password"));
-    }
-    if form.new_password.expose_secret() != form.new_password_check.expose_secret() {
+    let session_id = match session.get_session_id().map_err(e500)? {
+        Some(session_id) => session_id,
+        None => return Ok(see_other("/login")),
+    };
+    let username = get_username(*user_id, &pool).await.map_err(e500)?;
+    let FormData { current_password, new_password, new_password_check } = form.0;
+    if new_password.expose_secret() != new_password_check.expose_secret() {
         FlashMessage::error(
             "You entered two different new passwords - the field values must match.",
         )
             .send();
         return Ok(see_other("/admin/password"));
     }
-    let username = get_username(*user_id, &pool).await.map_err(e500)?;
+    let new_password = match NewPassword::parse(new_password, &[&username]) {
+        Ok(new_password) => new_password,
+        Err(e) => {
+            FlashMessage::error(e).send();
+            return Ok(see_other("/admin/password"));
+        }
+    };
     let credentials = Credentials {
         username,
-        password: form.0.current_password,
+        password: current_password,
     };
-    if let Err(e) = validate_credentials(credentials, &pool).await {
+    if let Err(e) = validate_credentials(credentials, &pool, &hashing).await {
         return match e {
             AuthError::InvalidCredentials(_) => {
                 FlashMessage::error("The current password is incorrect.").send();
@@ -51,7 +60,8 @@ pub async fn change_password(
             AuthError::UnexpectedError(_) => Err(e500(e)),
         };
     }
-    crate::authentication::change_password(*user_id, form.0.new_password, &pool)
+    let event = NewAuditEvent::new(Some(*user_id), AuditAction::PasswordChanged, &request);
+    crate::authentication::change_password(*user_id, session_id, new_password.into(), event, &pool, &hashing)
         .await
         .map_err(e500)?;
     FlashMessage::error("Your password has been changed.").send();
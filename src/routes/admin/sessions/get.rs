@@ -0,0 +1,68 @@
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use sqlx::PgPool;
+use std::fmt::Write;
+use crate::authentication::{csrf_field, get_active_sessions, UserId};
+use crate::session_state::TypedSession;
+use crate::utils::e500;
+
+pub async fn sessions_form(
+    flash_messages: IncomingFlashMessages,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+    session: TypedSession,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", htmlescape::encode_minimal(m.content())).unwrap();
+    }
+    let csrf_field = csrf_field(&session.csrf_token().map_err(e500)?);
+    let current_session_id = session.get_session_id().map_err(e500)?;
+    let sessions = get_active_sessions(&pool, **user_id).await.map_err(e500)?;
+    let mut rows_html = String::new();
+    for user_session in &sessions {
+        let action = if Some(user_session.session_id) == current_session_id {
+            "This session".to_string()
+        } else {
+            format!(
+                r#"<form action="/admin/sessions/{}/revoke" method="post">{}<button type="submit">Revoke</button></form>"#,
+                user_session.session_id, csrf_field
+            )
+        };
+        writeln!(
+            rows_html,
+            "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+            user_session.created_at.format("%Y-%m-%d %H:%M:%S UTC"),
+            user_session.last_seen_at.format("%Y-%m-%d %H:%M:%S UTC"),
+            htmlescape::encode_minimal(user_session.ip_address.as_deref().unwrap_or("unknown")),
+            htmlescape::encode_minimal(user_session.user_agent.as_deref().unwrap_or("unknown")),
+            action,
+        )
+        .unwrap();
+    }
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+                r#"<!DOCTYPE html>
+                <html lang="en">
+                <head>
+                <meta http-equiv="content-type" content="text/html; charset=utf-8">
+                <title>Sessions</title>
+                </head>
+                <body>
+                {msg_html}
+                <p>You are logged in here:</p>
+                <table>
+                <tr><th>Logged in at</th><th>Last seen at</th><th>IP address</th><th>Browser</th><th></th></tr>
+                {rows_html}
+                </table>
+                <form action="/admin/sessions/revoke-others" method="post">
+                {csrf_field}
+                <button type="submit">Log out all other sessions</button>
+                </form>
+                <p><a href="/admin/dashboard">&lt;- Back</a></p>
+                </body>
+                </html>"#,
+    )))
+}
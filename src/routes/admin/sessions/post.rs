@@ -0,0 +1,51 @@
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use sqlx::PgPool;
+use uuid::Uuid;
+use crate::authentication::{revoke_other_sessions, revoke_session, UserId};
+use crate::session_state::TypedSession;
+use crate::utils::{e500, see_other};
+
+#[tracing::instrument(name = "Revoke a user session", skip(pool, user_id, session), fields(user_id=%*user_id))]
+pub async fn revoke_user_session(
+    session_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+    session: TypedSession,
+) -> Result<HttpResponse, actix_web::Error> {
+    let session_id = session_id.into_inner();
+    if !revoke_session(&pool, **user_id, session_id).await.map_err(e500)? {
+        FlashMessage::error("The session does not exist or was already revoked.").send();
+        return Ok(see_other("/admin/sessions"));
+    }
+    // Revoking the current session is the same as logging out
+    if session.get_session_id().map_err(e500)? == Some(session_id) {
+        session.log_out();
+        FlashMessage::info("You have successfully logged out.").send();
+        return Ok(see_other("/login"));
+    }
+    FlashMessage::info("The session has been revoked.").send();
+    Ok(see_other("/admin/sessions"))
+}
+
+#[tracing::instrument(name = "Revoke other user sessions", skip(pool, user_id, session), fields(user_id=%*user_id))]
+pub async fn revoke_other_user_sessions(
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+    session: TypedSession,
+) -> Result<HttpResponse, actix_web::Error> {
+    let session_id = match session.get_session_id().map_err(e500)? {
+        Some(session_id) => session_id,
+        None => return Ok(see_other("/login")),
+    };
+    let n_revoked = revoke_other_sessions(pool.get_ref(), **user_id, session_id)
+        .await
+        .map_err(e500)?;
+    FlashMessage::info(format!(
+        "{} other session{} logged out.",
+        n_revoked,
+        if n_revoked == 1 { " has been" } else { "s have been" }
+    ))
+    .send();
+    Ok(see_other("/admin/sessions"))
+}
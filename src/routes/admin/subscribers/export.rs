@@ -0,0 +1,209 @@
+use actix_web::http::header::{ContentDisposition, DispositionParam, DispositionType};
+use actix_web::{web, HttpResponse};
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+use std::collections::BTreeMap;
+use super::get::SubscriberSearch;
+use crate::custom_fields::get_field_keys;
+use crate::utils::{e400, e500};
+
+// Rows fetched from the cursor per chunk of the response
+const EXPORT_BATCH_SIZE: usize = 1000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ExportFormat {
+    Csv,
+    // One JSON object per line
+    Ndjson,
+}
+
+impl TryFrom<String> for ExportFormat {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        match s.as_str() {
+            "" | "csv" => Ok(ExportFormat::Csv),
+            "ndjson" => Ok(ExportFormat::Ndjson),
+            other => Err(format!("{} is not a known export format.", other)),
+        }
+    }
+}
+
+#[derive(serde::Deserialize)]
+pub struct ExportParams {
+    #[serde(default)]
+    format: String,
+    #[serde(default)]
+    q: String,
+    #[serde(default)]
+    status: String,
+    #[serde(default)]
+    since: String,
+    #[serde(default)]
+    until: String,
+}
+
+#[derive(serde::Serialize, sqlx::FromRow)]
+struct ExportedSubscriber {
+    id: Uuid,
+    email: String,
+    name: String,
+    subscribed_at: DateTime<Utc>,
+    status: String,
+    confirmed_at: Option<DateTime<Utc>>,
+    // In the order of `ExportCursor::field_keys`
+    #[serde(skip)]
+    field_values: Vec<String>,
+}
+
+#[derive(serde::Serialize)]
+struct ExportedLine<'a> {
+    #[serde(flatten)]
+    subscriber: &'a ExportedSubscriber,
+    fields: BTreeMap<&'a str, &'a str>,
+}
+
+// The rows come from a cursor opened in `transaction`, a batch at a time,
+// so a large export never sits in memory on either side.
+struct ExportCursor {
+    transaction: Transaction<'static, Postgres>,
+    format: ExportFormat,
+    field_keys: Vec<String>,
+    n_batches: usize,
+}
+
+#[tracing::instrument(name = "Export subscribers", skip(query, pool))]
+pub async fn export_subscribers(
+    query: web::Query<ExportParams>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let params = query.into_inner();
+    let format = ExportFormat::try_from(params.format).map_err(e400)?;
+    let search = SubscriberSearch::parse(&params.q, &params.status, &params.since, &params.until)
+        .map_err(e400)?;
+    let cursor = open_cursor(&pool, &search, format).await.map_err(e500)?;
+    let export = futures_util::stream::try_unfold(Some(cursor), |cursor| async move {
+        let Some(mut cursor) = cursor else {
+            return Ok(None);
+        };
+        let rows: Vec<ExportedSubscriber> =
+            sqlx::query_as(&format!("FETCH {} FROM subscriber_export", EXPORT_BATCH_SIZE))
+                .fetch_all(&mut *cursor.transaction)
+                .await
+                .context("Failed to fetch subscribers from the export cursor.")?;
+        let chunk = encode(&rows, &cursor.field_keys, cursor.format, cursor.n_batches == 0)?;
+        cursor.n_batches += 1;
+        if rows.len() < EXPORT_BATCH_SIZE {
+            // Closes the cursor
+            cursor
+                .transaction
+                .commit()
+                .await
+                .context("Failed to close the export cursor.")?;
+            return Ok(Some((chunk, None)));
+        }
+        Ok::<_, anyhow::Error>(Some((chunk, Some(cursor))))
+    });
+    let (content_type, extension) = match format {
+        ExportFormat::Csv => ("text/csv; charset=utf-8", "csv"),
+        ExportFormat::Ndjson => ("application/x-ndjson", "ndjson"),
+    };
+    Ok(HttpResponse::Ok()
+        .content_type(content_type)
+        .insert_header(ContentDisposition {
+            disposition: DispositionType::Attachment,
+            parameters: vec![DispositionParam::Filename(format!("subscribers.{}", extension))],
+        })
+        .streaming(export))
+}
+
+async fn open_cursor(
+    pool: &PgPool,
+    search: &SubscriberSearch,
+    format: ExportFormat,
+) -> Result<ExportCursor, anyhow::Error> {
+    let mut transaction = pool.begin().await.context("Failed to acquire a Postgres connection from the pool")?;
+    // The field columns must line up with the fields the cursor sees
+    sqlx::query("SET TRANSACTION ISOLATION LEVEL REPEATABLE READ")
+        .execute(&mut *transaction)
+        .await
+        .context("Failed to set the export's isolation level.")?;
+    let field_keys = get_field_keys(&mut *transaction).await?;
+    // The same conditions as `search_subscribers`
+    sqlx::query(
+        r#"
+        DECLARE subscriber_export NO SCROLL CURSOR FOR
+        SELECT id, email, name, subscribed_at, status, confirmed_at,
+            ARRAY(
+                SELECT COALESCE(v.value, '')
+                FROM custom_fields f
+                LEFT JOIN subscriber_field_values v
+                    ON v.field_id = f.field_id AND v.subscriber_id = subscriptions.id
+                ORDER BY f.created_at, f.field_id
+            ) AS field_values
+        FROM subscriptions
+        WHERE ($1::TEXT IS NULL
+                OR strpos(lower(email), lower($1)) > 0
+                OR strpos(lower(name), lower($1)) > 0)
+            AND ($2::TEXT IS NULL OR status = $2)
+            AND ($3::TIMESTAMPTZ IS NULL OR subscribed_at >= $3)
+            AND ($4::TIMESTAMPTZ IS NULL OR subscribed_at < $4)
+        ORDER BY subscribed_at, id
+        "#,
+    )
+        .bind(&search.text)
+        .bind(search.status.map(|status| status.as_str()))
+        .bind(search.since)
+        .bind(search.until)
+        .execute(&mut *transaction)
+        .await
+        .context("Failed to open the export cursor.")?;
+    Ok(ExportCursor { transaction, format, field_keys, n_batches: 0 })
+}
+
+fn encode(
+    rows: &[ExportedSubscriber],
+    field_keys: &[String],
+    format: ExportFormat,
+    with_header: bool,
+) -> Result<web::Bytes, anyhow::Error> {
+    match format {
+        ExportFormat::Csv => {
+            let mut writer = csv::Writer::from_writer(Vec::new());
+            if with_header {
+                let columns = ["id", "email", "name", "subscribed_at", "status", "confirmed_at"];
+                writer.write_record(columns.into_iter().chain(field_keys.iter().map(String::as_str)))?;
+            }
+            for row in rows {
+                let columns = [
+                    row.id.to_string(),
+                    row.email.clone(),
+                    row.name.clone(),
+                    row.subscribed_at.to_rfc3339(),
+                    row.status.clone(),
+                    row.confirmed_at.map(|t| t.to_rfc3339()).unwrap_or_default(),
+                ];
+                writer.write_record(columns.iter().chain(&row.field_values))?;
+            }
+            Ok(writer.into_inner()?.into())
+        }
+        ExportFormat::Ndjson => {
+            let mut lines = Vec::new();
+            for row in rows {
+                let line = ExportedLine {
+                    subscriber: row,
+                    fields: field_keys
+                        .iter()
+                        .map(String::as_str)
+                        .zip(row.field_values.iter().map(String::as_str))
+                        .collect(),
+                };
+                serde_json::to_writer(&mut lines, &line)?;
+                lines.push(b'\n');
+            }
+            Ok(lines.into())
+        }
+    }
+}
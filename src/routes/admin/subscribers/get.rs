@@ -0,0 +1,444 @@
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use std::fmt::Write;
+use uuid::Uuid;
+use crate::audit::{get_audit_events, AuditFilter};
+use crate::authentication::csrf_field;
+use crate::custom_fields::get_field_values;
+use crate::domain::{EmailPolicy, SubscriberEmail, SubscriptionStatus};
+use crate::mailing_lists::get_memberships;
+use crate::session_state::TypedSession;
+use crate::tags::{get_subscriber_tag_ids, get_tags};
+use crate::utils::{e400, e500, parse_day, see_other};
+
+const PAGE_SIZE: i64 = 50;
+const TIME_FORMAT: &str = "%Y-%m-%d %H:%M:%S UTC";
+
+pub(crate) struct SubscriberRecord {
+    pub id: Uuid,
+    pub email: String,
+    pub name: String,
+    pub status: String,
+    pub subscribed_at: DateTime<Utc>,
+    pub confirmed_at: Option<DateTime<Utc>>,
+}
+
+// Every field is optional: an empty search matches every subscriber
+#[derive(Debug, Default)]
+pub(crate) struct SubscriberSearch {
+    // Matched against both the email address and the name
+    pub text: Option<String>,
+    pub status: Option<SubscriptionStatus>,
+    pub since: Option<DateTime<Utc>>,
+    pub until: Option<DateTime<Utc>>,
+}
+
+// Empty fields come from the search form when they are left blank
+#[derive(serde::Deserialize)]
+pub struct QueryParams {
+    #[serde(default)]
+    q: String,
+    #[serde(default)]
+    status: String,
+    #[serde(default)]
+    since: String,
+    #[serde(default)]
+    until: String,
+    page: Option<i64>,
+}
+
+impl SubscriberSearch {
+    // From the fields of the search form, blank when unused
+    pub(crate) fn parse(q: &str, status: &str, since: &str, until: &str) -> Result<Self, String> {
+        let text = match q.trim() {
+            "" => None,
+            text => Some(text.to_string()),
+        };
+        let status = match status {
+            "" => None,
+            status => Some(SubscriptionStatus::try_from(status.to_string())?),
+        };
+        Ok(Self {
+            text,
+            status,
+            since: parse_day(since, 0)?,
+            // `until` is inclusive, so it ends where the next day starts
+            until: parse_day(until, 1)?,
+        })
+    }
+}
+
+impl TryFrom<&QueryParams> for SubscriberSearch {
+    type Error = String;
+
+    fn try_from(params: &QueryParams) -> Result<Self, Self::Error> {
+        Self::parse(&params.q, &params.status, &params.since, &params.until)
+    }
+}
+
+// Most recent first
+#[tracing::instrument(name = "Search subscribers", skip(pool))]
+pub(crate) async fn search_subscribers(
+    pool: &PgPool,
+    search: &SubscriberSearch,
+    limit: i64,
+    offset: i64,
+) -> Result<(Vec<SubscriberRecord>, i64), anyhow::Error> {
+    let rows = sqlx::query!(
+        r#"
+        SELECT id, email, name, status, subscribed_at, confirmed_at,
+            COUNT(*) OVER () AS "n_matches!"
+        FROM subscriptions
+        WHERE ($1::TEXT IS NULL
+                OR strpos(lower(email), lower($1)) > 0
+                OR strpos(lower(name), lower($1)) > 0)
+            AND ($2::TEXT IS NULL OR status = $2)
+            AND ($3::TIMESTAMPTZ IS NULL OR subscribed_at >= $3)
+            AND ($4::TIMESTAMPTZ IS NULL OR subscribed_at < $4)
+        ORDER BY subscribed_at DESC, id
+        LIMIT $5 OFFSET $6
+        "#,
+        search.text,
+        search.status.map(|status| status.as_str()),
+        search.since,
+        search.until,
+        limit,
+        offset,
+    )
+        .fetch_all(pool)
+        .await
+        .context("Failed to search subscribers.")?;
+    let n_matches = rows.first().map(|r| r.n_matches).unwrap_or(0);
+    let subscribers = rows
+        .into_iter()
+        .map(|r| SubscriberRecord {
+            id: r.id,
+            email: r.email,
+            name: r.name,
+            status: r.status,
+            subscribed_at: r.subscribed_at,
+            confirmed_at: r.confirmed_at,
+        })
+        .collect();
+    Ok((subscribers, n_matches))
+}
+
+#[tracing::instrument(name = "Get subscriber", skip(pool))]
+pub(crate) async fn get_subscriber(
+    pool: &PgPool,
+    subscriber_id: Uuid,
+) -> Result<Option<SubscriberRecord>, anyhow::Error> {
+    let subscriber = sqlx::query_as!(
+        SubscriberRecord,
+        r#"
+        SELECT id, email, name, status, subscribed_at, confirmed_at
+        FROM subscriptions
+        WHERE id = $1
+        "#,
+        subscriber_id,
+    )
+        .fetch_optional(pool)
+        .await
+        .context("Failed to retrieve a subscriber.")?;
+    Ok(subscriber)
+}
+
+struct DeliveryRecord {
+    title: String,
+    outcome: String,
+    attempted_at: DateTime<Utc>,
+}
+
+// Most recent first
+#[tracing::instrument(name = "Get delivery history", skip(pool))]
+async fn get_delivery_history(
+    pool: &PgPool,
+    email: &str,
+) -> Result<Vec<DeliveryRecord>, anyhow::Error> {
+    let deliveries = sqlx::query_as!(
+        DeliveryRecord,
+        r#"
+        SELECT i.title, l.outcome, l.attempted_at
+        FROM issue_delivery_log l
+        JOIN newsletter_issues i ON i.newsletter_issue_id = l.newsletter_issue_id
+        WHERE l.subscriber_email = $1
+        ORDER BY l.attempted_at DESC
+        "#,
+        email,
+    )
+        .fetch_all(pool)
+        .await
+        .context("Failed to retrieve the delivery history.")?;
+    Ok(deliveries)
+}
+
+fn page(title: &str, flash_messages: IncomingFlashMessages, body: &str) -> HttpResponse {
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", htmlescape::encode_minimal(m.content())).unwrap();
+    }
+    HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+                r#"<!DOCTYPE html>
+                <html lang="en">
+                <head>
+                <meta http-equiv="content-type" content="text/html; charset=utf-8">
+                <title>{title}</title>
+                </head>
+                <body>
+                {msg_html}
+                {body}
+                </body>
+                </html>"#,
+        ))
+}
+
+pub async fn subscribers_list(
+    query: web::Query<QueryParams>,
+    flash_messages: IncomingFlashMessages,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let params = query.into_inner();
+    let search = SubscriberSearch::try_from(&params).map_err(e400)?;
+    let page_number = params.page.unwrap_or(1).max(1);
+    let offset = (page_number - 1)
+        .checked_mul(PAGE_SIZE)
+        .ok_or_else(|| e400("The page number is too large."))?;
+    // The search again, to carry it over to the other pages and the exports
+    let filters = [
+        ("q", &params.q),
+        ("status", &params.status),
+        ("since", &params.since),
+        ("until", &params.until),
+    ]
+    .iter()
+    .map(|(key, value)| format!("{}={}", key, urlencoding::encode(value)))
+    .collect::<Vec<_>>();
+    let (subscribers, n_matches) = search_subscribers(&pool, &search, PAGE_SIZE, offset)
+        .await
+        .map_err(e500)?;
+    // Past the last page, the rows carry no count: look it up and go there
+    if subscribers.is_empty() && page_number > 1 {
+        let (_, n_matches) = search_subscribers(&pool, &search, 1, 0).await.map_err(e500)?;
+        let n_pages = ((n_matches + PAGE_SIZE - 1) / PAGE_SIZE).max(1);
+        return Ok(see_other(&format!("/admin/subscribers?{}&page={}", filters.join("&"), n_pages)));
+    }
+    let filters = filters.join("&amp;");
+    let n_pages = ((n_matches + PAGE_SIZE - 1) / PAGE_SIZE).max(1);
+
+    let mut rows_html = String::new();
+    for subscriber in &subscribers {
+        writeln!(
+            rows_html,
+            r#"<tr><td><a href="/admin/subscribers/{}">{}</a></td><td>{}</td><td>{}</td><td>{}</td></tr>"#,
+            subscriber.id,
+            htmlescape::encode_minimal(&subscriber.email),
+            htmlescape::encode_minimal(&subscriber.name),
+            subscriber.status,
+            subscriber.subscribed_at.format(TIME_FORMAT),
+        )
+        .unwrap();
+    }
+    let mut status_options = String::from(r#"<option value="">Any status</option>"#);
+    for status in SubscriptionStatus::ALL {
+        let selected = if status.as_str() == params.status { " selected" } else { "" };
+        write!(status_options, r#"<option value="{0}"{1}>{0}</option>"#, status.as_str(), selected).unwrap();
+    }
+    let mut pagination_html = String::new();
+    if page_number > 1 {
+        write!(
+            pagination_html,
+            r#"<a href="/admin/subscribers?{}&amp;page={}">&lt; Previous</a> "#,
+            filters,
+            page_number - 1
+        )
+        .unwrap();
+    }
+    write!(pagination_html, "Page {} of {}", page_number, n_pages).unwrap();
+    if page_number < n_pages {
+        write!(
+            pagination_html,
+            r#" <a href="/admin/subscribers?{}&amp;page={}">Next &gt;</a>"#,
+            filters,
+            page_number + 1
+        )
+        .unwrap();
+    }
+
+    let body = format!(
+        r#"<form action="/admin/subscribers" method="get">
+                <input type="text" placeholder="Email or name" name="q" value="{q}">
+                <select name="status">{status_options}</select>
+                <label>Subscribed from <input type="date" name="since" value="{since}"></label>
+                <label>to <input type="date" name="until" value="{until}"></label>
+                <button type="submit">Search</button>
+                </form>
+                <p>{n_matches} matching subscriber(s)</p>
+                <table>
+                <tr><th>Email</th><th>Name</th><th>Status</th><th>Subscribed at</th></tr>
+                {rows_html}
+                </table>
+                <p>{pagination_html}</p>
+                <p>Export these subscribers as <a href="/admin/subscribers/export?{filters}&amp;format=csv">CSV</a>
+                or <a href="/admin/subscribers/export?{filters}&amp;format=ndjson">NDJSON</a></p>
+                <p><a href="/admin/subscribers/imports">Import subscribers from a CSV file</a></p>
+                <p><a href="/admin/dashboard">&lt;- Back</a></p>"#,
+        q = htmlescape::encode_minimal(&params.q),
+        since = htmlescape::encode_minimal(&params.since),
+        until = htmlescape::encode_minimal(&params.until),
+    );
+    Ok(page("Subscribers", flash_messages, &body))
+}
+
+pub async fn subscriber_details(
+    subscriber_id: web::Path<Uuid>,
+    flash_messages: IncomingFlashMessages,
+    pool: web::Data<PgPool>,
+    email_policy: web::Data<EmailPolicy>,
+    session: TypedSession,
+) -> Result<HttpResponse, actix_web::Error> {
+    let subscriber_id = subscriber_id.into_inner();
+    let subscriber = match get_subscriber(&pool, subscriber_id).await.map_err(e500)? {
+        Some(subscriber) => subscriber,
+        None => return Ok(HttpResponse::NotFound().finish()),
+    };
+    let csrf_field = csrf_field(&session.csrf_token().map_err(e500)?);
+    let is_flagged = SubscriberEmail::parse(subscriber.email.clone())
+        .is_ok_and(|email| email_policy.flags(&email));
+    let flag_html = if is_flagged {
+        "<p><i>This is a role address: it is usually read by a team or a machine.</i></p>"
+    } else {
+        ""
+    };
+
+    let mut fields_html = String::new();
+    for field in get_field_values(pool.get_ref(), subscriber_id).await.map_err(e500)? {
+        writeln!(
+            fields_html,
+            "<p>{}: {}</p>",
+            htmlescape::encode_minimal(&field.label),
+            htmlescape::encode_minimal(&field.value),
+        )
+        .unwrap();
+    }
+    let mut lists_html = String::new();
+    for membership in get_memberships(&pool, subscriber_id).await.map_err(e500)? {
+        writeln!(
+            lists_html,
+            "<li>{}: {}</li>",
+            htmlescape::encode_minimal(&membership.name),
+            membership.status,
+        )
+        .unwrap();
+    }
+    let subscriber_tag_ids = get_subscriber_tag_ids(&pool, subscriber_id).await.map_err(e500)?;
+    let mut tag_checkboxes = String::new();
+    for tag in get_tags(&pool).await.map_err(e500)? {
+        writeln!(
+            tag_checkboxes,
+            r#"<label><input type="checkbox" name="tag_ids" value="{}"{}> {}</label><br>"#,
+            tag.tag_id,
+            if subscriber_tag_ids.contains(&tag.tag_id) { " checked" } else { "" },
+            htmlescape::encode_minimal(&tag.name),
+        )
+        .unwrap();
+    }
+    let mut deliveries_html = String::new();
+    for delivery in get_delivery_history(&pool, &subscriber.email)
+        .await
+        .map_err(e500)?
+    {
+        writeln!(
+            deliveries_html,
+            "<tr><td>{}</td><td>{}</td><td>{}</td></tr>",
+            htmlescape::encode_minimal(&delivery.title),
+            delivery.outcome,
+            delivery.attempted_at.format(TIME_FORMAT),
+        )
+        .unwrap();
+    }
+    let filter = AuditFilter {
+        target: Some(subscriber_id.to_string()),
+        ..Default::default()
+    };
+    let mut history_html = String::new();
+    for event in get_audit_events(&pool, &filter, None).await.map_err(e500)? {
+        writeln!(
+            history_html,
+            "<tr><td>{}</td><td>{}</td><td>{}</td></tr>",
+            event.occurred_at.format(TIME_FORMAT),
+            event.action,
+            htmlescape::encode_minimal(event.actor_username.as_deref().unwrap_or("the subscriber")),
+        )
+        .unwrap();
+    }
+    let mut actions_html = String::new();
+    for (action, label) in [
+        ("confirm", "Confirm"),
+        ("unsubscribe", "Unsubscribe"),
+        ("suppress", "Suppress"),
+        ("delete", "Delete"),
+    ] {
+        writeln!(
+            actions_html,
+            r#"<form action="/admin/subscribers/{}/{}" method="post">{}<button type="submit">{}</button></form>"#,
+            subscriber_id, action, csrf_field, label,
+        )
+        .unwrap();
+    }
+
+    let body = format!(
+        r#"<h2>{email}</h2>
+                {flag_html}
+                <p>Status: {status}</p>
+                <p>Subscribed at: {subscribed_at}</p>
+                <p>Confirmed at: {confirmed_at}</p>
+                {fields_html}
+                <form action="/admin/subscribers/{subscriber_id}" method="post">
+                {csrf_field}
+                <label>Email:<br>
+                <input type="text" name="email" value="{email}">
+                </label>
+                <br>
+                <label>Name:<br>
+                <input type="text" name="name" value="{name}">
+                </label>
+                <br>
+                <button type="submit">Save</button>
+                </form>
+                {actions_html}
+                <h3>Lists</h3>
+                <ul>{lists_html}</ul>
+                <h3>Tags</h3>
+                <form action="/admin/subscribers/{subscriber_id}/tags" method="post">
+                {csrf_field}
+                {tag_checkboxes}
+                <button type="submit">Save tags</button>
+                </form>
+                <h3>History</h3>
+                <table>
+                <tr><th>Time</th><th>Event</th><th>By</th></tr>
+                {history_html}
+                </table>
+                <h3>Deliveries</h3>
+                <table>
+                <tr><th>Issue</th><th>Outcome</th><th>Attempted at</th></tr>
+                {deliveries_html}
+                </table>
+                <p><a href="/admin/subscribers">&lt;- Back</a></p>"#,
+        email = htmlescape::encode_minimal(&subscriber.email),
+        name = htmlescape::encode_minimal(&subscriber.name),
+        status = subscriber.status,
+        subscribed_at = subscriber.subscribed_at.format(TIME_FORMAT),
+        confirmed_at = subscriber
+            .confirmed_at
+            .map(|t| t.format(TIME_FORMAT).to_string())
+            .unwrap_or_else(|| "never".into()),
+    );
+    Ok(page("Subscriber", flash_messages, &body))
+}
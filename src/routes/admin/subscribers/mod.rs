@@ -0,0 +1,10 @@
+mod export;
+mod get;
+mod post;
+
+pub use export::export_subscribers;
+pub use get::{subscriber_details, subscribers_list};
+pub use post::{
+    confirm_subscriber_as_admin, delete_subscriber, suppress_subscriber,
+    unsubscribe_subscriber_as_admin, update_subscriber, update_subscriber_tags,
+};
@@ -0,0 +1,312 @@
+use actix_web::{web, HttpRequest, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+use crate::audit::{record_audit_event, AuditAction, NewAuditEvent};
+use crate::authentication::UserId;
+use crate::domain::{EmailPolicy, SubscriberEmail, SubscriberName, SubscriptionStatus};
+use crate::utils::{e400, e500, form_values, see_other};
+
+#[derive(serde::Deserialize)]
+pub struct SubscriberFormData {
+    email: String,
+    name: String,
+}
+
+#[tracing::instrument(
+    name = "Update a subscriber",
+    skip(form, pool, email_policy, user_id, request),
+    fields(user_id=%*user_id)
+)]
+pub async fn update_subscriber(
+    subscriber_id: web::Path<Uuid>,
+    form: web::Form<SubscriberFormData>,
+    pool: web::Data<PgPool>,
+    email_policy: web::Data<EmailPolicy>,
+    user_id: web::ReqData<UserId>,
+    request: HttpRequest,
+) -> Result<HttpResponse, actix_web::Error> {
+    let subscriber_id = subscriber_id.into_inner();
+    let location = format!("/admin/subscribers/{}", subscriber_id);
+    // The same rules as for signing up
+    let SubscriberFormData { email, name } = form.0;
+    let email = SubscriberEmail::parse(email).and_then(|email| email_policy.check(&email).map(|()| email));
+    let (email, name) = match (email, SubscriberName::parse(name)) {
+        (Ok(email), Ok(name)) => (email, name),
+        (Err(e), _) | (_, Err(e)) => {
+            FlashMessage::error(e).send();
+            return Ok(see_other(&location));
+        }
+    };
+    let mut transaction = pool.begin().await.context("Failed to acquire a Postgres connection from the pool").map_err(e500)?;
+    let old_email = match sqlx::query!("SELECT email FROM subscriptions WHERE id = $1 FOR UPDATE", subscriber_id)
+        .fetch_optional(&mut *transaction)
+        .await
+        .context("Failed to look up the subscriber.")
+        .map_err(e500)?
+    {
+        Some(r) => r.email,
+        None => return Ok(HttpResponse::NotFound().finish()),
+    };
+    let result = sqlx::query!(
+        r#"UPDATE subscriptions SET email = $2, name = $3 WHERE id = $1"#,
+        subscriber_id,
+        email.as_ref(),
+        name.as_ref(),
+    )
+        .execute(&mut *transaction)
+        .await;
+    match result {
+        Ok(_) => {}
+        Err(sqlx::Error::Database(e)) if e.is_unique_violation() => {
+            FlashMessage::error("Another subscriber already uses this email address.").send();
+            return Ok(see_other(&location));
+        }
+        Err(e) => return Err(e500(anyhow::anyhow!(e).context("Failed to update the subscriber."))),
+    }
+    // Issues still waiting to go out follow the subscriber to the new address
+    sqlx::query!(
+        "UPDATE issue_delivery_queue SET subscriber_email = $2 WHERE subscriber_email = $1",
+        old_email,
+        email.as_ref(),
+    )
+        .execute(&mut *transaction)
+        .await
+        .context("Failed to move pending deliveries to the new address.")
+        .map_err(e500)?;
+    let event = NewAuditEvent::new(Some(**user_id), AuditAction::SubscriberUpdated, &request)
+        .target(subscriber_id);
+    record_audit_event(&mut *transaction, event).await.map_err(e500)?;
+    transaction.commit().await.context("Failed to commit SQL transaction to update a subscriber.").map_err(e500)?;
+    FlashMessage::info("The subscriber has been saved.").send();
+    Ok(see_other(&location))
+}
+
+// The tag checkboxes share a name, so the body is parsed by hand
+#[tracing::instrument(
+    name = "Update a subscriber's tags",
+    skip(body, pool, user_id, request),
+    fields(user_id=%*user_id)
+)]
+pub async fn update_subscriber_tags(
+    subscriber_id: web::Path<Uuid>,
+    body: web::Bytes,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+    request: HttpRequest,
+) -> Result<HttpResponse, actix_web::Error> {
+    let subscriber_id = subscriber_id.into_inner();
+    let tag_ids = form_values(&body, "tag_ids")
+        .iter()
+        .map(|id| Uuid::parse_str(id))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(e400)?;
+    let mut transaction = pool.begin().await.context("Failed to acquire a Postgres connection from the pool").map_err(e500)?;
+    let exists = sqlx::query!("SELECT id FROM subscriptions WHERE id = $1 FOR UPDATE", subscriber_id)
+        .fetch_optional(&mut *transaction)
+        .await
+        .context("Failed to look up the subscriber.")
+        .map_err(e500)?
+        .is_some();
+    if !exists {
+        return Ok(HttpResponse::NotFound().finish());
+    }
+    sqlx::query!(
+        "DELETE FROM subscriber_tags WHERE subscriber_id = $1 AND NOT tag_id = ANY($2)",
+        subscriber_id,
+        &tag_ids,
+    )
+        .execute(&mut *transaction)
+        .await
+        .context("Failed to remove the subscriber's tags.")
+        .map_err(e500)?;
+    // Unknown tags are left out rather than failing the whole form
+    sqlx::query!(
+        r#"
+        INSERT INTO subscriber_tags (subscriber_id, tag_id, tagged_at)
+        SELECT $1, tag_id, now() FROM tags WHERE tag_id = ANY($2)
+        ON CONFLICT DO NOTHING
+        "#,
+        subscriber_id,
+        &tag_ids,
+    )
+        .execute(&mut *transaction)
+        .await
+        .context("Failed to tag the subscriber.")
+        .map_err(e500)?;
+    let event = NewAuditEvent::new(Some(**user_id), AuditAction::SubscriberUpdated, &request)
+        .target(subscriber_id);
+    record_audit_event(&mut *transaction, event).await.map_err(e500)?;
+    transaction.commit().await.context("Failed to commit SQL transaction to tag a subscriber.").map_err(e500)?;
+    FlashMessage::info("The subscriber's tags have been saved.").send();
+    Ok(see_other(&format!("/admin/subscribers/{}", subscriber_id)))
+}
+
+#[tracing::instrument(name = "Confirm a subscriber", skip(pool, user_id, request), fields(user_id=%*user_id))]
+pub async fn confirm_subscriber_as_admin(
+    subscriber_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+    request: HttpRequest,
+) -> Result<HttpResponse, actix_web::Error> {
+    change_status(
+        subscriber_id.into_inner(),
+        SubscriptionStatus::Confirmed,
+        &pool,
+        **user_id,
+        &request,
+    )
+    .await
+}
+
+#[tracing::instrument(name = "Unsubscribe a subscriber", skip(pool, user_id, request), fields(user_id=%*user_id))]
+pub async fn unsubscribe_subscriber_as_admin(
+    subscriber_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+    request: HttpRequest,
+) -> Result<HttpResponse, actix_web::Error> {
+    change_status(
+        subscriber_id.into_inner(),
+        SubscriptionStatus::Unsubscribed,
+        &pool,
+        **user_id,
+        &request,
+    )
+    .await
+}
+
+#[tracing::instrument(name = "Suppress a subscriber", skip(pool, user_id, request), fields(user_id=%*user_id))]
+pub async fn suppress_subscriber(
+    subscriber_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+    request: HttpRequest,
+) -> Result<HttpResponse, actix_web::Error> {
+    change_status(
+        subscriber_id.into_inner(),
+        SubscriptionStatus::Suppressed,
+        &pool,
+        **user_id,
+        &request,
+    )
+    .await
+}
+
+// Admins may move a subscriber to any status, including out of suppression.
+async fn change_status(
+    subscriber_id: Uuid,
+    status: SubscriptionStatus,
+    pool: &PgPool,
+    user_id: Uuid,
+    request: &HttpRequest,
+) -> Result<HttpResponse, actix_web::Error> {
+    let location = format!("/admin/subscribers/{}", subscriber_id);
+    let mut transaction = pool.begin().await.context("Failed to acquire a Postgres connection from the pool").map_err(e500)?;
+    let email = sqlx::query!(
+        r#"
+        UPDATE subscriptions
+        SET status = $2,
+            confirmed_at = CASE WHEN $2 = 'confirmed' THEN COALESCE(confirmed_at, now()) ELSE confirmed_at END
+        WHERE id = $1
+        RETURNING email
+        "#,
+        subscriber_id,
+        status.as_str(),
+    )
+        .fetch_optional(&mut *transaction)
+        .await
+        .context("Failed to update the subscription status.")
+        .map_err(e500)?
+        .map(|r| r.email);
+    let Some(email) = email else {
+        return Ok(HttpResponse::NotFound().finish());
+    };
+    // Confirming settles the lists waiting for confirmation; unsubscribing
+    // leaves every list. Suppression overrides the lists anyway.
+    sqlx::query!(
+        r#"
+        UPDATE list_memberships
+        SET status = $2,
+            confirmed_at = CASE WHEN $2 = 'confirmed' THEN COALESCE(confirmed_at, now()) ELSE confirmed_at END
+        WHERE subscriber_id = $1
+        AND (
+            ($2 = 'confirmed' AND status = 'pending_confirmation')
+            OR ($2 = 'unsubscribed' AND status <> 'unsubscribed')
+        )
+        "#,
+        subscriber_id,
+        status.as_str(),
+    )
+        .execute(&mut *transaction)
+        .await
+        .context("Failed to update the subscriber's lists.")
+        .map_err(e500)?;
+    let action = match status {
+        SubscriptionStatus::Confirmed => AuditAction::SubscriberConfirmed,
+        SubscriptionStatus::Suppressed => AuditAction::SubscriberSuppressed,
+        _ => AuditAction::SubscriberUnsubscribed,
+    };
+    if action != AuditAction::SubscriberConfirmed {
+        cancel_pending_deliveries(&mut transaction, &email).await.map_err(e500)?;
+    }
+    let event = NewAuditEvent::new(Some(user_id), action, request).target(subscriber_id);
+    record_audit_event(&mut *transaction, event).await.map_err(e500)?;
+    transaction.commit().await.context("Failed to commit SQL transaction to update a subscription status.").map_err(e500)?;
+    FlashMessage::info(format!("The subscriber is now {}.", status.as_str().replace('_', " "))).send();
+    Ok(see_other(&location))
+}
+
+#[tracing::instrument(name = "Delete a subscriber", skip(pool, user_id, request), fields(user_id=%*user_id))]
+pub async fn delete_subscriber(
+    subscriber_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+    request: HttpRequest,
+) -> Result<HttpResponse, actix_web::Error> {
+    let subscriber_id = subscriber_id.into_inner();
+    let mut transaction = pool.begin().await.context("Failed to acquire a Postgres connection from the pool").map_err(e500)?;
+    sqlx::query!(
+        r#"DELETE FROM subscription_tokens WHERE subscriber_id = $1"#,
+        subscriber_id,
+    )
+        .execute(&mut *transaction)
+        .await
+        .context("Failed to delete the subscription tokens.")
+        .map_err(e500)?;
+    let email = sqlx::query!(
+        r#"DELETE FROM subscriptions WHERE id = $1 RETURNING email"#,
+        subscriber_id,
+    )
+        .fetch_optional(&mut *transaction)
+        .await
+        .context("Failed to delete the subscriber.")
+        .map_err(e500)?
+        .map(|r| r.email);
+    let Some(email) = email else {
+        return Ok(HttpResponse::NotFound().finish());
+    };
+    cancel_pending_deliveries(&mut transaction, &email).await.map_err(e500)?;
+    let event = NewAuditEvent::new(Some(**user_id), AuditAction::SubscriberDeleted, &request)
+        .target(subscriber_id);
+    record_audit_event(&mut *transaction, event).await.map_err(e500)?;
+    transaction.commit().await.context("Failed to commit SQL transaction to delete a subscriber.").map_err(e500)?;
+    FlashMessage::info("The subscriber has been deleted.").send();
+    Ok(see_other("/admin/subscribers"))
+}
+
+// Issues already queued for the address would otherwise still go out
+async fn cancel_pending_deliveries(
+    transaction: &mut Transaction<'_, Postgres>,
+    email: &str,
+) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        r#"DELETE FROM issue_delivery_queue WHERE subscriber_email = $1"#,
+        email,
+    )
+        .execute(&mut **transaction)
+        .await
+        .context("Failed to cancel pending deliveries.")?;
+    Ok(())
+}
@@ -0,0 +1,59 @@
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use sqlx::PgPool;
+use std::fmt::Write;
+use crate::authentication::csrf_field;
+use crate::session_state::TypedSession;
+use crate::tags::get_tags;
+use crate::utils::e500;
+
+pub async fn tags_form(
+    flash_messages: IncomingFlashMessages,
+    pool: web::Data<PgPool>,
+    session: TypedSession,
+) -> Result<HttpResponse, actix_web::Error> {
+    let csrf_field = csrf_field(&session.csrf_token().map_err(e500)?);
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", htmlescape::encode_minimal(m.content())).unwrap();
+    }
+    let mut rows_html = String::new();
+    for tag in get_tags(&pool).await.map_err(e500)? {
+        writeln!(
+            rows_html,
+            "<tr><td>{}</td><td>{}</td></tr>",
+            htmlescape::encode_minimal(&tag.name),
+            tag.n_subscribers,
+        )
+        .unwrap();
+    }
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+                r#"<!DOCTYPE html>
+                <html lang="en">
+                <head>
+                <meta http-equiv="content-type" content="text/html; charset=utf-8">
+                <title>Tags</title>
+                </head>
+                <body>
+                {msg_html}
+                <table>
+                <tr><th>Tag</th><th>Subscribers</th></tr>
+                {rows_html}
+                </table>
+                <p>Tags are added to subscribers from their page.</p>
+                <h2>New tag</h2>
+                <form action="/admin/tags" method="post">
+                {csrf_field}
+                <label>Name
+                <input type="text" placeholder="Enter the tag name" name="name">
+                </label>
+                <button type="submit">Create tag</button>
+                </form>
+                <p><a href="/admin/dashboard">&lt;- Back</a></p>
+                </body>
+                </html>"#,
+    )))
+}
@@ -0,0 +1,49 @@
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use sqlx::PgPool;
+use unicode_segmentation::UnicodeSegmentation;
+use uuid::Uuid;
+use crate::utils::{e500, see_other};
+
+const MAX_TAG_LENGTH: usize = 64;
+
+#[derive(serde::Deserialize)]
+pub struct FormData {
+    name: String,
+}
+
+#[tracing::instrument(name = "Create a tag", skip(form, pool))]
+pub async fn create_tag(
+    form: web::Form<FormData>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let name = form.0.name.trim().to_string();
+    if name.is_empty() || name.graphemes(true).count() > MAX_TAG_LENGTH {
+        FlashMessage::error(format!(
+            "Tags need a name of at most {} characters.",
+            MAX_TAG_LENGTH
+        ))
+            .send();
+        return Ok(see_other("/admin/tags"));
+    }
+    let result = sqlx::query!(
+        r#"
+        INSERT INTO tags (tag_id, name, created_at)
+        VALUES ($1, $2, now())
+        ON CONFLICT (name) DO NOTHING
+        "#,
+        Uuid::new_v4(),
+        name,
+    )
+        .execute(pool.get_ref())
+        .await
+        .context("Failed to store the tag.")
+        .map_err(e500)?;
+    if result.rows_affected() == 0 {
+        FlashMessage::error("This tag already exists.").send();
+    } else {
+        FlashMessage::info("The tag has been created.").send();
+    }
+    Ok(see_other("/admin/tags"))
+}
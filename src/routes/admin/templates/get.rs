@@ -0,0 +1,198 @@
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use std::fmt::Write;
+use uuid::Uuid;
+use crate::authentication::csrf_field;
+use crate::custom_fields::get_field_keys;
+use crate::domain::{known_variables, NewsletterTemplate};
+use crate::session_state::TypedSession;
+use crate::utils::e500;
+
+pub struct TemplateRecord {
+    pub template_id: Uuid,
+    pub name: String,
+    pub header_html: String,
+    pub footer_html: String,
+    pub css: String,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl From<TemplateRecord> for NewsletterTemplate {
+    fn from(r: TemplateRecord) -> Self {
+        Self {
+            header_html: r.header_html,
+            footer_html: r.footer_html,
+            css: r.css,
+        }
+    }
+}
+
+#[tracing::instrument(name = "Get newsletter templates", skip(pool))]
+pub(crate) async fn get_newsletter_templates(
+    pool: &PgPool,
+) -> Result<Vec<TemplateRecord>, anyhow::Error> {
+    let templates = sqlx::query_as!(
+        TemplateRecord,
+        r#"
+        SELECT template_id, name, header_html, footer_html, css, updated_at
+        FROM newsletter_templates
+        ORDER BY name
+        "#,
+    )
+        .fetch_all(pool)
+        .await
+        .context("Failed to retrieve newsletter templates.")?;
+    Ok(templates)
+}
+
+#[tracing::instrument(name = "Get newsletter template", skip(pool))]
+pub(crate) async fn get_newsletter_template(
+    pool: &PgPool,
+    template_id: Uuid,
+) -> Result<Option<TemplateRecord>, anyhow::Error> {
+    let template = sqlx::query_as!(
+        TemplateRecord,
+        r#"
+        SELECT template_id, name, header_html, footer_html, css, updated_at
+        FROM newsletter_templates
+        WHERE template_id = $1
+        "#,
+        template_id,
+    )
+        .fetch_optional(pool)
+        .await
+        .context("Failed to retrieve a newsletter template.")?;
+    Ok(template)
+}
+
+fn template_form(
+    action: &str,
+    template: Option<&TemplateRecord>,
+    submit: &str,
+    csrf_field: &str,
+    field_keys: &[String],
+) -> String {
+    let value = |f: fn(&TemplateRecord) -> &str| {
+        template
+            .map(|t| htmlescape::encode_minimal(f(t)))
+            .unwrap_or_default()
+    };
+    let variables = known_variables(field_keys)
+        .iter()
+        .map(|v| format!("{{{{{}}}}}", v))
+        .collect::<Vec<_>>()
+        .join(", ");
+    format!(
+        r#"<form action="{action}" method="post">
+                {csrf_field}
+                <label>Name:<br>
+                <input type="text" name="name" value="{name}">
+                </label>
+                <br>
+                <label>Header HTML:<br>
+                <textarea name="header_html" rows="10" cols="50">{header_html}</textarea>
+                </label>
+                <br>
+                <label>Footer HTML:<br>
+                <textarea name="footer_html" rows="10" cols="50">{footer_html}</textarea>
+                </label>
+                <br>
+                <label>CSS:<br>
+                <textarea name="css" rows="10" cols="50">{css}</textarea>
+                </label>
+                <br>
+                <p>Available variables: {variables}</p>
+                <button type="submit">{submit}</button>
+                </form>"#,
+        name = value(|t| &t.name),
+        header_html = value(|t| &t.header_html),
+        footer_html = value(|t| &t.footer_html),
+        css = value(|t| &t.css),
+    )
+}
+
+fn page(title: &str, flash_messages: IncomingFlashMessages, body: &str) -> HttpResponse {
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", htmlescape::encode_minimal(m.content())).unwrap();
+    }
+    HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+                r#"<!DOCTYPE html>
+                <html lang="en">
+                <head>
+                <meta http-equiv="content-type" content="text/html; charset=utf-8">
+                <title>{title}</title>
+                </head>
+                <body>
+                {msg_html}
+                {body}
+                </body>
+                </html>"#,
+        ))
+}
+
+pub async fn newsletter_templates_form(
+    flash_messages: IncomingFlashMessages,
+    pool: web::Data<PgPool>,
+    session: TypedSession,
+) -> Result<HttpResponse, actix_web::Error> {
+    let csrf_field = csrf_field(&session.csrf_token().map_err(e500)?);
+    let templates = get_newsletter_templates(&pool).await.map_err(e500)?;
+    let mut list_html = String::new();
+    for template in &templates {
+        writeln!(
+            list_html,
+            r#"<li><a href="/admin/templates/{}">{}</a> (updated {})</li>"#,
+            template.template_id,
+            htmlescape::encode_minimal(&template.name),
+            template.updated_at.format("%Y-%m-%d %H:%M:%S UTC"),
+        )
+        .unwrap();
+    }
+    let body = format!(
+        r#"<ul>{list_html}</ul>
+                <h2>New template</h2>
+                {}
+                <p><a href="/admin/dashboard">&lt;- Back</a></p>"#,
+        template_form(
+            "/admin/templates",
+            None,
+            "Create template",
+            &csrf_field,
+            &get_field_keys(pool.get_ref()).await.map_err(e500)?,
+        ),
+    );
+    Ok(page("Newsletter Templates", flash_messages, &body))
+}
+
+pub async fn edit_newsletter_template_form(
+    template_id: web::Path<Uuid>,
+    flash_messages: IncomingFlashMessages,
+    pool: web::Data<PgPool>,
+    session: TypedSession,
+) -> Result<HttpResponse, actix_web::Error> {
+    let csrf_field = csrf_field(&session.csrf_token().map_err(e500)?);
+    let template_id = template_id.into_inner();
+    let template = match get_newsletter_template(&pool, template_id).await.map_err(e500)? {
+        Some(template) => template,
+        None => return Ok(HttpResponse::NotFound().finish()),
+    };
+    let body = format!(
+        r#"{}
+                <p><a href="/admin/templates">&lt;- Back</a></p>"#,
+        template_form(
+            &format!("/admin/templates/{}", template_id),
+            Some(&template),
+            "Save template",
+            &csrf_field,
+            &get_field_keys(pool.get_ref()).await.map_err(e500)?,
+        ),
+    );
+    Ok(page("Edit Newsletter Template", flash_messages, &body))
+}
@@ -0,0 +1,6 @@
+mod get;
+mod post;
+
+pub use get::{newsletter_templates_form, edit_newsletter_template_form};
+pub(crate) use get::{get_newsletter_template, get_newsletter_templates};
+pub use post::{create_newsletter_template, update_newsletter_template};
@@ -0,0 +1,115 @@
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use sqlx::PgPool;
+use uuid::Uuid;
+use crate::custom_fields::get_field_keys;
+use crate::domain::{known_variables, NewsletterTemplate};
+use crate::utils::{e500, see_other};
+
+#[derive(serde::Deserialize)]
+pub struct FormData {
+    name: String,
+    header_html: String,
+    footer_html: String,
+    css: String,
+}
+
+impl FormData {
+    fn parse(self, variables: &[&str]) -> Result<(String, NewsletterTemplate), String> {
+        let name = self.name.trim().to_string();
+        if name.is_empty() {
+            return Err("Templates need a name.".into());
+        }
+        let template = NewsletterTemplate::parse(
+            self.header_html,
+            self.footer_html,
+            self.css,
+            variables,
+        )?;
+        Ok((name, template))
+    }
+}
+
+#[tracing::instrument(name = "Create a newsletter template", skip(form, pool))]
+pub async fn create_newsletter_template(
+    form: web::Form<FormData>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let field_keys = get_field_keys(pool.get_ref()).await.map_err(e500)?;
+    let (name, template) = match form.0.parse(&known_variables(&field_keys)) {
+        Ok(parsed) => parsed,
+        Err(e) => {
+            FlashMessage::error(e).send();
+            return Ok(see_other("/admin/templates"));
+        }
+    };
+    let template_id = Uuid::new_v4();
+    let result = sqlx::query!(
+        r#"
+        INSERT INTO newsletter_templates (
+            template_id, name, header_html, footer_html, css, created_at, updated_at
+        )
+        VALUES ($1, $2, $3, $4, $5, now(), now())
+        ON CONFLICT (name) DO NOTHING
+        "#,
+        template_id,
+        name,
+        template.header_html,
+        template.footer_html,
+        template.css,
+    )
+        .execute(pool.get_ref())
+        .await
+        .context("Failed to store the newsletter template.")
+        .map_err(e500)?;
+    if result.rows_affected() == 0 {
+        FlashMessage::error("A template with this name already exists.").send();
+        return Ok(see_other("/admin/templates"));
+    }
+    FlashMessage::info("The template has been created.").send();
+    Ok(see_other(&format!("/admin/templates/{}", template_id)))
+}
+
+#[tracing::instrument(name = "Update a newsletter template", skip(form, pool))]
+pub async fn update_newsletter_template(
+    template_id: web::Path<Uuid>,
+    form: web::Form<FormData>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let template_id = template_id.into_inner();
+    let location = format!("/admin/templates/{}", template_id);
+    let field_keys = get_field_keys(pool.get_ref()).await.map_err(e500)?;
+    let (name, template) = match form.0.parse(&known_variables(&field_keys)) {
+        Ok(parsed) => parsed,
+        Err(e) => {
+            FlashMessage::error(e).send();
+            return Ok(see_other(&location));
+        }
+    };
+    let result = sqlx::query!(
+        r#"
+        UPDATE newsletter_templates
+        SET name = $2, header_html = $3, footer_html = $4, css = $5, updated_at = now()
+        WHERE template_id = $1
+        "#,
+        template_id,
+        name,
+        template.header_html,
+        template.footer_html,
+        template.css,
+    )
+        .execute(pool.get_ref())
+        .await;
+    match result {
+        Ok(r) if r.rows_affected() == 0 => return Ok(HttpResponse::NotFound().finish()),
+        Ok(_) => {}
+        Err(sqlx::Error::Database(e)) if e.is_unique_violation() => {
+            FlashMessage::error("A template with this name already exists.").send();
+            return Ok(see_other(&location));
+        }
+        Err(e) => return Err(e500(anyhow::anyhow!(e).context("Failed to update the newsletter template."))),
+    }
+    FlashMessage::info("The template has been saved.").send();
+    Ok(see_other(&location))
+}
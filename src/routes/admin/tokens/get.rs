@@ -0,0 +1,87 @@
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use sqlx::PgPool;
+use std::fmt::Write;
+use crate::authentication::{csrf_field, get_api_tokens, ApiTokenScope, UserId};
+use crate::session_state::TypedSession;
+use crate::utils::e500;
+
+pub async fn api_tokens_form(
+    flash_messages: IncomingFlashMessages,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+    session: TypedSession,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", htmlescape::encode_minimal(m.content())).unwrap();
+    }
+    let csrf_field = csrf_field(&session.csrf_token().map_err(e500)?);
+    let tokens = get_api_tokens(&pool, **user_id).await.map_err(e500)?;
+    let mut rows_html = String::new();
+    for token in &tokens {
+        let last_used_at = token
+            .last_used_at
+            .map(|t| t.format("%Y-%m-%d %H:%M:%S UTC").to_string())
+            .unwrap_or_else(|| "never".into());
+        let status = match token.revoked_at {
+            Some(revoked_at) => format!("revoked {}", revoked_at.format("%Y-%m-%d %H:%M:%S UTC")),
+            None => format!(
+                r#"<form action="/admin/tokens/{}/revoke" method="post">{}<button type="submit">Revoke</button></form>"#,
+                token.token_id, csrf_field
+            ),
+        };
+        writeln!(
+            rows_html,
+            "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+            htmlescape::encode_minimal(&token.name),
+            token.scopes.join(", "),
+            token.created_at.format("%Y-%m-%d %H:%M:%S UTC"),
+            last_used_at,
+            status,
+        )
+        .unwrap();
+    }
+    let mut scopes_html = String::new();
+    for scope in ApiTokenScope::ALL {
+        writeln!(
+            scopes_html,
+            r#"<label><input type="checkbox" name="scope" value="{0}" checked> {0}</label><br>"#,
+            scope.as_str()
+        )
+        .unwrap();
+    }
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+                r#"<!DOCTYPE html>
+                <html lang="en">
+                <head>
+                <meta http-equiv="content-type" content="text/html; charset=utf-8">
+                <title>API Tokens</title>
+                </head>
+                <body>
+                {msg_html}
+                <table>
+                <tr><th>Name</th><th>Scopes</th><th>Created at</th><th>Last used at</th><th>Status</th></tr>
+                {rows_html}
+                </table>
+                <form action="/admin/tokens" method="post">
+                {csrf_field}
+                <label>Name:<br>
+                <input
+                type="text"
+                placeholder="What will use this token?"
+                name="name"
+                >
+                </label>
+                <br>
+                {scopes_html}
+                <button type="submit">Create token</button>
+                </form>
+                <p><a href="/admin/dashboard">&lt;- Back</a></p>
+                </body>
+                </html>"#,
+    )))
+}
@@ -0,0 +1,79 @@
+use actix_web::http::header::{CacheControl, CacheDirective, ContentType};
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use secrecy::ExposeSecret;
+use sqlx::PgPool;
+use uuid::Uuid;
+use crate::authentication::{create_api_token, revoke_api_token, ApiTokenScope, UserId};
+use crate::utils::{e500, see_other};
+
+// The form is read as raw pairs since it can carry several `scope` fields.
+#[tracing::instrument(name = "Create an API token", skip(form, pool, user_id), fields(user_id=%*user_id))]
+pub async fn create_token(
+    form: web::Form<Vec<(String, String)>>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut name = String::new();
+    let mut scopes = Vec::new();
+    for (key, value) in form.0 {
+        match key.as_str() {
+            "name" => name = value.trim().to_string(),
+            "scope" => match ApiTokenScope::try_from(value) {
+                Ok(scope) => scopes.push(scope),
+                Err(e) => {
+                    FlashMessage::error(e).send();
+                    return Ok(see_other("/admin/tokens"));
+                }
+            },
+            _ => {}
+        }
+    }
+    if name.is_empty() {
+        FlashMessage::error("API tokens need a name.").send();
+        return Ok(see_other("/admin/tokens"));
+    }
+    if scopes.is_empty() {
+        FlashMessage::error("API tokens need at least one scope.").send();
+        return Ok(see_other("/admin/tokens"));
+    }
+    let token = create_api_token(&pool, **user_id, &name, &scopes)
+        .await
+        .map_err(e500)?;
+    // Shown in the response itself, and kept out of caches, rather than
+    // carried to the next page in a cookie
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .insert_header(CacheControl(vec![CacheDirective::NoStore]))
+        .body(format!(
+                r#"<!DOCTYPE html>
+                <html lang="en">
+                <head>
+                <meta http-equiv="content-type" content="text/html; charset=utf-8">
+                <title>New API Token</title>
+                </head>
+                <body>
+                <p>Your new API token is <code>{}</code> - copy it now, it will not be shown again.</p>
+                <p><a href="/admin/tokens">&lt;- Back</a></p>
+                </body>
+                </html>"#,
+                token.expose_secret(),
+    )))
+}
+
+#[tracing::instrument(name = "Revoke an API token", skip(pool, user_id), fields(user_id=%*user_id))]
+pub async fn revoke_token(
+    token_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    if revoke_api_token(&pool, **user_id, token_id.into_inner())
+        .await
+        .map_err(e500)?
+    {
+        FlashMessage::info("The API token has been revoked.").send();
+    } else {
+        FlashMessage::error("The API token does not exist or was already revoked.").send();
+    }
+    Ok(see_other("/admin/tokens"))
+}
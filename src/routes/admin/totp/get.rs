@@ -0,0 +1,86 @@
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use qrcode::render::svg;
+use qrcode::QrCode;
+use secrecy::ExposeSecret;
+use sqlx::PgPool;
+use std::fmt::Write;
+use crate::authentication::{csrf_field, get_totp_settings, otpauth_uri, UserId};
+use crate::routes::admin::dashboard::get_username;
+use crate::session_state::TypedSession;
+use crate::utils::e500;
+
+// The name authenticator apps list the account under
+const TOTP_ISSUER: &str = "zero2prod";
+
+pub async fn totp_form(
+    flash_messages: IncomingFlashMessages,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+    session: TypedSession,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", htmlescape::encode_minimal(m.content())).unwrap();
+    }
+    let csrf_field = csrf_field(&session.csrf_token().map_err(e500)?);
+    let code_input = r#"<label>Authentication code
+                <input type="text" inputmode="numeric" autocomplete="one-time-code" placeholder="Enter the code from your app" name="code">
+                </label>"#;
+    let content_html = match get_totp_settings(&pool, **user_id).await.map_err(e500)? {
+        Some(settings) if settings.enabled => format!(
+            r#"<p>Two-factor authentication is enabled.</p>
+                <form action="/admin/totp/disable" method="post">
+                {csrf_field}
+                {code_input}
+                <button type="submit">Disable two-factor authentication</button>
+                </form>"#,
+        ),
+        Some(settings) => {
+            let username = get_username(**user_id, &pool).await.map_err(e500)?;
+            let uri = otpauth_uri(&settings.secret, &username, TOTP_ISSUER);
+            let qr_svg = QrCode::new(uri.as_bytes())
+                .map_err(e500)?
+                .render::<svg::Color>()
+                .min_dimensions(200, 200)
+                .build();
+            format!(
+                r#"<p>Scan this code with your authenticator app:</p>
+                {qr_svg}
+                <p>Or add the account by hand with this URI:<br><code>{uri}</code></p>
+                <p>Secret: <code>{secret}</code></p>
+                <form action="/admin/totp/confirm" method="post">
+                {csrf_field}
+                {code_input}
+                <button type="submit">Turn on two-factor authentication</button>
+                </form>"#,
+                uri = htmlescape::encode_minimal(&uri),
+                secret = settings.secret.expose_secret(),
+            )
+        }
+        None => format!(
+            r#"<p>Two-factor authentication is disabled.</p>
+                <form action="/admin/totp" method="post">
+                {csrf_field}
+                <button type="submit">Set up two-factor authentication</button>
+                </form>"#,
+        ),
+    };
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+                r#"<!DOCTYPE html>
+                <html lang="en">
+                <head>
+                <meta http-equiv="content-type" content="text/html; charset=utf-8">
+                <title>Two-factor Authentication</title>
+                </head>
+                <body>
+                {msg_html}
+                {content_html}
+                <p><a href="/admin/dashboard">&lt;- Back</a></p>
+                </body>
+                </html>"#,
+    )))
+}
@@ -0,0 +1,5 @@
+mod get;
+mod post;
+
+pub use get::totp_form;
+pub use post::{set_up_totp, turn_off_totp, turn_on_totp};
@@ -0,0 +1,84 @@
+use actix_web::http::header::{CacheControl, CacheDirective, ContentType};
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use sqlx::PgPool;
+use crate::authentication::{
+    disable_totp, enable_totp, get_totp_settings, start_totp_enrollment,
+    verify_second_factor, verify_totp_code, UserId,
+};
+use crate::utils::{e500, see_other};
+
+#[tracing::instrument(name = "Start TOTP enrollment", skip(pool, user_id), fields(user_id=%*user_id))]
+pub async fn set_up_totp(
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    start_totp_enrollment(&pool, **user_id).await.map_err(e500)?;
+    Ok(see_other("/admin/totp"))
+}
+
+#[derive(serde::Deserialize)]
+pub struct FormData {
+    code: String,
+}
+
+#[tracing::instrument(name = "Confirm TOTP enrollment", skip(form, pool, user_id), fields(user_id=%*user_id))]
+pub async fn turn_on_totp(
+    form: web::Form<FormData>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let settings = match get_totp_settings(&pool, **user_id).await.map_err(e500)? {
+        Some(settings) if !settings.enabled => settings,
+        _ => return Ok(see_other("/admin/totp")),
+    };
+    let now = chrono::Utc::now().timestamp();
+    let step = match verify_totp_code(&settings.secret, &form.0.code, now).map_err(e500)? {
+        Some(step) => step,
+        None => {
+            FlashMessage::error("The authentication code is not valid.").send();
+            return Ok(see_other("/admin/totp"));
+        }
+    };
+    let recovery_codes = enable_totp(&pool, **user_id, step).await.map_err(e500)?;
+    // Shown in the response itself, and kept out of caches, rather than
+    // carried to the next page in a cookie
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .insert_header(CacheControl(vec![CacheDirective::NoStore]))
+        .body(format!(
+                r#"<!DOCTYPE html>
+                <html lang="en">
+                <head>
+                <meta http-equiv="content-type" content="text/html; charset=utf-8">
+                <title>Two-factor Authentication</title>
+                </head>
+                <body>
+                <p>Two-factor authentication is now on. Store these recovery codes somewhere safe -
+                each one can replace a code from your app once, and they will not be shown again:</p>
+                <p><code>{}</code></p>
+                <p><a href="/admin/totp">&lt;- Back</a></p>
+                </body>
+                </html>"#,
+                recovery_codes.join(" "),
+    )))
+}
+
+#[tracing::instrument(name = "Disable TOTP", skip(form, pool, user_id), fields(user_id=%*user_id))]
+pub async fn turn_off_totp(
+    form: web::Form<FormData>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    // Someone walking up to an unlocked session should not be able to turn it off
+    if !verify_second_factor(&pool, **user_id, &form.0.code)
+        .await
+        .map_err(e500)?
+    {
+        FlashMessage::error("The authentication code is not valid.").send();
+        return Ok(see_other("/admin/totp"));
+    }
+    disable_totp(&pool, **user_id).await.map_err(e500)?;
+    FlashMessage::info("Two-factor authentication has been turned off.").send();
+    Ok(see_other("/admin/totp"))
+}
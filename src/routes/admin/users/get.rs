@@ -0,0 +1,137 @@
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use std::fmt::Write;
+use uuid::Uuid;
+use crate::authentication::{csrf_field, Role};
+use crate::session_state::TypedSession;
+use crate::utils::e500;
+
+struct UserRecord {
+    user_id: Uuid,
+    username: String,
+    email: Option<String>,
+    role: String,
+}
+
+struct PendingInvitation {
+    email: String,
+    role: String,
+    expires_at: DateTime<Utc>,
+}
+
+#[tracing::instrument(name = "Get users", skip(pool))]
+async fn get_users(pool: &PgPool) -> Result<Vec<UserRecord>, anyhow::Error> {
+    let users = sqlx::query_as!(
+        UserRecord,
+        r#"
+        SELECT user_id, username, email, role
+        FROM users
+        ORDER BY username
+        "#,
+    )
+        .fetch_all(pool)
+        .await
+        .context("Failed to retrieve users.")?;
+    Ok(users)
+}
+
+#[tracing::instrument(name = "Get pending invitations", skip(pool))]
+async fn get_pending_invitations(pool: &PgPool) -> Result<Vec<PendingInvitation>, anyhow::Error> {
+    let invitations = sqlx::query_as!(
+        PendingInvitation,
+        r#"
+        SELECT email, role, expires_at
+        FROM user_invitations
+        WHERE accepted_at IS NULL AND expires_at > now()
+        ORDER BY created_at DESC
+        "#,
+    )
+        .fetch_all(pool)
+        .await
+        .context("Failed to retrieve pending invitations.")?;
+    Ok(invitations)
+}
+
+fn role_options(selected: &str) -> String {
+    let mut options = String::new();
+    for role in Role::ALL {
+        let selected = if role.as_str() == selected { " selected" } else { "" };
+        write!(options, r#"<option value="{0}"{1}>{0}</option>"#, role.as_str(), selected).unwrap();
+    }
+    options
+}
+
+pub async fn users_form(
+    flash_messages: IncomingFlashMessages,
+    pool: web::Data<PgPool>,
+    session: TypedSession,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", htmlescape::encode_minimal(m.content())).unwrap();
+    }
+    let csrf_field = csrf_field(&session.csrf_token().map_err(e500)?);
+    let mut users_html = String::new();
+    for user in get_users(&pool).await.map_err(e500)? {
+        writeln!(
+            users_html,
+            r#"<tr><td>{}</td><td>{}</td><td><form action="/admin/users/{}/role" method="post">{}<select name="role">{}</select><button type="submit">Change role</button></form></td></tr>"#,
+            htmlescape::encode_minimal(&user.username),
+            htmlescape::encode_minimal(user.email.as_deref().unwrap_or("")),
+            user.user_id,
+            csrf_field,
+            role_options(&user.role),
+        )
+        .unwrap();
+    }
+    let mut invitations_html = String::new();
+    for invitation in get_pending_invitations(&pool).await.map_err(e500)? {
+        writeln!(
+            invitations_html,
+            "<li>{} as {} (expires {})</li>",
+            htmlescape::encode_minimal(&invitation.email),
+            invitation.role,
+            invitation.expires_at.format("%Y-%m-%d %H:%M:%S UTC"),
+        )
+        .unwrap();
+    }
+    let invite_roles = role_options(Role::Editor.as_str());
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+                r#"<!DOCTYPE html>
+                <html lang="en">
+                <head>
+                <meta http-equiv="content-type" content="text/html; charset=utf-8">
+                <title>Users</title>
+                </head>
+                <body>
+                {msg_html}
+                <table>
+                <tr><th>Username</th><th>Email</th><th>Role</th></tr>
+                {users_html}
+                </table>
+                <h2>Pending invitations</h2>
+                <ul>{invitations_html}</ul>
+                <h2>Invite a collaborator</h2>
+                <form action="/admin/users/invitations" method="post">
+                {csrf_field}
+                <label>Email:<br>
+                <input type="text" placeholder="Enter their email" name="email">
+                </label>
+                <br>
+                <label>Role:<br>
+                <select name="role">{invite_roles}</select>
+                </label>
+                <br>
+                <button type="submit">Send invitation</button>
+                </form>
+                <p><a href="/admin/dashboard">&lt;- Back</a></p>
+                </body>
+                </html>"#,
+    )))
+}
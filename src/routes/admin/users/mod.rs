@@ -0,0 +1,5 @@
+mod get;
+mod post;
+
+pub use get::users_form;
+pub use post::{invite_user, change_user_role};
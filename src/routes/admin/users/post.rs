@@ -0,0 +1,168 @@
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use chrono::{Duration, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+use crate::authentication::{invitation_tag, Role, UserId, INVITATION_TTL_DAYS};
+use crate::domain::SubscriberEmail;
+use crate::email_client::EmailClient;
+use crate::startup::{ApplicationBaseUrl, HmacSecret};
+use crate::utils::{e500, see_other};
+
+#[derive(serde::Deserialize)]
+pub struct InviteFormData {
+    email: String,
+    role: String,
+}
+
+#[tracing::instrument(
+    name = "Invite a user",
+    skip(form, pool, email_client, base_url, secret, user_id),
+    fields(user_id=%*user_id)
+)]
+pub async fn invite_user(
+    form: web::Form<InviteFormData>,
+    pool: web::Data<PgPool>,
+    email_client: web::Data<EmailClient>,
+    base_url: web::Data<ApplicationBaseUrl>,
+    secret: web::Data<HmacSecret>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let InviteFormData { email, role } = form.0;
+    let (email, role) = match (SubscriberEmail::parse(email), Role::try_from(role)) {
+        (Ok(email), Ok(role)) => (email, role),
+        (Err(e), _) | (_, Err(e)) => {
+            FlashMessage::error(e).send();
+            return Ok(see_other("/admin/users"));
+        }
+    };
+    if email_is_taken(&pool, &email).await.map_err(e500)? {
+        FlashMessage::error("There already is a user with this email.").send();
+        return Ok(see_other("/admin/users"));
+    }
+    let invitation_id = insert_invitation(&pool, &email, role, **user_id)
+        .await
+        .map_err(e500)?;
+    let invitation_link = format!(
+        "{}/invitations/accept?invitation_id={}&tag={}",
+        base_url.0,
+        invitation_id,
+        invitation_tag(&invitation_id, &secret.0),
+    );
+    send_invitation_email(&email_client, &email, role, &invitation_link)
+        .await
+        .context("Failed to send the invitation email.")
+        .map_err(e500)?;
+    FlashMessage::info(format!("An invitation has been sent to {}.", email)).send();
+    Ok(see_other("/admin/users"))
+}
+
+#[tracing::instrument(name = "Check if email is taken", skip(pool))]
+async fn email_is_taken(pool: &PgPool, email: &SubscriberEmail) -> Result<bool, anyhow::Error> {
+    let row = sqlx::query!(
+        "SELECT user_id FROM users WHERE email = $1",
+        email.as_ref(),
+    )
+        .fetch_optional(pool)
+        .await
+        .context("Failed to look up users by email.")?;
+    Ok(row.is_some())
+}
+
+#[tracing::instrument(name = "Store invitation", skip(pool))]
+async fn insert_invitation(
+    pool: &PgPool,
+    email: &SubscriberEmail,
+    role: Role,
+    invited_by: Uuid,
+) -> Result<Uuid, anyhow::Error> {
+    let invitation_id = Uuid::new_v4();
+    sqlx::query!(
+        r#"
+        INSERT INTO user_invitations (
+            invitation_id, email, role, invited_by, created_at, expires_at
+        )
+        VALUES ($1, $2, $3, $4, now(), $5)
+        "#,
+        invitation_id,
+        email.as_ref(),
+        role.as_str(),
+        invited_by,
+        Utc::now() + Duration::days(INVITATION_TTL_DAYS),
+    )
+        .execute(pool)
+        .await
+        .context("Failed to store the invitation.")?;
+    Ok(invitation_id)
+}
+
+async fn send_invitation_email(
+    email_client: &EmailClient,
+    email: &SubscriberEmail,
+    role: Role,
+    invitation_link: &str,
+) -> Result<(), reqwest::Error> {
+    let html_body = format!(
+        "You have been invited to help run our newsletter as {}.<br />\
+        Click <a href=\"{}\">here</a> to set up your account.",
+        role, invitation_link,
+    );
+    let plain_body = format!(
+        "You have been invited to help run our newsletter as {}.\n\
+        Visit {} to set up your account.",
+        role, invitation_link,
+    );
+    email_client
+        .send_email(email, "You have been invited!", &html_body, &plain_body)
+        .await
+}
+
+#[derive(serde::Deserialize)]
+pub struct RoleFormData {
+    role: String,
+}
+
+#[tracing::instrument(name = "Change a user's role", skip(form, pool))]
+pub async fn change_user_role(
+    target_user_id: web::Path<Uuid>,
+    form: web::Form<RoleFormData>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let role = match Role::try_from(form.0.role) {
+        Ok(role) => role,
+        Err(e) => {
+            FlashMessage::error(e).send();
+            return Ok(see_other("/admin/users"));
+        }
+    };
+    // Lock the owners so two concurrent demotions cannot both succeed
+    let mut transaction = pool.begin().await.map_err(e500)?;
+    let owners: Vec<Uuid> = sqlx::query!(
+        "SELECT user_id FROM users WHERE role = 'owner' FOR UPDATE"
+    )
+        .fetch_all(&mut *transaction)
+        .await
+        .map_err(e500)?
+        .into_iter()
+        .map(|r| r.user_id)
+        .collect();
+    if role != Role::Owner && owners == [*target_user_id] {
+        FlashMessage::error("There must always be at least one owner.").send();
+        return Ok(see_other("/admin/users"));
+    }
+    let result = sqlx::query!(
+        "UPDATE users SET role = $1 WHERE user_id = $2",
+        role.as_str(),
+        *target_user_id,
+    )
+        .execute(&mut *transaction)
+        .await
+        .map_err(e500)?;
+    if result.rows_affected() == 0 {
+        return Ok(HttpResponse::NotFound().finish());
+    }
+    transaction.commit().await.map_err(e500)?;
+    FlashMessage::info("The user's role has been changed.").send();
+    Ok(see_other("/admin/users"))
+}
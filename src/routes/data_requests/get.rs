@@ -0,0 +1,378 @@
+use actix_web::http::header::{ContentDisposition, ContentType, DispositionParam, DispositionType};
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use secrecy::{ExposeSecret, Secret};
+use sqlx::PgPool;
+use std::fmt::Write;
+use uuid::Uuid;
+use super::{check_data_request_token, DataRequestKind};
+use crate::audit::{record_audit_event, AuditAction, NewAuditEvent};
+use crate::utils::e500;
+
+pub async fn data_request_form(
+    flash_messages: IncomingFlashMessages,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", htmlescape::encode_minimal(m.content())).unwrap();
+    }
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+                r#"<!DOCTYPE html>
+                <html lang="en">
+                <head>
+                <meta http-equiv="content-type" content="text/html; charset=utf-8">
+                <title>Your data</title>
+                </head>
+                <body>
+                {msg_html}
+                <p>Enter your email address and we will email you a link to confirm your request.</p>
+                <form action="/subscriptions/data" method="post">
+                <label>Email
+                <input type="text" placeholder="Enter your email address" name="email">
+                </label>
+                <br>
+                <label><input type="radio" name="kind" value="access" checked> Send me a copy of the data you hold about me</label>
+                <br>
+                <label><input type="radio" name="kind" value="erasure"> Erase the data you hold about me</label>
+                <br>
+                <button type="submit">Send the link</button>
+                </form>
+                </body>
+                </html>"#,
+    )))
+}
+
+#[derive(serde::Deserialize)]
+pub struct Parameters {
+    token: Secret<String>,
+}
+
+fn expired_link() -> HttpResponse {
+    HttpResponse::Gone()
+        .content_type(ContentType::html())
+        .body("<p>This link has already been used or has expired.</p>")
+}
+
+#[derive(serde::Serialize)]
+struct SubscriberData {
+    email: String,
+    generated_at: DateTime<Utc>,
+    subscription: Option<SubscriptionData>,
+    subscription_tokens: Vec<String>,
+    list_memberships: Vec<ListMembership>,
+    tags: Vec<String>,
+    fields: Vec<FieldValue>,
+    email_changes: Vec<EmailChange>,
+    pending_deliveries: Vec<PendingDelivery>,
+    delivery_history: Vec<Delivery>,
+    imports: Vec<ImportedRow>,
+    events: Vec<Event>,
+    data_requests: Vec<DataRequest>,
+}
+
+#[derive(serde::Serialize)]
+struct SubscriptionData {
+    id: Uuid,
+    email: String,
+    name: String,
+    status: String,
+    subscribed_at: DateTime<Utc>,
+    confirmed_at: Option<DateTime<Utc>>,
+    last_engaged_at: Option<DateTime<Utc>>,
+    frequency: String,
+    muted_topics: Vec<String>,
+}
+
+#[derive(serde::Serialize)]
+struct ListMembership {
+    list: String,
+    status: String,
+    subscribed_at: DateTime<Utc>,
+    confirmed_at: Option<DateTime<Utc>>,
+}
+
+#[derive(serde::Serialize)]
+struct FieldValue {
+    field: String,
+    value: String,
+}
+
+#[derive(serde::Serialize)]
+struct EmailChange {
+    new_email: String,
+    created_at: DateTime<Utc>,
+    expires_at: DateTime<Utc>,
+}
+
+#[derive(serde::Serialize)]
+struct PendingDelivery {
+    newsletter_issue_id: Uuid,
+    title: String,
+}
+
+#[derive(serde::Serialize)]
+struct Delivery {
+    newsletter_issue_id: Uuid,
+    title: String,
+    outcome: String,
+    attempted_at: DateTime<Utc>,
+}
+
+#[derive(serde::Serialize)]
+struct ImportedRow {
+    import_id: Uuid,
+    imported_at: DateTime<Utc>,
+    name: String,
+    outcome: String,
+    detail: String,
+}
+
+#[derive(serde::Serialize)]
+struct Event {
+    occurred_at: DateTime<Utc>,
+    action: String,
+    ip_address: Option<String>,
+}
+
+#[derive(serde::Serialize)]
+struct DataRequest {
+    kind: String,
+    created_at: DateTime<Utc>,
+    used_at: Option<DateTime<Utc>>,
+}
+
+// Everything tied to the address, whichever table it is in
+#[tracing::instrument(name = "Collect subscriber data", skip(pool, email))]
+async fn collect_subscriber_data(pool: &PgPool, email: &str) -> Result<SubscriberData, anyhow::Error> {
+    let subscription = sqlx::query_as!(
+        SubscriptionData,
+        r#"
+        SELECT id, email, name, status, subscribed_at, confirmed_at, last_engaged_at, frequency, muted_topics
+        FROM subscriptions
+        WHERE email = $1
+        "#,
+        email,
+    )
+        .fetch_optional(pool)
+        .await
+        .context("Failed to retrieve the subscription.")?;
+    let subscriber_id = subscription.as_ref().map(|s| s.id);
+    let subscription_tokens = sqlx::query!(
+        "SELECT subscription_token FROM subscription_tokens WHERE subscriber_id = $1",
+        subscriber_id,
+    )
+        .fetch_all(pool)
+        .await
+        .context("Failed to retrieve the subscription tokens.")?
+        .into_iter()
+        .map(|r| r.subscription_token)
+        .collect();
+    let list_memberships = sqlx::query_as!(
+        ListMembership,
+        r#"
+        SELECT l.name AS list, m.status, m.subscribed_at, m.confirmed_at
+        FROM list_memberships m
+        JOIN lists l ON l.list_id = m.list_id
+        WHERE m.subscriber_id = $1
+        ORDER BY m.subscribed_at
+        "#,
+        subscriber_id,
+    )
+        .fetch_all(pool)
+        .await
+        .context("Failed to retrieve the list memberships.")?;
+    let tags = sqlx::query!(
+        r#"
+        SELECT t.name
+        FROM subscriber_tags st
+        JOIN tags t ON t.tag_id = st.tag_id
+        WHERE st.subscriber_id = $1
+        ORDER BY t.name
+        "#,
+        subscriber_id,
+    )
+        .fetch_all(pool)
+        .await
+        .context("Failed to retrieve the subscriber's tags.")?
+        .into_iter()
+        .map(|r| r.name)
+        .collect();
+    let fields = sqlx::query_as!(
+        FieldValue,
+        r#"
+        SELECT f.label AS field, v.value
+        FROM subscriber_field_values v
+        JOIN custom_fields f ON f.field_id = v.field_id
+        WHERE v.subscriber_id = $1
+        ORDER BY f.created_at
+        "#,
+        subscriber_id,
+    )
+        .fetch_all(pool)
+        .await
+        .context("Failed to retrieve the subscriber's field values.")?;
+    // Changes the subscription asked for, and changes to this address that
+    // another subscription asked for and it has not confirmed
+    let email_changes = sqlx::query_as!(
+        EmailChange,
+        r#"
+        SELECT new_email, created_at, expires_at
+        FROM email_changes
+        WHERE subscriber_id = $1 OR new_email = $2
+        ORDER BY created_at
+        "#,
+        subscriber_id,
+        email,
+    )
+        .fetch_all(pool)
+        .await
+        .context("Failed to retrieve the email changes.")?;
+    let pending_deliveries = sqlx::query_as!(
+        PendingDelivery,
+        r#"
+        SELECT q.newsletter_issue_id, i.title
+        FROM issue_delivery_queue q
+        JOIN newsletter_issues i ON i.newsletter_issue_id = q.newsletter_issue_id
+        WHERE q.subscriber_email = $1
+        "#,
+        email,
+    )
+        .fetch_all(pool)
+        .await
+        .context("Failed to retrieve the pending deliveries.")?;
+    let delivery_history = sqlx::query_as!(
+        Delivery,
+        r#"
+        SELECT l.newsletter_issue_id, i.title, l.outcome, l.attempted_at
+        FROM issue_delivery_log l
+        JOIN newsletter_issues i ON i.newsletter_issue_id = l.newsletter_issue_id
+        WHERE l.subscriber_email = $1
+        ORDER BY l.attempted_at
+        "#,
+        email,
+    )
+        .fetch_all(pool)
+        .await
+        .context("Failed to retrieve the delivery history.")?;
+    let imports = sqlx::query_as!(
+        ImportedRow,
+        r#"
+        SELECT r.import_id, i.created_at AS imported_at, r.name, r.outcome, r.detail
+        FROM subscriber_import_rows r
+        JOIN subscriber_imports i ON i.import_id = r.import_id
+        WHERE r.email = $1
+        ORDER BY i.created_at
+        "#,
+        email,
+    )
+        .fetch_all(pool)
+        .await
+        .context("Failed to retrieve the imported rows.")?;
+    let events = sqlx::query_as!(
+        Event,
+        r#"
+        SELECT occurred_at, action, ip_address
+        FROM audit_events
+        WHERE target = $1::UUID::TEXT
+        ORDER BY occurred_at
+        "#,
+        subscriber_id,
+    )
+        .fetch_all(pool)
+        .await
+        .context("Failed to retrieve the subscriber's history.")?;
+    let data_requests = sqlx::query_as!(
+        DataRequest,
+        r#"
+        SELECT kind, created_at, used_at
+        FROM data_requests
+        WHERE email = $1
+        ORDER BY created_at
+        "#,
+        email,
+    )
+        .fetch_all(pool)
+        .await
+        .context("Failed to retrieve the data requests.")?;
+    Ok(SubscriberData {
+        email: email.to_string(),
+        generated_at: Utc::now(),
+        subscription,
+        subscription_tokens,
+        list_memberships,
+        tags,
+        fields,
+        email_changes,
+        pending_deliveries,
+        delivery_history,
+        imports,
+        events,
+        data_requests,
+    })
+}
+
+#[tracing::instrument(name = "Download subscriber data", skip(parameters, pool))]
+pub async fn download_subscriber_data(
+    parameters: web::Query<Parameters>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let email = match check_data_request_token(&pool, &parameters.0.token, DataRequestKind::Access)
+        .await
+        .map_err(e500)?
+    {
+        Some(email) => email,
+        None => return Ok(expired_link()),
+    };
+    let data = collect_subscriber_data(&pool, &email).await.map_err(e500)?;
+    let mut event = NewAuditEvent::by_subscriber(AuditAction::SubscriberDataAccessed);
+    if let Some(subscription) = &data.subscription {
+        event = event.target(subscription.id);
+    }
+    record_audit_event(pool.get_ref(), event).await.map_err(e500)?;
+    Ok(HttpResponse::Ok()
+        .insert_header(ContentDisposition {
+            disposition: DispositionType::Attachment,
+            parameters: vec![DispositionParam::Filename("your-data.json".into())],
+        })
+        .json(data))
+}
+
+// Following the link only asks for confirmation: mail scanners open links too
+pub async fn erasure_form(
+    parameters: web::Query<Parameters>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let token = parameters.0.token;
+    let email = match check_data_request_token(&pool, &token, DataRequestKind::Erasure)
+        .await
+        .map_err(e500)?
+    {
+        Some(email) => email,
+        None => return Ok(expired_link()),
+    };
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+                r#"<!DOCTYPE html>
+                <html lang="en">
+                <head>
+                <meta http-equiv="content-type" content="text/html; charset=utf-8">
+                <title>Erase your data</title>
+                </head>
+                <body>
+                <p>This erases everything we hold about {email}: your subscription and its history.
+                You will no longer receive the newsletter, and the address cannot be imported again.</p>
+                <form action="/subscriptions/data/erasure" method="post">
+                <input type="hidden" name="token" value="{token}">
+                <button type="submit">Erase my data</button>
+                </form>
+                </body>
+                </html>"#,
+                email = htmlescape::encode_minimal(&email),
+                token = htmlescape::encode_minimal(token.expose_secret()),
+    )))
+}
@@ -0,0 +1,126 @@
+mod get;
+mod post;
+
+pub use get::{data_request_form, download_subscriber_data, erasure_form};
+pub use post::{erase_subscriber_data, request_subscriber_data};
+
+use anyhow::Context;
+use chrono::{Duration, Utc};
+use rand::distributions::Alphanumeric;
+use rand::{thread_rng, Rng};
+use secrecy::{ExposeSecret, Secret};
+use sha2::{Digest, Sha256};
+use sqlx::{Executor, PgPool, Postgres};
+
+// How long the emailed link stays valid
+pub const DATA_REQUEST_TTL_HOURS: i64 = 24;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DataRequestKind {
+    // A copy of everything we hold about the address
+    Access,
+    Erasure,
+}
+
+impl DataRequestKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DataRequestKind::Access => "access",
+            DataRequestKind::Erasure => "erasure",
+        }
+    }
+}
+
+impl TryFrom<String> for DataRequestKind {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        match s.as_str() {
+            "access" => Ok(DataRequestKind::Access),
+            "erasure" => Ok(DataRequestKind::Erasure),
+            other => Err(format!("{} is not a known kind of request.", other)),
+        }
+    }
+}
+
+fn hash_request_token(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
+
+#[tracing::instrument(name = "Create data request token", skip(pool, email))]
+async fn create_data_request_token(
+    pool: &PgPool,
+    email: &str,
+    kind: DataRequestKind,
+) -> Result<Secret<String>, anyhow::Error> {
+    let token: String = {
+        let mut rng = thread_rng();
+        std::iter::repeat_with(|| rng.sample(Alphanumeric))
+            .map(char::from)
+            .take(40)
+            .collect()
+    };
+    sqlx::query!(
+        r#"
+        INSERT INTO data_requests (token_hash, email, kind, created_at, expires_at)
+        VALUES ($1, $2, $3, now(), $4)
+        "#,
+        hash_request_token(&token),
+        email,
+        kind.as_str(),
+        Utc::now() + Duration::hours(DATA_REQUEST_TTL_HOURS),
+    )
+        .execute(pool)
+        .await
+        .context("Failed to store the data request token.")?;
+    Ok(Secret::new(token))
+}
+
+// Returns the address the token was issued for, if it is still usable.
+// Access links can be followed again until they expire.
+#[tracing::instrument(name = "Check data request token", skip(token, pool))]
+async fn check_data_request_token(
+    pool: &PgPool,
+    token: &Secret<String>,
+    kind: DataRequestKind,
+) -> Result<Option<String>, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"
+        SELECT email
+        FROM data_requests
+        WHERE token_hash = $1 AND kind = $2 AND used_at IS NULL AND expires_at > now()
+        "#,
+        hash_request_token(token.expose_secret()),
+        kind.as_str(),
+    )
+        .fetch_optional(pool)
+        .await
+        .context("Failed to look up the data request token.")?;
+    Ok(row.map(|r| r.email))
+}
+
+// Like `check_data_request_token`, but the token cannot be used again
+#[tracing::instrument(name = "Consume data request token", skip(token, executor))]
+async fn consume_data_request_token<'c, E>(
+    executor: E,
+    token: &Secret<String>,
+    kind: DataRequestKind,
+) -> Result<Option<String>, anyhow::Error>
+where
+    E: Executor<'c, Database = Postgres>,
+{
+    let row = sqlx::query!(
+        r#"
+        UPDATE data_requests
+        SET used_at = now()
+        WHERE token_hash = $1 AND kind = $2 AND used_at IS NULL AND expires_at > now()
+        RETURNING email
+        "#,
+        hash_request_token(token.expose_secret()),
+        kind.as_str(),
+    )
+        .fetch_optional(executor)
+        .await
+        .context("Failed to consume the data request token.")?;
+    Ok(row.map(|r| r.email))
+}
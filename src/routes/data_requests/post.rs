@@ -0,0 +1,250 @@
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpRequest, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use secrecy::{ExposeSecret, Secret};
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+use super::{consume_data_request_token, create_data_request_token, DataRequestKind, DATA_REQUEST_TTL_HOURS};
+use crate::audit::{record_audit_event, AuditAction, NewAuditEvent};
+use crate::client_ip::client_ip;
+use crate::domain::SubscriberEmail;
+use crate::email_client::EmailClient;
+use crate::startup::ApplicationBaseUrl;
+use crate::subscribe_protection::{SubscribeAttemptSource, SubscribeProtection};
+use crate::suppression_list::{suppress_email, SuppressionKeys, SuppressionReason};
+use crate::telemetry::spawn_with_tracing;
+use crate::utils::{e500, see_other};
+
+#[derive(serde::Deserialize)]
+pub struct RequestFormData {
+    email: String,
+    kind: String,
+}
+
+#[tracing::instrument(
+    name = "Request subscriber data",
+    skip(form, pool, email_client, base_url, protection),
+    fields(kind=%form.kind)
+)]
+pub async fn request_subscriber_data(
+    form: web::Form<RequestFormData>,
+    pool: web::Data<PgPool>,
+    email_client: web::Data<EmailClient>,
+    base_url: web::Data<ApplicationBaseUrl>,
+    protection: web::Data<SubscribeProtection>,
+    request: HttpRequest,
+) -> Result<HttpResponse, actix_web::Error> {
+    let RequestFormData { email, kind } = form.0;
+    let (email, kind) = match (SubscriberEmail::parse(email), DataRequestKind::try_from(kind)) {
+        (Ok(email), Ok(kind)) => (email, kind),
+        (Err(e), _) | (_, Err(e)) => {
+            FlashMessage::error(e).send();
+            return Ok(see_other("/subscriptions/data"));
+        }
+    };
+    // This form emails whatever address it is given, like the sign-up form,
+    // so it shares its limits. Counted before the lookup, so that hitting
+    // them says nothing about the address either.
+    let mut sources = vec![SubscribeAttemptSource::Address(email.as_ref())];
+    if let Some(ip) = client_ip(&request) {
+        sources.push(SubscribeAttemptSource::Ip(ip));
+    }
+    for source in sources {
+        if !protection.throttle().allow_attempt(source).await.map_err(e500)? {
+            tracing::warn!("Rejected a data request over the attempt limits");
+            FlashMessage::error("Too many requests. Please try again later.").send();
+            return Ok(see_other("/subscriptions/data"));
+        }
+    }
+    // Whatever happens below, the response must not reveal whether we hold
+    // anything about the address. The email goes out in the background, so
+    // that neither the time it takes nor a failure to send it shows.
+    if holds_data_about(&pool, email.as_ref()).await.map_err(e500)? {
+        spawn_with_tracing(async move {
+            if let Err(e) = send_data_request_link(&pool, &email_client, &base_url.0, &email, kind).await {
+                tracing::error!(error.cause_chain = ?e, "Failed to send a data request email.");
+            }
+        });
+    }
+    FlashMessage::info(
+        "If we hold data about this address, we have emailed it a link to confirm your request.",
+    )
+        .send();
+    Ok(see_other("/subscriptions/data"))
+}
+
+async fn send_data_request_link(
+    pool: &PgPool,
+    email_client: &EmailClient,
+    base_url: &str,
+    email: &SubscriberEmail,
+    kind: DataRequestKind,
+) -> Result<(), anyhow::Error> {
+    let token = create_data_request_token(pool, email.as_ref(), kind).await?;
+    let path = match kind {
+        DataRequestKind::Access => "access",
+        DataRequestKind::Erasure => "erasure",
+    };
+    let link = format!(
+        "{}/subscriptions/data/{}?token={}",
+        base_url,
+        path,
+        token.expose_secret(),
+    );
+    send_data_request_email(email_client, email, kind, &link)
+        .await
+        .context("Failed to send the data request email.")
+}
+
+#[tracing::instrument(name = "Check for data about an address", skip(pool, email))]
+async fn holds_data_about(pool: &PgPool, email: &str) -> Result<bool, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"
+        SELECT
+            EXISTS (SELECT 1 FROM subscriptions WHERE email = $1)
+            OR EXISTS (SELECT 1 FROM email_changes WHERE new_email = $1)
+            OR EXISTS (SELECT 1 FROM issue_delivery_queue WHERE subscriber_email = $1)
+            OR EXISTS (SELECT 1 FROM issue_delivery_log WHERE subscriber_email = $1)
+            OR EXISTS (SELECT 1 FROM subscriber_import_rows WHERE email = $1)
+            AS "holds_data!"
+        "#,
+        email,
+    )
+        .fetch_one(pool)
+        .await
+        .context("Failed to check for data about an address.")?;
+    Ok(row.holds_data)
+}
+
+async fn send_data_request_email(
+    email_client: &EmailClient,
+    email: &SubscriberEmail,
+    kind: DataRequestKind,
+    link: &str,
+) -> Result<(), reqwest::Error> {
+    let (subject, action) = match kind {
+        DataRequestKind::Access => ("Your data", "download a copy of the data we hold about you"),
+        DataRequestKind::Erasure => ("Erase your data", "erase the data we hold about you"),
+    };
+    let html_body = format!(
+        "Someone asked to {} on our newsletter.<br />\
+        Click <a href=\"{}\">here</a> to continue. \
+        The link expires in {} hours.<br />\
+        If this was not you, you can ignore this email.",
+        action, link, DATA_REQUEST_TTL_HOURS,
+    );
+    let plain_body = format!(
+        "Someone asked to {} on our newsletter.\n\
+        Visit {} to continue. The link expires in {} hours.\n\
+        If this was not you, you can ignore this email.",
+        action, link, DATA_REQUEST_TTL_HOURS,
+    );
+    email_client
+        .send_email(email, subject, &html_body, &plain_body)
+        .await
+}
+
+#[derive(serde::Deserialize)]
+pub struct ErasureFormData {
+    token: Secret<String>,
+}
+
+#[tracing::instrument(name = "Erase subscriber data", skip(form, pool, suppression_keys))]
+pub async fn erase_subscriber_data(
+    form: web::Form<ErasureFormData>,
+    pool: web::Data<PgPool>,
+    suppression_keys: web::Data<SuppressionKeys>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut transaction = pool.begin().await.context("Failed to acquire a Postgres connection from the pool").map_err(e500)?;
+    let email = match consume_data_request_token(&mut *transaction, &form.0.token, DataRequestKind::Erasure)
+        .await
+        .map_err(e500)?
+    {
+        Some(email) => email,
+        None => {
+            return Ok(HttpResponse::Gone()
+                .content_type(ContentType::html())
+                .body("<p>This link has already been used or has expired.</p>"))
+        }
+    };
+    let subscriber_id = erase(&mut transaction, &email).await.map_err(e500)?;
+    suppress_email(&mut *transaction, &email, SuppressionReason::Erasure, &suppression_keys)
+        .await
+        .map_err(e500)?;
+    let mut event = NewAuditEvent::by_subscriber(AuditAction::SubscriberErased);
+    if let Some(subscriber_id) = subscriber_id {
+        event = event.target(subscriber_id);
+    }
+    record_audit_event(&mut *transaction, event).await.map_err(e500)?;
+    transaction.commit().await.context("Failed to commit SQL transaction to erase subscriber data.").map_err(e500)?;
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body("<p>Your data has been erased.</p>"))
+}
+
+// Rows that only make sense with the address are deleted. Rows that other
+// records count on, like the delivery log behind each issue's statistics,
+// lose the address instead. The audit log is append-only and names
+// subscribers by id alone, so it is kept as a record of the erasure.
+// Returns the id of the subscription, if there was one.
+#[tracing::instrument(name = "Erase data about an address", skip(transaction, email))]
+async fn erase(
+    transaction: &mut Transaction<'_, Postgres>,
+    email: &str,
+) -> Result<Option<Uuid>, anyhow::Error> {
+    let subscriber_id = sqlx::query!(
+        "SELECT id FROM subscriptions WHERE email = $1 FOR UPDATE",
+        email,
+    )
+        .fetch_optional(&mut **transaction)
+        .await
+        .context("Failed to look up the subscription.")?
+        .map(|r| r.id);
+    if let Some(subscriber_id) = subscriber_id {
+        sqlx::query!("DELETE FROM subscription_tokens WHERE subscriber_id = $1", subscriber_id)
+            .execute(&mut **transaction)
+            .await
+            .context("Failed to delete the subscription tokens.")?;
+        sqlx::query!("DELETE FROM subscriptions WHERE id = $1", subscriber_id)
+            .execute(&mut **transaction)
+            .await
+            .context("Failed to delete the subscription.")?;
+    }
+    // Changes the subscription asked for went with it
+    sqlx::query!("DELETE FROM email_changes WHERE new_email = $1", email)
+        .execute(&mut **transaction)
+        .await
+        .context("Failed to delete the pending email changes.")?;
+    sqlx::query!("DELETE FROM issue_delivery_queue WHERE subscriber_email = $1", email)
+        .execute(&mut **transaction)
+        .await
+        .context("Failed to delete the pending deliveries.")?;
+    // A fresh placeholder, unique per address so each issue's log keeps its rows
+    let placeholder = format!("erased:{}", Uuid::new_v4());
+    sqlx::query!(
+        "UPDATE issue_delivery_log SET subscriber_email = $2 WHERE subscriber_email = $1",
+        email,
+        placeholder,
+    )
+        .execute(&mut **transaction)
+        .await
+        .context("Failed to anonymize the delivery history.")?;
+    sqlx::query!(
+        r#"
+        UPDATE subscriber_import_rows
+        SET email = $2, name = '', detail = 'Erased at the request of the subscriber.'
+        WHERE email = $1
+        "#,
+        email,
+        placeholder,
+    )
+        .execute(&mut **transaction)
+        .await
+        .context("Failed to anonymize the imported rows.")?;
+    sqlx::query!("DELETE FROM data_requests WHERE email = $1", email)
+        .execute(&mut **transaction)
+        .await
+        .context("Failed to delete the data requests.")?;
+    Ok(subscriber_id)
+}
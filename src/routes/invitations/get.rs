@@ -0,0 +1,77 @@
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use sqlx::PgPool;
+use std::fmt::Write;
+use uuid::Uuid;
+use crate::authentication::verify_invitation_tag;
+use crate::routes::invitations::get_pending_invitation;
+use crate::startup::HmacSecret;
+use crate::utils::e500;
+
+#[derive(serde::Deserialize)]
+pub struct Parameters {
+    invitation_id: Uuid,
+    tag: String,
+}
+
+pub async fn accept_invitation_form(
+    parameters: web::Query<Parameters>,
+    flash_messages: IncomingFlashMessages,
+    pool: web::Data<PgPool>,
+    secret: web::Data<HmacSecret>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let Parameters { invitation_id, tag } = parameters.0;
+    if verify_invitation_tag(&invitation_id, &tag, &secret.0).is_err() {
+        return Ok(HttpResponse::Unauthorized().finish());
+    }
+    let invitation = match get_pending_invitation(pool.get_ref(), invitation_id)
+        .await
+        .map_err(e500)?
+    {
+        Some(invitation) => invitation,
+        None => {
+            return Ok(HttpResponse::Gone()
+                .content_type(ContentType::html())
+                .body("<p>This invitation has already been used or has expired.</p>"))
+        }
+    };
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", htmlescape::encode_minimal(m.content())).unwrap();
+    }
+    let email = htmlescape::encode_minimal(&invitation.email);
+    let role = invitation.role;
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+                r#"<!DOCTYPE html>
+                <html lang="en">
+                <head>
+                <meta http-equiv="content-type" content="text/html; charset=utf-8">
+                <title>Accept Invitation</title>
+                </head>
+                <body>
+                {msg_html}
+                <p>Set up the {role} account for {email}.</p>
+                <form action="/invitations/accept" method="post">
+                <label>Username
+                <input type="text" placeholder="Choose a username" name="username">
+                </label>
+                <br>
+                <label>Password
+                <input type="password" placeholder="Choose a password" name="password">
+                </label>
+                <br>
+                <label>Confirm password
+                <input type="password" placeholder="Type the password again" name="password_check">
+                </label>
+                <br>
+                <input hidden type="text" name="invitation_id" value="{invitation_id}">
+                <input hidden type="text" name="tag" value="{tag}">
+                <button type="submit">Create account</button>
+                </form>
+                </body>
+                </html>"#,
+    )))
+}
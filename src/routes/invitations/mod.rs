@@ -0,0 +1,45 @@
+mod get;
+mod post;
+
+pub use get::accept_invitation_form;
+pub use post::accept_invitation;
+
+use anyhow::Context;
+use sqlx::{Executor, Postgres};
+use uuid::Uuid;
+use crate::authentication::Role;
+
+pub(crate) struct PendingInvitation {
+    pub email: String,
+    pub role: Role,
+}
+
+// Only invitations that were neither accepted nor left to expire are usable
+#[tracing::instrument(name = "Get pending invitation", skip(executor))]
+pub(crate) async fn get_pending_invitation<'c, E>(
+    executor: E,
+    invitation_id: Uuid,
+) -> Result<Option<PendingInvitation>, anyhow::Error>
+where
+    E: Executor<'c, Database = Postgres>,
+{
+    let row = sqlx::query!(
+        r#"
+        SELECT email, role
+        FROM user_invitations
+        WHERE invitation_id = $1 AND accepted_at IS NULL AND expires_at > now()
+        FOR UPDATE
+        "#,
+        invitation_id,
+    )
+        .fetch_optional(executor)
+        .await
+        .context("Failed to retrieve the invitation.")?;
+    row.map(|r| {
+        Ok(PendingInvitation {
+            email: r.email,
+            role: Role::try_from(r.role).map_err(|e| anyhow::anyhow!(e))?,
+        })
+    })
+    .transpose()
+}
@@ -0,0 +1,122 @@
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use secrecy::{ExposeSecret, Secret};
+use sqlx::PgPool;
+use uuid::Uuid;
+use crate::authentication::{PasswordHashing, compute_password_hash, verify_invitation_tag};
+use crate::domain::NewPassword;
+use crate::routes::invitations::get_pending_invitation;
+use crate::startup::HmacSecret;
+use crate::telemetry::spawn_blocking_with_tracing;
+use crate::utils::{e500, see_other};
+
+#[derive(serde::Deserialize)]
+pub struct FormData {
+    invitation_id: Uuid,
+    tag: String,
+    username: String,
+    password: Secret<String>,
+    password_check: Secret<String>,
+}
+
+#[tracing::instrument(
+    name = "Accept an invitation",
+    skip(form, pool, secret, hashing),
+    fields(invitation_id=%form.invitation_id, user_id=tracing::field::Empty)
+)]
+pub async fn accept_invitation(
+    form: web::Form<FormData>,
+    pool: web::Data<PgPool>,
+    secret: web::Data<HmacSecret>,
+    hashing: web::Data<PasswordHashing>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let FormData { invitation_id, tag, username, password, password_check } = form.0;
+    if verify_invitation_tag(&invitation_id, &tag, &secret.0).is_err() {
+        return Ok(HttpResponse::Unauthorized().finish());
+    }
+    let form_location = format!(
+        "/invitations/accept?invitation_id={}&tag={}",
+        invitation_id, tag
+    );
+    let username = username.trim().to_string();
+    if username.is_empty() {
+        FlashMessage::error("You must choose a username.").send();
+        return Ok(see_other(&form_location));
+    }
+    if password.expose_secret() != password_check.expose_secret() {
+        FlashMessage::error(
+            "You entered two different passwords - the field values must match.",
+        )
+            .send();
+        return Ok(see_other(&form_location));
+    }
+    let password = match NewPassword::parse(password, &[&username]) {
+        Ok(password) => password,
+        Err(e) => {
+            FlashMessage::error(e).send();
+            return Ok(see_other(&form_location));
+        }
+    };
+
+    let mut transaction = pool.begin().await.map_err(e500)?;
+    // Locks the invitation row, so it can only be accepted once
+    let invitation = match get_pending_invitation(&mut *transaction, invitation_id)
+        .await
+        .map_err(e500)?
+    {
+        Some(invitation) => invitation,
+        None => {
+            return Ok(HttpResponse::Gone()
+                .body("This invitation has already been used or has expired."))
+        }
+    };
+    let password_hash = spawn_blocking_with_tracing(move || compute_password_hash(password.into(), &hashing))
+        .await
+        .map_err(e500)?
+        .context("Failed to hash password.")
+        .map_err(e500)?;
+    let user_id = Uuid::new_v4();
+    let inserted = sqlx::query!(
+        r#"
+        INSERT INTO users (user_id, username, password_hash, role, email)
+        VALUES ($1, $2, $3, $4, $5)
+        "#,
+        user_id,
+        username,
+        password_hash.expose_secret(),
+        invitation.role.as_str(),
+        invitation.email,
+    )
+        .execute(&mut *transaction)
+        .await;
+    match inserted {
+        Ok(_) => {}
+        Err(sqlx::Error::Database(e)) if e.is_unique_violation() => {
+            // Two invitations to one address can both be pending
+            let message = match e.constraint() {
+                Some("users_email_key") => "There already is an account with this email address.",
+                _ => "This username is already taken.",
+            };
+            FlashMessage::error(message).send();
+            return Ok(see_other(&form_location));
+        }
+        Err(e) => return Err(e500(e)),
+    }
+    sqlx::query!(
+        r#"
+        UPDATE user_invitations
+        SET accepted_at = now(), accepted_user_id = $2
+        WHERE invitation_id = $1
+        "#,
+        invitation_id,
+        user_id,
+    )
+        .execute(&mut *transaction)
+        .await
+        .map_err(e500)?;
+    transaction.commit().await.map_err(e500)?;
+    tracing::Span::current().record("user_id", tracing::field::display(&user_id));
+    FlashMessage::info("Your account has been created - you can now log in.").send();
+    Ok(see_other("/login"))
+}
@@ -5,7 +5,7 @@ use std::fmt::Write;
 pub async fn login_form(flash_messages: IncomingFlashMessages) -> HttpResponse {
     let mut error_hmtl = String::new();
         for m in flash_messages.iter() {
-            writeln!(error_hmtl, "<p><i>{}</i></p>", m.content()).unwrap();
+            writeln!(error_hmtl, "<p><i>{}</i></p>", htmlescape::encode_minimal(m.content())).unwrap();
         }
     HttpResponse::Ok()
         .content_type(ContentType::html())
@@ -28,6 +28,7 @@ pub async fn login_form(flash_messages: IncomingFlashMessages) -> HttpResponse {
 
                     <button type="submit">Login</button>
                 </form>
+                <p><a href="/password-reset">Forgot your password?</a></p>
             </body>
             </html>
             "#,
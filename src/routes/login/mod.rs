@@ -1,5 +1,8 @@
 mod get;
 mod post;
+mod totp;
 
 pub use get::login_form;
 pub use post::login;
+pub(crate) use post::login_attempt_sources;
+pub use totp::{login_totp, login_totp_form};
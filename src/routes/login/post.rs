@@ -1,8 +1,12 @@
-use actix_web::{HttpResponse, web};
+use actix_web::{HttpRequest, HttpResponse, web};
 use actix_web::http::header::LOCATION;
 use actix_web::error::InternalError;
 use secrecy::Secret;
-use crate::authentication::{validate_credentials, Credentials, AuthError};
+use crate::authentication::{register_session, validate_credentials, Credentials, AuthError, SessionOrigin};
+use crate::authentication::{is_totp_enabled, PasswordHashing};
+use crate::authentication::{lockout_message, LoginAttemptSource, LoginThrottle};
+use crate::audit::{record_audit_event, AuditAction, NewAuditEvent};
+use crate::client_ip::client_ip;
 use crate::routes::error_chain_fmt;
 use crate::startup::HmacSecret;
 use sqlx::PgPool;
@@ -16,7 +20,7 @@ pub struct FormData {
 }
 
 #[tracing::instrument(
-    skip(form, pool, session),
+    skip(form, pool, session, throttle, request, hashing),
     fields(username=tracing::field::Empty, user_id=tracing::field::Empty)
 )]
 pub async fn login(
@@ -24,31 +28,98 @@ pub async fn login(
     pool: web::Data<PgPool>,
     secret: web::Data<HmacSecret>,
     session: TypedSession,
+    throttle: web::Data<LoginThrottle>,
+    request: HttpRequest,
+    hashing: web::Data<PasswordHashing>,
 ) -> Result<HttpResponse, InternalError<LoginError>> {
     let credentials = Credentials {
         username: form.0.username,
         password: form.0.password,
     };
-    tracing::Span::current().record("username", &tracing::field::display(&credentials.username));
-    match validate_credentials(credentials, &pool).await {
+    tracing::Span::current().record("username", tracing::field::display(&credentials.username));
+    let username = credentials.username.clone();
+    let sources = login_attempt_sources(&username, &request);
+    // Refuse before verifying the password, so locked-out attempts cost no Argon2 work
+    if let Some(seconds) = throttle
+        .locked_out_for(&sources)
+        .await
+        .map_err(|e| login_redirect(LoginError::UnexpectedError(e)))?
+    {
+        return Err(login_redirect(LoginError::LockedOut(seconds)));
+    }
+    match validate_credentials(credentials, &pool, &hashing).await {
         Ok(user_id) => {
             tracing::Span::current()
-                .record("user_id", &tracing::field::display(&user_id));
-            session.renew();
-            session
-                .insert_user_id(user_id)
-                .map_err(|e| login_redirect(LoginError::UnexpectedError(e.into())))?;
+                .record("user_id", tracing::field::display(&user_id));
+            let totp_enabled = is_totp_enabled(&pool, user_id)
+                .await
+                .map_err(|e| login_redirect(LoginError::UnexpectedError(e)))?;
+            if totp_enabled {
+                session.renew();
+                session
+                    .insert_pending_login(user_id)
+                    .map_err(|e| login_redirect(LoginError::UnexpectedError(e.into())))?;
+                return Ok(HttpResponse::SeeOther().insert_header((LOCATION, "/login/totp")).finish());
+            }
+            // Only a complete login clears the failures, so the second step
+            // cannot be brute-forced by re-entering the password
+            throttle
+                .record_success(&username)
+                .await
+                .map_err(|e| login_redirect(LoginError::UnexpectedError(e)))?;
+            start_session(&session, &pool, user_id, &request)
+                .await
+                .map_err(|e| login_redirect(LoginError::UnexpectedError(e)))?;
             Ok(HttpResponse::SeeOther().insert_header((LOCATION, "/admin/dashboard")).finish())
         }
         Err(e) => {
             let e = match e {
-                AuthError::InvalidCredentials(_) => LoginError::AuthError(e.into()),
+                AuthError::InvalidCredentials(_) => {
+                    throttle
+                        .record_failure(&sources)
+                        .await
+                        .map_err(|e| login_redirect(LoginError::UnexpectedError(e)))?;
+                    let event = NewAuditEvent::new(None, AuditAction::LoginFailed, &request)
+                        .target(&username);
+                    record_audit_event(pool.get_ref(), event)
+                        .await
+                        .map_err(|e| login_redirect(LoginError::UnexpectedError(e)))?;
+                    LoginError::AuthError(e.into())
+                }
                 AuthError::UnexpectedError(_) => LoginError::UnexpectedError(e.into()),
             };
             Err(login_redirect(e))
         }
     }
 }
+
+pub(crate) fn login_attempt_sources<'a>(
+    username: &'a str,
+    request: &HttpRequest,
+) -> Vec<LoginAttemptSource<'a>> {
+    let mut sources = vec![LoginAttemptSource::Username(username)];
+    if let Some(ip) = client_ip(request) {
+        sources.push(LoginAttemptSource::Ip(ip));
+    }
+    sources
+}
+// Turn `session` into a fully logged-in session for `user_id`
+pub(crate) async fn start_session(
+    session: &TypedSession,
+    pool: &PgPool,
+    user_id: uuid::Uuid,
+    request: &HttpRequest,
+) -> Result<(), anyhow::Error> {
+    let session_id = register_session(pool, user_id, &SessionOrigin::from_request(request)).await?;
+    session.renew();
+    session.remove_pending_login();
+    session.insert_user_id(user_id)?;
+    session.insert_session_id(session_id)?;
+    session.renew_csrf_token()?;
+    record_audit_event(pool, NewAuditEvent::new(Some(user_id), AuditAction::Login, request)).await?;
+    Ok(())
+}
+
 fn login_redirect(e: LoginError) -> InternalError<LoginError> {
     FlashMessage::error(e.to_string()).send();
     let response = HttpResponse::SeeOther()
@@ -61,6 +132,8 @@ fn login_redirect(e: LoginError) -> InternalError<LoginError> {
 pub enum LoginError {
     #[error("Authenicaton failed")]
     AuthError(#[source] anyhow::Error),
+    #[error("{}", lockout_message(*.0))]
+    LockedOut(u64),
     #[error("Something went wrong")]
     UnexpectedError(#[from] anyhow::Error),
 }
@@ -0,0 +1,95 @@
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpRequest, HttpResponse};
+use actix_web_flash_messages::{FlashMessage, IncomingFlashMessages};
+use sqlx::PgPool;
+use std::fmt::Write;
+use crate::audit::{record_audit_event, AuditAction, NewAuditEvent};
+use crate::authentication::{lockout_message, verify_second_factor, LoginThrottle};
+use crate::routes::admin::get_username;
+use crate::routes::login::post::{login_attempt_sources, start_session};
+use crate::session_state::TypedSession;
+use crate::utils::{e500, see_other};
+
+pub async fn login_totp_form(
+    flash_messages: IncomingFlashMessages,
+    session: TypedSession,
+) -> Result<HttpResponse, actix_web::Error> {
+    if session.get_pending_login().map_err(e500)?.is_none() {
+        return Ok(see_other("/login"));
+    }
+    let mut error_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(error_html, "<p><i>{}</i></p>", htmlescape::encode_minimal(m.content())).unwrap();
+    }
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+            <html lang="en">
+            <head>
+                <meta http-equiv="content-type" content="text/html; charset=utf-8">
+                <title>Two-factor authentication</title>
+            </head>
+            <body>
+                {error_html}
+                <form action="/login/totp" method="post">
+                    <label>Authentication code
+                        <input type="text" inputmode="numeric" autocomplete="one-time-code" placeholder="Enter the code from your app, or a recovery code" name="code">
+                    </label>
+
+                    <button type="submit">Verify</button>
+                </form>
+            </body>
+            </html>
+            "#,
+        )))
+}
+
+#[derive(serde::Deserialize)]
+pub struct FormData {
+    code: String,
+}
+
+#[tracing::instrument(
+    name = "Verify the second login factor",
+    skip(form, pool, session, throttle, request),
+    fields(user_id=tracing::field::Empty)
+)]
+pub async fn login_totp(
+    form: web::Form<FormData>,
+    pool: web::Data<PgPool>,
+    session: TypedSession,
+    throttle: web::Data<LoginThrottle>,
+    request: HttpRequest,
+) -> Result<HttpResponse, actix_web::Error> {
+    let user_id = match session.get_pending_login().map_err(e500)? {
+        Some(user_id) => user_id,
+        None => {
+            FlashMessage::error("Your login attempt has expired - please log in again.").send();
+            return Ok(see_other("/login"));
+        }
+    };
+    tracing::Span::current().record("user_id", tracing::field::display(&user_id));
+    // Wrong codes count against the same limits as wrong passwords
+    let username = get_username(user_id, &pool).await.map_err(e500)?;
+    let sources = login_attempt_sources(&username, &request);
+    if let Some(seconds) = throttle.locked_out_for(&sources).await.map_err(e500)? {
+        session.remove_pending_login();
+        FlashMessage::error(lockout_message(seconds)).send();
+        return Ok(see_other("/login"));
+    }
+    if !verify_second_factor(&pool, user_id, &form.0.code)
+        .await
+        .map_err(e500)?
+    {
+        throttle.record_failure(&sources).await.map_err(e500)?;
+        let event = NewAuditEvent::new(Some(user_id), AuditAction::LoginFailed, &request)
+            .target(&username);
+        record_audit_event(pool.get_ref(), event).await.map_err(e500)?;
+        FlashMessage::error("The authentication code is not valid.").send();
+        return Ok(see_other("/login/totp"));
+    }
+    throttle.record_success(&username).await.map_err(e500)?;
+    start_session(&session, &pool, user_id, &request).await.map_err(e500)?;
+    Ok(see_other("/admin/dashboard"))
+}
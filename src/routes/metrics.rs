@@ -0,0 +1,28 @@
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use std::fmt::Write;
+use crate::subscribe_protection::SubscribeProtection;
+use crate::utils::e500;
+
+// Counters in the Prometheus text format. They are served under /admin, so
+// a scraper has to log in like anyone else.
+pub async fn metrics(
+    protection: web::Data<SubscribeProtection>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut body = String::from(
+        "# HELP subscribe_rejections_total Sign-up attempts turned away by the anti-abuse checks.\n\
+        # TYPE subscribe_rejections_total counter\n",
+    );
+    for (reason, count) in protection.throttle().rejection_counts().await.map_err(e500)? {
+        writeln!(
+            body,
+            r#"subscribe_rejections_total{{reason="{}"}} {}"#,
+            reason.as_str(),
+            count,
+        )
+        .unwrap();
+    }
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::plaintext())
+        .body(body))
+}
@@ -1,13 +1,37 @@
 mod health_check;
 mod subscriptions;
 mod subscriptions_confirm;
+mod subscriptions_unsubscribe;
 mod home;
 mod login;
 mod admin;
+mod newsletters;
+mod invitations;
+mod setup;
+mod password_reset;
+mod data_requests;
+mod preferences;
+mod metrics;
 
 pub use health_check::*;
+pub use metrics::metrics;
 pub use subscriptions::*;
 pub use subscriptions_confirm::*;
+pub use subscriptions_unsubscribe::{unsubscribe, unsubscribe_form, unsubscribe_subscriber};
 pub use home::*;
 pub use login::*;
 pub use admin::*;
+pub use newsletters::*;
+pub use invitations::{accept_invitation, accept_invitation_form};
+pub use setup::{set_up_first_owner, setup_form};
+pub use password_reset::{
+    password_reset_form, request_password_reset, request_password_reset_form, reset_password,
+};
+pub use data_requests::{
+    data_request_form, download_subscriber_data, erase_subscriber_data, erasure_form,
+    request_subscriber_data,
+};
+pub use preferences::{
+    confirm_email_change, preferences_form, request_email_change,
+    unsubscribe_from_preferences, update_preferences,
+};
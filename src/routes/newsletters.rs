@@ -3,11 +3,18 @@ use actix_web::http::StatusCode;
 use actix_web::http::header::HeaderValue;
 use reqwest::header;
 use sqlx::PgPool;
-use crate::domain::SubscriberEmail;
-use crate::email_client::EmailClient;
-use crate::routes::error_chain_fmt;
-use crate::authentication::AuthError;
-use crate::authentication::{validate_credentials, Credentials};
+use crate::routes::{error_chain_fmt, login_attempt_sources};
+use crate::audit::{record_audit_event, AuditAction, NewAuditEvent};
+use crate::routes::{insert_newsletter_issue, enqueue_delivery_tasks, parse_topic, resolve_lists, resolve_segment};
+use crate::configuration::PreferenceSettings;
+use crate::domain::Segment;
+use crate::routes::{prepare_newsletter_content, ContentError};
+use crate::authentication::{AuthError, UserId};
+use crate::authentication::{validate_credentials, Credentials, PasswordHashing};
+use crate::authentication::{validate_api_token, ApiTokenScope};
+use crate::authentication::{get_user_role, is_totp_enabled};
+use crate::authentication::LoginThrottle;
+use crate::idempotency::{try_processing, NextAction, IdempotencyKey, save_response};
 use anyhow::Context;
 use base64::Engine;
 use secrecy::Secret;
@@ -17,6 +24,12 @@ use secrecy::Secret;
 pub enum PublishError {
     #[error("Authentication failed")]
     AuthError(#[source] anyhow::Error),
+    #[error("{0}")]
+    ValidationError(String),
+    #[error("Forbidden")]
+    Forbidden(#[source] anyhow::Error),
+    #[error("Too many failed authentication attempts")]
+    LockedOut(u64),
     #[error(transparent)]
     UnexpectedError(#[from] anyhow::Error),
 }
@@ -27,12 +40,32 @@ impl std::fmt::Debug for PublishError {
     }
 }
 
+impl From<ContentError> for PublishError {
+    fn from(e: ContentError) -> Self {
+        match e {
+            ContentError::ValidationError(e) => PublishError::ValidationError(e),
+            ContentError::UnexpectedError(e) => PublishError::UnexpectedError(e),
+        }
+    }
+}
+
 impl ResponseError for PublishError {
     fn error_response(&self) -> HttpResponse {
         match self {
             PublishError::UnexpectedError(_) => {
                 HttpResponse::new(StatusCode::INTERNAL_SERVER_ERROR)
             }
+            PublishError::ValidationError(message) => {
+                HttpResponse::BadRequest().body(message.clone())
+            }
+            PublishError::Forbidden(_) => {
+                HttpResponse::Forbidden().body("Your role does not allow publishing.")
+            }
+            PublishError::LockedOut(seconds) => {
+                HttpResponse::TooManyRequests()
+                    .insert_header((header::RETRY_AFTER, seconds.to_string()))
+                    .body("Too many failed authentication attempts.")
+            }
             PublishError::AuthError(_) => {
                 let mut response = HttpResponse::new(StatusCode::UNAUTHORIZED);
                 let header_value = HeaderValue::from_str(r#"Basic realm="publish""#)
@@ -40,43 +73,29 @@ impl ResponseError for PublishError {
                 response
                     .headers_mut()
                     .insert(header::WWW_AUTHENTICATE, header_value);
+                let header_value = HeaderValue::from_str(r#"Bearer realm="publish""#)
+                    .unwrap();
+                response
+                    .headers_mut()
+                    .append(header::WWW_AUTHENTICATE, header_value);
                 response
             },
         }
     }
 }
 
-struct ConfirmedSubscriber{
-    email: SubscriberEmail,
-}
-
-#[tracing::instrument(name = "Get confirmed subscribers.", skip(connection_pool))]
-async fn get_confirmed_subscribers(
-    connection_pool: &PgPool,
-) -> Result<Vec<Result<ConfirmedSubscriber, anyhow::Error>>, anyhow::Error> {
-    let confirmed_subscribers = sqlx::query!(
-        r#"
-        SELECT email
-        FROM subscriptions
-        WHERE status = 'confirmed'
-        "#,
-    )
-        .fetch_all(connection_pool)
-        .await?
-
-        .into_iter()
-        .map(|r| match SubscriberEmail::parse(r.email) {
-            Ok(email) => Ok(ConfirmedSubscriber{ email }),
-            Err(error) => Err(anyhow::anyhow!(error)),
-        })
-    .collect();
-    Ok(confirmed_subscribers)
-}
-
 #[derive(serde::Deserialize)]
 pub struct BodyData {
     title: String,
     content: Content,
+    template_id: Option<uuid::Uuid>,
+    topic: Option<String>,
+    // The default list if left out
+    #[serde(default)]
+    list_ids: Vec<uuid::Uuid>,
+    // Everyone on the lists if left out
+    #[serde(default)]
+    segment: Segment,
 }
 
 #[derive(serde::Deserialize)]
@@ -85,6 +104,11 @@ pub struct Content {
     text: String,
 }
 
+#[derive(serde::Serialize)]
+struct PublishedIssue {
+    newsletter_issue_id: uuid::Uuid,
+}
+
 fn basic_authentication(headers: &actix_web::http::header::HeaderMap) -> Result<Credentials, anyhow::Error>{
     // Check header value is present and valid
     let header_value = headers
@@ -97,7 +121,7 @@ fn basic_authentication(headers: &actix_web::http::header::HeaderMap) -> Result<
     let base64encoded_segment = header_value
         .strip_prefix("Basic ")
         .context("The authorization scheme was not 'Basic'.")?;
-    
+
     // Decode the encoded bytes
     let decoded_bytes = base64::engine::general_purpose::STANDARD
         .decode(base64encoded_segment)
@@ -108,7 +132,7 @@ fn basic_authentication(headers: &actix_web::http::header::HeaderMap) -> Result<
         .context("The decoded credentials string is not valid UTF8.")?;
 
     // break credentials into two fields split on colon
-    let mut credentials = decoded_credentials.splitn(2, ":");
+    let mut credentials = decoded_credentials.splitn(2, ':');
 
 
     let username = credentials
@@ -131,55 +155,155 @@ fn basic_authentication(headers: &actix_web::http::header::HeaderMap) -> Result<
     })
 }
 
+fn bearer_token(headers: &actix_web::http::header::HeaderMap) -> Option<Secret<String>> {
+    headers
+        .get("Authorization")?
+        .to_str()
+        .ok()?
+        .strip_prefix("Bearer ")
+        .map(|token| Secret::new(token.trim().to_string()))
+}
+
+// API clients authenticate either with an API token (`Bearer`) or with an
+// admin's username and password (`Basic`).
+async fn authenticate(
+    request: &HttpRequest,
+    connection_pool: &PgPool,
+    throttle: &LoginThrottle,
+    hashing: &PasswordHashing,
+) -> Result<uuid::Uuid, PublishError> {
+    let outcome = if let Some(token) = bearer_token(request.headers()) {
+        validate_api_token(token, ApiTokenScope::PublishNewsletters, connection_pool).await
+    } else {
+        let credentials = basic_authentication(request.headers())
+            .map_err(PublishError::AuthError)?;
+        tracing::Span::current().record(
+            "username",
+            tracing::field::display(&credentials.username)
+        );
+        // Passwords share the login page's limits; API tokens are too
+        // long to guess, so they are not throttled.
+        let username = credentials.username.clone();
+        let sources = login_attempt_sources(&username, request);
+        if let Some(seconds) = throttle.locked_out_for(&sources).await? {
+            return Err(PublishError::LockedOut(seconds));
+        }
+        let outcome = match validate_credentials(credentials, connection_pool, hashing).await {
+            // A password alone must not get around two-factor authentication
+            Ok(user_id) => match is_totp_enabled(connection_pool, user_id).await {
+                Ok(false) => Ok(user_id),
+                Ok(true) => Err(AuthError::InvalidCredentials(anyhow::anyhow!(
+                    "Users with two-factor authentication must publish with an API token."
+                ))),
+                Err(e) => Err(AuthError::UnexpectedError(e)),
+            },
+            Err(e) => Err(e),
+        };
+        // Only a password that lets the user in clears their failures
+        match &outcome {
+            Ok(_) => throttle.record_success(&username).await?,
+            Err(AuthError::InvalidCredentials(_)) => throttle.record_failure(&sources).await?,
+            Err(AuthError::UnexpectedError(_)) => {}
+        }
+        outcome
+    };
+    outcome.map_err(|e| match e {
+        AuthError::InvalidCredentials(_) => PublishError::AuthError(e.into()),
+        AuthError::UnexpectedError(_) => PublishError::UnexpectedError(e.into()),
+    })
+}
+
+fn idempotency_key(request: &HttpRequest) -> Result<IdempotencyKey, PublishError> {
+    let header_value = request
+        .headers()
+        .get("Idempotency-Key")
+        .ok_or_else(|| {
+            PublishError::ValidationError("The 'Idempotency-Key' header is missing.".into())
+        })?
+        .to_str()
+        .map_err(|_| {
+            PublishError::ValidationError(
+                "The 'Idempotency-Key' header was not a valid UTF8 string.".into()
+            )
+        })?;
+    header_value
+        .to_string()
+        .try_into()
+        .map_err(|e: anyhow::Error| PublishError::ValidationError(e.to_string()))
+}
+
 #[tracing::instrument(
-    name = "Publish a newsletter issue",
-    skip(body, connection_pool, email_client, request),
-    fields(username=tracing::field::Empty, user_id=tracing::field::Empty)
+    name = "Publish a newsletter issue through the API",
+    skip(body, connection_pool, preferences, request, throttle, hashing),
+    fields(
+        username=tracing::field::Empty,
+        user_id=tracing::field::Empty,
+        newsletter_issue_id=tracing::field::Empty
+    )
 )]
-pub async fn publish_newsletter(
+pub async fn publish_newsletter_api(
     body: web::Json<BodyData>,
     connection_pool: web::Data<PgPool>,
-    email_client: web::Data<EmailClient>,
     request: HttpRequest,
+    throttle: web::Data<LoginThrottle>,
+    hashing: web::Data<PasswordHashing>,
+    preferences: web::Data<PreferenceSettings>,
     ) -> Result<HttpResponse, PublishError> {
-    let subscribers = get_confirmed_subscribers(&connection_pool).await?;
-    let credentials = basic_authentication(request.headers())
-        .map_err(PublishError::AuthError)?;
-    tracing::Span::current().record(
-        "username",
-        &tracing::field::display(&credentials.username)
-    );
-    let user_id = validate_credentials(credentials, &connection_pool)
+    let user_id = authenticate(&request, &connection_pool, &throttle, &hashing).await?;
+    tracing::Span::current().record("user_id", tracing::field::display(&user_id));
+    let role = get_user_role(&connection_pool, user_id)
+        .await?
+        .context("The authenticated user does not exist anymore.")?;
+    if !role.can_publish() {
+        return Err(PublishError::Forbidden(anyhow::anyhow!(
+            "Users with the {} role cannot publish.",
+            role
+        )));
+    }
+    let user_id = UserId::from(user_id);
+
+    let idempotency_key = idempotency_key(&request)?;
+    let BodyData { title, content, template_id, topic, list_ids, segment } = body.0;
+    let topic = parse_topic(&preferences, topic).map_err(PublishError::ValidationError)?;
+    let content = prepare_newsletter_content(
+        &connection_pool,
+        template_id,
+        title,
+        content.text,
+        content.html,
+    )
+        .await?;
+    let list_ids = resolve_lists(&connection_pool, list_ids).await?;
+    let segment = resolve_segment(&connection_pool, segment).await?;
+    let mut transaction = match try_processing(&connection_pool, &idempotency_key, *user_id)
+        .await?
+    {
+        NextAction::StartProcessing(t) => t,
+        NextAction::ReturnSavedResponse(saved_response) => return Ok(saved_response),
+    };
+    let issue_id = insert_newsletter_issue(
+        &mut transaction,
+        &user_id,
+        template_id,
+        topic.as_deref(),
+        &list_ids,
+        &segment,
+        &content,
+    )
         .await
-        .map_err(|e| match e {
-            AuthError::InvalidCredentials(_) => PublishError::AuthError(e.into()),
-            AuthError::UnexpectedError(_) => PublishError::UnexpectedError(e.into()),
-        })?;
+        .context("Failed to store newsletter issue details.")?;
+    tracing::Span::current().record("newsletter_issue_id", tracing::field::display(&issue_id));
+    enqueue_delivery_tasks(&mut transaction, issue_id)
+        .await
+        .context("Failed to enqueue delivery tasks.")?;
+    let event = NewAuditEvent::new(Some(*user_id), AuditAction::NewsletterPublished, &request)
+        .target(issue_id);
+    record_audit_event(&mut *transaction, event).await?;
 
-    tracing::Span::current().record("user_id", &tracing::field::display(&user_id));
-
-    for subscriber in subscribers {
-        match subscriber {
-            Ok(subscriber) => {
-                email_client
-                    .send_email(
-                        &subscriber.email,
-                        &body.title,
-                        &body.content.html,
-                        &body.content.text,
-                    )
-                    .await
-                    .with_context(|| {
-                        format!("Failed to newsletter issue to {}", subscriber.email)
-                    })?;
-            }
-            Err(error) => { 
-                tracing::warn!(error.cause_chain = ?error,
-                    "Skipping a confirmed subscriber. \
-                    Their stored contact details are invalid",
-                );
-            }
-        }
-    }
-    Ok(HttpResponse::Ok().finish())
+    let response = HttpResponse::Accepted().json(PublishedIssue {
+        newsletter_issue_id: issue_id,
+    });
+    let response = save_response(transaction, &idempotency_key, *user_id, response).await?;
+    tracing::info!(author_id = %user_id, "Newsletter issue {} published", issue_id);
+    Ok(response)
 }
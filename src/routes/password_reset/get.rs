@@ -0,0 +1,88 @@
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use secrecy::{ExposeSecret, Secret};
+use sqlx::PgPool;
+use std::fmt::Write;
+use crate::authentication::check_password_reset_token;
+use crate::utils::e500;
+
+pub async fn request_password_reset_form(
+    flash_messages: IncomingFlashMessages,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", htmlescape::encode_minimal(m.content())).unwrap();
+    }
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+                r#"<!DOCTYPE html>
+                <html lang="en">
+                <head>
+                <meta http-equiv="content-type" content="text/html; charset=utf-8">
+                <title>Forgot Password</title>
+                </head>
+                <body>
+                {msg_html}
+                <p>Enter your username and we will email you a link to reset your password.</p>
+                <form action="/password-reset" method="post">
+                <label>Username
+                <input type="text" placeholder="Enter Username" name="username">
+                </label>
+                <button type="submit">Send reset link</button>
+                </form>
+                <p><a href="/login">&lt;- Back</a></p>
+                </body>
+                </html>"#,
+    )))
+}
+
+#[derive(serde::Deserialize)]
+pub struct Parameters {
+    token: Secret<String>,
+}
+
+pub async fn password_reset_form(
+    parameters: web::Query<Parameters>,
+    flash_messages: IncomingFlashMessages,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let token = parameters.0.token;
+    if check_password_reset_token(&pool, &token).await.map_err(e500)?.is_none() {
+        return Ok(HttpResponse::Gone()
+            .content_type(ContentType::html())
+            .body("<p>This reset link has already been used or has expired.</p>"));
+    }
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", htmlescape::encode_minimal(m.content())).unwrap();
+    }
+    let token = htmlescape::encode_attribute(token.expose_secret());
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+                r#"<!DOCTYPE html>
+                <html lang="en">
+                <head>
+                <meta http-equiv="content-type" content="text/html; charset=utf-8">
+                <title>Reset Password</title>
+                </head>
+                <body>
+                {msg_html}
+                <form action="/password-reset/confirm" method="post">
+                <label>New password
+                <input type="password" placeholder="Enter new password" name="new_password">
+                </label>
+                <br>
+                <label>Confirm new password
+                <input type="password" placeholder="Type the new password again" name="new_password_check">
+                </label>
+                <br>
+                <input hidden type="text" name="token" value="{token}">
+                <button type="submit">Reset password</button>
+                </form>
+                </body>
+                </html>"#,
+    )))
+}
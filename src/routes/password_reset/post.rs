@@ -0,0 +1,212 @@
+use actix_web::{web, HttpRequest, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use secrecy::{ExposeSecret, Secret};
+use sqlx::PgPool;
+use uuid::Uuid;
+use crate::authentication::{PasswordHashing, 
+    compute_password_hash, consume_password_reset_token, create_password_reset_token,
+    revoke_all_sessions, PASSWORD_RESET_TTL_MINUTES,
+};
+use crate::client_ip::client_ip;
+use crate::audit::{record_audit_event, AuditAction, NewAuditEvent};
+use crate::domain::{NewPassword, SubscriberEmail};
+use crate::email_client::EmailClient;
+use crate::startup::ApplicationBaseUrl;
+use crate::subscribe_protection::{SubscribeAttemptSource, SubscribeProtection};
+use crate::telemetry::{spawn_blocking_with_tracing, spawn_with_tracing};
+use crate::utils::{e500, see_other};
+
+#[derive(serde::Deserialize)]
+pub struct RequestFormData {
+    username: String,
+}
+
+#[tracing::instrument(
+    name = "Request a password reset",
+    skip(form, pool, email_client, base_url, protection, request),
+    fields(username=%form.username, user_id=tracing::field::Empty)
+)]
+pub async fn request_password_reset(
+    form: web::Form<RequestFormData>,
+    pool: web::Data<PgPool>,
+    email_client: web::Data<EmailClient>,
+    base_url: web::Data<ApplicationBaseUrl>,
+    protection: web::Data<SubscribeProtection>,
+    request: HttpRequest,
+) -> Result<HttpResponse, actix_web::Error> {
+    let username = form.0.username;
+    // Reset links are emailed too, so this form shares the limits of the
+    // sign-up form. Counted before the lookup, so that hitting them says
+    // nothing about the username.
+    let mut sources = vec![SubscribeAttemptSource::Username(&username)];
+    if let Some(ip) = client_ip(&request) {
+        sources.push(SubscribeAttemptSource::Ip(ip));
+    }
+    for source in sources {
+        if !protection.throttle().allow_attempt(source).await.map_err(e500)? {
+            tracing::warn!("Rejected a password reset request over the attempt limits");
+            FlashMessage::error("Too many requests. Please try again later.").send();
+            return Ok(see_other("/password-reset"));
+        }
+    }
+    // Whatever happens below, the response must not reveal whether the
+    // username exists. The email goes out in the background, so that
+    // neither the time it takes nor a failure to send it shows.
+    if let Some((user_id, email)) = get_user_email(&pool, &username)
+        .await
+        .map_err(e500)?
+    {
+        tracing::Span::current().record("user_id", tracing::field::display(&user_id));
+        match email {
+            Some(email) => {
+                spawn_with_tracing(async move {
+                    if let Err(e) = send_password_reset_link(&pool, &email_client, &base_url.0, user_id, &email).await {
+                        tracing::error!(error.cause_chain = ?e, "Failed to send a password reset email.");
+                    }
+                });
+            }
+            None => tracing::warn!("The user has no email address to send a reset link to."),
+        }
+    }
+    FlashMessage::info(
+        "If this account exists, we have emailed it a link to reset its password.",
+    )
+        .send();
+    Ok(see_other("/password-reset"))
+}
+
+async fn send_password_reset_link(
+    pool: &PgPool,
+    email_client: &EmailClient,
+    base_url: &str,
+    user_id: Uuid,
+    email: &SubscriberEmail,
+) -> Result<(), anyhow::Error> {
+    let token = create_password_reset_token(pool, user_id).await?;
+    let reset_link = format!(
+        "{}/password-reset/confirm?token={}",
+        base_url,
+        token.expose_secret(),
+    );
+    send_password_reset_email(email_client, email, &reset_link)
+        .await
+        .context("Failed to send the password reset email.")
+}
+
+#[tracing::instrument(name = "Get user email", skip(pool))]
+async fn get_user_email(
+    pool: &PgPool,
+    username: &str,
+) -> Result<Option<(Uuid, Option<SubscriberEmail>)>, anyhow::Error> {
+    let row = sqlx::query!(
+        "SELECT user_id, email FROM users WHERE username = $1",
+        username,
+    )
+        .fetch_optional(pool)
+        .await
+        .context("Failed to look up the user.")?;
+    row.map(|r| {
+        let email = r
+            .email
+            .map(SubscriberEmail::parse)
+            .transpose()
+            .map_err(|e| anyhow::anyhow!(e))?;
+        Ok((r.user_id, email))
+    })
+    .transpose()
+}
+
+async fn send_password_reset_email(
+    email_client: &EmailClient,
+    email: &SubscriberEmail,
+    reset_link: &str,
+) -> Result<(), reqwest::Error> {
+    let html_body = format!(
+        "Someone asked to reset your password.<br />\
+        Click <a href=\"{}\">here</a> to choose a new one. \
+        The link expires in {} minutes.<br />\
+        If this was not you, you can ignore this email.",
+        reset_link, PASSWORD_RESET_TTL_MINUTES,
+    );
+    let plain_body = format!(
+        "Someone asked to reset your password.\n\
+        Visit {} to choose a new one. The link expires in {} minutes.\n\
+        If this was not you, you can ignore this email.",
+        reset_link, PASSWORD_RESET_TTL_MINUTES,
+    );
+    email_client
+        .send_email(email, "Reset your password", &html_body, &plain_body)
+        .await
+}
+
+#[derive(serde::Deserialize)]
+pub struct ResetFormData {
+    token: Secret<String>,
+    new_password: Secret<String>,
+    new_password_check: Secret<String>,
+}
+
+#[tracing::instrument(
+    name = "Reset a password",
+    skip(form, pool, hashing, request),
+    fields(user_id=tracing::field::Empty)
+)]
+pub async fn reset_password(
+    form: web::Form<ResetFormData>,
+    pool: web::Data<PgPool>,
+    hashing: web::Data<PasswordHashing>,
+    request: HttpRequest,
+) -> Result<HttpResponse, actix_web::Error> {
+    let ResetFormData { token, new_password, new_password_check } = form.0;
+    let form_location = format!("/password-reset/confirm?token={}", token.expose_secret());
+    if new_password.expose_secret() != new_password_check.expose_secret() {
+        FlashMessage::error(
+            "You entered two different new passwords - the field values must match.",
+        )
+            .send();
+        return Ok(see_other(&form_location));
+    }
+    let new_password = match NewPassword::parse(new_password, &[]) {
+        Ok(new_password) => new_password,
+        Err(e) => {
+            FlashMessage::error(e).send();
+            return Ok(see_other(&form_location));
+        }
+    };
+
+    let password_hash = spawn_blocking_with_tracing(move || compute_password_hash(new_password.into(), &hashing))
+        .await
+        .map_err(e500)?
+        .context("Failed to hash password.")
+        .map_err(e500)?;
+    let mut transaction = pool.begin().await.map_err(e500)?;
+    let user_id = match consume_password_reset_token(&mut *transaction, &token)
+        .await
+        .map_err(e500)?
+    {
+        Some(user_id) => user_id,
+        None => {
+            return Ok(HttpResponse::Gone()
+                .body("This reset link has already been used or has expired."))
+        }
+    };
+    tracing::Span::current().record("user_id", tracing::field::display(&user_id));
+    sqlx::query!(
+        "UPDATE users SET password_hash = $1 WHERE user_id = $2",
+        password_hash.expose_secret(),
+        user_id,
+    )
+        .execute(&mut *transaction)
+        .await
+        .map_err(e500)?;
+    // Whoever knew the old password must not stay logged in
+    revoke_all_sessions(&mut *transaction, user_id)
+        .await
+        .map_err(e500)?;
+    let event = NewAuditEvent::new(Some(user_id), AuditAction::PasswordReset, &request);
+    record_audit_event(&mut *transaction, event).await.map_err(e500)?;
+    transaction.commit().await.map_err(e500)?;
+    FlashMessage::info("Your password has been reset - you can now log in.").send();
+    Ok(see_other("/login"))
+}
@@ -0,0 +1,220 @@
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use anyhow::Context;
+use secrecy::Secret;
+use sqlx::PgPool;
+use std::fmt::Write;
+use super::{consume_email_change_token, get_subscriber_preferences};
+use crate::audit::{record_audit_event, AuditAction, NewAuditEvent};
+use crate::configuration::PreferenceSettings;
+use crate::domain::{DeliveryFrequency, SubscriptionStatus};
+use crate::mailing_lists::get_memberships;
+use crate::suppression_list::{is_email_suppressed, SuppressionKeys};
+use crate::utils::e500;
+
+#[derive(serde::Deserialize)]
+pub struct Parameters {
+    subscription_token: String,
+}
+
+#[tracing::instrument(
+    name = "Show subscriber preferences",
+    skip(parameters, pool, preferences, flash_messages)
+)]
+pub async fn preferences_form(
+    parameters: web::Query<Parameters>,
+    pool: web::Data<PgPool>,
+    preferences: web::Data<PreferenceSettings>,
+    flash_messages: IncomingFlashMessages,
+) -> Result<HttpResponse, actix_web::Error> {
+    let token = parameters.0.subscription_token;
+    let subscriber = match get_subscriber_preferences(&pool, &token).await.map_err(e500)? {
+        Some(subscriber) => subscriber,
+        None => return Ok(HttpResponse::Unauthorized().finish()),
+    };
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", htmlescape::encode_minimal(m.content())).unwrap();
+    }
+    let mut topic_checkboxes = String::new();
+    for topic in &preferences.topics {
+        writeln!(
+            topic_checkboxes,
+            r#"<label><input type="checkbox" name="topics" value="{0}"{1}> {0}</label><br>"#,
+            htmlescape::encode_minimal(topic),
+            if subscriber.muted_topics.contains(topic) { "" } else { " checked" },
+        )
+        .unwrap();
+    }
+    let mut frequency_options = String::new();
+    for frequency in DeliveryFrequency::ALL {
+        write!(
+            frequency_options,
+            r#"<option value="{}"{}>{}</option>"#,
+            frequency,
+            if frequency.as_str() == subscriber.frequency { " selected" } else { "" },
+            frequency.description(),
+        )
+        .unwrap();
+    }
+    let mut lists_html = String::new();
+    for membership in get_memberships(&pool, subscriber.id).await.map_err(e500)? {
+        let status = match SubscriptionStatus::try_from(membership.status) {
+            Ok(SubscriptionStatus::Confirmed) => "subscribed",
+            Ok(SubscriptionStatus::PendingConfirmation) => "waiting for confirmation",
+            _ => "not subscribed",
+        };
+        writeln!(
+            lists_html,
+            "<li>{}: {}</li>",
+            htmlescape::encode_minimal(&membership.name),
+            status,
+        )
+        .unwrap();
+    }
+    let token = htmlescape::encode_minimal(&token);
+    let (status, can_unsubscribe) = match SubscriptionStatus::try_from(subscriber.status) {
+        Ok(SubscriptionStatus::Confirmed) => ("You are subscribed.", true),
+        Ok(SubscriptionStatus::PendingConfirmation) => {
+            ("Your subscription is waiting for you to confirm it.", true)
+        }
+        _ => ("You are not subscribed.", false),
+    };
+    let unsubscribe_form = if can_unsubscribe {
+        format!(
+            r#"<form action="/preferences/unsubscribe" method="post">
+            <input type="hidden" name="subscription_token" value="{token}">
+            <button type="submit">Unsubscribe</button>
+            </form>"#
+        )
+    } else {
+        String::new()
+    };
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+                r#"<!DOCTYPE html>
+                <html lang="en">
+                <head>
+                <meta http-equiv="content-type" content="text/html; charset=utf-8">
+                <title>Your preferences</title>
+                </head>
+                <body>
+                {msg_html}
+                <p>{status} We send the newsletter to {email}.</p>
+                <ul>{lists_html}</ul>
+                <form action="/preferences" method="post">
+                <input type="hidden" name="subscription_token" value="{token}">
+                <label>Name
+                <input type="text" name="name" value="{name}">
+                </label>
+                <fieldset>
+                <legend>Topics you want to hear about</legend>
+                {topic_checkboxes}
+                </fieldset>
+                <label>How often
+                <select name="frequency">{frequency_options}</select>
+                </label>
+                <p>With fewer emails, issues published in between are skipped, not sent later.</p>
+                <button type="submit">Save my preferences</button>
+                </form>
+                <form action="/preferences/email" method="post">
+                <input type="hidden" name="subscription_token" value="{token}">
+                <label>New email address
+                <input type="text" placeholder="Enter your new email address" name="email">
+                </label>
+                <button type="submit">Change my email address</button>
+                </form>
+                {unsubscribe_form}
+                </body>
+                </html>"#,
+                email = htmlescape::encode_minimal(&subscriber.email),
+                name = htmlescape::encode_minimal(&subscriber.name),
+    )))
+}
+
+#[derive(serde::Deserialize)]
+pub struct EmailChangeParameters {
+    token: Secret<String>,
+}
+
+#[tracing::instrument(name = "Confirm an email change", skip(parameters, pool, suppression_keys))]
+pub async fn confirm_email_change(
+    parameters: web::Query<EmailChangeParameters>,
+    pool: web::Data<PgPool>,
+    suppression_keys: web::Data<SuppressionKeys>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")
+        .map_err(e500)?;
+    let (subscriber_id, new_email) =
+        match consume_email_change_token(&mut *transaction, &parameters.0.token)
+            .await
+            .map_err(e500)?
+        {
+            Some(change) => change,
+            None => {
+                return Ok(HttpResponse::Gone()
+                    .content_type(ContentType::html())
+                    .body("<p>This link has already been used or has expired.</p>"))
+            }
+        };
+    // The address may have been taken or erased since the link was sent
+    let is_taken = sqlx::query!(
+        r#"SELECT EXISTS (SELECT 1 FROM subscriptions WHERE email = $1) AS "is_taken!""#,
+        new_email,
+    )
+        .fetch_one(&mut *transaction)
+        .await
+        .context("Failed to check whether the address is in use.")
+        .map_err(e500)?
+        .is_taken;
+    if is_taken || is_email_suppressed(&mut *transaction, &new_email, &suppression_keys).await.map_err(e500)? {
+        transaction.commit().await.context("Failed to commit SQL transaction.").map_err(e500)?;
+        return Ok(HttpResponse::Conflict()
+            .content_type(ContentType::html())
+            .body("<p>This address cannot be used for the newsletter.</p>"));
+    }
+    let old_email = sqlx::query!(
+        "SELECT email FROM subscriptions WHERE id = $1 FOR UPDATE",
+        subscriber_id,
+    )
+        .fetch_one(&mut *transaction)
+        .await
+        .context("Failed to look up the subscriber.")
+        .map_err(e500)?
+        .email;
+    sqlx::query!(
+        "UPDATE subscriptions SET email = $2, last_engaged_at = now() WHERE id = $1",
+        subscriber_id,
+        new_email,
+    )
+        .execute(&mut *transaction)
+        .await
+        .context("Failed to change the subscriber's email address.")
+        .map_err(e500)?;
+    // Issues still waiting to go out follow the subscriber to the new address
+    sqlx::query!(
+        "UPDATE issue_delivery_queue SET subscriber_email = $2 WHERE subscriber_email = $1",
+        old_email,
+        new_email,
+    )
+        .execute(&mut *transaction)
+        .await
+        .context("Failed to move pending deliveries to the new address.")
+        .map_err(e500)?;
+    let event = NewAuditEvent::by_subscriber(AuditAction::SubscriberUpdated)
+        .target(subscriber_id);
+    record_audit_event(&mut *transaction, event).await.map_err(e500)?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to change an email address.")
+        .map_err(e500)?;
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body("<p>Your email address has been changed.</p>"))
+}
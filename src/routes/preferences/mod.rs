@@ -0,0 +1,110 @@
+mod get;
+mod post;
+
+pub use get::{confirm_email_change, preferences_form};
+pub use post::{request_email_change, unsubscribe_from_preferences, update_preferences};
+
+use anyhow::Context;
+use chrono::{Duration, Utc};
+use rand::distributions::Alphanumeric;
+use rand::{thread_rng, Rng};
+use secrecy::{ExposeSecret, Secret};
+use sha2::{Digest, Sha256};
+use sqlx::{Executor, PgPool, Postgres};
+use uuid::Uuid;
+
+// How long the link sent to a new address stays valid
+pub const EMAIL_CHANGE_TTL_HOURS: i64 = 24;
+
+pub struct SubscriberPreferences {
+    pub id: Uuid,
+    pub email: String,
+    pub name: String,
+    pub status: String,
+    pub frequency: String,
+    pub muted_topics: Vec<String>,
+}
+
+// The preferences page is reached with the subscriber's subscription token,
+// the same one behind their confirmation and unsubscribe links.
+#[tracing::instrument(name = "Get subscriber preferences", skip(pool, subscription_token))]
+pub async fn get_subscriber_preferences(
+    pool: &PgPool,
+    subscription_token: &str,
+) -> Result<Option<SubscriberPreferences>, anyhow::Error> {
+    let preferences = sqlx::query_as!(
+        SubscriberPreferences,
+        r#"
+        SELECT s.id, s.email, s.name, s.status, s.frequency, s.muted_topics
+        FROM subscription_tokens t
+        JOIN subscriptions s ON s.id = t.subscriber_id
+        WHERE t.subscription_token = $1
+        "#,
+        subscription_token,
+    )
+        .fetch_optional(pool)
+        .await
+        .context("Failed to retrieve the subscriber's preferences.")?;
+    Ok(preferences)
+}
+
+pub fn preferences_path(subscription_token: &str) -> String {
+    format!("/preferences?subscription_token={}", subscription_token)
+}
+
+fn hash_email_change_token(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
+
+#[tracing::instrument(name = "Create email change token", skip(pool, new_email))]
+async fn create_email_change_token(
+    pool: &PgPool,
+    subscriber_id: Uuid,
+    new_email: &str,
+) -> Result<Secret<String>, anyhow::Error> {
+    let token: String = {
+        let mut rng = thread_rng();
+        std::iter::repeat_with(|| rng.sample(Alphanumeric))
+            .map(char::from)
+            .take(40)
+            .collect()
+    };
+    sqlx::query!(
+        r#"
+        INSERT INTO email_changes (token_hash, subscriber_id, new_email, created_at, expires_at)
+        VALUES ($1, $2, $3, now(), $4)
+        "#,
+        hash_email_change_token(&token),
+        subscriber_id,
+        new_email,
+        Utc::now() + Duration::hours(EMAIL_CHANGE_TTL_HOURS),
+    )
+        .execute(pool)
+        .await
+        .context("Failed to store the email change token.")?;
+    Ok(Secret::new(token))
+}
+
+// Returns the subscriber and the address they asked to move to, if the token
+// is still valid. A token can only be used once.
+#[tracing::instrument(name = "Consume email change token", skip(token, executor))]
+async fn consume_email_change_token<'c, E>(
+    executor: E,
+    token: &Secret<String>,
+) -> Result<Option<(Uuid, String)>, anyhow::Error>
+where
+    E: Executor<'c, Database = Postgres>,
+{
+    let row = sqlx::query!(
+        r#"
+        DELETE FROM email_changes
+        WHERE token_hash = $1 AND expires_at > now()
+        RETURNING subscriber_id, new_email
+        "#,
+        hash_email_change_token(token.expose_secret()),
+    )
+        .fetch_optional(executor)
+        .await
+        .context("Failed to consume the email change token.")?;
+    Ok(row.map(|r| (r.subscriber_id, r.new_email)))
+}
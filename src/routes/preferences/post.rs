@@ -0,0 +1,246 @@
+use actix_web::{web, HttpRequest, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use secrecy::ExposeSecret;
+use sqlx::PgPool;
+use uuid::Uuid;
+use super::{
+    create_email_change_token, get_subscriber_preferences, preferences_path,
+    EMAIL_CHANGE_TTL_HOURS,
+};
+use crate::audit::{record_audit_event, AuditAction, NewAuditEvent};
+use crate::client_ip::client_ip;
+use crate::configuration::PreferenceSettings;
+use crate::domain::{DeliveryFrequency, EmailPolicy, SubscriberEmail, SubscriberName};
+use crate::email_client::EmailClient;
+use crate::routes::unsubscribe_subscriber;
+use crate::startup::ApplicationBaseUrl;
+use crate::subscribe_protection::{SubscribeAttemptSource, SubscribeProtection};
+use crate::suppression_list::{is_email_suppressed, SuppressionKeys};
+use crate::telemetry::spawn_with_tracing;
+use crate::utils::{e400, e500, see_other};
+
+// The topic checkboxes share a name, so the form is read as a list of pairs
+#[tracing::instrument(name = "Update subscriber preferences", skip(form, pool, preferences))]
+pub async fn update_preferences(
+    form: web::Form<Vec<(String, String)>>,
+    pool: web::Data<PgPool>,
+    preferences: web::Data<PreferenceSettings>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let field = |name: &str| {
+        form.iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.clone())
+            .ok_or_else(|| e400(format!("The '{}' field is missing.", name)))
+    };
+    let token = field("subscription_token")?;
+    let subscriber = match get_subscriber_preferences(&pool, &token).await.map_err(e500)? {
+        Some(subscriber) => subscriber,
+        None => return Ok(HttpResponse::Unauthorized().finish()),
+    };
+    let chosen_topics: Vec<&str> = form
+        .iter()
+        .filter(|(key, _)| key == "topics")
+        .map(|(_, value)| value.as_str())
+        .collect();
+    let parsed = (
+        SubscriberName::parse(field("name")?),
+        DeliveryFrequency::try_from(field("frequency")?),
+        match chosen_topics.iter().find(|t| !preferences.is_topic(t)) {
+            Some(topic) => Err(format!("{} is not a known topic.", topic)),
+            None => Ok(()),
+        },
+    );
+    let (name, frequency) = match parsed {
+        (Ok(name), Ok(frequency), Ok(())) => (name, frequency),
+        (Err(e), _, _) | (_, Err(e), _) | (_, _, Err(e)) => {
+            FlashMessage::error(e).send();
+            return Ok(see_other(&preferences_path(&token)));
+        }
+    };
+    // Stored the other way round, so that new topics reach everyone
+    let muted_topics: Vec<String> = preferences
+        .topics
+        .iter()
+        .filter(|topic| !chosen_topics.contains(&topic.as_str()))
+        .cloned()
+        .collect();
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")
+        .map_err(e500)?;
+    sqlx::query!(
+        r#"
+        UPDATE subscriptions
+        SET name = $2, frequency = $3, muted_topics = $4, last_engaged_at = now()
+        WHERE id = $1
+        "#,
+        subscriber.id,
+        name.as_ref(),
+        frequency.as_str(),
+        &muted_topics,
+    )
+        .execute(&mut *transaction)
+        .await
+        .context("Failed to update the subscriber's preferences.")
+        .map_err(e500)?;
+    let event = NewAuditEvent::by_subscriber(AuditAction::SubscriberUpdated)
+        .target(subscriber.id);
+    record_audit_event(&mut *transaction, event).await.map_err(e500)?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to update preferences.")
+        .map_err(e500)?;
+    FlashMessage::info("Your preferences have been saved.").send();
+    Ok(see_other(&preferences_path(&token)))
+}
+
+#[derive(serde::Deserialize)]
+pub struct EmailChangeFormData {
+    subscription_token: String,
+    email: String,
+}
+
+#[allow(clippy::too_many_arguments)]
+#[tracing::instrument(
+    name = "Request an email change",
+    skip(form, pool, email_client, base_url, email_policy, suppression_keys, protection)
+)]
+pub async fn request_email_change(
+    form: web::Form<EmailChangeFormData>,
+    pool: web::Data<PgPool>,
+    email_client: web::Data<EmailClient>,
+    base_url: web::Data<ApplicationBaseUrl>,
+    email_policy: web::Data<EmailPolicy>,
+    suppression_keys: web::Data<SuppressionKeys>,
+    protection: web::Data<SubscribeProtection>,
+    request: HttpRequest,
+) -> Result<HttpResponse, actix_web::Error> {
+    let EmailChangeFormData { subscription_token: token, email } = form.0;
+    let subscriber = match get_subscriber_preferences(&pool, &token).await.map_err(e500)? {
+        Some(subscriber) => subscriber,
+        None => return Ok(HttpResponse::Unauthorized().finish()),
+    };
+    let new_email = match SubscriberEmail::parse(email)
+        .and_then(|email| email_policy.check(&email).map(|()| email))
+    {
+        Ok(email) => email,
+        Err(e) => {
+            FlashMessage::error(e).send();
+            return Ok(see_other(&preferences_path(&token)));
+        }
+    };
+    if new_email.as_ref() == subscriber.email {
+        FlashMessage::error("This is already your email address.").send();
+        return Ok(see_other(&preferences_path(&token)));
+    }
+    // This form emails whatever address it is given, like the sign-up form,
+    // so it shares its limits. Counted before the lookup, so that hitting
+    // them says nothing about the address either.
+    let mut sources = vec![SubscribeAttemptSource::Address(new_email.as_ref())];
+    if let Some(ip) = client_ip(&request) {
+        sources.push(SubscribeAttemptSource::Ip(ip));
+    }
+    for source in sources {
+        if !protection.throttle().allow_attempt(source).await.map_err(e500)? {
+            tracing::warn!("Rejected an email change over the attempt limits");
+            FlashMessage::error("Too many requests. Please try again later.").send();
+            return Ok(see_other(&preferences_path(&token)));
+        }
+    }
+    // The page must not reveal whether someone else uses the address, so an
+    // unusable one gets the same answer without the email. The email goes
+    // out in the background, so that neither the time it takes nor a failure
+    // to send it shows.
+    let is_taken = sqlx::query!(
+        r#"SELECT EXISTS (SELECT 1 FROM subscriptions WHERE email = $1) AS "is_taken!""#,
+        new_email.as_ref(),
+    )
+        .fetch_one(pool.get_ref())
+        .await
+        .context("Failed to check whether the address is in use.")
+        .map_err(e500)?
+        .is_taken;
+    let is_suppressed = is_email_suppressed(pool.get_ref(), new_email.as_ref(), &suppression_keys)
+        .await
+        .map_err(e500)?;
+    if !is_taken && !is_suppressed {
+        spawn_with_tracing(async move {
+            if let Err(e) = send_email_change_link(&pool, &email_client, &base_url.0, subscriber.id, &new_email).await {
+                tracing::error!(error.cause_chain = ?e, "Failed to send an email change confirmation.");
+            }
+        });
+    }
+    FlashMessage::info(
+        "We have emailed a link to your new address. \
+        It will replace the current one once you follow the link.",
+    )
+        .send();
+    Ok(see_other(&preferences_path(&token)))
+}
+
+async fn send_email_change_link(
+    pool: &PgPool,
+    email_client: &EmailClient,
+    base_url: &str,
+    subscriber_id: Uuid,
+    new_email: &SubscriberEmail,
+) -> Result<(), anyhow::Error> {
+    let token = create_email_change_token(pool, subscriber_id, new_email.as_ref()).await?;
+    let link = format!(
+        "{}/preferences/email/confirm?token={}",
+        base_url,
+        token.expose_secret(),
+    );
+    send_email_change_email(email_client, new_email, &link)
+        .await
+        .context("Failed to send the email change confirmation.")
+}
+
+async fn send_email_change_email(
+    email_client: &EmailClient,
+    new_email: &SubscriberEmail,
+    link: &str,
+) -> Result<(), reqwest::Error> {
+    let html_body = format!(
+        "Someone asked to receive our newsletter at this address instead.<br />\
+        Click <a href=\"{}\">here</a> to confirm. \
+        The link expires in {} hours.<br />\
+        If this was not you, you can ignore this email.",
+        link, EMAIL_CHANGE_TTL_HOURS,
+    );
+    let plain_body = format!(
+        "Someone asked to receive our newsletter at this address instead.\n\
+        Visit {} to confirm. The link expires in {} hours.\n\
+        If this was not you, you can ignore this email.",
+        link, EMAIL_CHANGE_TTL_HOURS,
+    );
+    email_client
+        .send_email(new_email, "Confirm your new address", &html_body, &plain_body)
+        .await
+}
+
+#[derive(serde::Deserialize)]
+pub struct UnsubscribeFormData {
+    subscription_token: String,
+}
+
+#[tracing::instrument(name = "Unsubscribe from the preferences page", skip(form, pool))]
+pub async fn unsubscribe_from_preferences(
+    form: web::Form<UnsubscribeFormData>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let token = form.0.subscription_token;
+    let subscriber = match get_subscriber_preferences(&pool, &token).await.map_err(e500)? {
+        Some(subscriber) => subscriber,
+        None => return Ok(HttpResponse::Unauthorized().finish()),
+    };
+    unsubscribe_subscriber(&pool, subscriber.id, None).await.map_err(e500)?;
+    let event = NewAuditEvent::by_subscriber(AuditAction::SubscriberUnsubscribed)
+        .target(subscriber.id);
+    record_audit_event(pool.get_ref(), event).await.map_err(e500)?;
+    FlashMessage::info("You have been unsubscribed.").send();
+    Ok(see_other(&preferences_path(&token)))
+}
@@ -0,0 +1,55 @@
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use sqlx::PgPool;
+use std::fmt::Write;
+use crate::authentication::{users_exist, SetupToken};
+use crate::utils::e500;
+
+pub async fn setup_form(
+    flash_messages: IncomingFlashMessages,
+    pool: web::Data<PgPool>,
+    setup_token: web::Data<Option<SetupToken>>,
+) -> Result<HttpResponse, actix_web::Error> {
+    if setup_token.is_none() || users_exist(pool.get_ref()).await.map_err(e500)? {
+        return Ok(HttpResponse::NotFound().finish());
+    }
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", htmlescape::encode_minimal(m.content())).unwrap();
+    }
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+                r#"<!DOCTYPE html>
+                <html lang="en">
+                <head>
+                <meta http-equiv="content-type" content="text/html; charset=utf-8">
+                <title>Set Up</title>
+                </head>
+                <body>
+                {msg_html}
+                <p>Create the first owner account. The setup token was printed in the server logs.</p>
+                <form action="/setup" method="post">
+                <label>Setup token
+                <input type="password" placeholder="Enter the setup token" name="setup_token">
+                </label>
+                <br>
+                <label>Username
+                <input type="text" placeholder="Choose a username" name="username">
+                </label>
+                <br>
+                <label>Password
+                <input type="password" placeholder="Choose a password" name="password">
+                </label>
+                <br>
+                <label>Confirm password
+                <input type="password" placeholder="Type the password again" name="password_check">
+                </label>
+                <br>
+                <button type="submit">Create account</button>
+                </form>
+                </body>
+                </html>"#,
+    )))
+}
@@ -0,0 +1,93 @@
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use secrecy::{ExposeSecret, Secret};
+use sqlx::PgPool;
+use uuid::Uuid;
+use crate::authentication::{PasswordHashing, compute_password_hash, users_exist, Role, SetupToken};
+use crate::domain::NewPassword;
+use crate::telemetry::spawn_blocking_with_tracing;
+use crate::utils::{e500, see_other};
+
+#[derive(serde::Deserialize)]
+pub struct FormData {
+    setup_token: Secret<String>,
+    username: String,
+    password: Secret<String>,
+    password_check: Secret<String>,
+}
+
+#[tracing::instrument(
+    name = "Set up the first owner",
+    skip(form, pool, setup_token, hashing),
+    fields(username=tracing::field::Empty, user_id=tracing::field::Empty)
+)]
+pub async fn set_up_first_owner(
+    form: web::Form<FormData>,
+    pool: web::Data<PgPool>,
+    setup_token: web::Data<Option<SetupToken>>,
+    hashing: web::Data<PasswordHashing>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let FormData { setup_token: candidate, username, password, password_check } = form.0;
+    let setup_token = match setup_token.as_ref() {
+        Some(setup_token) => setup_token,
+        None => return Ok(HttpResponse::NotFound().finish()),
+    };
+    if !setup_token.verify(candidate.expose_secret()) {
+        FlashMessage::error("The setup token is not valid.").send();
+        return Ok(see_other("/setup"));
+    }
+    let username = username.trim().to_string();
+    tracing::Span::current().record("username", tracing::field::display(&username));
+    if username.is_empty() {
+        FlashMessage::error("You must choose a username.").send();
+        return Ok(see_other("/setup"));
+    }
+    if password.expose_secret() != password_check.expose_secret() {
+        FlashMessage::error(
+            "You entered two different passwords - the field values must match.",
+        )
+            .send();
+        return Ok(see_other("/setup"));
+    }
+    let password = match NewPassword::parse(password, &[&username]) {
+        Ok(password) => password,
+        Err(e) => {
+            FlashMessage::error(e).send();
+            return Ok(see_other("/setup"));
+        }
+    };
+
+    let password_hash = spawn_blocking_with_tracing(move || compute_password_hash(password.into(), &hashing))
+        .await
+        .map_err(e500)?
+        .context("Failed to hash password.")
+        .map_err(e500)?;
+    let mut transaction = pool.begin().await.map_err(e500)?;
+    // Serialise concurrent attempts, so only one of them creates the first owner
+    sqlx::query!("LOCK TABLE users IN SHARE ROW EXCLUSIVE MODE")
+        .execute(&mut *transaction)
+        .await
+        .map_err(e500)?;
+    if users_exist(&mut *transaction).await.map_err(e500)? {
+        return Ok(HttpResponse::Gone().body("The application has already been set up."));
+    }
+    let user_id = Uuid::new_v4();
+    sqlx::query!(
+        r#"
+        INSERT INTO users (user_id, username, password_hash, role)
+        VALUES ($1, $2, $3, $4)
+        "#,
+        user_id,
+        username,
+        password_hash.expose_secret(),
+        Role::Owner.as_str(),
+    )
+        .execute(&mut *transaction)
+        .await
+        .map_err(e500)?;
+    transaction.commit().await.map_err(e500)?;
+    tracing::Span::current().record("user_id", tracing::field::display(&user_id));
+    FlashMessage::info("Your account has been created - you can now log in.").send();
+    Ok(see_other("/login"))
+}
@@ -1,19 +1,49 @@
 use crate::{email_client::EmailClient, startup::ApplicationBaseUrl};
-use actix_web::{web, HttpResponse, ResponseError};
+use actix_web::{web, HttpRequest, HttpResponse, ResponseError};
+use actix_web::http::header::ContentType;
 use actix_web::http::StatusCode;
 use sqlx::{PgPool, Postgres, Transaction, Executor};
 use chrono::Utc;
 use uuid::Uuid;
-use crate::domain::{NewSubscriber, SubscriberName, SubscriberEmail};
+use crate::domain::{NewSubscriber, SubscriberName, SubscriberEmail, SubscriptionStatus};
+use crate::mailing_lists::{get_list_by_slug, join_list, DEFAULT_LIST_SLUG};
 use std::convert::{TryFrom, TryInto};
 use rand::distributions::Alphanumeric;
 use rand::{thread_rng, Rng};
 use anyhow::Context;
+use std::collections::HashMap;
+use crate::custom_fields::{get_custom_fields, store_field_values};
+use crate::domain::{CustomField, CustomFieldKind, EmailPolicy};
+use crate::client_ip::client_ip;
+use crate::startup::HmacSecret;
+use crate::subscribe_protection::{
+    form_token, RejectionReason, SubscribeAttemptSource, SubscribeProtection, HONEYPOT_FIELD,
+};
+use std::fmt::Write;
 
 #[derive(serde::Deserialize)]
 pub struct FormData {
     email: String,
     name: String,
+    // The slug of the list to join
+    #[serde(default)]
+    list: String,
+    // Hidden from people, see `HONEYPOT_FIELD`
+    #[serde(default)]
+    website: String,
+    // Issued by `GET /subscriptions`, to tell how long the form was open
+    #[serde(default)]
+    form_token: String,
+    #[serde(
+        default,
+        alias = "cf-turnstile-response",
+        alias = "h-captcha-response",
+        alias = "g-recaptcha-response"
+    )]
+    challenge_response: String,
+    // Values for the custom fields, by key
+    #[serde(flatten)]
+    fields: HashMap<String, String>,
 }
 impl TryFrom<FormData> for NewSubscriber {
     type Error = String;
@@ -64,6 +94,9 @@ pub enum SubscribeError {
     #[error("{0}")]
     ValidationError(String),
 
+    #[error("Too many sign-up attempts. Try again later.")]
+    TooManyAttempts,
+
     #[error(transparent)]
     UnexpectedError(#[from] anyhow::Error),
 }
@@ -77,6 +110,7 @@ impl ResponseError for SubscribeError {
     fn status_code(&self) -> StatusCode {
         match self {
             SubscribeError::ValidationError(_) => StatusCode::BAD_REQUEST,
+            SubscribeError::TooManyAttempts => StatusCode::TOO_MANY_REQUESTS,
             SubscribeError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
         }
     }
@@ -84,7 +118,22 @@ impl ResponseError for SubscribeError {
 
 
 
-fn generate_subscription_token() -> String {
+// What to store for each custom field. Fields that are not set up are
+// ignored rather than rejected.
+fn parse_field_values(
+    custom_fields: &[CustomField],
+    submitted: &HashMap<String, String>,
+) -> Result<Vec<(Uuid, String)>, String> {
+    let mut values = Vec::new();
+    for field in custom_fields {
+        if let Some(value) = field.parse_value(submitted.get(&field.key).map(String::as_str))? {
+            values.push((field.field_id, value));
+        }
+    }
+    Ok(values)
+}
+
+pub fn generate_subscription_token() -> String {
     let mut rng = thread_rng();
     std::iter::repeat_with(|| rng.sample(Alphanumeric))
         .map(char::from)
@@ -115,12 +164,14 @@ pub async fn insert_subscriber(
     Ok(subscriber_id)
 }
 
+#[allow(clippy::too_many_arguments)]
 #[tracing::instrument(
     name = "Adding a new subscriber",
-    skip(form, connection_pool, email_client, base_url),
+    skip(form, connection_pool, email_client, base_url, protection, email_policy, secret, request),
     fields(
         subscriber_email = %form.email,
         subscriber_name = %form.name,
+        list = %form.list,
     )
 )]
 pub async fn subscribe(
@@ -128,21 +179,102 @@ pub async fn subscribe(
     connection_pool: web::Data<PgPool>,
     email_client: web::Data<EmailClient>,
     base_url: web::Data<ApplicationBaseUrl>,
+    protection: web::Data<SubscribeProtection>,
+    email_policy: web::Data<EmailPolicy>,
+    secret: web::Data<HmacSecret>,
+    request: HttpRequest,
 ) -> Result<HttpResponse, SubscribeError> {
-    let new_subscriber = form.0.try_into()
+    let mut form = form.0;
+    let remote_ip = client_ip(&request);
+    if let Some(ip) = remote_ip {
+        if !protection.throttle().allow_attempt(SubscribeAttemptSource::Ip(ip)).await? {
+            protection.record_rejection(RejectionReason::IpRateLimit).await?;
+            return Err(SubscribeError::TooManyAttempts);
+        }
+    }
+    if !form.website.is_empty() {
+        // Answered like a success, so that bots learn nothing
+        protection.record_rejection(RejectionReason::Honeypot).await?;
+        return Ok(HttpResponse::Ok().finish());
+    }
+    if !protection.is_filled_in_time(&form.form_token, &secret.0) {
+        protection.record_rejection(RejectionReason::FormTiming).await?;
+        return Err(SubscribeError::ValidationError(
+            "The form was sent too quickly or too late. Reload the page and try again.".into(),
+        ));
+    }
+    if !protection.passes_challenge(&form.challenge_response, remote_ip).await? {
+        protection.record_rejection(RejectionReason::Challenge).await?;
+        return Err(SubscribeError::ValidationError("The challenge was not passed.".into()));
+    }
+    let list_slug = match std::mem::take(&mut form.list) {
+        slug if slug.is_empty() => DEFAULT_LIST_SLUG.to_string(),
+        slug => slug,
+    };
+    let submitted_fields = std::mem::take(&mut form.fields);
+    let new_subscriber: NewSubscriber = form.try_into()
+        .map_err(SubscribeError::ValidationError)?;
+    email_policy
+        .check(&new_subscriber.email)
         .map_err(SubscribeError::ValidationError)?;
+    let custom_fields = get_custom_fields(connection_pool.get_ref()).await?;
+    let field_values = parse_field_values(&custom_fields, &submitted_fields)
+        .map_err(SubscribeError::ValidationError)?;
+    let list = get_list_by_slug(connection_pool.get_ref(), &list_slug)
+        .await?
+        .ok_or_else(|| {
+            SubscribeError::ValidationError(format!("{} is not a known list.", list_slug))
+        })?;
+    // Counted once the form is known to be valid, so that typos do not
+    // use up the attempts of whoever owns the address
+    let address = SubscribeAttemptSource::Address(new_subscriber.email.as_ref());
+    if !protection.throttle().allow_attempt(address).await? {
+        protection.record_rejection(RejectionReason::AddressRateLimit).await?;
+        return Err(SubscribeError::TooManyAttempts);
+    }
 
     let mut transaction = connection_pool
         .begin()
         .await 
         .context("Failed to acquire a Postgres connection from the pool")?;
 
-    let subscriber_id = insert_subscriber(&mut transaction, &new_subscriber)
-        .await     
-        .context("Failed to insert new subscriber in the database.")?;
+    // People already on another list join this one with the same address.
+    // Either way, the response looks the same as for a new subscriber.
+    let subscriber = sqlx::query!(
+        "SELECT id, status FROM subscriptions WHERE email = $1 FOR UPDATE",
+        new_subscriber.email.as_ref(),
+    )
+        .fetch_optional(&mut *transaction)
+        .await
+        .context("Failed to look up the subscriber.")?;
+    let subscriber_id = match subscriber {
+        Some(s) if s.status == SubscriptionStatus::Suppressed.as_str() => {
+            return Ok(HttpResponse::Ok().finish());
+        }
+        // Anyone can post someone else's address, so the values sent for an
+        // existing subscriber are ignored
+        Some(s) => s.id,
+        None => {
+            let subscriber_id = insert_subscriber(&mut transaction, &new_subscriber)
+                .await
+                .context("Failed to insert new subscriber in the database.")?;
+            store_field_values(&mut transaction, subscriber_id, &field_values).await?;
+            subscriber_id
+        }
+    };
+    let previous_status = join_list(
+        &mut transaction,
+        list.list_id,
+        subscriber_id,
+        SubscriptionStatus::PendingConfirmation,
+    )
+        .await?;
+    if previous_status.as_deref() == Some(SubscriptionStatus::Confirmed.as_str()) {
+        return Ok(HttpResponse::Ok().finish());
+    }
 
     let subscription_token = generate_subscription_token();
-    store_token(&mut transaction, subscriber_id, &subscription_token)
+    store_token(&mut transaction, subscriber_id, list.list_id, &subscription_token)
         .await
         .context("Failed to store the confirmation token for a new subscriber.")?;
    
@@ -162,6 +294,80 @@ pub async fn subscribe(
     Ok(HttpResponse::Ok().finish())
 }
 
+// The sign-up form. It carries the signed time it was rendered at, a
+// honeypot field hidden from people and, if one is set up, the challenge.
+#[tracing::instrument(name = "Show the sign-up form", skip(connection_pool, protection, secret))]
+pub async fn subscribe_form(
+    connection_pool: web::Data<PgPool>,
+    protection: web::Data<SubscribeProtection>,
+    secret: web::Data<HmacSecret>,
+) -> Result<HttpResponse, SubscribeError> {
+    let mut fields_html = String::new();
+    for field in get_custom_fields(connection_pool.get_ref()).await? {
+        let key = htmlescape::encode_minimal(&field.key);
+        let label = htmlescape::encode_minimal(&field.label);
+        let required = if field.is_required { " required" } else { "" };
+        match field.kind {
+            CustomFieldKind::Text => writeln!(
+                fields_html,
+                r#"<label>{label}<input type="text" name="{key}"{required}></label><br>"#,
+            ),
+            CustomFieldKind::Boolean => writeln!(
+                fields_html,
+                r#"<label><input type="checkbox" name="{key}" value="true"{required}> {label}</label><br>"#,
+            ),
+            CustomFieldKind::Select => {
+                let mut options_html = String::from(r#"<option value=""></option>"#);
+                for option in &field.options {
+                    write!(
+                        options_html,
+                        r#"<option value="{0}">{0}</option>"#,
+                        htmlescape::encode_minimal(option),
+                    )
+                    .unwrap();
+                }
+                writeln!(
+                    fields_html,
+                    r#"<label>{label}<select name="{key}"{required}>{options_html}</select></label><br>"#,
+                )
+            }
+        }
+        .unwrap();
+    }
+    let form_token = form_token(Utc::now(), &secret.0);
+    let challenge_widget = protection.challenge_widget();
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Subscribe</title>
+</head>
+<body>
+    <form action="/subscriptions" method="post">
+        <label>Name
+            <input type="text" name="name" required>
+        </label><br>
+        <label>Email
+            <input type="email" name="email" required>
+        </label><br>
+        {fields_html}
+        <div style="display: none" aria-hidden="true">
+            <label>Leave this empty
+                <input type="text" name="{HONEYPOT_FIELD}" tabindex="-1" autocomplete="off">
+            </label>
+        </div>
+        <input type="hidden" name="form_token" value="{form_token}">
+        {challenge_widget}
+        <button type="submit">Subscribe</button>
+    </form>
+</body>
+</html>"#,
+        )))
+}
+
 #[tracing::instrument(
     name = "Store subscription token in the database",
     skip(subscription_token, transaction)
@@ -169,15 +375,17 @@ pub async fn subscribe(
 pub async fn store_token(
     transaction: &mut Transaction<'_, Postgres>,
     subscriber_id: Uuid,
+    list_id: Uuid,
     subscription_token: &str,
 ) -> Result<(), StoreTokenError> {
 let query = sqlx::query!(
         r#"
-        INSERT INTO subscription_tokens(subscription_token, subscriber_id)
-        VALUES ($1, $2)
+        INSERT INTO subscription_tokens(subscription_token, subscriber_id, list_id)
+        VALUES ($1, $2, $3)
         "#,
         subscription_token,
         subscriber_id,
+        list_id,
     );
     transaction.execute(query)
     .await
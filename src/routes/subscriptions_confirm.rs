@@ -1,4 +1,5 @@
 use actix_web::{HttpResponse, web};
+use crate::audit::{record_audit_event, AuditAction, NewAuditEvent};
 use sqlx::PgPool;
 use uuid::Uuid;
 
@@ -15,17 +16,22 @@ pub async fn confirm(
     parameters: web::Query<Parameters>,
     connection_pool: web::Data<PgPool>,
     ) -> HttpResponse {
-    let id = match get_subscriber_id_from_token(
+    let subscription = match get_subscription_from_token(
         &connection_pool,
         &parameters.subscription_token,
     ).await{
-        Ok(id) => id,
+        Ok(subscription) => subscription,
         Err(_) => return HttpResponse::InternalServerError().finish(),
     };
-    match id {
+    match subscription {
         None => HttpResponse::Unauthorized().finish(),
-        Some(subscriber_id) => {
-            if confirm_subscriber(&connection_pool, subscriber_id).await.is_err() {
+        Some((subscriber_id, list_id)) => {
+            if confirm_subscriber(&connection_pool, subscriber_id, list_id).await.is_err() {
+                return HttpResponse::InternalServerError().finish();
+            }
+            let event = NewAuditEvent::by_subscriber(AuditAction::SubscriberConfirmed)
+                .target(subscriber_id);
+            if record_audit_event(connection_pool.get_ref(), event).await.is_err() {
                 return HttpResponse::InternalServerError().finish();
             }
             HttpResponse::Ok().finish()
@@ -40,11 +46,22 @@ pub async fn confirm(
 pub async fn confirm_subscriber(
     connection_pool: &PgPool,
     subscriber_id: Uuid,
+    list_id: Uuid,
 ) -> Result<(), sqlx::Error> {
+    // Confirming any list proves the address works
     sqlx::query!(
         r#"
-        UPDATE subscriptions SET status = 'confirmed' WHERE id = $1"#,
+        WITH subscriber AS (
+            UPDATE subscriptions
+            SET status = 'confirmed', confirmed_at = COALESCE(confirmed_at, now()), last_engaged_at = now()
+            WHERE id = $1 AND status <> 'suppressed'
+            RETURNING id
+        )
+        UPDATE list_memberships
+        SET status = 'confirmed', confirmed_at = COALESCE(confirmed_at, now())
+        WHERE subscriber_id IN (SELECT id FROM subscriber) AND list_id = $2"#,
         subscriber_id,
+        list_id,
     )
     .execute(connection_pool)
     .await
@@ -54,16 +71,18 @@ pub async fn confirm_subscriber(
     })?;
     Ok(())
 }
+
+// Each token belongs to the list it confirmed
 #[tracing::instrument(
-    name = "Get subscriber id from token",
+    name = "Get subscription from token",
     skip(subscription_token, connection_pool)
 )]
-pub async fn get_subscriber_id_from_token(
+pub async fn get_subscription_from_token(
     connection_pool: &PgPool,
     subscription_token: &str,
-) -> Result<Option<Uuid>, sqlx::Error> {
+) -> Result<Option<(Uuid, Uuid)>, sqlx::Error> {
     let result = sqlx::query!(
-        "SELECT subscriber_id FROM subscription_tokens \
+        "SELECT subscriber_id, list_id FROM subscription_tokens \
         WHERE subscription_token = $1",
         subscription_token,
     )
@@ -73,6 +92,5 @@ pub async fn get_subscriber_id_from_token(
             tracing::error!("Failed to execute query: {:?}", e);
             e
         })?;
-    Ok(result.map(|r| r.subscriber_id))
+    Ok(result.map(|r| (r.subscriber_id, r.list_id)))
 }
-
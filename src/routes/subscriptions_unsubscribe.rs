@@ -0,0 +1,116 @@
+use actix_web::{HttpResponse, web};
+use crate::audit::{record_audit_event, AuditAction, NewAuditEvent};
+use actix_web::http::header::ContentType;
+use sqlx::PgPool;
+use uuid::Uuid;
+use crate::routes::get_subscription_from_token;
+
+#[derive(serde::Deserialize)]
+pub struct Parameters{
+    subscription_token: String
+}
+
+// Following the link only asks for confirmation: mail scanners open links too
+#[tracing::instrument(
+    name = "Show the unsubscribe form",
+    skip(parameters, connection_pool)
+)]
+pub async fn unsubscribe_form(
+    parameters: web::Query<Parameters>,
+    connection_pool: web::Data<PgPool>,
+    ) -> HttpResponse {
+    match get_subscription_from_token(&connection_pool, &parameters.subscription_token).await {
+        Err(_) => HttpResponse::InternalServerError().finish(),
+        Ok(None) => HttpResponse::Unauthorized().finish(),
+        Ok(Some(_)) => HttpResponse::Ok()
+            .content_type(ContentType::html())
+            .body(format!(
+                r#"<!DOCTYPE html>
+                <html lang="en">
+                <head>
+                <meta http-equiv="content-type" content="text/html; charset=utf-8">
+                <title>Unsubscribe</title>
+                </head>
+                <body>
+                <p>You will no longer receive this newsletter.</p>
+                <form action="/subscriptions/unsubscribe" method="post">
+                <input type="hidden" name="subscription_token" value="{}">
+                <button type="submit">Unsubscribe</button>
+                </form>
+                </body>
+                </html>"#,
+                htmlescape::encode_minimal(&parameters.subscription_token),
+            )),
+    }
+}
+
+#[tracing::instrument(
+    name = "Unsubscribe a subscriber",
+    skip(form, connection_pool)
+)]
+pub async fn unsubscribe(
+    form: web::Form<Parameters>,
+    connection_pool: web::Data<PgPool>,
+    ) -> HttpResponse {
+    let subscription = match get_subscription_from_token(
+        &connection_pool,
+        &form.subscription_token,
+    ).await{
+        Ok(subscription) => subscription,
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+    match subscription {
+        None => HttpResponse::Unauthorized().finish(),
+        Some((subscriber_id, list_id)) => {
+            if unsubscribe_subscriber(&connection_pool, subscriber_id, Some(list_id)).await.is_err() {
+                return HttpResponse::InternalServerError().finish();
+            }
+            let event = NewAuditEvent::by_subscriber(AuditAction::SubscriberUnsubscribed)
+                .target(subscriber_id);
+            if record_audit_event(connection_pool.get_ref(), event).await.is_err() {
+                return HttpResponse::InternalServerError().finish();
+            }
+            HttpResponse::Ok()
+                .content_type(ContentType::html())
+                .body("<p>You have been unsubscribed.</p>")
+        }
+    }
+}
+
+// Leaves one list, or every list when `list_id` is `None`. The subscriber
+// is only marked as unsubscribed once no confirmed list is left.
+#[tracing::instrument(
+    name = "Mark subscriber as unsubscribed",
+    skip(subscriber_id, connection_pool)
+)]
+pub async fn unsubscribe_subscriber(
+    connection_pool: &PgPool,
+    subscriber_id: Uuid,
+    list_id: Option<Uuid>,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        WITH memberships AS (
+            UPDATE list_memberships
+            SET status = 'unsubscribed'
+            WHERE subscriber_id = $1 AND ($2::uuid IS NULL OR list_id = $2)
+        )
+        UPDATE subscriptions SET status = 'unsubscribed'
+        WHERE id = $1 AND status <> 'suppressed'
+        -- Changes made above are not visible here, hence the check on list_id
+        AND NOT EXISTS (
+            SELECT 1 FROM list_memberships
+            WHERE subscriber_id = $1 AND status = 'confirmed'
+            AND $2::uuid IS NOT NULL AND list_id <> $2
+        )"#,
+        subscriber_id,
+        list_id,
+    )
+    .execute(connection_pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })?;
+    Ok(())
+}
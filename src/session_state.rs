@@ -4,11 +4,16 @@ use actix_session::SessionExt;
 use actix_web::dev::Payload;
 use actix_web::{FromRequest, HttpRequest};
 use std::future::{Ready, ready};
+use rand::distributions::Alphanumeric;
+use rand::{thread_rng, Rng};
 
 
 pub struct TypedSession(Session);
 impl TypedSession {
     const USER_ID_KEY: &'static str = "user_id";
+    const SESSION_ID_KEY: &'static str = "session_id";
+    const PENDING_LOGIN_KEY: &'static str = "pending_login";
+    const CSRF_TOKEN_KEY: &'static str = "csrf_token";
 
     pub fn renew(&self) {
         self.0.renew();
@@ -22,11 +27,76 @@ impl TypedSession {
         self.0.get(Self::USER_ID_KEY)
     }
 
+    pub fn insert_session_id(&self, session_id: Uuid) -> Result<(), SessionInsertError> {
+        self.0.insert(Self::SESSION_ID_KEY, session_id)
+    }
+
+    pub fn get_session_id(&self) -> Result<Option<Uuid>, SessionGetError> {
+        self.0.get(Self::SESSION_ID_KEY)
+    }
+
+    // A login that passed the password check but still owes a second factor.
+    // It does not set the user id, so it grants no access on its own.
+    pub fn insert_pending_login(&self, user_id: Uuid) -> Result<(), SessionInsertError> {
+        let pending_login = PendingLogin {
+            user_id,
+            started_at: chrono::Utc::now().timestamp(),
+        };
+        self.0.insert(Self::PENDING_LOGIN_KEY, pending_login)
+    }
+
+    // Pending logins that were not completed in time are discarded
+    pub fn get_pending_login(&self) -> Result<Option<Uuid>, SessionGetError> {
+        let pending_login: Option<PendingLogin> = self.0.get(Self::PENDING_LOGIN_KEY)?;
+        let now = chrono::Utc::now().timestamp();
+        Ok(pending_login
+            .filter(|p| now - p.started_at <= PendingLogin::TTL_SECONDS)
+            .map(|p| p.user_id))
+    }
+
+    pub fn remove_pending_login(&self) {
+        self.0.remove(Self::PENDING_LOGIN_KEY);
+    }
+
+    // Forms must echo this token back: other sites can make the browser
+    // submit a form, but cannot read the token to put in it.
+    pub fn csrf_token(&self) -> Result<String, anyhow::Error> {
+        match self.get_csrf_token()? {
+            Some(token) => Ok(token),
+            None => Ok(self.renew_csrf_token()?),
+        }
+    }
+
+    pub fn get_csrf_token(&self) -> Result<Option<String>, SessionGetError> {
+        self.0.get(Self::CSRF_TOKEN_KEY)
+    }
+
+    // Issued when logging in, so pages loaded side by side share one token
+    pub fn renew_csrf_token(&self) -> Result<String, SessionInsertError> {
+        let mut rng = thread_rng();
+        let token: String = std::iter::repeat_with(|| rng.sample(Alphanumeric))
+            .map(char::from)
+            .take(32)
+            .collect();
+        self.0.insert(Self::CSRF_TOKEN_KEY, &token)?;
+        Ok(token)
+    }
+
     pub fn log_out(self) {
         self.0.purge()
     }
 }
 
+#[derive(serde::Serialize, serde::Deserialize)]
+struct PendingLogin {
+    user_id: Uuid,
+    started_at: i64,
+}
+
+impl PendingLogin {
+    const TTL_SECONDS: i64 = 5 * 60;
+}
+
 impl FromRequest for TypedSession {
     type Error = <Session as FromRequest>::Error;
     type Future = Ready<Result<TypedSession, Self::Error>>;
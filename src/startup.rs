@@ -1,7 +1,32 @@
-use crate::routes::{health_check, subscribe, confirm,
+use crate::routes::{health_check, subscribe, subscribe_form, confirm, unsubscribe, unsubscribe_form, metrics,
                     home, login_form, login, log_out,
                     admin_dashboard, change_password, change_password_form,
-                    publish_newsletter, publish_newsletter_form,
+                    publish_newsletter, publish_newsletter_form, preview_newsletter,
+                    newsletter_issues_history, publish_newsletter_api,
+                    api_tokens_form, create_token, revoke_token,
+                    newsletter_templates_form, create_newsletter_template,
+                    edit_newsletter_template_form, update_newsletter_template,
+                    users_form, invite_user, change_user_role,
+                    accept_invitation_form, accept_invitation,
+                    setup_form, set_up_first_owner,
+                    request_password_reset_form, request_password_reset,
+                    password_reset_form, reset_password,
+                    login_totp_form, login_totp,
+                    totp_form, set_up_totp, turn_on_totp, turn_off_totp,
+                    sessions_form, revoke_user_session, revoke_other_user_sessions,
+                    audit_log, export_audit_log,
+                    subscribers_list, subscriber_details, update_subscriber,
+                    confirm_subscriber_as_admin, unsubscribe_subscriber_as_admin,
+                    suppress_subscriber, delete_subscriber,
+                    imports_form, import_subscribers, import_details, import_report,
+                    export_subscribers,
+                    data_request_form, request_subscriber_data, download_subscriber_data,
+                    erasure_form, erase_subscriber_data,
+                    preferences_form, update_preferences, request_email_change,
+                    confirm_email_change, unsubscribe_from_preferences,
+                    mailing_lists_form, create_mailing_list,
+                    tags_form, create_tag, update_subscriber_tags,
+                    custom_fields_form, create_custom_field,
                     };
 use actix_web::{web, App, HttpServer};
 use actix_web::dev::Server;
@@ -9,29 +34,51 @@ use sqlx::PgPool;
 use std::net::TcpListener;
 use tracing_actix_web::TracingLogger;
 use crate::email_client::EmailClient;
-use crate::configuration::{Settings, DatabaseSettings};
+use crate::configuration::{
+    CookieSettings, Settings, DatabaseSettings, LoginThrottlingSettings, PreferenceSettings,
+};
 use sqlx::postgres::PgPoolOptions;
 use secrecy::{Secret, ExposeSecret};
 use actix_web_flash_messages::FlashMessagesFramework;
-use actix_web_flash_messages::storage::CookieMessageStore;
+use crate::flash_store::FlashCookieStore;
 use actix_web::cookie::Key;
 use actix_session::SessionMiddleware;
 use actix_session::storage::RedisSessionStore;
-use crate::authentication::reject_anonymous_users;
+use crate::authentication::{
+    reject_anonymous_users, reject_forged_requests, reject_non_owners, reject_viewers,
+};
+use crate::authentication::{check_seeded_admin_password, issue_setup_token, SetupToken};
+use crate::authentication::{LoginThrottle, PasswordHashing};
 use actix_web_lab::middleware::from_fn;
+use crate::subscribe_protection::SubscribeProtection;
+use crate::client_ip::TrustedProxies;
+use crate::suppression_list::SuppressionKeys;
+use crate::domain::EmailPolicy;
 
 // Application struct to wrap actix_web server
 pub struct Application {
     port: u16,
     server: Server,
+    setup_token: Option<SetupToken>,
 }
 
 impl Application {
     // Build fn to initialize variables
     pub async fn build(configuration: Settings) -> Result<Self, anyhow::Error> {
         let connection_pool = get_connection_pool(&configuration.database);
+        let password_hashing = PasswordHashing::new(&configuration.password_hashing)?;
+        let email_policy = EmailPolicy::new(&configuration.email_policy)?;
+        check_seeded_admin_password(&connection_pool, configuration.environment).await?;
+        let setup_token = issue_setup_token(
+            &connection_pool,
+            &configuration.application.base_url,
+        ).await?;
 
         let email_client = configuration.email_client.client();
+        let subscribe_protection = SubscribeProtection::new(
+            &configuration.redis_uri,
+            configuration.subscribe_protection,
+        ).await?;
 
         let address = format!(
             "{}:{}",
@@ -45,15 +92,29 @@ impl Application {
             email_client,
             configuration.application.base_url,
             configuration.application.hmac_secret,
+            configuration.application.suppression_secret,
             configuration.redis_uri,
+            configuration.login_throttling,
+            subscribe_protection,
+            setup_token.clone(),
+            password_hashing,
+            email_policy,
+            configuration.cookies,
+            configuration.preferences,
+            TrustedProxies(configuration.application.trusted_proxies),
             ).await?;
-        Ok(Self { port, server })
+        Ok(Self { port, server, setup_token })
     }
 
     // Return port of Application
     pub fn port(&self) -> u16 {
         self.port
     }
+
+    // Only set when the application started without any user account
+    pub fn setup_token(&self) -> Option<&Secret<String>> {
+        self.setup_token.as_ref().map(SetupToken::expose)
+    }
     
     pub async fn run_until_stopped(self) -> Result<(), std::io::Error> {
         self.server.await
@@ -62,46 +123,233 @@ impl Application {
 
 pub struct ApplicationBaseUrl(pub String);
 
+#[allow(clippy::too_many_arguments)]
 pub async fn run(
     listener: TcpListener,
     connection_pool: PgPool,
     email_client: EmailClient,
     base_url: String,
     hmac_secret: Secret<String>,
-    redis_uri: Secret<String>
+    suppression_secret: Secret<String>,
+    redis_uri: Secret<String>,
+    login_throttling: LoginThrottlingSettings,
+    subscribe_protection: SubscribeProtection,
+    setup_token: Option<SetupToken>,
+    password_hashing: PasswordHashing,
+    email_policy: EmailPolicy,
+    cookies: CookieSettings,
+    preferences: PreferenceSettings,
+    trusted_proxies: TrustedProxies,
 ) -> Result<Server, anyhow::Error> {
     let connection_pool = web::Data::new(connection_pool);
     let email_client = web::Data::new(email_client);
     let base_url = web::Data::new(ApplicationBaseUrl(base_url));
+    let setup_token = web::Data::new(setup_token);
+    let password_hashing = web::Data::new(password_hashing);
+    let email_policy = web::Data::new(email_policy);
+    let preferences = web::Data::new(preferences);
+    let trusted_proxies = web::Data::new(trusted_proxies);
+    let suppression_keys = web::Data::new(SuppressionKeys { key: suppression_secret });
     let redis_store = RedisSessionStore::new(redis_uri.expose_secret()).await?;
+    let login_throttle = web::Data::new(LoginThrottle::new(&redis_uri, login_throttling).await?);
+    let subscribe_protection = web::Data::new(subscribe_protection);
     let secret_key = Key::from(hmac_secret.expose_secret().as_bytes());
-    let message_store = CookieMessageStore::builder(secret_key.clone()).build();
+    let message_store = FlashCookieStore::new(secret_key.clone(), cookies.flash_same_site.into());
     let message_framework = FlashMessagesFramework::builder(message_store).build();
     let server = HttpServer::new(move || {
         App::new()
             .wrap(message_framework.clone())
-            .wrap(SessionMiddleware::new(redis_store.clone(), secret_key.clone()))
+            .wrap(
+                SessionMiddleware::builder(redis_store.clone(), secret_key.clone())
+                    .cookie_same_site(cookies.session_same_site.into())
+                    .build()
+            )
             .wrap(TracingLogger::default())
             .route("/", web::get().to(home))
             .route("/health_check", web::get().to(health_check))
+            .route("/subscriptions", web::get().to(subscribe_form))
             .route("/subscriptions", web::post().to(subscribe))
             .route("/subscriptions/confirm", web::get().to(confirm))
-            .route("/newsletters", web::post().to(publish_newsletter))
+            .route("/subscriptions/unsubscribe", web::get().to(unsubscribe_form))
+            .route("/subscriptions/unsubscribe", web::post().to(unsubscribe))
+            .route("/newsletters", web::post().to(publish_newsletter_api))
             .route("/login", web::get().to(login_form))
             .route("/login", web::post().to(login))
+            .route("/login/totp", web::get().to(login_totp_form))
+            .route("/login/totp", web::post().to(login_totp))
+            .route("/invitations/accept", web::get().to(accept_invitation_form))
+            .route("/invitations/accept", web::post().to(accept_invitation))
+            .route("/setup", web::get().to(setup_form))
+            .route("/setup", web::post().to(set_up_first_owner))
+            .route("/password-reset", web::get().to(request_password_reset_form))
+            .route("/password-reset", web::post().to(request_password_reset))
+            .route("/password-reset/confirm", web::get().to(password_reset_form))
+            .route("/password-reset/confirm", web::post().to(reset_password))
+            .route("/subscriptions/data", web::get().to(data_request_form))
+            .route("/subscriptions/data", web::post().to(request_subscriber_data))
+            .route("/subscriptions/data/access", web::get().to(download_subscriber_data))
+            .route("/subscriptions/data/erasure", web::get().to(erasure_form))
+            .route("/subscriptions/data/erasure", web::post().to(erase_subscriber_data))
+            .route("/preferences", web::get().to(preferences_form))
+            .route("/preferences", web::post().to(update_preferences))
+            .route("/preferences/email", web::post().to(request_email_change))
+            .route("/preferences/email/confirm", web::get().to(confirm_email_change))
+            .route("/preferences/unsubscribe", web::post().to(unsubscribe_from_preferences))
             .service(
                 web::scope("/admin")
+                    // Registered first so that it runs after the login check
+                    .wrap(from_fn(reject_forged_requests))
                     .wrap(from_fn(reject_anonymous_users))
                     .route("/dashboard", web::get().to(admin_dashboard))
-                    .route("/newsletters", web::get().to(publish_newsletter_form))
-                    .route("/newsletters", web::post().to(publish_newsletter))
+                    .route("/metrics", web::get().to(metrics))
+                    .route("/newsletters/history", web::get().to(newsletter_issues_history))
                     .route("/password", web::get().to(change_password_form))
                     .route("/password", web::post().to(change_password))
                     .route("/logout", web::post().to(log_out))
+                    .route("/totp", web::get().to(totp_form))
+                    .route("/totp", web::post().to(set_up_totp))
+                    .route("/totp/confirm", web::post().to(turn_on_totp))
+                    .route("/totp/disable", web::post().to(turn_off_totp))
+                    .route("/sessions", web::get().to(sessions_form))
+                    .route("/sessions/revoke-others", web::post().to(revoke_other_user_sessions))
+                    .route("/sessions/{session_id}/revoke", web::post().to(revoke_user_session))
+                    .route("/subscribers", web::get().to(subscribers_list))
+                    // Registered before `/subscribers/{subscriber_id}`, which would match too
+                    .route("/subscribers/imports", web::get().to(imports_form))
+                    .route("/subscribers/imports/{import_id}", web::get().to(import_details))
+                    .route("/subscribers/imports/{import_id}/report", web::get().to(import_report))
+                    .service(
+                        web::resource("/subscribers/imports")
+                            .wrap(from_fn(reject_viewers))
+                            .route(web::post().to(import_subscribers))
+                    )
+                    .service(
+                        web::resource("/subscribers/export")
+                            .wrap(from_fn(reject_non_owners))
+                            .route(web::get().to(export_subscribers))
+                    )
+                    .route("/subscribers/{subscriber_id}", web::get().to(subscriber_details))
+                    .route("/lists", web::get().to(mailing_lists_form))
+                    .route("/tags", web::get().to(tags_form))
+                    .route("/fields", web::get().to(custom_fields_form))
+                    .service(
+                        web::resource("/lists")
+                            .wrap(from_fn(reject_viewers))
+                            .route(web::post().to(create_mailing_list))
+                    )
+                    .service(
+                        web::resource("/tags")
+                            .wrap(from_fn(reject_viewers))
+                            .route(web::post().to(create_tag))
+                    )
+                    .service(
+                        web::resource("/fields")
+                            .wrap(from_fn(reject_viewers))
+                            .route(web::post().to(create_custom_field))
+                    )
+                    // Publishing and everything that feeds it is off-limits to viewers
+                    .service(
+                        web::resource("/newsletters")
+                            .wrap(from_fn(reject_viewers))
+                            .route(web::get().to(publish_newsletter_form))
+                            .route(web::post().to(publish_newsletter))
+                    )
+                    .service(
+                        web::resource("/newsletters/preview")
+                            .wrap(from_fn(reject_viewers))
+                            .route(web::post().to(preview_newsletter))
+                    )
+                    .service(
+                        web::resource("/templates")
+                            .wrap(from_fn(reject_viewers))
+                            .route(web::get().to(newsletter_templates_form))
+                            .route(web::post().to(create_newsletter_template))
+                    )
+                    .service(
+                        web::resource("/templates/{template_id}")
+                            .wrap(from_fn(reject_viewers))
+                            .route(web::get().to(edit_newsletter_template_form))
+                            .route(web::post().to(update_newsletter_template))
+                    )
+                    .service(
+                        web::resource("/tokens")
+                            .wrap(from_fn(reject_viewers))
+                            .route(web::get().to(api_tokens_form))
+                            .route(web::post().to(create_token))
+                    )
+                    .service(
+                        web::resource("/tokens/{token_id}/revoke")
+                            .wrap(from_fn(reject_viewers))
+                            .route(web::post().to(revoke_token))
+                    )
+                    .service(
+                        web::resource("/subscribers/{subscriber_id}")
+                            .wrap(from_fn(reject_viewers))
+                            .route(web::post().to(update_subscriber))
+                    )
+                    .service(
+                        web::resource("/subscribers/{subscriber_id}/tags")
+                            .wrap(from_fn(reject_viewers))
+                            .route(web::post().to(update_subscriber_tags))
+                    )
+                    .service(
+                        web::resource("/subscribers/{subscriber_id}/confirm")
+                            .wrap(from_fn(reject_viewers))
+                            .route(web::post().to(confirm_subscriber_as_admin))
+                    )
+                    .service(
+                        web::resource("/subscribers/{subscriber_id}/unsubscribe")
+                            .wrap(from_fn(reject_viewers))
+                            .route(web::post().to(unsubscribe_subscriber_as_admin))
+                    )
+                    .service(
+                        web::resource("/subscribers/{subscriber_id}/suppress")
+                            .wrap(from_fn(reject_viewers))
+                            .route(web::post().to(suppress_subscriber))
+                    )
+                    .service(
+                        web::resource("/subscribers/{subscriber_id}/delete")
+                            .wrap(from_fn(reject_viewers))
+                            .route(web::post().to(delete_subscriber))
+                    )
+                    // Only owners manage who has access
+                    .service(
+                        web::resource("/users")
+                            .wrap(from_fn(reject_non_owners))
+                            .route(web::get().to(users_form))
+                    )
+                    .service(
+                        web::resource("/users/invitations")
+                            .wrap(from_fn(reject_non_owners))
+                            .route(web::post().to(invite_user))
+                    )
+                    .service(
+                        web::resource("/users/{user_id}/role")
+                            .wrap(from_fn(reject_non_owners))
+                            .route(web::post().to(change_user_role))
+                    )
+                    .service(
+                        web::resource("/audit")
+                            .wrap(from_fn(reject_non_owners))
+                            .route(web::get().to(audit_log))
+                    )
+                    .service(
+                        web::resource("/audit/export")
+                            .wrap(from_fn(reject_non_owners))
+                            .route(web::get().to(export_audit_log))
+                    )
             )
             .app_data(connection_pool.clone())
             .app_data(email_client.clone())
             .app_data(base_url.clone())
+            .app_data(setup_token.clone())
+            .app_data(login_throttle.clone())
+            .app_data(subscribe_protection.clone())
+            .app_data(password_hashing.clone())
+            .app_data(email_policy.clone())
+            .app_data(preferences.clone())
+            .app_data(trusted_proxies.clone())
+            .app_data(suppression_keys.clone())
             .app_data(web::Data::new(HmacSecret(hmac_secret.clone())))
     })
     .listen(listener)?
@@ -0,0 +1,89 @@
+use crate::configuration::ChallengeProvider;
+use anyhow::Context;
+use futures_util::future::BoxFuture;
+use futures_util::FutureExt;
+use secrecy::{ExposeSecret, Secret};
+use std::net::IpAddr;
+
+// Checks the answer to a challenge (a CAPTCHA or similar) that the sign-up
+// form posts as `challenge_response`.
+pub trait ChallengeVerifier: Send + Sync {
+    fn verify<'a>(
+        &'a self,
+        response: &'a str,
+        remote_ip: Option<IpAddr>,
+    ) -> BoxFuture<'a, Result<bool, anyhow::Error>>;
+}
+
+// Talks to the "siteverify" endpoint that Turnstile, hCaptcha and reCAPTCHA
+// all expose: the secret and the response go in, `{"success": bool}` comes out.
+pub struct SiteVerifyChallenge {
+    http_client: reqwest::Client,
+    verify_url: String,
+    secret: Secret<String>,
+}
+
+#[derive(serde::Deserialize)]
+struct SiteVerifyResponse {
+    success: bool,
+}
+
+impl SiteVerifyChallenge {
+    pub fn new(verify_url: String, secret: Secret<String>, timeout: std::time::Duration) -> Self {
+        let http_client = reqwest::Client::builder()
+            .timeout(timeout)
+            .build()
+            .unwrap();
+        Self { http_client, verify_url, secret }
+    }
+}
+
+impl ChallengeVerifier for SiteVerifyChallenge {
+    fn verify<'a>(
+        &'a self,
+        response: &'a str,
+        remote_ip: Option<IpAddr>,
+    ) -> BoxFuture<'a, Result<bool, anyhow::Error>> {
+        async move {
+            let mut form = vec![
+                ("secret", self.secret.expose_secret().clone()),
+                ("response", response.to_string()),
+            ];
+            if let Some(ip) = remote_ip {
+                form.push(("remoteip", ip.to_string()));
+            }
+            let outcome: SiteVerifyResponse = self
+                .http_client
+                .post(&self.verify_url)
+                .form(&form)
+                .send()
+                .await
+                .context("Failed to reach the challenge verifier.")?
+                .error_for_status()
+                .context("The challenge verifier returned an error.")?
+                .json()
+                .await
+                .context("Failed to read the challenge verifier's answer.")?;
+            Ok(outcome.success)
+        }
+        .boxed()
+    }
+}
+
+// The script and the element that show the challenge on the sign-up form.
+// Each widget posts its answer under its own field name; the form data
+// accepts all of them as `challenge_response`.
+pub fn challenge_widget_html(provider: ChallengeProvider, site_key: &str) -> String {
+    let (script_url, class) = match provider {
+        ChallengeProvider::Turnstile => {
+            ("https://challenges.cloudflare.com/turnstile/v0/api.js", "cf-turnstile")
+        }
+        ChallengeProvider::Hcaptcha => ("https://js.hcaptcha.com/1/api.js", "h-captcha"),
+        ChallengeProvider::Recaptcha => ("https://www.google.com/recaptcha/api.js", "g-recaptcha"),
+    };
+    format!(
+        r#"<script src="{script_url}" async defer></script>
+        <div class="{class}" data-sitekey="{}"></div>"#,
+        htmlescape::encode_minimal(site_key),
+    )
+}
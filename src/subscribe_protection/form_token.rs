@@ -0,0 +1,69 @@
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use secrecy::{ExposeSecret, Secret};
+use sha2::Sha256;
+
+// How long a rendered sign-up form can be submitted for
+pub const FORM_TOKEN_TTL_HOURS: i64 = 24;
+
+fn form_mac(issued_at: i64, secret: &Secret<String>) -> Hmac<Sha256> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.expose_secret().as_bytes())
+        .expect("HMAC can take a key of any size");
+    // Prefix the message so the tag cannot be replayed for another purpose
+    mac.update(b"subscribe-form:");
+    mac.update(&issued_at.to_be_bytes());
+    mac
+}
+
+// Signs the time the form was rendered, so bots cannot backdate it
+pub fn form_token(issued_at: DateTime<Utc>, secret: &Secret<String>) -> String {
+    let issued_at = issued_at.timestamp();
+    let tag = hex::encode(form_mac(issued_at, secret).finalize().into_bytes());
+    format!("{}.{}", issued_at, tag)
+}
+
+// How many seconds passed between rendering the form and `now`
+pub fn form_age(
+    token: &str,
+    now: DateTime<Utc>,
+    secret: &Secret<String>,
+) -> Result<i64, anyhow::Error> {
+    let (issued_at, tag) = token
+        .split_once('.')
+        .context("The form token has no tag.")?;
+    let issued_at: i64 = issued_at.parse().context("The form token has no timestamp.")?;
+    let tag = hex::decode(tag).context("The form token tag is not valid hex.")?;
+    form_mac(issued_at, secret)
+        .verify_slice(&tag)
+        .context("The form token tag does not match.")?;
+    Ok(now.timestamp() - issued_at)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{form_age, form_token};
+    use chrono::{Duration, Utc};
+    use claims::{assert_err, assert_ok_eq};
+    use secrecy::Secret;
+
+    #[test]
+    fn the_age_is_measured_from_when_the_form_was_rendered() {
+        let secret = Secret::new("secret".to_string());
+        let rendered_at = Utc::now();
+        let token = form_token(rendered_at, &secret);
+        assert_ok_eq!(form_age(&token, rendered_at + Duration::seconds(5), &secret), 5);
+    }
+
+    #[test]
+    fn a_backdated_token_is_rejected() {
+        let secret = Secret::new("secret".to_string());
+        let now = Utc::now();
+        let token = form_token(now, &secret);
+        let (_, tag) = token.split_once('.').unwrap();
+        let backdated = format!("{}.{}", now.timestamp() - 60, tag);
+        assert_err!(form_age(&backdated, now, &secret));
+        assert_err!(form_age(&token, now, &Secret::new("another-secret".to_string())));
+        assert_err!(form_age("not-a-token", now, &secret));
+    }
+}
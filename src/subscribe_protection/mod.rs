@@ -0,0 +1,124 @@
+mod challenge;
+mod form_token;
+mod throttle;
+
+pub use challenge::{challenge_widget_html, ChallengeVerifier, SiteVerifyChallenge};
+pub use form_token::{form_age, form_token, FORM_TOKEN_TTL_HOURS};
+pub use throttle::{SubscribeAttemptSource, SubscribeThrottle};
+
+use crate::configuration::SubscribeProtectionSettings;
+use chrono::{Duration, Utc};
+use secrecy::Secret;
+use std::net::IpAddr;
+
+// Left empty by people. Bots fill in every field they find.
+pub const HONEYPOT_FIELD: &str = "website";
+
+// Why a sign-up was turned away, as reported in the metrics
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RejectionReason {
+    IpRateLimit,
+    AddressRateLimit,
+    Honeypot,
+    FormTiming,
+    Challenge,
+}
+
+impl RejectionReason {
+    pub const ALL: [RejectionReason; 5] = [
+        RejectionReason::IpRateLimit,
+        RejectionReason::AddressRateLimit,
+        RejectionReason::Honeypot,
+        RejectionReason::FormTiming,
+        RejectionReason::Challenge,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RejectionReason::IpRateLimit => "ip_rate_limit",
+            RejectionReason::AddressRateLimit => "address_rate_limit",
+            RejectionReason::Honeypot => "honeypot",
+            RejectionReason::FormTiming => "form_timing",
+            RejectionReason::Challenge => "challenge",
+        }
+    }
+}
+
+// Everything `POST /subscriptions` checks before it sends an email, so the
+// endpoint cannot be used to flood someone else's inbox.
+pub struct SubscribeProtection {
+    throttle: SubscribeThrottle,
+    min_fill_seconds: u64,
+    challenge: Option<Box<dyn ChallengeVerifier>>,
+    // Rendered on the sign-up form when a challenge is set up
+    challenge_widget: Option<String>,
+}
+
+impl SubscribeProtection {
+    pub async fn new(
+        redis_uri: &Secret<String>,
+        settings: SubscribeProtectionSettings,
+    ) -> Result<Self, anyhow::Error> {
+        let challenge_widget = settings
+            .challenge
+            .as_ref()
+            .map(|challenge| challenge_widget_html(challenge.provider, &challenge.site_key));
+        let challenge = settings.challenge.clone().map(|challenge| {
+            let timeout = challenge.timeout();
+            Box::new(SiteVerifyChallenge::new(challenge.verify_url, challenge.secret, timeout))
+                as Box<dyn ChallengeVerifier>
+        });
+        Ok(Self {
+            min_fill_seconds: settings.min_fill_seconds,
+            throttle: SubscribeThrottle::new(redis_uri, settings).await?,
+            challenge,
+            challenge_widget,
+        })
+    }
+
+    // Empty when no challenge is set up
+    pub fn challenge_widget(&self) -> &str {
+        self.challenge_widget.as_deref().unwrap_or_default()
+    }
+
+    pub fn throttle(&self) -> &SubscribeThrottle {
+        &self.throttle
+    }
+
+    // Forms must come from `GET /subscriptions`, and spend a moment with a
+    // person before they are sent back. Setting the minimum to 0 turns the
+    // check off, for clients that post directly.
+    pub fn is_filled_in_time(&self, form_token: &str, secret: &Secret<String>) -> bool {
+        if self.min_fill_seconds == 0 {
+            return true;
+        }
+        match form_age(form_token, Utc::now(), secret) {
+            Ok(age) => {
+                age >= self.min_fill_seconds as i64
+                    && age <= Duration::hours(FORM_TOKEN_TTL_HOURS).num_seconds()
+            }
+            Err(e) => {
+                tracing::debug!(error.message = %e, "Invalid sign-up form token");
+                false
+            }
+        }
+    }
+
+    // Passes when no verifier is set up
+    pub async fn passes_challenge(
+        &self,
+        response: &str,
+        remote_ip: Option<IpAddr>,
+    ) -> Result<bool, anyhow::Error> {
+        match &self.challenge {
+            None => Ok(true),
+            Some(_) if response.is_empty() => Ok(false),
+            Some(challenge) => challenge.verify(response, remote_ip).await,
+        }
+    }
+
+    pub async fn record_rejection(&self, reason: RejectionReason) -> Result<(), anyhow::Error> {
+        tracing::warn!(reason = reason.as_str(), "Rejected a sign-up attempt");
+        self.throttle.record_rejection(reason).await
+    }
+}
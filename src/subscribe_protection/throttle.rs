@@ -0,0 +1,122 @@
+use super::RejectionReason;
+use crate::configuration::SubscribeProtectionSettings;
+use anyhow::Context;
+use redis::aio::ConnectionManager;
+use secrecy::{ExposeSecret, Secret};
+use sha2::{Digest, Sha256};
+use std::net::IpAddr;
+
+// What a sign-up attempt is counted against
+#[derive(Debug, Clone, Copy)]
+pub enum SubscribeAttemptSource<'a> {
+    Ip(IpAddr),
+    // The address that would receive the confirmation email
+    Address(&'a str),
+    // The account a password reset link was asked for
+    Username(&'a str),
+}
+
+// Counts sign-up attempts, and the ones that were turned away, in the Redis
+// instance that already stores sessions, so every replica shares them.
+#[derive(Clone)]
+pub struct SubscribeThrottle {
+    connection: ConnectionManager,
+    settings: SubscribeProtectionSettings,
+}
+
+impl SubscribeThrottle {
+    pub async fn new(
+        redis_uri: &Secret<String>,
+        settings: SubscribeProtectionSettings,
+    ) -> Result<Self, anyhow::Error> {
+        let client = redis::Client::open(redis_uri.expose_secret().as_str())
+            .context("Failed to parse the Redis URI.")?;
+        let connection = ConnectionManager::new(client)
+            .await
+            .context("Failed to connect to Redis.")?;
+        Ok(Self { connection, settings })
+    }
+
+    fn max_attempts(&self, source: SubscribeAttemptSource) -> u32 {
+        match source {
+            SubscribeAttemptSource::Ip(_) => self.settings.max_attempts_per_ip,
+            SubscribeAttemptSource::Address(_) | SubscribeAttemptSource::Username(_) => {
+                self.settings.max_attempts_per_address
+            }
+        }
+    }
+
+    // Addresses and usernames are hashed to keep attacker-chosen strings
+    // out of key names
+    fn key(&self, source: SubscribeAttemptSource) -> String {
+        let subject = match source {
+            SubscribeAttemptSource::Ip(ip) => format!("ip:{}", ip),
+            SubscribeAttemptSource::Address(address) => {
+                format!("address:{}", hex::encode(Sha256::digest(address.as_bytes())))
+            }
+            SubscribeAttemptSource::Username(username) => {
+                format!("username:{}", hex::encode(Sha256::digest(username.as_bytes())))
+            }
+        };
+        format!("{}:attempts:{}", self.settings.key_prefix, subject)
+    }
+
+    fn rejections_key(&self, reason: RejectionReason) -> String {
+        format!("{}:rejections:{}", self.settings.key_prefix, reason.as_str())
+    }
+
+    // Counts an attempt and tells whether it is still within the limit
+    #[tracing::instrument(name = "Count sign-up attempt", skip(self))]
+    pub async fn allow_attempt(
+        &self,
+        source: SubscribeAttemptSource<'_>,
+    ) -> Result<bool, anyhow::Error> {
+        let mut connection = self.connection.clone();
+        let key = self.key(source);
+        let (n_attempts,): (u32,) = redis::pipe()
+            .atomic()
+            .cmd("SET")
+            .arg(&key)
+            .arg(0)
+            .arg("EX")
+            .arg(self.settings.window_seconds)
+            .arg("NX")
+            .ignore()
+            .cmd("INCR")
+            .arg(&key)
+            .query_async(&mut connection)
+            .await
+            .context("Failed to count a sign-up attempt in Redis.")?;
+        Ok(n_attempts <= self.max_attempts(source))
+    }
+
+    #[tracing::instrument(name = "Record rejected sign-up", skip(self))]
+    pub async fn record_rejection(&self, reason: RejectionReason) -> Result<(), anyhow::Error> {
+        let mut connection = self.connection.clone();
+        redis::cmd("INCR")
+            .arg(self.rejections_key(reason))
+            .query_async::<_, ()>(&mut connection)
+            .await
+            .context("Failed to count a rejected sign-up in Redis.")?;
+        Ok(())
+    }
+
+    // How many sign-ups were rejected for each reason, since the counters
+    // were created
+    #[tracing::instrument(name = "Get rejected sign-ups", skip(self))]
+    pub async fn rejection_counts(&self) -> Result<Vec<(RejectionReason, u64)>, anyhow::Error> {
+        let mut connection = self.connection.clone();
+        let mut pipe = redis::pipe();
+        for reason in RejectionReason::ALL {
+            pipe.cmd("GET").arg(self.rejections_key(reason));
+        }
+        let counts: Vec<Option<u64>> = pipe
+            .query_async(&mut connection)
+            .await
+            .context("Failed to read rejected sign-ups from Redis.")?;
+        Ok(RejectionReason::ALL
+            .into_iter()
+            .zip(counts.into_iter().map(Option::unwrap_or_default))
+            .collect())
+    }
+}
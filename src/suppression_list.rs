@@ -0,0 +1,117 @@
+use anyhow::Context;
+use hmac::{Hmac, Mac};
+use secrecy::{ExposeSecret, Secret};
+use sha2::Sha256;
+use sqlx::{Executor, Postgres};
+use crate::domain::normalize_email;
+
+// Why an address was added to the suppression list
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SuppressionReason {
+    // Its owner asked for their data to be erased
+    Erasure,
+}
+
+impl SuppressionReason {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SuppressionReason::Erasure => "erasure",
+        }
+    }
+}
+
+// The key tombstones are made with. The list has its own, so a leak of
+// the secret behind session cookies and form tokens does not open it up.
+#[derive(Clone)]
+pub struct SuppressionKeys {
+    pub key: Secret<String>,
+}
+
+// What the suppression list keeps instead of the address. Keyed, so that
+// the list cannot be checked against guessed addresses without the secret.
+// The address is normalized as subscriptions store it.
+pub fn email_tombstone(email: &str, key: &Secret<String>) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key.expose_secret().as_bytes())
+        .expect("HMAC can take a key of any size");
+    // Prefix the message so the tag cannot be replayed for another purpose
+    mac.update(b"suppression:");
+    mac.update(normalize_email(email).as_bytes());
+    hex::encode(mac.finalize().into_bytes())
+}
+
+#[tracing::instrument(name = "Add to the suppression list", skip(executor, email, keys))]
+pub async fn suppress_email<'c, E>(
+    executor: E,
+    email: &str,
+    reason: SuppressionReason,
+    keys: &SuppressionKeys,
+) -> Result<(), anyhow::Error>
+where
+    E: Executor<'c, Database = Postgres>,
+{
+    sqlx::query!(
+        r#"
+        INSERT INTO suppression_list (email_hash, reason, created_at)
+        VALUES ($1, $2, now())
+        ON CONFLICT (email_hash) DO NOTHING
+        "#,
+        email_tombstone(email, &keys.key),
+        reason.as_str(),
+    )
+        .execute(executor)
+        .await
+        .context("Failed to add an address to the suppression list.")?;
+    Ok(())
+}
+
+#[tracing::instrument(name = "Check the suppression list", skip(executor, email, keys))]
+pub async fn is_email_suppressed<'c, E>(
+    executor: E,
+    email: &str,
+    keys: &SuppressionKeys,
+) -> Result<bool, anyhow::Error>
+where
+    E: Executor<'c, Database = Postgres>,
+{
+    let row = sqlx::query!(
+        "SELECT email_hash FROM suppression_list WHERE email_hash = $1",
+        email_tombstone(email, &keys.key),
+    )
+        .fetch_optional(executor)
+        .await
+        .context("Failed to check the suppression list.")?;
+    Ok(row.is_some())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::email_tombstone;
+    use secrecy::Secret;
+
+    #[test]
+    fn tombstones_ignore_case_and_surrounding_whitespace() {
+        let secret = Secret::new("secret".to_string());
+        assert_eq!(
+            email_tombstone(" Ursula@Example.com ", &secret),
+            email_tombstone("ursula@example.com", &secret)
+        );
+    }
+
+    #[test]
+    fn tombstones_of_international_domains_use_punycode() {
+        let secret = Secret::new("secret".to_string());
+        assert_eq!(
+            email_tombstone("ursula@Bücher.example", &secret),
+            email_tombstone("ursula@xn--bcher-kva.example", &secret)
+        );
+    }
+
+    #[test]
+    fn tombstones_depend_on_the_secret() {
+        let email = "ursula@example.com";
+        assert_ne!(
+            email_tombstone(email, &Secret::new("one".to_string())),
+            email_tombstone(email, &Secret::new("two".to_string()))
+        );
+    }
+}
@@ -0,0 +1,58 @@
+use anyhow::Context;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+pub struct TagSummary {
+    pub tag_id: Uuid,
+    pub name: String,
+    pub n_subscribers: i64,
+}
+
+#[tracing::instrument(name = "Get tags", skip(pool))]
+pub async fn get_tags(pool: &PgPool) -> Result<Vec<TagSummary>, anyhow::Error> {
+    let tags = sqlx::query_as!(
+        TagSummary,
+        r#"
+        SELECT t.tag_id, t.name, COUNT(st.subscriber_id) AS "n_subscribers!"
+        FROM tags t
+        LEFT JOIN subscriber_tags st ON st.tag_id = t.tag_id
+        GROUP BY t.tag_id
+        ORDER BY t.name
+        "#,
+    )
+        .fetch_all(pool)
+        .await
+        .context("Failed to retrieve the tags.")?;
+    Ok(tags)
+}
+
+#[tracing::instrument(name = "Get subscriber tags", skip(pool))]
+pub async fn get_subscriber_tag_ids(
+    pool: &PgPool,
+    subscriber_id: Uuid,
+) -> Result<Vec<Uuid>, anyhow::Error> {
+    let tag_ids = sqlx::query!(
+        "SELECT tag_id FROM subscriber_tags WHERE subscriber_id = $1",
+        subscriber_id,
+    )
+        .fetch_all(pool)
+        .await
+        .context("Failed to retrieve the subscriber's tags.")?
+        .into_iter()
+        .map(|r| r.tag_id)
+        .collect();
+    Ok(tag_ids)
+}
+
+#[tracing::instrument(name = "Check that a tag exists", skip(pool))]
+pub async fn tag_exists(pool: &PgPool, tag_id: Uuid) -> Result<bool, anyhow::Error> {
+    let exists = sqlx::query!(
+        r#"SELECT EXISTS (SELECT 1 FROM tags WHERE tag_id = $1) AS "exists!""#,
+        tag_id,
+    )
+        .fetch_one(pool)
+        .await
+        .context("Failed to look up the tag.")?
+        .exists;
+    Ok(exists)
+}
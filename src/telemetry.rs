@@ -1,10 +1,11 @@
 use tracing::subscriber::set_global_default;
-use tracing::Subscriber;
+use tracing::{Instrument, Subscriber};
 use tracing_bunyan_formatter::{BunyanFormattingLayer, JsonStorageLayer};
 use tracing_log::LogTracer;
 use tracing_subscriber::{layer::SubscriberExt, EnvFilter, Registry};
 use tracing_subscriber::fmt::MakeWriter;
 use tokio::task::JoinHandle;
+use std::future::Future;
 
 pub fn get_subscriber<Sink>(
     name: String,
@@ -40,3 +41,11 @@ where
     let current_span = tracing::Span::current();
     tokio::task::spawn_blocking(move || current_span.in_scope(f))
 }
+
+pub fn spawn_with_tracing<F>(future: F) -> JoinHandle<F::Output>
+where
+    F: Future + Send + 'static,
+    F::Output: Send + 'static,
+{
+    tokio::spawn(future.instrument(tracing::Span::current()))
+}
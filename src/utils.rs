@@ -1,5 +1,6 @@
 use actix_web::http::header::LOCATION;
 use actix_web::HttpResponse;
+use chrono::{DateTime, NaiveDate, Utc};
 
 // Return an opaque 500 while preserving the error root's cause for logging.
 pub fn e500<T>(e: T) -> actix_web::Error
@@ -23,3 +24,29 @@ where
     actix_web::error::ErrorBadRequest(e)
 }
 
+// Every value submitted for `name` in a url-encoded body. A group of
+// checkboxes repeats its name, which `web::Form` cannot collect.
+pub fn form_values(body: &[u8], name: &str) -> Vec<String> {
+    serde_urlencoded::from_bytes::<Vec<(String, String)>>(body)
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|(key, _)| key == name)
+        .map(|(_, value)| value)
+        .collect()
+}
+
+// Turn a `YYYY-MM-DD` filter into the instant that day starts, in UTC. Blank
+// filters are `None`. `offset_days` moves the instant forward, e.g. by 1 to
+// turn an inclusive end date into an exclusive bound.
+pub fn parse_day(day: &str, offset_days: u64) -> Result<Option<DateTime<Utc>>, String> {
+    if day.is_empty() {
+        return Ok(None);
+    }
+    let date = NaiveDate::parse_from_str(day, "%Y-%m-%d")
+        .map_err(|_| format!("{} is not a date in the YYYY-MM-DD format.", day))?;
+    let start = date
+        .checked_add_days(chrono::Days::new(offset_days))
+        .and_then(|date| date.and_hms_opt(0, 0, 0))
+        .ok_or_else(|| format!("{} is out of range.", day))?;
+    Ok(Some(start.and_utc()))
+}
@@ -0,0 +1,165 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp};
+use uuid::Uuid;
+
+fn newsletter_body() -> serde_json::Value {
+    serde_json::json!({
+        "title": "Newsletter title",
+        "content": {
+            "text": "Newsletter body as plain text",
+            "html": "<p>Newsletter body as HTML</p>",
+        }
+    })
+}
+
+async fn post_newsletters_with_token(app: &TestApp, token: &str) -> reqwest::Response {
+    app.api_client
+        .post(format!("{}/newsletters", &app.address))
+        .bearer_auth(token)
+        .header("Idempotency-Key", Uuid::new_v4().to_string())
+        .json(&newsletter_body())
+        .send()
+        .await
+        .expect("Failed to execute request.")
+}
+
+// Create a token through the admin area and read it back from the response
+async fn create_api_token(app: &TestApp, name: &str) -> String {
+    let response = app
+        .post_create_api_token(&[("name", name), ("scope", "newsletters:publish")])
+        .await;
+    assert_eq!(200, response.status().as_u16());
+    let html_page = response.text().await.unwrap();
+    let start = html_page.find("z2p_").expect("No token in the page.");
+    html_page[start..]
+        .chars()
+        .take_while(|c| c.is_ascii_alphanumeric() || *c == '_')
+        .collect()
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_manage_api_tokens() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app
+        .post_create_api_token(&[("name", "cms"), ("scope", "newsletters:publish")])
+        .await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn api_tokens_are_stored_hashed() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    // Act
+    let token = create_api_token(&app, "cms").await;
+
+    // Assert
+    let saved = sqlx::query!("SELECT name, token_hash FROM api_tokens")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved token.");
+    assert_eq!(saved.name, "cms");
+    assert_ne!(saved.token_hash, token);
+    assert!(!saved.token_hash.contains(&token));
+}
+
+#[tokio::test]
+async fn a_new_api_token_is_shown_once_and_not_cached() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    // Act
+    let response = app
+        .post_create_api_token(&[("name", "cms"), ("scope", "newsletters:publish")])
+        .await;
+
+    // Assert
+    assert_eq!(200, response.status().as_u16());
+    assert_eq!(response.headers()["Cache-Control"], "no-store");
+    assert!(response.text().await.unwrap().contains("z2p_"));
+    let html_page = app.get_api_tokens_html().await;
+    assert!(!html_page.contains("z2p_"));
+}
+
+#[tokio::test]
+async fn a_valid_api_token_can_publish_a_newsletter() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let token = create_api_token(&app, "cms").await;
+
+    // Act
+    let response = post_newsletters_with_token(&app, &token).await;
+
+    // Assert
+    assert_eq!(202, response.status().as_u16());
+    let saved = sqlx::query!("SELECT author_id FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved issue.");
+    assert_eq!(saved.author_id, Some(app.test_user.user_id));
+    let token_row = sqlx::query!("SELECT last_used_at FROM api_tokens")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved token.");
+    assert!(token_row.last_used_at.is_some());
+}
+
+#[tokio::test]
+async fn an_unknown_api_token_is_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = post_newsletters_with_token(&app, "z2p_not-a-real-token").await;
+
+    // Assert
+    assert_eq!(401, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn a_revoked_api_token_is_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let token = create_api_token(&app, "cms").await;
+    let token_id = sqlx::query!("SELECT token_id FROM api_tokens")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved token.")
+        .token_id;
+
+    // Act - Part 1 - Revoke the token
+    let response = app.post_revoke_api_token(&token_id).await;
+    assert_is_redirect_to(&response, "/admin/tokens");
+    let html_page = app.get_api_tokens_html().await;
+    assert!(html_page.contains("<p><i>The API token has been revoked.</i></p>"));
+
+    // Act - Part 2 - Try to use it
+    let response = post_newsletters_with_token(&app, &token).await;
+
+    // Assert
+    assert_eq!(401, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn a_token_without_a_scope_cannot_be_created() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    // Act
+    let response = app.post_create_api_token(&[("name", "cms")]).await;
+    assert_is_redirect_to(&response, "/admin/tokens");
+
+    // Assert
+    let html_page = app.get_api_tokens_html().await;
+    assert!(html_page.contains("<p><i>API tokens need at least one scope.</i></p>"));
+}
@@ -0,0 +1,234 @@
+use crate::helpers::{
+    assert_is_redirect_to, create_confirmed_subscriber, spawn_app, spawn_app_with, TestApp, TestUser,
+};
+use uuid::Uuid;
+
+// (action, target) of every recorded event, oldest first
+async fn recorded_events(app: &TestApp) -> Vec<(String, Option<String>)> {
+    sqlx::query!("SELECT action, target FROM audit_events ORDER BY occurred_at")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|r| (r.action, r.target))
+        .collect()
+}
+
+#[tokio::test]
+async fn logins_password_changes_and_logouts_are_recorded() {
+    // Arrange
+    let app = spawn_app().await;
+    let new_password = Uuid::new_v4().to_string();
+
+    // Act
+    app.test_user.login(&app).await;
+    app.post_change_password(&serde_json::json!({
+        "current_password": &app.test_user.password,
+        "new_password": &new_password,
+        "new_password_check": &new_password,
+    }))
+    .await;
+    app.post_logout().await;
+
+    // Assert
+    let actions: Vec<String> = recorded_events(&app)
+        .await
+        .into_iter()
+        .map(|(action, _)| action)
+        .collect();
+    assert_eq!(actions, vec!["login", "password_changed", "logout"]);
+    let actor = sqlx::query!("SELECT DISTINCT actor_user_id FROM audit_events")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .actor_user_id;
+    assert_eq!(actor, Some(app.test_user.user_id));
+}
+
+#[tokio::test]
+async fn failed_logins_are_recorded_with_the_attempted_username() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    app.post_login(&serde_json::json!({
+        "username": "someone-else",
+        "password": "wrong-password"
+    }))
+    .await;
+
+    // Assert
+    assert_eq!(
+        recorded_events(&app).await,
+        vec![("login_failed".to_string(), Some("someone-else".to_string()))]
+    );
+}
+
+#[tokio::test]
+async fn publishing_an_issue_is_recorded_with_the_issue_id() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+
+    // Act
+    let response = app
+        .post_publish_newsletter(&serde_json::json!({
+            "title": "Newsletter title",
+            "text_content": "Newsletter body as plain text",
+            "html_content": "<p>Newsletter body as HTML</p>",
+            "idempotency_key": Uuid::new_v4().to_string()
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+
+    // Assert
+    let issue_id = sqlx::query!("SELECT newsletter_issue_id FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .newsletter_issue_id;
+    let events = recorded_events(&app).await;
+    assert_eq!(
+        events.last().unwrap(),
+        &("newsletter_published".to_string(), Some(issue_id.to_string()))
+    );
+}
+
+#[tokio::test]
+async fn audit_events_cannot_be_changed_or_deleted() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    // Act
+    let updated = sqlx::query!("UPDATE audit_events SET action = 'nothing'")
+        .execute(&app.db_pool)
+        .await;
+    let deleted = sqlx::query!("DELETE FROM audit_events")
+        .execute(&app.db_pool)
+        .await;
+
+    // Assert
+    assert!(updated.is_err());
+    assert!(deleted.is_err());
+    assert_eq!(recorded_events(&app).await.len(), 1);
+}
+
+#[tokio::test]
+async fn only_owners_can_see_the_audit_log() {
+    // Arrange
+    let app = spawn_app().await;
+    let editor = TestUser::generate_with_role("editor");
+    editor.store(&app.db_pool).await;
+    editor.login(&app).await;
+
+    // Act
+    let page = app.get_audit_log("").await;
+    let export = app.get_audit_export("").await;
+
+    // Assert
+    assert_eq!(page.status().as_u16(), 403);
+    assert_eq!(export.status().as_u16(), 403);
+}
+
+#[tokio::test]
+async fn the_audit_log_can_be_filtered_by_action() {
+    // Arrange
+    let app = spawn_app().await;
+    app.post_login(&serde_json::json!({
+        "username": "someone-else",
+        "password": "wrong-password"
+    }))
+    .await;
+    app.test_user.login(&app).await;
+
+    // Act
+    let all_events = app.get_audit_log_html("").await;
+    let failed_logins = app.get_audit_log_html("action=login_failed").await;
+
+    // Assert
+    assert!(all_events.contains("someone-else"));
+    assert!(all_events.contains("<td>login</td>"));
+    assert!(failed_logins.contains("someone-else"));
+    assert!(!failed_logins.contains("<td>login</td>"));
+}
+
+#[tokio::test]
+async fn the_export_is_json_and_honours_the_filters() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    app.post_logout().await;
+    app.test_user.login(&app).await;
+    let today = chrono::Utc::now().format("%Y-%m-%d");
+
+    // Act
+    let response = app
+        .get_audit_export(&format!(
+            "action=login&actor={}&since={}&until={}",
+            app.test_user.username, today, today
+        ))
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(response
+        .headers()
+        .get("Content-Disposition")
+        .unwrap()
+        .to_str()
+        .unwrap()
+        .starts_with("attachment"));
+    let events: Vec<serde_json::Value> = response.json().await.unwrap();
+    assert_eq!(events.len(), 2);
+    for event in events {
+        assert_eq!(event["action"], "login");
+        assert_eq!(event["actor_username"], app.test_user.username.as_str());
+        assert_eq!(event["ip_address"], "127.0.0.1");
+    }
+}
+
+#[tokio::test]
+async fn behind_a_trusted_proxy_the_client_address_is_recorded() {
+    // Arrange
+    let app = spawn_app_with(|c| {
+        c.application.trusted_proxies = vec!["127.0.0.1".parse().unwrap()];
+    })
+    .await;
+
+    // Act
+    app.api_client
+        .post(format!("{}/login", &app.address))
+        .header("X-Forwarded-For", "203.0.113.7")
+        .form(&serde_json::json!({
+            "username": &app.test_user.username,
+            "password": "wrong-password"
+        }))
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    let ip_address = sqlx::query!("SELECT ip_address FROM audit_events WHERE action = 'login_failed'")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .ip_address;
+    assert_eq!(ip_address.as_deref(), Some("203.0.113.7"));
+}
+
+#[tokio::test]
+async fn invalid_filters_are_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    for query in ["action=deleted_everything", "since=yesterday"] {
+        // Act
+        let response = app.get_audit_log(query).await;
+
+        // Assert
+        assert_eq!(response.status().as_u16(), 400, "{} was accepted.", query);
+    }
+}
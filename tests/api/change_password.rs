@@ -109,3 +109,73 @@ pub async fn changing_password_works() {
     let response = app.post_login(&login_body).await;
     assert_is_redirect_to(&response, "/admin/dashboard");
 }
+
+#[tokio::test]
+async fn new_password_must_be_between_8_and_64_characters_long() {
+    // Arrange
+    let app = spawn_app().await;
+    app.post_login(&serde_json::json!({
+        "username": &app.test_user.username,
+        "password": &app.test_user.password
+    }))
+    .await;
+    let test_cases = vec![("x7#Qp!z".to_string(), "too short"), ("x7#Qp!z".repeat(10), "too long")];
+
+    for (new_password, error_message) in test_cases {
+        // Act
+        let response = app
+            .post_change_password(&serde_json::json!({
+                "current_password": &app.test_user.password,
+                "new_password": &new_password,
+                "new_password_check": &new_password,
+            }))
+        .await;
+
+        // Assert
+        assert_is_redirect_to(&response, "/admin/password");
+        let html_page = app.get_change_password_html().await;
+        assert!(
+            html_page.contains(
+                "<p><i>Your password must be between 8 and 64 characters long.</i></p>"
+            ),
+            "The password was not rejected when it was {}.",
+            error_message
+        );
+    }
+}
+
+#[tokio::test]
+async fn common_or_easily_guessed_passwords_are_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    app.post_login(&serde_json::json!({
+        "username": &app.test_user.username,
+        "password": &app.test_user.password
+    }))
+    .await;
+    let test_cases = vec![
+        ("Password1".to_string(), "This password is too common"),
+        ("aaaaaaaaaaaa".to_string(), "This password is too easy to guess."),
+        (format!("{}2024", app.test_user.username), "This password is too easy to guess."),
+    ];
+
+    for (new_password, error_message) in test_cases {
+        // Act
+        let response = app
+            .post_change_password(&serde_json::json!({
+                "current_password": &app.test_user.password,
+                "new_password": &new_password,
+                "new_password_check": &new_password,
+            }))
+        .await;
+
+        // Assert
+        assert_is_redirect_to(&response, "/admin/password");
+        let html_page = app.get_change_password_html().await;
+        assert!(
+            html_page.contains(&format!("<p><i>{}", error_message)),
+            "{} was not rejected.",
+            new_password
+        );
+    }
+}
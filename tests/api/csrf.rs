@@ -0,0 +1,140 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app};
+use uuid::Uuid;
+
+#[tokio::test]
+async fn admin_forms_carry_a_csrf_token() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    // Act
+    let html_page = app.get_change_password_html().await;
+
+    // Assert
+    let token = app.csrf_token().await;
+    assert!(!token.is_empty());
+    assert!(html_page.contains(&format!(
+        r#"<input type="hidden" name="csrf_token" value="{}">"#,
+        token
+    )));
+}
+
+#[tokio::test]
+async fn admin_form_submissions_without_a_valid_csrf_token_are_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let new_password = Uuid::new_v4().to_string();
+    let test_cases = vec![(None, "no token"), (Some("not-the-token"), "a wrong token")];
+
+    for (csrf_token, description) in test_cases {
+        // Act
+        let mut body = serde_json::json!({
+            "current_password": &app.test_user.password,
+            "new_password": &new_password,
+            "new_password_check": &new_password,
+        });
+        if let Some(csrf_token) = csrf_token {
+            body["csrf_token"] = csrf_token.into();
+        }
+        let response = app
+            .api_client
+            .post(format!("{}/admin/password", &app.address))
+            .form(&body)
+            .send()
+            .await
+            .expect("Failed to execute request.");
+
+        // Assert
+        assert_eq!(
+            403,
+            response.status().as_u16(),
+            "The form was not rejected with {}.",
+            description
+        );
+    }
+    // The password was never changed
+    app.post_logout().await;
+    let response = app
+        .post_login(&serde_json::json!({
+            "username": &app.test_user.username,
+            "password": &app.test_user.password
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+}
+
+#[tokio::test]
+async fn the_csrf_token_can_be_sent_as_a_form_field() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let csrf_token = app.csrf_token().await;
+
+    // Act
+    let response = app
+        .api_client
+        .post(format!("{}/admin/logout", &app.address))
+        .form(&serde_json::json!({ "csrf_token": csrf_token }))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    // Assert
+    assert_is_redirect_to(&response, "/login");
+    let html_page = app.get_login_html().await;
+    assert!(html_page.contains("<p><i>You have successfully logged out.</i></p>"));
+}
+
+#[tokio::test]
+async fn a_new_login_gets_a_new_csrf_token() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let first_token = app.csrf_token().await;
+    app.post_logout().await;
+
+    // Act
+    app.test_user.login(&app).await;
+
+    // Assert
+    let second_token = app.csrf_token().await;
+    assert!(!second_token.is_empty());
+    assert_ne!(first_token, second_token);
+}
+
+#[tokio::test]
+async fn session_and_flash_cookies_are_same_site_strict() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act - a failed login sets a flash message, a successful one a session
+    let failed = app
+        .post_login(&serde_json::json!({
+            "username": &app.test_user.username,
+            "password": "wrong-password"
+        }))
+        .await;
+    let succeeded = app
+        .post_login(&serde_json::json!({
+            "username": &app.test_user.username,
+            "password": &app.test_user.password
+        }))
+        .await;
+
+    // Assert
+    let set_cookies = failed
+        .headers()
+        .get_all("Set-Cookie")
+        .iter()
+        .chain(succeeded.headers().get_all("Set-Cookie"))
+        .map(|value| value.to_str().unwrap().to_string())
+        .collect::<Vec<_>>();
+    for name in ["_flash=", "id="] {
+        let cookie = set_cookies
+            .iter()
+            .find(|cookie| cookie.starts_with(name))
+            .unwrap_or_else(|| panic!("No {} cookie was set.", name));
+        assert!(cookie.contains("SameSite=Strict"), "{}", cookie);
+    }
+}
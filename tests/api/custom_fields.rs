@@ -0,0 +1,241 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app, when_sending_an_email, TestApp};
+use uuid::Uuid;
+use wiremock::ResponseTemplate;
+
+// A required company name, an optional t-shirt size and an optional
+// opt-in for partner offers
+async fn create_fields(app: &TestApp) {
+    for field in [
+        serde_json::json!({ "key": "company", "label": "Company", "kind": "text", "is_required": true }),
+        serde_json::json!({ "key": "size", "label": "T-shirt size", "kind": "select", "options": "S\nM\nL" }),
+        serde_json::json!({ "key": "partner_offers", "label": "Partner offers", "kind": "boolean" }),
+    ] {
+        let response = app.post_create_custom_field(&field).await;
+        assert_is_redirect_to(&response, "/admin/fields");
+    }
+}
+
+async fn subscribe(app: &TestApp, fields: &str) -> reqwest::Response {
+    app.post_subscriptions(format!("name=le%20guin&email=ursula_le_guin%40gmail.com{}", fields))
+        .await
+}
+
+async fn subscriber_id(app: &TestApp) -> Uuid {
+    sqlx::query!("SELECT id FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .id
+}
+
+#[tokio::test]
+async fn admins_can_define_custom_fields() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    // Act
+    create_fields(&app).await;
+
+    // Assert
+    let html_page = app.get_custom_fields_html().await;
+    assert!(html_page.contains("<tr><td>Company</td><td>company</td><td>Free text</td><td></td><td>Required</td></tr>"));
+    assert!(html_page.contains("<td>S, M, L</td><td>Optional</td>"));
+}
+
+#[tokio::test]
+async fn invalid_field_definitions_are_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let test_cases = [
+        (
+            serde_json::json!({ "key": "size", "label": "Size", "kind": "select" }),
+            "Select fields need at least one option.",
+        ),
+        (
+            serde_json::json!({ "key": "name", "label": "Name", "kind": "text" }),
+            "name is reserved.",
+        ),
+        (
+            serde_json::json!({ "key": "Company Name", "label": "Company", "kind": "text" }),
+            "Field keys are up to 32 lowercase letters",
+        ),
+    ];
+
+    for (body, error) in test_cases {
+        // Act
+        app.post_create_custom_field(&body).await;
+
+        // Assert
+        let html_page = app.get_custom_fields_html().await;
+        assert!(html_page.contains(error), "Expected '{}' for {}", error, body);
+    }
+    let n_fields = sqlx::query!(r#"SELECT COUNT(*) AS "n!" FROM custom_fields"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .n;
+    assert_eq!(n_fields, 0);
+}
+
+#[tokio::test]
+async fn subscribe_returns_400_when_custom_fields_are_invalid() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    create_fields(&app).await;
+    let test_cases = [
+        ("", "the required field missing"),
+        ("&company=%20", "a blank required field"),
+        ("&company=Acme&size=XXL", "an unknown option"),
+        ("&company=Acme&partner_offers=maybe", "neither yes nor no"),
+    ];
+
+    for (fields, description) in test_cases {
+        // Act
+        let response = subscribe(&app, fields).await;
+
+        // Assert
+        assert_eq!(
+            400,
+            response.status().as_u16(),
+            "The API did not return a 400 Bad Request when the payload had {}.",
+            description
+        );
+    }
+}
+
+#[tokio::test]
+async fn custom_fields_are_stored_and_shown_to_admins() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    create_fields(&app).await;
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = subscribe(&app, "&company=Acme%20%26%20Co&size=M").await;
+
+    // Assert
+    assert_eq!(200, response.status().as_u16());
+    let html_page = app.get_subscriber_details_html(subscriber_id(&app).await).await;
+    assert!(html_page.contains("<p>Company: Acme &amp; Co</p>"));
+    assert!(html_page.contains("<p>T-shirt size: M</p>"));
+    assert!(html_page.contains("<p>Partner offers: false</p>"));
+}
+
+#[tokio::test]
+async fn signing_up_again_does_not_overwrite_the_stored_values() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    create_fields(&app).await;
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    subscribe(&app, "&company=Acme&size=M").await;
+
+    // Act
+    let response = subscribe(&app, "&company=Someone%20else&size=S").await;
+
+    // Assert
+    assert_eq!(200, response.status().as_u16());
+    let html_page = app.get_subscriber_details_html(subscriber_id(&app).await).await;
+    assert!(html_page.contains("<p>Company: Acme</p>"));
+    assert!(html_page.contains("<p>T-shirt size: M</p>"));
+}
+
+#[tokio::test]
+async fn custom_fields_are_exported() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    create_fields(&app).await;
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    subscribe(&app, "&company=Acme&partner_offers=on").await;
+
+    // Act - Part 1 - CSV
+    let body = app
+        .get_subscribers_export("format=csv")
+        .await
+        .text()
+        .await
+        .unwrap();
+
+    // Assert
+    let mut lines = body.lines();
+    assert_eq!(
+        lines.next().unwrap(),
+        "id,email,name,subscribed_at,status,confirmed_at,company,size,partner_offers"
+    );
+    assert!(lines.next().unwrap().ends_with(",Acme,,true"));
+
+    // Act - Part 2 - NDJSON
+    let body = app
+        .get_subscribers_export("format=ndjson")
+        .await
+        .text()
+        .await
+        .unwrap();
+
+    // Assert
+    let line: serde_json::Value = serde_json::from_str(body.lines().next().unwrap()).unwrap();
+    assert_eq!(
+        line["fields"],
+        serde_json::json!({ "company": "Acme", "size": "", "partner_offers": "true" })
+    );
+}
+
+#[tokio::test]
+async fn custom_fields_can_be_used_as_variables_in_issues() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    create_fields(&app).await;
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    subscribe(&app, "&company=Acme%20%26%20Co").await;
+    let token = sqlx::query!("SELECT subscription_token FROM subscription_tokens")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .subscription_token;
+    reqwest::get(format!(
+        "{}/subscriptions/confirm?subscription_token={}",
+        app.address, token
+    ))
+    .await
+    .unwrap()
+    .error_for_status()
+    .unwrap();
+
+    // Act
+    let response = app
+        .post_publish_newsletter(&serde_json::json!({
+            "title": "News for {{company}}",
+            "text_content": "Hello {{company}}, size {{size}}",
+            "html_content": "<p>Hello {{company}}</p>",
+            "idempotency_key": Uuid::new_v4().to_string()
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let email_request = app.email_server.received_requests().await.unwrap().pop().unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    assert_eq!(body["Subject"], "News for Acme & Co");
+    // Followed by the link to the preferences page
+    assert!(body["TextBody"].as_str().unwrap().starts_with("Hello Acme & Co, size \n"));
+    assert!(body["HtmlBody"].as_str().unwrap().starts_with("<p>Hello Acme &amp; Co</p><p>"));
+}
@@ -0,0 +1,366 @@
+use crate::helpers::{
+    assert_is_redirect_to, create_confirmed_subscriber, spawn_app, spawn_app_with,
+    when_sending_an_email, TestApp,
+};
+use uuid::Uuid;
+use wiremock::ResponseTemplate;
+
+async fn subscriber_email(app: &TestApp) -> String {
+    sqlx::query!("SELECT email FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .email
+}
+
+// Asks for `kind` on behalf of `email` and returns the link we emailed
+async fn request_link(app: &TestApp, email: &str, kind: &str) -> reqwest::Url {
+    let _mock_guard = when_sending_an_email()
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount_as_scoped(&app.email_server)
+        .await;
+    app.post_data_request(email, kind).await;
+    // The email is sent in the background
+    _mock_guard.wait_until_satisfied().await;
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    app.get_confirmation_links(&email_request).plain_text
+}
+
+async fn queue_an_issue_for(app: &TestApp, email: &str) {
+    let issue_id = Uuid::new_v4();
+    sqlx::query!(
+        "INSERT INTO newsletter_issues (newsletter_issue_id, title, text_content, html_content, published_at)
+        VALUES ($1, 'An issue', 'Text', '<p>Html</p>', now())",
+        issue_id,
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    sqlx::query!(
+        "INSERT INTO issue_delivery_queue (newsletter_issue_id, subscriber_email) VALUES ($1, $2)",
+        issue_id,
+        email,
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    sqlx::query!(
+        "INSERT INTO issue_delivery_log (newsletter_issue_id, subscriber_email, outcome, attempted_at)
+        VALUES ($1, $2, 'delivered', now())",
+        issue_id,
+        email,
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+}
+
+#[tokio::test]
+async fn nothing_is_sent_for_an_address_we_hold_no_data_about() {
+    // Arrange
+    let app = spawn_app().await;
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = app.post_data_request("nobody@example.com", "access").await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/subscriptions/data");
+    let html_page = app
+        .api_client
+        .get(format!("{}/subscriptions/data", &app.address))
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    assert!(html_page.contains(
+        "If we hold data about this address, we have emailed it a link to confirm your request."
+    ));
+}
+
+#[tokio::test]
+async fn a_failure_to_send_looks_the_same_as_holding_no_data() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let email = subscriber_email(&app).await;
+    let mock_guard = when_sending_an_email()
+        .respond_with(ResponseTemplate::new(500))
+        .expect(1)
+        .mount_as_scoped(&app.email_server)
+        .await;
+
+    // Act
+    let response = app.post_data_request(&email, "access").await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/subscriptions/data");
+    mock_guard.wait_until_satisfied().await;
+}
+
+#[tokio::test]
+async fn requests_for_the_same_address_are_rate_limited() {
+    // Arrange
+    let app = spawn_app_with(|c| c.subscribe_protection.max_attempts_per_address = 1).await;
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+    app.post_data_request("nobody@example.com", "access").await;
+
+    // Act
+    let response = app.post_data_request("nobody@example.com", "erasure").await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/subscriptions/data");
+    let html_page = app
+        .api_client
+        .get(format!("{}/subscriptions/data", &app.address))
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    assert!(html_page.contains("Too many requests. Please try again later."));
+}
+
+#[tokio::test]
+async fn the_access_link_downloads_everything_tied_to_the_address() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let email = subscriber_email(&app).await;
+    queue_an_issue_for(&app, &email).await;
+    let link = request_link(&app, &email, "access").await;
+    assert_eq!(link.path(), "/subscriptions/data/access");
+
+    // Act
+    let response = reqwest::get(link.clone()).await.unwrap();
+
+    // Assert
+    assert_eq!(200, response.status().as_u16());
+    let data: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(data["email"], email);
+    assert_eq!(data["subscription"]["email"], email);
+    assert_eq!(data["subscription"]["status"], "confirmed");
+    assert_eq!(data["subscription_tokens"].as_array().unwrap().len(), 1);
+    assert_eq!(data["pending_deliveries"][0]["title"], "An issue");
+    assert_eq!(data["delivery_history"][0]["outcome"], "delivered");
+    assert_eq!(data["events"][0]["action"], "subscriber_confirmed");
+    assert_eq!(data["data_requests"][0]["kind"], "access");
+    // The link can be followed again until it expires
+    assert_eq!(200, reqwest::get(link).await.unwrap().status().as_u16());
+}
+
+#[tokio::test]
+async fn an_unknown_token_is_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = reqwest::get(format!(
+        "{}/subscriptions/data/access?token=not-a-token",
+        app.address
+    ))
+    .await
+    .unwrap();
+
+    // Assert
+    assert_eq!(410, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn following_the_erasure_link_only_asks_for_confirmation() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let email = subscriber_email(&app).await;
+    let link = request_link(&app, &email, "erasure").await;
+
+    // Act
+    let html_page = reqwest::get(link).await.unwrap().text().await.unwrap();
+
+    // Assert
+    assert!(html_page.contains("Erase my data"));
+    assert_eq!(subscriber_email(&app).await, email);
+}
+
+#[tokio::test]
+async fn erasure_removes_the_address_and_leaves_a_tombstone() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let email = subscriber_email(&app).await;
+    queue_an_issue_for(&app, &email).await;
+    let link = request_link(&app, &email, "erasure").await;
+    let token = link
+        .query_pairs()
+        .find(|(key, _)| key == "token")
+        .unwrap()
+        .1
+        .into_owned();
+
+    // Act
+    let erase = || {
+        app.api_client
+            .post(format!("{}/subscriptions/data/erasure", &app.address))
+            .form(&serde_json::json!({ "token": &token }))
+            .send()
+    };
+    let response = erase().await.unwrap();
+
+    // Assert
+    assert_eq!(200, response.status().as_u16());
+    let remaining = sqlx::query!(
+        r#"SELECT
+            (SELECT COUNT(*) FROM subscriptions) AS "subscriptions!",
+            (SELECT COUNT(*) FROM subscription_tokens) AS "tokens!",
+            (SELECT COUNT(*) FROM issue_delivery_queue) AS "queued!",
+            (SELECT COUNT(*) FROM issue_delivery_log WHERE subscriber_email = $1) AS "logged!",
+            (SELECT COUNT(*) FROM issue_delivery_log) AS "anonymized!",
+            (SELECT COUNT(*) FROM data_requests) AS "requests!""#,
+        email,
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(remaining.subscriptions, 0);
+    assert_eq!(remaining.tokens, 0);
+    assert_eq!(remaining.queued, 0);
+    assert_eq!(remaining.logged, 0);
+    assert_eq!(remaining.anonymized, 1);
+    assert_eq!(remaining.requests, 0);
+    let tombstone = sqlx::query!("SELECT email_hash, reason FROM suppression_list")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(tombstone.reason, "erasure");
+    assert!(!tombstone.email_hash.contains(&email));
+    // The audit log cannot be changed, so it never had their IP address
+    let events = sqlx::query!(
+        "SELECT action, ip_address FROM audit_events WHERE actor_user_id IS NULL ORDER BY occurred_at"
+    )
+    .fetch_all(&app.db_pool)
+    .await
+    .unwrap();
+    let actions: Vec<&str> = events.iter().map(|e| e.action.as_str()).collect();
+    assert_eq!(actions, vec!["subscriber_confirmed", "subscriber_erased"]);
+    assert!(events.iter().all(|e| e.ip_address.is_none()));
+    // The link cannot be used twice
+    assert_eq!(410, erase().await.unwrap().status().as_u16());
+}
+
+#[tokio::test]
+async fn pending_changes_to_the_address_are_included_and_erased() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    sqlx::query!(
+        "INSERT INTO email_changes (token_hash, subscriber_id, new_email, created_at, expires_at)
+        SELECT 'a-token-hash', id, 'new-address@example.com', now(), now() + interval '1 day'
+        FROM subscriptions",
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    let access_link = request_link(&app, "new-address@example.com", "access").await;
+    let erasure_link = request_link(&app, "new-address@example.com", "erasure").await;
+    let token = erasure_link
+        .query_pairs()
+        .find(|(key, _)| key == "token")
+        .unwrap()
+        .1
+        .into_owned();
+
+    // Act - Part 1 - Download
+    let data: serde_json::Value = reqwest::get(access_link).await.unwrap().json().await.unwrap();
+
+    // Assert
+    assert_eq!(data["email_changes"][0]["new_email"], "new-address@example.com");
+
+    // Act - Part 2 - Erase
+    let response = app
+        .api_client
+        .post(format!("{}/subscriptions/data/erasure", &app.address))
+        .form(&serde_json::json!({ "token": &token }))
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(200, response.status().as_u16());
+    let n_changes = sqlx::query!(r#"SELECT COUNT(*) AS "n!" FROM email_changes"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .n;
+    assert_eq!(n_changes, 0);
+    // The subscription that asked for the change is someone else's
+    assert_eq!(
+        sqlx::query!(r#"SELECT COUNT(*) AS "n!" FROM subscriptions"#)
+            .fetch_one(&app.db_pool)
+            .await
+            .unwrap()
+            .n,
+        1
+    );
+}
+
+#[tokio::test]
+async fn erased_addresses_are_not_imported_again() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let email = subscriber_email(&app).await;
+    let link = request_link(&app, &email, "erasure").await;
+    let token = link
+        .query_pairs()
+        .find(|(key, _)| key == "token")
+        .unwrap()
+        .1
+        .into_owned();
+    app.api_client
+        .post(format!("{}/subscriptions/data/erasure", &app.address))
+        .form(&serde_json::json!({ "token": &token }))
+        .send()
+        .await
+        .unwrap();
+    app.test_user.login(&app).await;
+
+    // Act
+    app.post_subscriber_import(
+        "confirmed",
+        "Book fair",
+        &format!("email,name\n{},Someone\n", email.to_uppercase()),
+    )
+    .await;
+
+    // Assert
+    let n_subscribers = sqlx::query!("SELECT COUNT(*) AS \"n!\" FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .n;
+    assert_eq!(n_subscribers, 0);
+    let detail = sqlx::query!("SELECT outcome, detail FROM subscriber_import_rows")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(detail.outcome, "skipped");
+    assert_eq!(detail.detail, "This address is on the suppression list.");
+}
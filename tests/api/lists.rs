@@ -0,0 +1,233 @@
+use crate::helpers::{
+    assert_is_redirect_to, create_confirmed_subscriber, spawn_app, when_sending_an_email,
+    ConfirmationLinks, TestApp,
+};
+use uuid::Uuid;
+use wiremock::ResponseTemplate;
+
+async fn create_list(app: &TestApp, slug: &str) -> Uuid {
+    let response = app
+        .post_create_mailing_list(&serde_json::json!({
+            "name": format!("The {} list", slug),
+            "slug": slug,
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/lists");
+    sqlx::query!("SELECT list_id FROM lists WHERE slug = $1", slug)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .list_id
+}
+
+async fn subscribe_to(app: &TestApp, list: &str) -> ConfirmationLinks {
+    let _mock_guard = when_sending_an_email()
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount_as_scoped(&app.email_server)
+        .await;
+    let body = format!("name=le%20guin&email=ursula_le_guin%40gmail.com&list={}", list);
+    app.post_subscriptions(body).await.error_for_status().unwrap();
+    let email_request = app.email_server.received_requests().await.unwrap().pop().unwrap();
+    app.get_confirmation_links(&email_request)
+}
+
+async fn membership_status(app: &TestApp, list_id: Uuid) -> String {
+    sqlx::query!("SELECT status FROM list_memberships WHERE list_id = $1", list_id)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .status
+}
+
+#[tokio::test]
+async fn admins_can_create_lists() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    // Act
+    create_list(&app, "product-updates").await;
+
+    // Assert
+    let html_page = app.get_mailing_lists_html().await;
+    assert!(html_page.contains("<p><i>The list has been created.</i></p>"));
+    assert!(html_page.contains("The product-updates list"));
+}
+
+#[tokio::test]
+async fn lists_need_a_url_friendly_identifier() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    // Act
+    for slug in ["", "Product Updates", "-updates"] {
+        app.post_create_mailing_list(&serde_json::json!({
+            "name": "Product updates",
+            "slug": slug,
+        }))
+        .await;
+    }
+    app.post_create_mailing_list(&serde_json::json!({
+        "name": "Another newsletter",
+        "slug": "newsletter",
+    }))
+    .await;
+
+    // Assert
+    let n_lists = sqlx::query!(r#"SELECT COUNT(*) AS "n!" FROM lists"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .n;
+    assert_eq!(n_lists, 1);
+    let html_page = app.get_mailing_lists_html().await;
+    assert!(html_page.contains("A list with this identifier already exists."));
+}
+
+#[tokio::test]
+async fn subscribing_to_an_unknown_list_is_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app
+        .post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com&list=gossip".into())
+        .await;
+
+    // Assert
+    assert_eq!(400, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn each_list_is_confirmed_separately() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let list_id = create_list(&app, "events").await;
+    let links = subscribe_to(&app, "newsletter").await;
+    reqwest::get(links.html).await.unwrap().error_for_status().unwrap();
+
+    // Act - Part 1 - Join a second list
+    let links = subscribe_to(&app, "events").await;
+
+    // Assert
+    assert_eq!(membership_status(&app, list_id).await, "pending_confirmation");
+
+    // Act - Part 2 - Confirm it
+    reqwest::get(links.html).await.unwrap().error_for_status().unwrap();
+
+    // Assert
+    assert_eq!(membership_status(&app, list_id).await, "confirmed");
+    let n_subscribers = sqlx::query!(r#"SELECT COUNT(*) AS "n!" FROM subscriptions"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .n;
+    assert_eq!(n_subscribers, 1);
+}
+
+#[tokio::test]
+async fn subscribers_on_several_lists_get_each_issue_once() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let events_id = create_list(&app, "events").await;
+    let newsletter_id = sqlx::query!("SELECT list_id FROM lists WHERE slug = 'newsletter'")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .list_id;
+    let links = subscribe_to(&app, "newsletter").await;
+    reqwest::get(links.html).await.unwrap().error_for_status().unwrap();
+    let links = subscribe_to(&app, "events").await;
+    reqwest::get(links.html).await.unwrap().error_for_status().unwrap();
+    create_confirmed_subscriber(&app).await;
+
+    // Act
+    let response = app
+        .post_publish_newsletter(&[
+            ("title", "Newsletter title"),
+            ("text_content", "Newsletter body as plain text"),
+            ("html_content", "<p>Newsletter body as HTML</p>"),
+            ("list_ids", newsletter_id.to_string().as_str()),
+            ("list_ids", events_id.to_string().as_str()),
+            ("idempotency_key", Uuid::new_v4().to_string().as_str()),
+        ])
+        .await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    let n_queued = sqlx::query!(r#"SELECT COUNT(*) AS "n!" FROM issue_delivery_queue"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .n;
+    assert_eq!(n_queued, 2);
+}
+
+#[tokio::test]
+async fn issues_only_go_to_the_lists_they_are_published_to() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let events_id = create_list(&app, "events").await;
+    let links = subscribe_to(&app, "events").await;
+    reqwest::get(links.html).await.unwrap().error_for_status().unwrap();
+    create_confirmed_subscriber(&app).await;
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    app.post_publish_newsletter(&[
+        ("title", "Newsletter title"),
+        ("text_content", "Newsletter body as plain text"),
+        ("html_content", "<p>Newsletter body as HTML</p>"),
+        ("list_ids", events_id.to_string().as_str()),
+        ("idempotency_key", Uuid::new_v4().to_string().as_str()),
+    ])
+    .await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let email_request = app.email_server.received_requests().await.unwrap().pop().unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    assert_eq!(body["To"], "ursula_le_guin@gmail.com");
+}
+
+#[tokio::test]
+async fn unsubscribing_leaves_only_the_list_the_link_was_for() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let events_id = create_list(&app, "events").await;
+    let links = subscribe_to(&app, "newsletter").await;
+    reqwest::get(links.html).await.unwrap().error_for_status().unwrap();
+    let links = subscribe_to(&app, "events").await;
+    reqwest::get(links.html).await.unwrap().error_for_status().unwrap();
+    let token = sqlx::query!(
+        "SELECT subscription_token FROM subscription_tokens WHERE list_id = $1",
+        events_id,
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap()
+    .subscription_token;
+
+    // Act
+    let response = app.post_unsubscribe(&token).await;
+
+    // Assert
+    assert_eq!(200, response.status().as_u16());
+    assert_eq!(membership_status(&app, events_id).await, "unsubscribed");
+    let status = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .status;
+    assert_eq!(status, "confirmed");
+}
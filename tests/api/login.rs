@@ -1,4 +1,6 @@
-use crate::helpers::{spawn_app, assert_is_redirect_to};
+use crate::helpers::{spawn_app, spawn_app_with, assert_is_redirect_to};
+use argon2::password_hash::SaltString;
+use argon2::{Algorithm, Argon2, Params, PasswordHasher, Version};
 
 /*
 #[tokio::test]
@@ -52,3 +54,165 @@ async fn an_error_flash_message_is_set_on_failure() {
     let html_page = app.get_login_html().await;
     assert!(!html_page.contains("Authenicaton failed"));
 }
+
+#[tokio::test]
+async fn an_account_is_locked_out_after_too_many_failures() {
+    // Arrange
+    let app = spawn_app().await;
+    let wrong_login_body = serde_json::json!({
+        "username": &app.test_user.username,
+        "password": "wrong-password"
+    });
+    for _ in 0..5 {
+        app.post_login(&wrong_login_body).await;
+    }
+
+    // Act - the right password does not help anymore
+    let response = app
+        .post_login(&serde_json::json!({
+            "username": &app.test_user.username,
+            "password": &app.test_user.password
+        }))
+        .await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/login");
+    let html_page = app.get_login_html().await;
+    assert!(html_page
+        .contains("<p><i>Too many failed login attempts. Try again in 15 minutes.</i></p>"));
+}
+
+#[tokio::test]
+async fn a_successful_login_clears_the_failures_of_the_account() {
+    // Arrange
+    let app = spawn_app().await;
+    let wrong_login_body = serde_json::json!({
+        "username": &app.test_user.username,
+        "password": "wrong-password"
+    });
+    for _ in 0..4 {
+        app.post_login(&wrong_login_body).await;
+    }
+    app.test_user.login(&app).await;
+    app.post_logout().await;
+
+    // Act
+    for _ in 0..4 {
+        app.post_login(&wrong_login_body).await;
+    }
+    let response = app
+        .post_login(&serde_json::json!({
+            "username": &app.test_user.username,
+            "password": &app.test_user.password
+        }))
+        .await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/dashboard");
+}
+
+#[tokio::test]
+async fn an_ip_address_is_locked_out_after_too_many_failures() {
+    // Arrange
+    let app = spawn_app().await;
+    for i in 0..50 {
+        app.post_login(&serde_json::json!({
+            "username": format!("someone-{}", i),
+            "password": "wrong-password"
+        }))
+        .await;
+    }
+
+    // Act - another account, from the same address
+    let response = app
+        .post_login(&serde_json::json!({
+            "username": &app.test_user.username,
+            "password": &app.test_user.password
+        }))
+        .await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/login");
+    let html_page = app.get_login_html().await;
+    assert!(html_page.contains("Too many failed login attempts."));
+}
+
+#[tokio::test]
+async fn behind_a_trusted_proxy_each_client_address_is_locked_out_on_its_own() {
+    // Arrange
+    let app = spawn_app_with(|c| {
+        c.login_throttling.max_failures_per_ip = 2;
+        c.application.trusted_proxies = vec!["127.0.0.1".parse().unwrap()];
+    })
+    .await;
+    let login_from = |client: &'static str, username: String, password: String| {
+        app.api_client
+            .post(format!("{}/login", &app.address))
+            .header("X-Forwarded-For", client)
+            .form(&serde_json::json!({ "username": username, "password": password }))
+            .send()
+    };
+    for i in 0..2 {
+        login_from("203.0.113.7", format!("someone-{}", i), "wrong-password".into())
+            .await
+            .unwrap();
+    }
+
+    // Act - another client behind the same proxy
+    let response = login_from(
+        "203.0.113.8",
+        app.test_user.username.clone(),
+        app.test_user.password.clone(),
+    )
+    .await
+    .unwrap();
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/dashboard");
+}
+
+#[tokio::test]
+async fn a_weak_password_hash_is_upgraded_on_login() {
+    // Arrange
+    let app = spawn_app().await;
+    let salt = SaltString::generate(&mut rand::thread_rng());
+    let weak_hash = Argon2::new(
+        Algorithm::Argon2d,
+        Version::V0x13,
+        Params::new(1024, 1, 1, None).unwrap(),
+    )
+        .hash_password(app.test_user.password.as_bytes(), &salt)
+        .unwrap()
+        .to_string();
+    sqlx::query!(
+        "UPDATE users SET password_hash = $1 WHERE user_id = $2",
+        weak_hash,
+        app.test_user.user_id
+    )
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    // Act
+    let response = app
+        .post_login(&serde_json::json!({
+            "username": &app.test_user.username,
+            "password": &app.test_user.password
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+
+    // Assert
+    let stored_hash = sqlx::query!(
+        "SELECT password_hash FROM users WHERE user_id = $1",
+        app.test_user.user_id
+    )
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .password_hash;
+    assert!(stored_hash.starts_with("$argon2id$v=19$m=15000,t=2,p=1$"));
+    app.post_logout().await;
+    app.test_user.login(&app).await;
+    assert_eq!(200, app.get_admin_dashboard().await.status().as_u16());
+}
@@ -6,3 +6,20 @@ mod newsletter;
 mod login;
 mod admin_dashboard;
 mod change_password;
+mod api_tokens;
+mod newsletter_templates;
+mod users;
+mod setup;
+mod password_reset;
+mod totp;
+mod sessions;
+mod csrf;
+mod audit;
+mod subscribers;
+mod subscriber_imports;
+mod data_requests;
+mod preferences;
+mod lists;
+mod segments;
+mod custom_fields;
+mod subscribe_protection;
@@ -1,57 +1,8 @@
-use crate::helpers::{spawn_app, TestApp, ConfirmationLinks, assert_is_redirect_to};
+use crate::helpers::{spawn_app, assert_is_redirect_to};
+use crate::helpers::{create_confirmed_subscriber, create_unconfirmed_subscriber, when_sending_an_email};
 use wiremock::matchers::{any, method, path};
 use wiremock::{Mock, ResponseTemplate};
 use std::time::Duration;
-use fake::faker::internet::en::SafeEmail;
-use fake::faker::name::en::Name;
-use fake::Fake;
-use wiremock::MockBuilder;
-
-fn when_sending_an_email() -> MockBuilder {
-    Mock::given(path("/email")).and(method("POST"))
-}
-
-async fn create_unconfirmed_subscriber(app: &TestApp) -> ConfirmationLinks{
-
-    let name: String = Name().fake();
-    let email: String = SafeEmail().fake();
-    let body = serde_urlencoded::to_string(&serde_json::json!({
-        "name": name,
-        "email": email
-    }))
-    .unwrap();
-
-    let _mock_guard = Mock::given(path("/email"))
-        .and(method("POST"))
-        .respond_with(ResponseTemplate::new(200))
-        .named("Create unconfirmed subscriber")
-        .expect(1)
-        .mount_as_scoped(&app.email_server)
-        .await;
-    app.post_subscriptions(body.into())
-        .await
-        .error_for_status()
-        .unwrap();
-
-    let email_request = &app
-        .email_server
-        .received_requests()
-        .await
-        .unwrap()
-        .pop()
-        .unwrap();
-    app.get_confirmation_links(&email_request)
-}
-
-
-async fn create_confirmed_subscriber(app: &TestApp) {
-    let confirmation_link = create_unconfirmed_subscriber(app).await.html;
-    reqwest::get(confirmation_link)
-        .await
-        .unwrap()
-        .error_for_status()
-        .unwrap();
-}
 
 #[tokio::test]
 async fn newsletters_are_not_delivered_to_unconfirmed_subscribers() {
@@ -209,3 +160,273 @@ async fn concurrent_form_submission_is_handled_gracefully() {
     // Mock verifies on drop that we only sent one email
 }
 
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_see_the_newsletter_history() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app.get_newsletter_history().await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn newsletter_history_shows_recipients_and_delivery_outcome() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act - Part 1 - Publish an issue
+    let newsletter_request_body = serde_json::json!({
+        "title": "History <title>",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": uuid::Uuid::new_v4().to_string()
+    });
+    let response = app.post_publish_newsletter(&newsletter_request_body).await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+
+    // Act - Part 2 - The issue is pending until the worker runs
+    let html_page = app.get_newsletter_history_html().await;
+    assert!(html_page.contains("History &lt;title&gt;"));
+    assert!(html_page.contains(&format!(
+        "<td>{} ({})</td>",
+        app.test_user.username, app.test_user.user_id
+    )));
+    assert!(html_page.contains("<td>1</td><td>In progress (1 pending)</td>"));
+
+    // Act - Part 3 - Deliver and check the outcome
+    app.dispatch_all_pending_emails().await;
+    let html_page = app.get_newsletter_history_html().await;
+    assert!(html_page.contains("<td>1</td><td>Delivered (1)</td>"));
+}
+
+#[tokio::test]
+async fn published_issues_record_their_author() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    // Act
+    let newsletter_request_body = serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": uuid::Uuid::new_v4().to_string()
+    });
+    let response = app.post_publish_newsletter(&newsletter_request_body).await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+
+    // Assert
+    let saved = sqlx::query!("SELECT author_id FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved issue.");
+    assert_eq!(saved.author_id, Some(app.test_user.user_id));
+}
+
+fn api_newsletter_body() -> serde_json::Value {
+    serde_json::json!({
+        "title": "Newsletter title",
+        "content": {
+            "text": "Newsletter body as plain text",
+            "html": "<p>Newsletter body as HTML</p>",
+        }
+    })
+}
+
+#[tokio::test]
+async fn api_requests_missing_authorization_are_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app
+        .api_client
+        .post(format!("{}/newsletters", &app.address))
+        .header("Idempotency-Key", uuid::Uuid::new_v4().to_string())
+        .json(&api_newsletter_body())
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    // Assert
+    assert_eq!(401, response.status().as_u16());
+    assert_eq!(
+        r#"Basic realm="publish""#,
+        response.headers()["WWW-Authenticate"]
+    );
+}
+
+#[tokio::test]
+async fn api_requests_with_an_invalid_password_are_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app
+        .api_client
+        .post(format!("{}/newsletters", &app.address))
+        .basic_auth(&app.test_user.username, Some(uuid::Uuid::new_v4().to_string()))
+        .header("Idempotency-Key", uuid::Uuid::new_v4().to_string())
+        .json(&api_newsletter_body())
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    // Assert
+    assert_eq!(401, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn api_requests_are_refused_after_too_many_invalid_passwords() {
+    // Arrange
+    let app = spawn_app().await;
+    let send = |password: String| {
+        app.api_client
+            .post(format!("{}/newsletters", &app.address))
+            .basic_auth(&app.test_user.username, Some(password))
+            .header("Idempotency-Key", uuid::Uuid::new_v4().to_string())
+            .json(&api_newsletter_body())
+            .send()
+    };
+    for _ in 0..5 {
+        let response = send(uuid::Uuid::new_v4().to_string()).await.unwrap();
+        assert_eq!(401, response.status().as_u16());
+    }
+
+    // Act
+    let response = send(app.test_user.password.clone()).await.unwrap();
+
+    // Assert
+    assert_eq!(429, response.status().as_u16());
+    assert!(response.headers().get("Retry-After").is_some());
+}
+
+#[tokio::test]
+async fn api_requests_without_an_idempotency_key_are_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app.post_newsletters_api(&api_newsletter_body(), None).await;
+
+    // Assert
+    assert_eq!(400, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn api_returns_400_for_invalid_data() {
+    // Arrange
+    let app = spawn_app().await;
+    let test_cases = vec![
+        (
+            serde_json::json!({
+                "content": {
+                    "text": "Newsletter body as plain text",
+                    "html": "<p>Newsletter body as HTML</p>",
+                }
+            }),
+            "missing title",
+        ),
+        (
+            serde_json::json!({"title": "Newsletter!"}),
+            "missing content",
+        ),
+    ];
+
+    for (invalid_body, error_message) in test_cases {
+        // Act
+        let response = app
+            .post_newsletters_api(&invalid_body, Some(&uuid::Uuid::new_v4().to_string()))
+            .await;
+
+        // Assert
+        assert_eq!(
+            400,
+            response.status().as_u16(),
+            "The API did not fail with 400 Bad Request when the payload was {}.",
+            error_message
+        );
+    }
+}
+
+#[tokio::test]
+async fn api_enqueues_the_issue_for_confirmed_subscribers() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    create_unconfirmed_subscriber(&app).await;
+
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = app
+        .post_newsletters_api(&api_newsletter_body(), Some(&uuid::Uuid::new_v4().to_string()))
+        .await;
+
+    // Assert
+    assert_eq!(202, response.status().as_u16());
+    let body: serde_json::Value = response.json().await.unwrap();
+    let issue_id: uuid::Uuid = body["newsletter_issue_id"].as_str().unwrap().parse().unwrap();
+    let saved = sqlx::query!(
+        "SELECT author_id, n_recipients FROM newsletter_issues WHERE newsletter_issue_id = $1",
+        issue_id
+    )
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved issue.");
+    assert_eq!(saved.author_id, Some(app.test_user.user_id));
+    assert_eq!(saved.n_recipients, Some(1));
+
+    app.dispatch_all_pending_emails().await;
+    // Mock verifies on Drop that we have sent the newsletter email once
+}
+
+#[tokio::test]
+async fn api_publishing_is_idempotent() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    let idempotency_key = uuid::Uuid::new_v4().to_string();
+
+    // Act - Part 1 - Publish
+    let response1 = app
+        .post_newsletters_api(&api_newsletter_body(), Some(&idempotency_key))
+        .await;
+    assert_eq!(202, response1.status().as_u16());
+
+    // Act - Part 2 - Publish again with the same key
+    let response2 = app
+        .post_newsletters_api(&api_newsletter_body(), Some(&idempotency_key))
+        .await;
+    assert_eq!(202, response2.status().as_u16());
+
+    // Assert
+    assert_eq!(
+        response1.text().await.unwrap(),
+        response2.text().await.unwrap()
+    );
+    app.dispatch_all_pending_emails().await;
+    // Mock verifies on Drop that we have sent the newsletter email once
+}
@@ -0,0 +1,194 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp};
+use crate::helpers::{create_confirmed_subscriber, when_sending_an_email};
+use uuid::Uuid;
+use wiremock::ResponseTemplate;
+
+async fn create_template(app: &TestApp) -> Uuid {
+    let response = app
+        .post_create_newsletter_template(&serde_json::json!({
+            "name": "Branded",
+            "header_html": "<header>Our brand</header>",
+            "footer_html": r#"<footer><a href="{{unsubscribe_url}}">Unsubscribe</a></footer>"#,
+            "css": "p { color: red; }",
+        }))
+        .await;
+    let template_id = sqlx::query!("SELECT template_id FROM newsletter_templates")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved template.")
+        .template_id;
+    assert_is_redirect_to(&response, &format!("/admin/templates/{}", template_id));
+    template_id
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_manage_templates() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app
+        .api_client
+        .get(format!("{}/admin/templates", &app.address))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    // Assert
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn templates_with_unknown_variables_are_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    // Act
+    let response = app
+        .post_create_newsletter_template(&serde_json::json!({
+            "name": "Broken",
+            "header_html": "<header>{{brand}}</header>",
+            "footer_html": "",
+            "css": "",
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/templates");
+
+    // Assert
+    let html_page = app.get_newsletter_templates_html().await;
+    assert!(html_page.contains("{{brand}} is not a known variable"));
+}
+
+#[tokio::test]
+async fn issues_with_unknown_variables_are_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    // Act
+    let response = app
+        .post_publish_newsletter(&serde_json::json!({
+            "title": "Newsletter title",
+            "text_content": "Hello {{first_name}}",
+            "html_content": "<p>Hello</p>",
+            "idempotency_key": Uuid::new_v4().to_string()
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+
+    // Assert
+    let html_page = app.get_publish_newsletter_html().await;
+    assert!(html_page.contains("{{first_name}} is not a known variable"));
+    let n_issues = sqlx::query!(r#"SELECT COUNT(*) AS "count!" FROM newsletter_issues"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(n_issues, 0);
+}
+
+#[tokio::test]
+async fn issues_are_rendered_into_their_template_for_each_subscriber() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    let template_id = create_template(&app).await;
+    let subscriber_name = sqlx::query!("SELECT name FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .name;
+
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = app
+        .post_publish_newsletter(&serde_json::json!({
+            "title": "Newsletter title",
+            "text_content": "Hello {{name}}",
+            "html_content": "<p>Hello {{ name }}</p>",
+            "template_id": template_id.to_string(),
+            "idempotency_key": Uuid::new_v4().to_string()
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let email_request = app.email_server.received_requests().await.unwrap().pop().unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    let html_body = body["HtmlBody"].as_str().unwrap();
+    assert!(html_body.contains("<header>Our brand</header>"));
+    assert!(html_body.contains(&format!(
+        "<p>Hello {}</p>",
+        htmlescape::encode_minimal(&subscriber_name)
+    )));
+    assert!(html_body.contains("/subscriptions/unsubscribe?subscription_token="));
+    // The template's footer links to the unsubscribe page, the plain text
+    // gets the link to the preferences page
+    assert!(body["TextBody"]
+        .as_str()
+        .unwrap()
+        .starts_with(&format!("Hello {}\n", subscriber_name)));
+    assert!(!html_body.contains("Manage your subscription or unsubscribe"));
+}
+
+#[tokio::test]
+async fn the_unsubscribe_link_unsubscribes_the_subscriber() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let token = sqlx::query!("SELECT subscription_token FROM subscription_tokens")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .subscription_token;
+
+    // Act - Part 1 - Follow the link
+    let html_page = reqwest::get(format!(
+        "{}/subscriptions/unsubscribe?subscription_token={}",
+        app.address, token
+    ))
+    .await
+    .unwrap()
+    .text()
+    .await
+    .unwrap();
+
+    // Assert - Following the link only asks for confirmation
+    assert!(html_page.contains(r#"<form action="/subscriptions/unsubscribe" method="post">"#));
+    let saved = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.status, "confirmed");
+
+    // Act - Part 2 - Confirm
+    let response = app.post_unsubscribe(&token).await;
+
+    // Assert
+    assert_eq!(200, response.status().as_u16());
+    let saved = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.status, "unsubscribed");
+}
+
+#[tokio::test]
+async fn an_unknown_unsubscribe_token_is_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app.post_unsubscribe("not-a-token").await;
+
+    // Assert
+    assert_eq!(401, response.status().as_u16());
+}
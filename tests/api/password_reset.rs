@@ -0,0 +1,225 @@
+use crate::helpers::{
+    assert_is_redirect_to, spawn_app, spawn_app_with, when_sending_an_email, TestApp,
+};
+use uuid::Uuid;
+use wiremock::ResponseTemplate;
+
+async fn give_test_user_an_email(app: &TestApp) {
+    sqlx::query!(
+        "UPDATE users SET email = 'test.user@example.com' WHERE user_id = $1",
+        app.test_user.user_id
+    )
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+}
+
+// Ask for a reset link for the test user and return the token it carries
+async fn request_reset_token(app: &TestApp) -> String {
+    let _mock_guard = when_sending_an_email()
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount_as_scoped(&app.email_server)
+        .await;
+    let response = app.post_request_password_reset(&app.test_user.username).await;
+    assert_is_redirect_to(&response, "/password-reset");
+    // The email is sent in the background
+    _mock_guard.wait_until_satisfied().await;
+    let email_request = app.email_server.received_requests().await.unwrap().pop().unwrap();
+    let link = app.get_confirmation_links(&email_request).html;
+    link.query_pairs()
+        .find(|(key, _)| key == "token")
+        .map(|(_, value)| value.into_owned())
+        .unwrap()
+}
+
+fn reset_body(token: &str, password: &str) -> serde_json::Value {
+    serde_json::json!({
+        "token": token,
+        "new_password": password,
+        "new_password_check": password,
+    })
+}
+
+#[tokio::test]
+async fn requesting_a_reset_does_not_reveal_whether_the_username_exists() {
+    // Arrange
+    let app = spawn_app().await;
+    give_test_user_an_email(&app).await;
+    let mock_guard = when_sending_an_email()
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount_as_scoped(&app.email_server)
+        .await;
+
+    // Act - Part 1 - Known username
+    let response = app.post_request_password_reset(&app.test_user.username).await;
+    assert_is_redirect_to(&response, "/password-reset");
+    let known_html = app.get_request_password_reset_html().await;
+
+    // Act - Part 2 - Unknown username
+    let response = app.post_request_password_reset(&Uuid::new_v4().to_string()).await;
+    assert_is_redirect_to(&response, "/password-reset");
+    let unknown_html = app.get_request_password_reset_html().await;
+
+    // Assert
+    assert!(known_html.contains(
+        "<p><i>If this account exists, we have emailed it a link to reset its password.</i></p>"
+    ));
+    assert_eq!(known_html, unknown_html);
+    mock_guard.wait_until_satisfied().await;
+}
+
+#[tokio::test]
+async fn a_failure_to_send_the_reset_link_is_not_revealed() {
+    // Arrange
+    let app = spawn_app().await;
+    give_test_user_an_email(&app).await;
+    let mock_guard = when_sending_an_email()
+        .respond_with(ResponseTemplate::new(500))
+        .expect(1)
+        .mount_as_scoped(&app.email_server)
+        .await;
+
+    // Act
+    let response = app.post_request_password_reset(&app.test_user.username).await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/password-reset");
+    mock_guard.wait_until_satisfied().await;
+}
+
+#[tokio::test]
+async fn reset_requests_for_the_same_username_are_rate_limited() {
+    // Arrange
+    let app = spawn_app_with(|c| c.subscribe_protection.max_attempts_per_address = 1).await;
+    let username = Uuid::new_v4().to_string();
+    app.post_request_password_reset(&username).await;
+
+    // Act
+    let response = app.post_request_password_reset(&username).await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/password-reset");
+    let html_page = app.get_request_password_reset_html().await;
+    assert!(html_page.contains("<p><i>Too many requests. Please try again later.</i></p>"));
+}
+
+#[tokio::test]
+async fn a_reset_changes_the_password_and_revokes_existing_sessions() {
+    // Arrange
+    let app = spawn_app().await;
+    give_test_user_an_email(&app).await;
+    app.test_user.login(&app).await;
+    let token = request_reset_token(&app).await;
+    let new_password = Uuid::new_v4().to_string();
+
+    // Act - Part 1 - Reset
+    let response = app.post_reset_password(&reset_body(&token, &new_password)).await;
+    assert_is_redirect_to(&response, "/login");
+
+    // Act - Part 2 - The existing session is gone
+    let response = app.get_admin_dashboard().await;
+    assert_is_redirect_to(&response, "/login");
+
+    // Act - Part 3 - The old password no longer works
+    let response = app
+        .post_login(&serde_json::json!({
+            "username": &app.test_user.username,
+            "password": &app.test_user.password
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/login");
+
+    // Act - Part 4 - The new one does
+    let response = app
+        .post_login(&serde_json::json!({
+            "username": &app.test_user.username,
+            "password": &new_password
+        }))
+        .await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/dashboard");
+}
+
+#[tokio::test]
+async fn a_reset_token_can_only_be_used_once() {
+    // Arrange
+    let app = spawn_app().await;
+    give_test_user_an_email(&app).await;
+    let token = request_reset_token(&app).await;
+    let response = app
+        .post_reset_password(&reset_body(&token, &Uuid::new_v4().to_string()))
+        .await;
+    assert_is_redirect_to(&response, "/login");
+
+    // Act
+    let response = app
+        .post_reset_password(&reset_body(&token, &Uuid::new_v4().to_string()))
+        .await;
+
+    // Assert
+    assert_eq!(410, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn a_weak_password_is_rejected_without_spending_the_token() {
+    // Arrange
+    let app = spawn_app().await;
+    give_test_user_an_email(&app).await;
+    let token = request_reset_token(&app).await;
+    let form_location = format!("/password-reset/confirm?token={}", token);
+
+    // Act - Part 1 - Pick a common password
+    let response = app.post_reset_password(&reset_body(&token, "password123")).await;
+
+    // Assert
+    assert_is_redirect_to(&response, &form_location);
+
+    // Act - Part 2 - The link still works with a strong password
+    let response = app
+        .post_reset_password(&reset_body(&token, &Uuid::new_v4().to_string()))
+        .await;
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn an_expired_reset_token_is_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    give_test_user_an_email(&app).await;
+    let token = request_reset_token(&app).await;
+    sqlx::query!("UPDATE password_reset_tokens SET expires_at = now() - interval '1 minute'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    // Act
+    let response = app
+        .post_reset_password(&reset_body(&token, &Uuid::new_v4().to_string()))
+        .await;
+
+    // Assert
+    assert_eq!(410, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn logging_out_revokes_the_session() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    // Act
+    app.post_logout().await;
+
+    // Assert
+    let n_active = sqlx::query!(
+        r#"SELECT COUNT(*) AS "n!" FROM user_sessions WHERE revoked_at IS NULL"#
+    )
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .n;
+    assert_eq!(n_active, 0);
+}
@@ -0,0 +1,470 @@
+use crate::helpers::{
+    assert_is_redirect_to, create_confirmed_subscriber, spawn_app, spawn_app_with,
+    when_sending_an_email, TestApp,
+};
+use uuid::Uuid;
+use wiremock::ResponseTemplate;
+
+async fn subscription_token(app: &TestApp) -> String {
+    sqlx::query!("SELECT subscription_token FROM subscription_tokens")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .subscription_token
+}
+
+async fn publish_issue(app: &TestApp, topic: &str) {
+    let response = app
+        .post_publish_newsletter(&serde_json::json!({
+            "title": "Newsletter title",
+            "text_content": "Newsletter body as plain text",
+            "html_content": "<p>Newsletter body as HTML</p>",
+            "topic": topic,
+            "idempotency_key": Uuid::new_v4().to_string()
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+}
+
+async fn n_queued_deliveries(app: &TestApp) -> i64 {
+    sqlx::query!(r#"SELECT COUNT(*) AS "n!" FROM issue_delivery_queue"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .n
+}
+
+#[tokio::test]
+async fn an_unknown_token_is_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app.get_preferences("not-a-token").await;
+
+    // Assert
+    assert_eq!(401, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn every_issue_can_link_to_the_preferences_page() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    app.post_publish_newsletter(&serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Manage your subscription at {{preferences_url}}",
+        "html_content": "<p>Manage your subscription at {{preferences_url}}</p>",
+        "idempotency_key": Uuid::new_v4().to_string()
+    }))
+    .await;
+    app.dispatch_all_pending_emails().await;
+    let email_request = app.email_server.received_requests().await.unwrap().pop().unwrap();
+    let link = app.get_confirmation_links(&email_request).plain_text;
+
+    // Act
+    let response = reqwest::get(link).await.unwrap();
+
+    // Assert
+    assert_eq!(200, response.status().as_u16());
+    let email = sqlx::query!("SELECT email FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .email;
+    let html_page = response.text().await.unwrap();
+    assert!(html_page.contains("You are subscribed."));
+    assert!(html_page.contains(&htmlescape::encode_minimal(&email)));
+}
+
+#[tokio::test]
+async fn issues_that_do_not_link_to_the_preferences_page_get_a_footer_that_does() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    publish_issue(&app, "").await;
+    app.dispatch_all_pending_emails().await;
+
+    // Act
+    let email_request = app.email_server.received_requests().await.unwrap().pop().unwrap();
+    let links = app.get_confirmation_links(&email_request);
+
+    // Assert
+    let token = subscription_token(&app).await;
+    for link in [links.html, links.plain_text] {
+        assert_eq!(link.path(), "/preferences");
+        assert_eq!(link.query(), Some(format!("subscription_token={}", token).as_str()));
+    }
+}
+
+#[tokio::test]
+async fn subscribers_can_change_their_name_topics_and_frequency() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let token = subscription_token(&app).await;
+
+    // Act - Part 1 - Save
+    let response = app
+        .post_preferences(
+            "",
+            &[
+                ("subscription_token", token.as_str()),
+                ("name", "Ursula Le Guin"),
+                ("topics", "Announcements"),
+                ("topics", "Tips"),
+                ("frequency", "weekly"),
+            ],
+        )
+        .await;
+
+    // Assert
+    assert_is_redirect_to(&response, &format!("/preferences?subscription_token={}", token));
+    let saved = sqlx::query!("SELECT name, frequency, muted_topics FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.name, "Ursula Le Guin");
+    assert_eq!(saved.frequency, "weekly");
+    assert_eq!(saved.muted_topics, vec!["Events".to_string()]);
+
+    // Act - Part 2 - Follow the redirect
+    let html_page = app.get_preferences_html(&token).await;
+    assert!(html_page.contains("<p><i>Your preferences have been saved.</i></p>"));
+    assert!(html_page.contains(r#"value="Ursula Le Guin""#));
+    assert!(html_page.contains(r#"<option value="weekly" selected>"#));
+    assert!(html_page.contains(r#"value="Events"> Events"#));
+    assert!(html_page.contains(r#"value="Tips" checked> Tips"#));
+}
+
+#[tokio::test]
+async fn an_invalid_name_is_not_saved() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let token = subscription_token(&app).await;
+
+    // Act
+    app.post_preferences(
+        "",
+        &[
+            ("subscription_token", token.as_str()),
+            ("name", "<script>"),
+            ("frequency", "weekly"),
+        ],
+    )
+    .await;
+
+    // Assert
+    let html_page = app.get_preferences_html(&token).await;
+    assert!(html_page.contains("is not a valid subscriber name."));
+    let frequency = sqlx::query!("SELECT frequency FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .frequency;
+    assert_eq!(frequency, "every_issue");
+}
+
+#[tokio::test]
+async fn a_new_email_address_must_be_confirmed_before_it_is_used() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let token = subscription_token(&app).await;
+    let old_email = sqlx::query!("SELECT email FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .email;
+    let mock_guard = when_sending_an_email()
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount_as_scoped(&app.email_server)
+        .await;
+
+    // Act - Part 1 - Ask for the change
+    let response = app
+        .post_preferences(
+            "/email",
+            &serde_json::json!({
+                "subscription_token": token,
+                "email": "new-address@example.com",
+            }),
+        )
+        .await;
+    assert_is_redirect_to(&response, &format!("/preferences?subscription_token={}", token));
+
+    // Assert
+    mock_guard.wait_until_satisfied().await;
+    let email_request = app.email_server.received_requests().await.unwrap().pop().unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    assert_eq!(body["To"], "new-address@example.com");
+    let email = sqlx::query!("SELECT email FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .email;
+    assert_eq!(email, old_email);
+
+    // Act - Part 2 - Follow the link
+    let link = app.get_confirmation_links(&email_request).html;
+    let response = reqwest::get(link.clone()).await.unwrap();
+
+    // Assert
+    assert_eq!(200, response.status().as_u16());
+    let email = sqlx::query!("SELECT email FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .email;
+    assert_eq!(email, "new-address@example.com");
+    // The link cannot be used twice
+    assert_eq!(410, reqwest::get(link).await.unwrap().status().as_u16());
+}
+
+#[tokio::test]
+async fn nothing_is_sent_to_an_address_that_is_already_subscribed() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let token = subscription_token(&app).await;
+    create_confirmed_subscriber(&app).await;
+    let other_email = sqlx::query!(
+        r#"
+        SELECT s.email
+        FROM subscriptions s
+        JOIN subscription_tokens t ON t.subscriber_id = s.id
+        WHERE t.subscription_token <> $1
+        "#,
+        token,
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap()
+    .email;
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    app.post_preferences(
+        "/email",
+        &serde_json::json!({
+            "subscription_token": token,
+            "email": other_email,
+        }),
+    )
+    .await;
+
+    // Assert
+    let html_page = app.get_preferences_html(&token).await;
+    assert!(html_page.contains("We have emailed a link to your new address."));
+}
+
+#[tokio::test]
+async fn a_failure_to_send_the_email_change_link_is_not_shown() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let token = subscription_token(&app).await;
+    let mock_guard = when_sending_an_email()
+        .respond_with(ResponseTemplate::new(500))
+        .expect(1)
+        .mount_as_scoped(&app.email_server)
+        .await;
+
+    // Act
+    let response = app
+        .post_preferences(
+            "/email",
+            &serde_json::json!({
+                "subscription_token": token,
+                "email": "new-address@example.com",
+            }),
+        )
+        .await;
+
+    // Assert
+    assert_is_redirect_to(&response, &format!("/preferences?subscription_token={}", token));
+    mock_guard.wait_until_satisfied().await;
+}
+
+#[tokio::test]
+async fn email_changes_are_rate_limited() {
+    // Arrange
+    let app = spawn_app_with(|c| c.subscribe_protection.max_attempts_per_address = 1).await;
+    create_confirmed_subscriber(&app).await;
+    let token = subscription_token(&app).await;
+    let mock_guard = when_sending_an_email()
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount_as_scoped(&app.email_server)
+        .await;
+    let body = serde_json::json!({
+        "subscription_token": token,
+        "email": "new-address@example.com",
+    });
+    app.post_preferences("/email", &body).await;
+
+    // Act
+    let response = app.post_preferences("/email", &body).await;
+
+    // Assert
+    assert_is_redirect_to(&response, &format!("/preferences?subscription_token={}", token));
+    let html_page = app.get_preferences_html(&token).await;
+    assert!(html_page.contains("Too many requests. Please try again later."));
+    mock_guard.wait_until_satisfied().await;
+}
+
+#[tokio::test]
+async fn subscribers_can_unsubscribe_from_the_preferences_page() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let token = subscription_token(&app).await;
+
+    // Act
+    let response = app
+        .post_preferences(
+            "/unsubscribe",
+            &serde_json::json!({ "subscription_token": token }),
+        )
+        .await;
+
+    // Assert
+    assert_is_redirect_to(&response, &format!("/preferences?subscription_token={}", token));
+    let status = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .status;
+    assert_eq!(status, "unsubscribed");
+    let html_page = app.get_preferences_html(&token).await;
+    assert!(html_page.contains("You are not subscribed."));
+}
+
+#[tokio::test]
+async fn issues_on_a_muted_topic_are_not_delivered() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let token = subscription_token(&app).await;
+    app.post_preferences(
+        "",
+        &[
+            ("subscription_token", token.as_str()),
+            ("name", "Ursula Le Guin"),
+            ("topics", "Announcements"),
+            ("frequency", "every_issue"),
+        ],
+    )
+    .await;
+    app.test_user.login(&app).await;
+
+    // Act
+    publish_issue(&app, "Events").await;
+    let after_muted_topic = n_queued_deliveries(&app).await;
+    publish_issue(&app, "Announcements").await;
+    publish_issue(&app, "").await;
+
+    // Assert
+    assert_eq!(after_muted_topic, 0);
+    assert_eq!(n_queued_deliveries(&app).await, 2);
+}
+
+#[tokio::test]
+async fn weekly_subscribers_get_at_most_one_issue_a_week() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let token = subscription_token(&app).await;
+    app.post_preferences(
+        "",
+        &[
+            ("subscription_token", token.as_str()),
+            ("name", "Ursula Le Guin"),
+            ("frequency", "weekly"),
+        ],
+    )
+    .await;
+    app.test_user.login(&app).await;
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    publish_issue(&app, "").await;
+    app.dispatch_all_pending_emails().await;
+    publish_issue(&app, "").await;
+
+    // Assert
+    assert_eq!(n_queued_deliveries(&app).await, 0);
+    // The second issue is not sent later either, as the subscriber is told
+    let html_page = app.get_preferences_html(&token).await;
+    assert!(html_page.contains("issues published in between are skipped, not sent later"));
+}
+
+#[tokio::test]
+async fn issues_cannot_be_published_under_an_unknown_topic() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    // Act
+    let response = app
+        .post_publish_newsletter(&serde_json::json!({
+            "title": "Newsletter title",
+            "text_content": "Newsletter body as plain text",
+            "html_content": "<p>Newsletter body as HTML</p>",
+            "topic": "Gossip",
+            "idempotency_key": Uuid::new_v4().to_string()
+        }))
+        .await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    let html_page = app.get_publish_newsletter_html().await;
+    assert!(html_page.contains("<p><i>Gossip is not a known topic.</i></p>"));
+}
+
+#[tokio::test]
+async fn submitted_values_are_escaped_when_echoed_in_an_error() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let token = subscription_token(&app).await;
+
+    // Act
+    app.post_preferences(
+        "",
+        &[
+            ("subscription_token", token.as_str()),
+            ("name", "le guin"),
+            ("frequency", "weekly"),
+            ("topics", "<script>alert(1)</script>"),
+        ],
+    )
+    .await;
+
+    // Assert
+    let html_page = app.get_preferences_html(&token).await;
+    assert!(html_page.contains("&lt;script&gt;alert(1)&lt;/script&gt; is not a known topic."));
+    assert!(!html_page.contains("<script>alert(1)</script>"));
+}
@@ -0,0 +1,278 @@
+use crate::helpers::{assert_is_redirect_to, create_confirmed_subscriber, spawn_app, TestApp};
+use uuid::Uuid;
+
+async fn create_tag(app: &TestApp, name: &str) -> Uuid {
+    let response = app.post_create_tag(name).await;
+    assert_is_redirect_to(&response, "/admin/tags");
+    sqlx::query!("SELECT tag_id FROM tags WHERE name = $1", name)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .tag_id
+}
+
+// Confirmed, but without anything the subscriber did themselves on record
+async fn create_quiet_subscriber(app: &TestApp, email: &str) -> Uuid {
+    let subscriber_id = Uuid::new_v4();
+    sqlx::query!(
+        r#"
+        INSERT INTO subscriptions (id, email, name, subscribed_at, status, confirmed_at)
+        VALUES ($1, $2, 'Quiet subscriber', now(), 'confirmed', now())
+        "#,
+        subscriber_id,
+        email,
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    sqlx::query!(
+        r#"
+        INSERT INTO list_memberships (list_id, subscriber_id, status, subscribed_at, confirmed_at)
+        SELECT list_id, $1, 'confirmed', now(), now() FROM lists WHERE slug = 'newsletter'
+        "#,
+        subscriber_id,
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    subscriber_id
+}
+
+async fn subscriber_ids(app: &TestApp) -> Vec<Uuid> {
+    sqlx::query!("SELECT id FROM subscriptions ORDER BY subscribed_at")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|r| r.id)
+        .collect()
+}
+
+async fn queued_emails(app: &TestApp) -> Vec<String> {
+    sqlx::query!("SELECT subscriber_email FROM issue_delivery_queue")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|r| r.subscriber_email)
+        .collect()
+}
+
+fn issue_form<'a>(segment: &[(&'a str, &'a str)]) -> Vec<(&'a str, String)> {
+    let mut form = vec![
+        ("title", "Newsletter title".to_string()),
+        ("text_content", "Newsletter body as plain text".to_string()),
+        ("html_content", "<p>Newsletter body as HTML</p>".to_string()),
+        ("idempotency_key", Uuid::new_v4().to_string()),
+    ];
+    form.extend(segment.iter().map(|(name, value)| (*name, value.to_string())));
+    form
+}
+
+#[tokio::test]
+async fn admins_can_tag_subscribers() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    create_confirmed_subscriber(&app).await;
+    let subscriber_id = subscriber_ids(&app).await[0];
+    let tag_id = create_tag(&app, "Early adopters").await;
+
+    // Act
+    let response = app.post_subscriber_tags(subscriber_id, &[tag_id]).await;
+
+    // Assert
+    assert_is_redirect_to(&response, &format!("/admin/subscribers/{}", subscriber_id));
+    let html_page = app.get_subscriber_details_html(subscriber_id).await;
+    assert!(html_page.contains(&format!(r#"value="{}" checked> Early adopters"#, tag_id)));
+    let html_page = app.get_tags_html().await;
+    assert!(html_page.contains("<tr><td>Early adopters</td><td>1</td></tr>"));
+
+    // Act - Untag
+    app.post_subscriber_tags(subscriber_id, &[]).await;
+
+    // Assert
+    let html_page = app.get_subscriber_details_html(subscriber_id).await;
+    assert!(html_page.contains(&format!(r#"value="{}"> Early adopters"#, tag_id)));
+}
+
+#[tokio::test]
+async fn tags_need_a_unique_name() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    create_tag(&app, "VIP").await;
+
+    // Act - Part 1 - The same name again
+    app.post_create_tag("VIP").await;
+
+    // Assert
+    let html_page = app.get_tags_html().await;
+    assert!(html_page.contains("This tag already exists."));
+
+    // Act - Part 2 - No name
+    app.post_create_tag("  ").await;
+
+    // Assert
+    let html_page = app.get_tags_html().await;
+    assert!(html_page.contains("Tags need a name"));
+    let n_tags = sqlx::query!(r#"SELECT COUNT(*) AS "n!" FROM tags"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .n;
+    assert_eq!(n_tags, 1);
+}
+
+#[tokio::test]
+async fn issues_for_a_tag_only_go_to_tagged_subscribers() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    create_quiet_subscriber(&app, "tagged@example.com").await;
+    create_quiet_subscriber(&app, "untagged@example.com").await;
+    let tag_id = create_tag(&app, "VIP").await;
+    app.post_subscriber_tags(subscriber_ids(&app).await[0], &[tag_id]).await;
+
+    // Act
+    let response = app
+        .post_publish_newsletter(&issue_form(&[("tag_id", &tag_id.to_string())]))
+        .await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    assert_eq!(queued_emails(&app).await, vec!["tagged@example.com".to_string()]);
+    let segment_tag_id = sqlx::query!("SELECT segment_tag_id FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .segment_tag_id;
+    assert_eq!(segment_tag_id, Some(tag_id));
+}
+
+#[tokio::test]
+async fn issues_can_go_to_recent_subscribers_only() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let old_id = create_quiet_subscriber(&app, "old@example.com").await;
+    create_quiet_subscriber(&app, "new@example.com").await;
+    sqlx::query!(
+        "UPDATE subscriptions SET subscribed_at = '2020-01-01T00:00:00Z' WHERE id = $1",
+        old_id,
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+
+    // Act
+    app.post_publish_newsletter(&issue_form(&[("joined_since", "2024-01-01")]))
+        .await;
+
+    // Assert
+    assert_eq!(queued_emails(&app).await, vec!["new@example.com".to_string()]);
+}
+
+#[tokio::test]
+async fn issues_can_go_to_engaged_subscribers_only() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    create_quiet_subscriber(&app, "quiet@example.com").await;
+    // Confirming through the emailed link counts as engaging
+    create_confirmed_subscriber(&app).await;
+
+    // Act
+    app.post_publish_newsletter(&issue_form(&[("engaged_within_days", "30")]))
+        .await;
+
+    // Assert
+    let queued = queued_emails(&app).await;
+    assert_eq!(queued.len(), 1);
+    assert_ne!(queued[0], "quiet@example.com");
+}
+
+#[tokio::test]
+async fn leaving_another_list_does_not_count_as_engaging() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let subscriber_id = create_quiet_subscriber(&app, "quiet@example.com").await;
+    sqlx::query!(
+        r#"
+        INSERT INTO audit_events (event_id, occurred_at, actor_user_id, action, target)
+        VALUES ($1, now(), NULL, 'subscriber_unsubscribed', $2)
+        "#,
+        Uuid::new_v4(),
+        subscriber_id.to_string(),
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+
+    // Act
+    app.post_publish_newsletter(&issue_form(&[("engaged_within_days", "30")]))
+        .await;
+
+    // Assert
+    assert!(queued_emails(&app).await.is_empty());
+}
+
+#[tokio::test]
+async fn the_preview_counts_recipients_without_sending() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    create_quiet_subscriber(&app, "tagged@example.com").await;
+    create_quiet_subscriber(&app, "untagged@example.com").await;
+    create_quiet_subscriber(&app, "also-tagged@example.com").await;
+    let tag_id = create_tag(&app, "VIP").await;
+    let ids = subscriber_ids(&app).await;
+    app.post_subscriber_tags(ids[0], &[tag_id]).await;
+    app.post_subscriber_tags(ids[2], &[tag_id]).await;
+
+    // Act
+    let response = app
+        .post_preview_newsletter(&issue_form(&[("tag_id", &tag_id.to_string())]))
+        .await;
+
+    // Assert
+    assert_eq!(200, response.status().as_u16());
+    let html_page = response.text().await.unwrap();
+    assert!(html_page.contains("This issue will go to 2 subscriber(s)."));
+    assert!(html_page.contains(&format!(r#"name="tag_id" value="{}""#, tag_id)));
+    assert!(queued_emails(&app).await.is_empty());
+    let n_issues = sqlx::query!(r#"SELECT COUNT(*) AS "n!" FROM newsletter_issues"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .n;
+    assert_eq!(n_issues, 0);
+}
+
+#[tokio::test]
+async fn an_invalid_segment_is_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    for (field, value, error) in [
+        ("tag_id", Uuid::new_v4().to_string(), "The selected tag does not exist."),
+        ("engaged_within_days", "0".to_string(), "Engagement is looked up over 1 to 3650 days."),
+        ("joined_since", "yesterday".to_string(), "yesterday is not a date in the YYYY-MM-DD format."),
+    ] {
+        // Act
+        let response = app.post_publish_newsletter(&issue_form(&[(field, &value)])).await;
+
+        // Assert
+        assert_is_redirect_to(&response, "/admin/newsletters");
+        let html_page = app.get_publish_newsletter_html().await;
+        assert!(html_page.contains(error));
+    }
+    let n_issues = sqlx::query!(r#"SELECT COUNT(*) AS "n!" FROM newsletter_issues"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .n;
+    assert_eq!(n_issues, 0);
+}
@@ -0,0 +1,172 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app, spawn_app_with, TestApp};
+use uuid::Uuid;
+
+// The sessions the test user has not logged out of, oldest first
+async fn active_session_ids(app: &TestApp) -> Vec<Uuid> {
+    sqlx::query!(
+        r#"
+        SELECT session_id
+        FROM user_sessions
+        WHERE user_id = $1 AND revoked_at IS NULL
+        ORDER BY created_at
+        "#,
+        app.test_user.user_id,
+    )
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|r| r.session_id)
+        .collect()
+}
+
+async fn can_see_dashboard(app: &TestApp, client: &reqwest::Client) -> bool {
+    let response = client
+        .get(format!("{}/admin/dashboard", &app.address))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    response.status().as_u16() == 200
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_see_your_sessions() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app.get_admin_sessions().await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn the_sessions_page_lists_where_you_are_logged_in() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    app.log_in_from_another_device("Phone Browser/1.0").await;
+
+    // Act
+    let html_page = app.get_admin_sessions_html().await;
+
+    // Assert
+    assert!(html_page.contains("Phone Browser/1.0"));
+    assert!(html_page.contains("127.0.0.1"));
+    assert!(html_page.contains("This session"));
+    assert_eq!(html_page.matches(">Revoke</button>").count(), 1);
+}
+
+#[tokio::test]
+async fn behind_a_trusted_proxy_the_client_address_is_shown() {
+    // Arrange
+    let app = spawn_app_with(|c| {
+        c.application.trusted_proxies = vec!["127.0.0.1".parse().unwrap()];
+    })
+    .await;
+    let response = app
+        .api_client
+        .post(format!("{}/login", &app.address))
+        .header("X-Forwarded-For", "203.0.113.7")
+        .form(&serde_json::json!({
+            "username": &app.test_user.username,
+            "password": &app.test_user.password
+        }))
+        .send()
+        .await
+        .unwrap();
+    assert_is_redirect_to(&response, "/admin/dashboard");
+
+    // Act
+    let html_page = app.get_admin_sessions_html().await;
+
+    // Assert
+    assert!(html_page.contains("203.0.113.7"));
+    assert!(!html_page.contains("127.0.0.1"));
+}
+
+#[tokio::test]
+async fn revoking_a_session_logs_it_out() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let other_device = app.log_in_from_another_device("Phone Browser/1.0").await;
+    let other_session_id = active_session_ids(&app).await[1];
+
+    // Act
+    let response = app.post_revoke_session(other_session_id).await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/sessions");
+    let html_page = app.get_admin_sessions_html().await;
+    assert!(html_page.contains("<p><i>The session has been revoked.</i></p>"));
+    assert!(!can_see_dashboard(&app, &other_device).await);
+    assert!(can_see_dashboard(&app, &app.api_client).await);
+}
+
+#[tokio::test]
+async fn you_cannot_revoke_another_users_session() {
+    // Arrange
+    let app = spawn_app().await;
+    let other_device = app.log_in_from_another_device("Phone Browser/1.0").await;
+    let session_id = active_session_ids(&app).await[0];
+    let other_user = crate::helpers::TestUser::generate();
+    other_user.store(&app.db_pool).await;
+    other_user.login(&app).await;
+
+    // Act
+    let response = app.post_revoke_session(session_id).await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/sessions");
+    let html_page = app.get_admin_sessions_html().await;
+    assert!(html_page.contains(
+        "<p><i>The session does not exist or was already revoked.</i></p>"
+    ));
+    assert!(can_see_dashboard(&app, &other_device).await);
+}
+
+#[tokio::test]
+async fn revoking_other_sessions_keeps_the_current_one() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let first_device = app.log_in_from_another_device("Phone Browser/1.0").await;
+    let second_device = app.log_in_from_another_device("Tablet Browser/1.0").await;
+
+    // Act
+    let response = app.post_revoke_other_sessions().await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/sessions");
+    let html_page = app.get_admin_sessions_html().await;
+    assert!(html_page.contains("<p><i>2 other sessions have been logged out.</i></p>"));
+    assert!(!can_see_dashboard(&app, &first_device).await);
+    assert!(!can_see_dashboard(&app, &second_device).await);
+    assert!(can_see_dashboard(&app, &app.api_client).await);
+}
+
+#[tokio::test]
+async fn changing_your_password_logs_out_your_other_sessions() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let other_device = app.log_in_from_another_device("Phone Browser/1.0").await;
+    let new_password = Uuid::new_v4().to_string();
+
+    // Act
+    let response = app
+        .post_change_password(&serde_json::json!({
+            "current_password": &app.test_user.password,
+            "new_password": &new_password,
+            "new_password_check": &new_password,
+        }))
+        .await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/password");
+    assert!(!can_see_dashboard(&app, &other_device).await);
+    assert!(can_see_dashboard(&app, &app.api_client).await);
+    assert_eq!(active_session_ids(&app).await.len(), 1);
+}
@@ -0,0 +1,147 @@
+use crate::helpers::{assert_is_redirect_to, configure_database, spawn_app, TestApp};
+use uuid::Uuid;
+use zero2prod::authentication::SEEDED_ADMIN_PASSWORD_HASH;
+use zero2prod::configuration::{get_configuration, Environment};
+use zero2prod::startup::{get_connection_pool, Application};
+
+// Start from an empty users table, as a fresh deployment would
+async fn remove_all_users(app: &TestApp) {
+    sqlx::query!("DELETE FROM users")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+}
+
+fn setup_body(token: &str, username: &str, password: &str) -> serde_json::Value {
+    serde_json::json!({
+        "setup_token": token,
+        "username": username,
+        "password": password,
+        "password_check": password,
+    })
+}
+
+#[tokio::test]
+async fn the_seeded_admin_account_is_not_left_in_place() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let seeded = sqlx::query!(
+        "SELECT username FROM users WHERE password_hash = $1",
+        SEEDED_ADMIN_PASSWORD_HASH
+    )
+        .fetch_optional(&app.db_pool)
+        .await
+        .unwrap();
+
+    // Assert
+    assert!(seeded.is_none());
+}
+
+#[tokio::test]
+async fn the_setup_token_creates_the_first_owner() {
+    // Arrange
+    let app = spawn_app().await;
+    remove_all_users(&app).await;
+    let token = app.setup_token.clone().expect("No setup token was issued.");
+    let username = Uuid::new_v4().to_string();
+    let password = Uuid::new_v4().to_string();
+
+    // Act - Part 1 - Set up
+    let response = app.post_setup(&setup_body(&token, &username, &password)).await;
+    assert_is_redirect_to(&response, "/login");
+
+    // Act - Part 2 - Log in
+    let response = app
+        .post_login(&serde_json::json!({"username": &username, "password": &password}))
+        .await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+
+    // Assert
+    let html_page = app.get_admin_dashboard_html().await;
+    assert!(html_page.contains("You are signed in as owner."));
+}
+
+#[tokio::test]
+async fn the_setup_token_can_only_be_used_once() {
+    // Arrange
+    let app = spawn_app().await;
+    remove_all_users(&app).await;
+    let token = app.setup_token.clone().unwrap();
+    let response = app
+        .post_setup(&setup_body(&token, "first", &Uuid::new_v4().to_string()))
+        .await;
+    assert_is_redirect_to(&response, "/login");
+
+    // Act
+    let response = app
+        .post_setup(&setup_body(&token, "second", &Uuid::new_v4().to_string()))
+        .await;
+
+    // Assert
+    assert_eq!(410, response.status().as_u16());
+    assert_eq!(404, app.get_setup().await.status().as_u16());
+}
+
+#[tokio::test]
+async fn an_invalid_setup_token_is_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    remove_all_users(&app).await;
+
+    // Act
+    let response = app
+        .post_setup(&setup_body("not-the-token", "first", "a-password"))
+        .await;
+    assert_is_redirect_to(&response, "/setup");
+
+    // Assert
+    let html_page = app.get_setup().await.text().await.unwrap();
+    assert!(html_page.contains("<p><i>The setup token is not valid.</i></p>"));
+    let n_users = sqlx::query!(r#"SELECT COUNT(*) AS "n!" FROM users"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .n;
+    assert_eq!(n_users, 0);
+}
+
+#[tokio::test]
+async fn setup_is_not_available_once_a_user_exists() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app.get_setup().await;
+
+    // Assert
+    assert_eq!(404, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn production_refuses_to_start_while_the_seeded_password_is_in_use() {
+    // Arrange
+    let configuration = {
+        let mut c = get_configuration().expect("Failed to read configuration.");
+        c.database.database_name = Uuid::new_v4().to_string();
+        c.application.port = 0;
+        c.environment = Environment::Production;
+        c
+    };
+    configure_database(&configuration.database).await;
+    sqlx::query!(
+        "INSERT INTO users (user_id, username, password_hash, role) VALUES ($1, 'admin', $2, 'owner')",
+        Uuid::new_v4(),
+        SEEDED_ADMIN_PASSWORD_HASH,
+    )
+        .execute(&get_connection_pool(&configuration.database))
+        .await
+        .unwrap();
+
+    // Act
+    let outcome = Application::build(configuration).await;
+
+    // Assert
+    assert!(outcome.is_err());
+}
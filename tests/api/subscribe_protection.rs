@@ -0,0 +1,293 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app, spawn_app_with, when_sending_an_email, TestApp};
+use secrecy::Secret;
+use std::time::Duration;
+use wiremock::matchers::{body_string_contains, method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+use zero2prod::configuration::{ChallengeProvider, ChallengeSettings};
+
+fn sign_up_body(email: &str, extra: &[(&str, &str)]) -> String {
+    let mut fields = vec![("name", "le guin"), ("email", email)];
+    fields.extend_from_slice(extra);
+    serde_urlencoded::to_string(fields).unwrap()
+}
+
+async fn post_subscriptions_forwarded_for(
+    app: &TestApp,
+    body: String,
+    forwarded_for: &str,
+) -> reqwest::Response {
+    app.api_client
+        .post(format!("{}/subscriptions", &app.address))
+        .header("Content-Type", "application/x-www-form-urlencoded")
+        .header("X-Forwarded-For", forwarded_for)
+        .body(body)
+        .send()
+        .await
+        .expect("Failed to execute request.")
+}
+
+fn form_token(html_page: &str) -> String {
+    let (_, rest) = html_page
+        .split_once(r#"name="form_token" value=""#)
+        .expect("The form has no token.");
+    rest.split('"').next().unwrap().to_string()
+}
+
+#[tokio::test]
+async fn sign_ups_beyond_the_limit_for_an_ip_address_are_rejected() {
+    // Arrange
+    let app = spawn_app_with(|c| c.subscribe_protection.max_attempts_per_ip = 2).await;
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let mut statuses = Vec::new();
+    for email in ["a@example.com", "b@example.com", "c@example.com"] {
+        let response = app.post_subscriptions(sign_up_body(email, &[])).await;
+        statuses.push(response.status().as_u16());
+    }
+
+    // Assert
+    assert_eq!(statuses, vec![200, 200, 429]);
+}
+
+#[tokio::test]
+async fn behind_a_trusted_proxy_the_forwarded_address_is_limited() {
+    // Arrange
+    let app = spawn_app_with(|c| {
+        c.subscribe_protection.max_attempts_per_ip = 1;
+        c.application.trusted_proxies = vec!["127.0.0.1".parse().unwrap()];
+    })
+    .await;
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let mut statuses = Vec::new();
+    for (email, client) in [
+        ("a@example.com", "203.0.113.7"),
+        ("b@example.com", "203.0.113.8"),
+        ("c@example.com", "203.0.113.7"),
+    ] {
+        let response =
+            post_subscriptions_forwarded_for(&app, sign_up_body(email, &[]), client).await;
+        statuses.push(response.status().as_u16());
+    }
+
+    // Assert
+    assert_eq!(statuses, vec![200, 200, 429]);
+}
+
+#[tokio::test]
+async fn a_forwarded_address_from_an_untrusted_peer_is_ignored() {
+    // Arrange
+    let app = spawn_app_with(|c| c.subscribe_protection.max_attempts_per_ip = 1).await;
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let mut statuses = Vec::new();
+    for (email, client) in [("a@example.com", "203.0.113.7"), ("b@example.com", "203.0.113.8")] {
+        let response =
+            post_subscriptions_forwarded_for(&app, sign_up_body(email, &[]), client).await;
+        statuses.push(response.status().as_u16());
+    }
+
+    // Assert
+    assert_eq!(statuses, vec![200, 429]);
+}
+
+#[tokio::test]
+async fn the_same_address_cannot_be_signed_up_again_and_again() {
+    // Arrange
+    let app = spawn_app_with(|c| c.subscribe_protection.max_attempts_per_address = 2).await;
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(200))
+        .expect(3)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let mut statuses = Vec::new();
+    for email in ["victim@example.com", "victim@example.com", "VICTIM@example.com"] {
+        let response = app.post_subscriptions(sign_up_body(email, &[])).await;
+        statuses.push(response.status().as_u16());
+    }
+    let other_address = app
+        .post_subscriptions(sign_up_body("someone-else@example.com", &[]))
+        .await;
+
+    // Assert
+    assert_eq!(statuses, vec![200, 200, 429]);
+    assert_eq!(other_address.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn a_filled_in_honeypot_looks_like_a_success_but_sends_nothing() {
+    // Arrange
+    let app = spawn_app().await;
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = app
+        .post_subscriptions(sign_up_body(
+            "victim@example.com",
+            &[("website", "https://spam.example.com")],
+        ))
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let n_subscribers = sqlx::query!(r#"SELECT COUNT(*) AS "n!" FROM subscriptions"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .n;
+    assert_eq!(n_subscribers, 0);
+}
+
+#[tokio::test]
+async fn forms_sent_back_too_quickly_are_rejected() {
+    // Arrange
+    let app = spawn_app_with(|c| c.subscribe_protection.min_fill_seconds = 3600).await;
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+    let token = form_token(&app.get_subscribe_form_html().await);
+
+    // Act
+    let right_away = app
+        .post_subscriptions(sign_up_body("a@example.com", &[("form_token", &token)]))
+        .await;
+    let without_token = app.post_subscriptions(sign_up_body("a@example.com", &[])).await;
+    let forged_token = app
+        .post_subscriptions(sign_up_body("a@example.com", &[("form_token", "0.00")]))
+        .await;
+
+    // Assert
+    assert_eq!(right_away.status().as_u16(), 400);
+    assert_eq!(without_token.status().as_u16(), 400);
+    assert_eq!(forged_token.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn a_form_left_open_long_enough_is_accepted() {
+    // Arrange
+    let app = spawn_app_with(|c| c.subscribe_protection.min_fill_seconds = 1).await;
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    let html_page = app.get_subscribe_form_html().await;
+    assert!(html_page.contains(r#"name="website""#));
+    let token = form_token(&html_page);
+
+    // Act
+    tokio::time::sleep(Duration::from_millis(1100)).await;
+    let response = app
+        .post_subscriptions(sign_up_body("a@example.com", &[("form_token", &token)]))
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn the_challenge_must_be_passed_when_a_verifier_is_set_up() {
+    // Arrange
+    let challenge_server = MockServer::start().await;
+    let verify_url = format!("{}/siteverify", challenge_server.uri());
+    let app = spawn_app_with(|c| {
+        c.subscribe_protection.challenge = Some(ChallengeSettings {
+            provider: ChallengeProvider::Turnstile,
+            site_key: "public-site-key".to_string(),
+            verify_url,
+            secret: Secret::new("challenge-secret".to_string()),
+            timeout_milliseconds: 1000,
+        })
+    })
+    .await;
+    Mock::given(path("/siteverify"))
+        .and(method("POST"))
+        .and(body_string_contains("secret=challenge-secret"))
+        .and(body_string_contains("response=solved"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({ "success": true })))
+        .with_priority(1)
+        .expect(1)
+        .mount(&challenge_server)
+        .await;
+    Mock::given(path("/siteverify"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({ "success": false })))
+        .expect(1)
+        .mount(&challenge_server)
+        .await;
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let html_page = app.get_subscribe_form_html().await;
+    let unanswered = app.post_subscriptions(sign_up_body("a@example.com", &[])).await;
+    let failed = app
+        .post_subscriptions(sign_up_body("a@example.com", &[("challenge_response", "guessed")]))
+        .await;
+    // The field the Turnstile widget fills in
+    let passed = app
+        .post_subscriptions(sign_up_body("a@example.com", &[("cf-turnstile-response", "solved")]))
+        .await;
+
+    // Assert
+    assert!(html_page.contains(r#"<div class="cf-turnstile" data-sitekey="public-site-key"></div>"#));
+    assert!(!html_page.contains("challenge-secret"));
+    assert_eq!(unanswered.status().as_u16(), 400);
+    assert_eq!(failed.status().as_u16(), 400);
+    assert_eq!(passed.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn rejected_sign_ups_are_counted_by_reason() {
+    // Arrange
+    let app = spawn_app_with(|c| c.subscribe_protection.max_attempts_per_ip = 1).await;
+    app.post_subscriptions(sign_up_body("a@example.com", &[("website", "spam")]))
+        .await;
+
+    // Act
+    app.post_subscriptions(sign_up_body("b@example.com", &[])).await;
+    app.test_user.login(&app).await;
+    let metrics = app.get_metrics_text().await;
+
+    // Assert
+    assert!(metrics.contains(r#"subscribe_rejections_total{reason="honeypot"} 1"#));
+    assert!(metrics.contains(r#"subscribe_rejections_total{reason="ip_rate_limit"} 1"#));
+    assert!(metrics.contains(r#"subscribe_rejections_total{reason="challenge"} 0"#));
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_read_the_metrics() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app.get_metrics().await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/login");
+}
@@ -0,0 +1,424 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app, when_sending_an_email, TestApp, TestUser};
+use uuid::Uuid;
+use wiremock::ResponseTemplate;
+
+async fn last_import_id(app: &TestApp) -> Uuid {
+    sqlx::query!("SELECT import_id FROM subscriber_imports")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .import_id
+}
+
+// The n-th import, counting from 0 in the order they were made
+async fn nth_import_id(app: &TestApp, n: i64) -> Uuid {
+    sqlx::query!(
+        "SELECT import_id FROM subscriber_imports ORDER BY created_at OFFSET $1 LIMIT 1",
+        n,
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap()
+    .import_id
+}
+
+async fn stored_subscribers(app: &TestApp) -> Vec<(String, String)> {
+    sqlx::query!("SELECT email, status FROM subscriptions ORDER BY email")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|r| (r.email, r.status))
+        .collect()
+}
+
+#[tokio::test]
+async fn rows_are_validated_and_deduplicated_and_reported() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    sqlx::query!(
+        "INSERT INTO subscriptions (id, email, name, subscribed_at, status)
+        VALUES ($1, 'octavia@example.com', 'Octavia', now(), 'unsubscribed')",
+        Uuid::new_v4(),
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    let csv = "Name,Email,Source\n\
+        Ursula,ursula@example.com,fair\n\
+        Octavia,octavia@example.com,fair\n\
+        Nobody,not-an-email,web\n\
+        \"Le Guin, Ursula\",ursula@example.com,web\n";
+
+    // Act
+    let response = app
+        .post_subscriber_import("confirmed", "Signed up at the book fair", csv)
+        .await;
+
+    // Assert
+    let import_id = last_import_id(&app).await;
+    assert_is_redirect_to(&response, &format!("/admin/subscribers/imports/{}", import_id));
+    assert_eq!(
+        stored_subscribers(&app).await,
+        vec![
+            ("octavia@example.com".to_string(), "unsubscribed".to_string()),
+            ("ursula@example.com".to_string(), "confirmed".to_string()),
+        ]
+    );
+    let report = app.get_subscriber_import_report(import_id).await;
+    assert_eq!(200, report.status().as_u16());
+    assert_eq!(report.headers()["Content-Type"], "text/csv; charset=utf-8");
+    let report = report.text().await.unwrap();
+    let lines: Vec<&str> = report.lines().collect();
+    assert_eq!(lines[0], "row,email,name,outcome,detail");
+    assert!(lines[1].starts_with("1,ursula@example.com,Ursula,accepted,"));
+    assert!(lines[2].starts_with("2,octavia@example.com,Octavia,skipped,"));
+    assert_eq!(lines[3], "3,not-an-email,Nobody,invalid,not-an-email is not a valid subscriber email.");
+    assert!(lines[4].starts_with("4,ursula@example.com,\"Le Guin, Ursula\",skipped,"));
+    let html_page = app.get_subscriber_imports_html().await;
+    assert!(html_page.contains("contacts.csv"));
+}
+
+#[tokio::test]
+async fn the_outcome_is_summarised_after_the_import() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let csv = "email,name\nursula@example.com,Ursula\nnot-an-email,Nobody\n";
+
+    // Act
+    app.post_subscriber_import("confirmed", "Signed up at the book fair", csv)
+        .await;
+
+    // Assert
+    let import_id = last_import_id(&app).await;
+    let html_page = app
+        .api_client
+        .get(format!("{}/admin/subscribers/imports/{}", &app.address, import_id))
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    assert!(html_page.contains(
+        "The import has finished: 1 row(s) accepted, 0 skipped and 1 invalid."
+    ));
+    assert!(html_page.contains("Signed up at the book fair"));
+    let n_events = sqlx::query!(
+        "SELECT COUNT(*) AS \"n!\" FROM audit_events WHERE action = 'subscribers_imported' AND target = $1",
+        import_id.to_string(),
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap()
+    .n;
+    assert_eq!(n_events, 1);
+}
+
+#[tokio::test]
+async fn confirmed_imports_require_a_consent_attestation() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    // Act
+    let response = app
+        .post_subscriber_import("confirmed", "  ", "email,name\nursula@example.com,Ursula\n")
+        .await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/subscribers/imports");
+    let html_page = app.get_subscriber_imports_html().await;
+    assert!(html_page.contains("Please describe how the imported contacts gave their consent."));
+    assert!(stored_subscribers(&app).await.is_empty());
+}
+
+#[tokio::test]
+async fn contacts_imported_as_confirmed_get_a_working_unsubscribe_link() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    app.post_subscriber_import(
+        "confirmed",
+        "Signed up at the book fair",
+        "email,name\nursula@example.com,Ursula\n",
+    )
+    .await;
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = app
+        .post_publish_newsletter(&serde_json::json!({
+            "title": "Newsletter title",
+            "text_content": "Unsubscribe: {{unsubscribe_url}}",
+            "html_content": "<p>Newsletter body as HTML</p>",
+            "idempotency_key": Uuid::new_v4().to_string()
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let email_request = app.email_server.received_requests().await.unwrap().pop().unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    let (_, unsubscribe_url) = body["TextBody"]
+        .as_str()
+        .unwrap()
+        .split_once("Unsubscribe: ")
+        .unwrap();
+    assert!(unsubscribe_url.contains("/subscriptions/unsubscribe?subscription_token="));
+    let mut unsubscribe_url = reqwest::Url::parse(unsubscribe_url.trim()).unwrap();
+    unsubscribe_url.set_port(Some(app.port)).unwrap();
+    let response = reqwest::get(unsubscribe_url.clone()).await.unwrap();
+    assert_eq!(200, response.status().as_u16());
+    let token = unsubscribe_url
+        .query_pairs()
+        .find(|(key, _)| key == "subscription_token")
+        .unwrap()
+        .1
+        .into_owned();
+    let response = app.post_unsubscribe(&token).await;
+    assert_eq!(200, response.status().as_u16());
+    assert_eq!(
+        stored_subscribers(&app).await,
+        vec![("ursula@example.com".to_string(), "unsubscribed".to_string())]
+    );
+}
+
+#[tokio::test]
+async fn imported_contacts_can_be_sent_a_confirmation_email() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    app.post_subscriber_import(
+        "send_confirmation",
+        "",
+        "email,name\nursula@example.com,Ursula\noctavia@example.com,Octavia\n",
+    )
+    .await;
+    // The emails are queued, not sent while the file is read
+    assert!(app.email_server.received_requests().await.unwrap().is_empty());
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    assert_eq!(
+        stored_subscribers(&app).await,
+        vec![
+            ("octavia@example.com".to_string(), "pending_confirmation".to_string()),
+            ("ursula@example.com".to_string(), "pending_confirmation".to_string()),
+        ]
+    );
+    let n_tokens = sqlx::query!("SELECT COUNT(*) AS \"n!\" FROM subscription_tokens")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .n;
+    assert_eq!(n_tokens, 2);
+}
+
+#[tokio::test]
+async fn existing_subscribers_are_added_to_the_list_being_imported_into() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    app.post_subscriber_import("confirmed", "Book fair", "email,name\nursula@example.com,Ursula\n")
+        .await;
+    let response = app
+        .post_create_mailing_list(&serde_json::json!({
+            "name": "Events",
+            "slug": "events",
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/lists");
+    let members_of_events = || async {
+        sqlx::query!(
+            "SELECT m.status FROM list_memberships m JOIN lists l USING (list_id) WHERE l.slug = 'events'"
+        )
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|r| r.status)
+        .collect::<Vec<_>>()
+    };
+
+    // Act - Part 1 - Import into another list
+    app.post_subscriber_import_to_list("events", "confirmed", "Book fair", "email,name\nursula@example.com,Ursula\n")
+        .await;
+
+    // Assert - Part 1
+    assert_eq!(members_of_events().await, vec!["confirmed".to_string()]);
+    let import_id = nth_import_id(&app, 1).await;
+    let report = app.get_subscriber_import_report(import_id).await.text().await.unwrap();
+    assert!(report.lines().nth(1).unwrap().starts_with("1,ursula@example.com,Ursula,accepted,"));
+
+    // Act - Part 2 - Import into the same list again
+    app.post_subscriber_import_to_list("events", "confirmed", "Book fair", "email,name\nursula@example.com,Ursula\n")
+        .await;
+
+    // Assert - Part 2
+    assert_eq!(members_of_events().await, vec!["confirmed".to_string()]);
+    let import_id = nth_import_id(&app, 2).await;
+    let report = app.get_subscriber_import_report(import_id).await.text().await.unwrap();
+    assert!(report.lines().nth(1).unwrap().starts_with("1,ursula@example.com,Ursula,skipped,"));
+}
+
+#[tokio::test]
+async fn files_without_an_email_and_a_name_column_are_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    // Act
+    let response = app
+        .post_subscriber_import("confirmed", "Book fair", "address,full name\nursula@example.com,Ursula\n")
+        .await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/subscribers/imports");
+    let html_page = app.get_subscriber_imports_html().await;
+    assert!(html_page.contains("The first row of the file must name an email and a name column."));
+    assert!(stored_subscribers(&app).await.is_empty());
+}
+
+#[tokio::test]
+async fn large_reports_are_downloaded_in_full() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let mut csv = String::from("email,name\n");
+    for i in 0..1500 {
+        csv.push_str(&format!("reader{}@example.com,Reader {}\n", i, i));
+    }
+
+    // Act
+    app.post_subscriber_import("confirmed", "Book fair", &csv).await;
+
+    // Assert
+    let import_id = last_import_id(&app).await;
+    let report = app
+        .get_subscriber_import_report(import_id)
+        .await
+        .text()
+        .await
+        .unwrap();
+    let lines: Vec<&str> = report.lines().collect();
+    assert_eq!(lines.len(), 1501);
+    assert!(lines[1500].starts_with("1500,reader1499@example.com,"));
+}
+
+#[tokio::test]
+async fn the_upload_form_carries_its_csrf_token_as_its_first_field() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let token = app.csrf_token().await;
+    let html_page = app.get_subscriber_imports_html().await;
+    let form_start = html_page.find(r#"enctype="multipart/form-data">"#).unwrap();
+    assert!(html_page[form_start..]
+        .split('\n')
+        .nth(1)
+        .unwrap()
+        .contains(r#"name="csrf_token""#));
+    let form = |leading_token: Option<&str>| {
+        let mut form = reqwest::multipart::Form::new();
+        if let Some(token) = leading_token {
+            form = form.text("csrf_token", token.to_string());
+        }
+        form.text("mode", "confirmed")
+            .text("consent_attestation", "Book fair")
+            .part(
+                "file",
+                reqwest::multipart::Part::text("email,name\nursula@example.com,Ursula\n")
+                    .file_name("contacts.csv"),
+            )
+    };
+
+    // Act - Part 1 - Without the token
+    let response = app
+        .api_client
+        .post(format!("{}/admin/subscribers/imports", &app.address))
+        .multipart(form(None))
+        .send()
+        .await
+        .unwrap();
+
+    // Assert - Part 1
+    assert_eq!(403, response.status().as_u16());
+
+    // Act - Part 2 - With the token in the query string
+    let response = app
+        .api_client
+        .post(format!("{}/admin/subscribers/imports?csrf_token={}", &app.address, token))
+        .multipart(form(None))
+        .send()
+        .await
+        .unwrap();
+
+    // Assert - Part 2
+    assert_eq!(403, response.status().as_u16());
+
+    // Act - Part 3 - With the token as the first field
+    let response = app
+        .api_client
+        .post(format!("{}/admin/subscribers/imports", &app.address))
+        .multipart(form(Some(&token)))
+        .send()
+        .await
+        .unwrap();
+
+    // Assert - Part 3
+    assert_eq!(303, response.status().as_u16());
+    assert_eq!(stored_subscribers(&app).await.len(), 1);
+}
+
+#[tokio::test]
+async fn a_quote_that_is_never_closed_stops_the_import() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let mut csv = String::from("email,name\n\"ursula@example.com,Ursula\n");
+    for i in 0..5000 {
+        csv.push_str(&format!("reader{}@example.com,Reader {}\n", i, i));
+    }
+
+    // Act
+    let response = app.post_subscriber_import("confirmed", "Book fair", &csv).await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/subscribers/imports");
+    let html_page = app.get_subscriber_imports_html().await;
+    assert!(html_page.contains("A row of the file is too long"));
+    assert!(stored_subscribers(&app).await.is_empty());
+}
+
+#[tokio::test]
+async fn viewers_cannot_import_subscribers() {
+    // Arrange
+    let app = spawn_app().await;
+    let viewer = TestUser::generate_with_role("viewer");
+    viewer.store(&app.db_pool).await;
+    viewer.login(&app).await;
+
+    // Act
+    let response = app
+        .post_subscriber_import("confirmed", "Book fair", "email,name\nursula@example.com,Ursula\n")
+        .await;
+
+    // Assert
+    assert_eq!(403, response.status().as_u16());
+    assert!(stored_subscribers(&app).await.is_empty());
+}
@@ -0,0 +1,578 @@
+use crate::helpers::{
+    assert_is_redirect_to, create_unconfirmed_subscriber, spawn_app, spawn_app_with, TestApp,
+    TestUser,
+};
+use chrono::{TimeZone, Utc};
+use uuid::Uuid;
+use zero2prod::configuration::RoleAddressPolicy;
+
+async fn insert_subscriber(
+    app: &TestApp,
+    email: &str,
+    name: &str,
+    status: &str,
+    subscribed_at: chrono::DateTime<Utc>,
+) -> Uuid {
+    let subscriber_id = Uuid::new_v4();
+    sqlx::query!(
+        "INSERT INTO subscriptions (id, email, name, subscribed_at, status)
+        VALUES ($1, $2, $3, $4, $5)",
+        subscriber_id,
+        email,
+        name,
+        subscribed_at,
+        status,
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    subscriber_id
+}
+
+async fn subscriber_status(app: &TestApp, subscriber_id: Uuid) -> Option<String> {
+    sqlx::query!("SELECT status FROM subscriptions WHERE id = $1", subscriber_id)
+        .fetch_optional(&app.db_pool)
+        .await
+        .unwrap()
+        .map(|r| r.status)
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_see_the_subscribers() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app.get_subscribers("").await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn subscribers_can_be_searched_by_email_or_name_and_filtered_by_status() {
+    // Arrange
+    let app = spawn_app().await;
+    let now = Utc::now();
+    insert_subscriber(&app, "ursula@example.com", "Ursula Le Guin", "confirmed", now).await;
+    insert_subscriber(&app, "octavia@example.com", "Octavia Butler", "confirmed", now).await;
+    insert_subscriber(&app, "le.guin@example.net", "Another reader", "unsubscribed", now).await;
+    app.test_user.login(&app).await;
+
+    // Act - Part 1 - Search
+    let html_page = app.get_subscribers_html("q=GUIN").await;
+
+    // Assert - Part 1
+    assert!(html_page.contains("ursula@example.com"));
+    assert!(html_page.contains("le.guin@example.net"));
+    assert!(!html_page.contains("octavia@example.com"));
+
+    // Act - Part 2 - Search and filter
+    let html_page = app.get_subscribers_html("q=guin&status=confirmed").await;
+
+    // Assert - Part 2
+    assert!(html_page.contains("ursula@example.com"));
+    assert!(!html_page.contains("le.guin@example.net"));
+}
+
+#[tokio::test]
+async fn subscribers_can_be_filtered_by_subscription_date() {
+    // Arrange
+    let app = spawn_app().await;
+    let early = Utc.with_ymd_and_hms(2024, 1, 10, 12, 0, 0).unwrap();
+    let late = Utc.with_ymd_and_hms(2024, 3, 10, 12, 0, 0).unwrap();
+    insert_subscriber(&app, "early@example.com", "Early", "confirmed", early).await;
+    insert_subscriber(&app, "late@example.com", "Late", "confirmed", late).await;
+    app.test_user.login(&app).await;
+
+    // Act
+    let html_page = app
+        .get_subscribers_html("since=2024-01-01&until=2024-01-10")
+        .await;
+
+    // Assert
+    assert!(html_page.contains("early@example.com"));
+    assert!(!html_page.contains("late@example.com"));
+}
+
+#[tokio::test]
+async fn an_invalid_filter_is_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    // Act
+    let response = app.get_subscribers("status=sleeping").await;
+
+    // Assert
+    assert_eq!(400, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn subscribers_are_paginated_and_pages_keep_the_filters() {
+    // Arrange
+    let app = spawn_app().await;
+    let now = Utc::now();
+    for i in 0..51 {
+        let subscribed_at = now - chrono::Duration::minutes(i);
+        insert_subscriber(&app, &format!("reader{}@example.com", i), "Reader", "confirmed", subscribed_at).await;
+    }
+    app.test_user.login(&app).await;
+
+    // Act - Part 1 - First page
+    let html_page = app.get_subscribers_html("q=reader").await;
+
+    // Assert - Part 1
+    assert!(html_page.contains("51 matching subscriber(s)"));
+    assert!(html_page.contains("Page 1 of 2"));
+    assert!(html_page.contains("reader0@example.com"));
+    assert!(!html_page.contains("reader50@example.com"));
+    assert!(html_page.contains("q=reader&amp;status=&amp;since=&amp;until=&amp;page=2"));
+
+    // Act - Part 2 - Second page
+    let html_page = app.get_subscribers_html("q=reader&page=2").await;
+
+    // Assert - Part 2
+    assert!(html_page.contains("reader50@example.com"));
+    assert!(!html_page.contains("reader0@example.com"));
+
+    // Act - Part 3 - Past the last page
+    let response = app.get_subscribers("q=reader&page=7").await;
+
+    // Assert - Part 3
+    assert_is_redirect_to(&response, "/admin/subscribers?q=reader&status=&since=&until=&page=2");
+
+    // Act - Part 4 - A page number too large to have an offset
+    let response = app.get_subscribers(&format!("page={}", i64::MAX)).await;
+
+    // Assert - Part 4
+    assert_eq!(400, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn an_unknown_subscriber_is_not_found() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    // Act
+    let response = app.get_subscriber_details(Uuid::new_v4()).await;
+
+    // Assert
+    assert_eq!(404, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn subscriber_edits_are_validated_like_signups() {
+    // Arrange
+    let app = spawn_app().await;
+    let subscriber_id =
+        insert_subscriber(&app, "ursula@example.com", "Ursula", "confirmed", Utc::now()).await;
+    app.test_user.login(&app).await;
+
+    // Act
+    let response = app
+        .post_update_subscriber(subscriber_id, &serde_json::json!({
+            "email": "not-an-email",
+            "name": "Ursula",
+        }))
+        .await;
+
+    // Assert
+    assert_is_redirect_to(&response, &format!("/admin/subscribers/{}", subscriber_id));
+    let html_page = app.get_subscriber_details_html(subscriber_id).await;
+    assert!(html_page.contains("not-an-email is not a valid subscriber email."));
+    let email = sqlx::query!("SELECT email FROM subscriptions WHERE id = $1", subscriber_id)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .email;
+    assert_eq!(email, "ursula@example.com");
+}
+
+#[tokio::test]
+async fn an_email_taken_by_another_subscriber_is_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    let subscriber_id =
+        insert_subscriber(&app, "ursula@example.com", "Ursula", "confirmed", Utc::now()).await;
+    insert_subscriber(&app, "octavia@example.com", "Octavia", "confirmed", Utc::now()).await;
+    app.test_user.login(&app).await;
+
+    // Act
+    app.post_update_subscriber(subscriber_id, &serde_json::json!({
+        "email": "octavia@example.com",
+        "name": "Ursula",
+    }))
+    .await;
+
+    // Assert
+    let html_page = app.get_subscriber_details_html(subscriber_id).await;
+    assert!(html_page.contains("Another subscriber already uses this email address."));
+}
+
+#[tokio::test]
+async fn subscriber_edits_are_saved_and_shown_in_the_history() {
+    // Arrange
+    let app = spawn_app().await;
+    let subscriber_id =
+        insert_subscriber(&app, "ursula@example.com", "Ursula", "confirmed", Utc::now()).await;
+    app.test_user.login(&app).await;
+
+    // Act
+    let response = app
+        .post_update_subscriber(subscriber_id, &serde_json::json!({
+            "email": "ursula@example.org",
+            "name": "Ursula K. Le Guin",
+        }))
+        .await;
+
+    // Assert
+    assert_is_redirect_to(&response, &format!("/admin/subscribers/{}", subscriber_id));
+    let html_page = app.get_subscriber_details_html(subscriber_id).await;
+    assert!(html_page.contains("The subscriber has been saved."));
+    assert!(html_page.contains("ursula@example.org"));
+    assert!(html_page.contains("Ursula K. Le Guin"));
+    assert!(html_page.contains("subscriber_updated"));
+    assert!(html_page.contains(&app.test_user.username));
+}
+
+#[tokio::test]
+async fn subscriber_edits_follow_the_email_policy() {
+    // Arrange
+    let app = spawn_app_with(|c| c.email_policy.role_addresses = RoleAddressPolicy::Reject).await;
+    let subscriber_id =
+        insert_subscriber(&app, "ursula@example.com", "Ursula", "confirmed", Utc::now()).await;
+    app.test_user.login(&app).await;
+
+    // Act
+    app.post_update_subscriber(subscriber_id, &serde_json::json!({
+        "email": "postmaster@example.com",
+        "name": "Ursula",
+    }))
+    .await;
+
+    // Assert
+    let email = sqlx::query!("SELECT email FROM subscriptions WHERE id = $1", subscriber_id)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .email;
+    assert_eq!(email, "ursula@example.com");
+}
+
+#[tokio::test]
+async fn pending_deliveries_follow_an_edited_address() {
+    // Arrange
+    let app = spawn_app().await;
+    let subscriber_id =
+        insert_subscriber(&app, "ursula@example.com", "Ursula", "confirmed", Utc::now()).await;
+    let issue_id = Uuid::new_v4();
+    sqlx::query!(
+        "INSERT INTO newsletter_issues (newsletter_issue_id, title, text_content, html_content, published_at)
+        VALUES ($1, 'Title', 'Text', '<p>Html</p>', now())",
+        issue_id,
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    sqlx::query!(
+        "INSERT INTO issue_delivery_queue (newsletter_issue_id, subscriber_email)
+        VALUES ($1, 'ursula@example.com')",
+        issue_id,
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    app.test_user.login(&app).await;
+
+    // Act
+    app.post_update_subscriber(subscriber_id, &serde_json::json!({
+        "email": "ursula@example.org",
+        "name": "Ursula",
+    }))
+    .await;
+
+    // Assert
+    let queued = sqlx::query!("SELECT subscriber_email FROM issue_delivery_queue")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .subscriber_email;
+    assert_eq!(queued, "ursula@example.org");
+}
+
+#[tokio::test]
+async fn confirming_a_subscriber_records_when_they_were_confirmed() {
+    // Arrange
+    let app = spawn_app().await;
+    let subscriber_id = insert_subscriber(
+        &app,
+        "ursula@example.com",
+        "Ursula",
+        "pending_confirmation",
+        Utc::now(),
+    )
+    .await;
+    app.test_user.login(&app).await;
+
+    // Act
+    app.post_subscriber_action(subscriber_id, "confirm").await;
+
+    // Assert
+    let saved = sqlx::query!(
+        "SELECT status, confirmed_at FROM subscriptions WHERE id = $1",
+        subscriber_id
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(saved.status, "confirmed");
+    assert!(saved.confirmed_at.is_some());
+    let html_page = app.get_subscriber_details_html(subscriber_id).await;
+    assert!(html_page.contains("The subscriber is now confirmed."));
+    assert!(html_page.contains("subscriber_confirmed"));
+}
+
+#[tokio::test]
+async fn the_detail_page_shows_self_service_confirmations() {
+    // Arrange
+    let app = spawn_app().await;
+    let confirmation_link = create_unconfirmed_subscriber(&app).await.html;
+    reqwest::get(confirmation_link).await.unwrap();
+    let subscriber_id = sqlx::query!("SELECT id FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .id;
+    app.test_user.login(&app).await;
+
+    // Act
+    let html_page = app.get_subscriber_details_html(subscriber_id).await;
+
+    // Assert
+    assert!(html_page.contains("subscriber_confirmed"));
+    assert!(html_page.contains("the subscriber"));
+}
+
+#[tokio::test]
+async fn suppressed_subscribers_cannot_confirm_themselves_back_in() {
+    // Arrange
+    let app = spawn_app().await;
+    let confirmation_link = create_unconfirmed_subscriber(&app).await.html;
+    let subscriber_id = sqlx::query!("SELECT id FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .id;
+    app.test_user.login(&app).await;
+
+    // Act
+    app.post_subscriber_action(subscriber_id, "suppress").await;
+    reqwest::get(confirmation_link).await.unwrap();
+
+    // Assert
+    assert_eq!(subscriber_status(&app, subscriber_id).await.as_deref(), Some("suppressed"));
+}
+
+#[tokio::test]
+async fn unsubscribing_a_subscriber_cancels_their_pending_deliveries() {
+    // Arrange
+    let app = spawn_app().await;
+    let subscriber_id =
+        insert_subscriber(&app, "ursula@example.com", "Ursula", "confirmed", Utc::now()).await;
+    let issue_id = Uuid::new_v4();
+    sqlx::query!(
+        "INSERT INTO newsletter_issues (newsletter_issue_id, title, text_content, html_content, published_at)
+        VALUES ($1, 'Title', 'Text', '<p>Html</p>', now())",
+        issue_id,
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    sqlx::query!(
+        "INSERT INTO issue_delivery_queue (newsletter_issue_id, subscriber_email)
+        VALUES ($1, 'ursula@example.com')",
+        issue_id,
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    app.test_user.login(&app).await;
+
+    // Act
+    app.post_subscriber_action(subscriber_id, "unsubscribe").await;
+
+    // Assert
+    assert_eq!(subscriber_status(&app, subscriber_id).await.as_deref(), Some("unsubscribed"));
+    let n_queued = sqlx::query!("SELECT COUNT(*) AS \"n!\" FROM issue_delivery_queue")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .n;
+    assert_eq!(n_queued, 0);
+}
+
+#[tokio::test]
+async fn deleted_subscribers_are_gone_but_remain_in_the_audit_log() {
+    // Arrange
+    let app = spawn_app().await;
+    create_unconfirmed_subscriber(&app).await;
+    let subscriber_id = sqlx::query!("SELECT id FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .id;
+    app.test_user.login(&app).await;
+
+    // Act
+    let response = app.post_subscriber_action(subscriber_id, "delete").await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/subscribers");
+    assert_eq!(subscriber_status(&app, subscriber_id).await, None);
+    let n_tokens = sqlx::query!("SELECT COUNT(*) AS \"n!\" FROM subscription_tokens")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .n;
+    assert_eq!(n_tokens, 0);
+    let html_page = app.get_audit_log_html("action=subscriber_deleted").await;
+    assert!(html_page.contains(&subscriber_id.to_string()));
+}
+
+#[tokio::test]
+async fn viewers_can_browse_subscribers_but_not_change_them() {
+    // Arrange
+    let app = spawn_app().await;
+    let subscriber_id =
+        insert_subscriber(&app, "ursula@example.com", "Ursula", "confirmed", Utc::now()).await;
+    let viewer = TestUser::generate_with_role("viewer");
+    viewer.store(&app.db_pool).await;
+    viewer.login(&app).await;
+
+    // Act
+    let list_response = app.get_subscribers("").await;
+    let details_response = app.get_subscriber_details(subscriber_id).await;
+    let delete_response = app.post_subscriber_action(subscriber_id, "delete").await;
+
+    // Assert
+    assert_eq!(200, list_response.status().as_u16());
+    assert_eq!(200, details_response.status().as_u16());
+    assert_eq!(403, delete_response.status().as_u16());
+    assert_eq!(subscriber_status(&app, subscriber_id).await.as_deref(), Some("confirmed"));
+}
+
+#[tokio::test]
+async fn subscribers_are_exported_as_csv_with_the_chosen_filters() {
+    // Arrange
+    let app = spawn_app().await;
+    let subscribed_at = Utc.with_ymd_and_hms(2024, 1, 10, 12, 0, 0).unwrap();
+    let subscriber_id = insert_subscriber(
+        &app,
+        "ursula@example.com",
+        "Le Guin, Ursula",
+        "confirmed",
+        subscribed_at,
+    )
+    .await;
+    insert_subscriber(&app, "octavia@example.com", "Octavia", "unsubscribed", subscribed_at).await;
+    app.test_user.login(&app).await;
+
+    // Act
+    let response = app.get_subscribers_export("status=confirmed&since=2024-01-10").await;
+
+    // Assert
+    assert_eq!(200, response.status().as_u16());
+    assert_eq!(response.headers()["Content-Type"], "text/csv; charset=utf-8");
+    let body = response.text().await.unwrap();
+    assert_eq!(
+        body,
+        format!(
+            "id,email,name,subscribed_at,status,confirmed_at\n\
+            {},ursula@example.com,\"Le Guin, Ursula\",2024-01-10T12:00:00+00:00,confirmed,\n",
+            subscriber_id
+        )
+    );
+}
+
+#[tokio::test]
+async fn subscribers_are_exported_as_ndjson() {
+    // Arrange
+    let app = spawn_app().await;
+    let subscriber_id =
+        insert_subscriber(&app, "ursula@example.com", "Ursula", "confirmed", Utc::now()).await;
+    app.test_user.login(&app).await;
+    app.post_subscriber_action(subscriber_id, "confirm").await;
+
+    // Act
+    let response = app.get_subscribers_export("format=ndjson").await;
+
+    // Assert
+    assert_eq!(response.headers()["Content-Type"], "application/x-ndjson");
+    let body = response.text().await.unwrap();
+    let lines: Vec<serde_json::Value> = body
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert_eq!(lines.len(), 1);
+    assert_eq!(lines[0]["id"], subscriber_id.to_string());
+    assert_eq!(lines[0]["email"], "ursula@example.com");
+    assert_eq!(lines[0]["status"], "confirmed");
+    assert!(lines[0]["confirmed_at"].is_string());
+}
+
+#[tokio::test]
+async fn large_exports_are_streamed_in_full() {
+    // Arrange
+    let app = spawn_app().await;
+    sqlx::query!(
+        "INSERT INTO subscriptions (id, email, name, subscribed_at, status)
+        SELECT gen_random_uuid(), 'reader' || i || '@example.com', 'Reader', now(), 'confirmed'
+        FROM generate_series(1, 2500) i"
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    app.test_user.login(&app).await;
+
+    // Act
+    let body = app
+        .get_subscribers_export("format=csv")
+        .await
+        .text()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(body.lines().count(), 2501);
+    assert_eq!(body.matches("id,email").count(), 1);
+}
+
+#[tokio::test]
+async fn an_unknown_export_format_is_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    // Act
+    let response = app.get_subscribers_export("format=xml").await;
+
+    // Assert
+    assert_eq!(400, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn only_owners_can_export_subscribers() {
+    // Arrange
+    let app = spawn_app().await;
+    let editor = TestUser::generate_with_role("editor");
+    editor.store(&app.db_pool).await;
+    editor.login(&app).await;
+
+    // Act
+    let response = app.get_subscribers_export("").await;
+
+    // Assert
+    assert_eq!(403, response.status().as_u16());
+}
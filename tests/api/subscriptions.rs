@@ -1,4 +1,5 @@
-use crate::helpers::spawn_app;
+use crate::helpers::{spawn_app, spawn_app_with};
+use zero2prod::configuration::RoleAddressPolicy;
 use wiremock::matchers::{method, path};
 use wiremock::{Mock, ResponseTemplate};
 
@@ -152,8 +153,97 @@ async fn subscribe_sends_a_confirmation_email_with_a_link() {
     // Assert
     // Get email request for the confirmation links
     let email_request = &app.email_server.received_requests().await.unwrap()[0];
-    let confirmation_links = &app.get_confirmation_links(&email_request);
+    let confirmation_links = &app.get_confirmation_links(email_request);
 
     // Both should be equal
     assert_eq!(confirmation_links.html, confirmation_links.plain_text);
 }
+
+#[tokio::test]
+async fn addresses_that_only_differ_in_case_are_one_subscription() {
+    // Arrange
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    for body in [
+        "name=le%20guin&email=%20Ursula_Le_Guin%40Gmail.COM%20",
+        "name=le%20guin&email=ursula_le_guin%40gmail.com",
+    ] {
+        let response = app.post_subscriptions(body.into()).await;
+        assert_eq!(200, response.status().as_u16());
+    }
+
+    // Assert
+    let saved = sqlx::query!("SELECT email FROM subscriptions")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.len(), 1);
+    assert_eq!(saved[0].email, "ursula_le_guin@gmail.com");
+}
+
+#[tokio::test]
+async fn subscribe_returns_a_400_for_a_disposable_address() {
+    // Arrange
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = app
+        .post_subscriptions("name=le%20guin&email=ursula%40mail.Mailinator.com".into())
+        .await;
+
+    // Assert
+    assert_eq!(400, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn role_addresses_are_rejected_when_configured_to() {
+    // Arrange
+    let app = spawn_app_with(|c| c.email_policy.role_addresses = RoleAddressPolicy::Reject).await;
+
+    // Act
+    let response = app
+        .post_subscriptions("name=le%20guin&email=postmaster%40example.com".into())
+        .await;
+
+    // Assert
+    assert_eq!(400, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn flagged_role_addresses_are_pointed_out_to_admins() {
+    // Arrange
+    let app = spawn_app_with(|c| c.email_policy.role_addresses = RoleAddressPolicy::Flag).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    let response = app
+        .post_subscriptions("name=le%20guin&email=abuse%2Breports%40example.com".into())
+        .await;
+    assert_eq!(200, response.status().as_u16());
+    let subscriber_id = sqlx::query!("SELECT id FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .id;
+    app.test_user.login(&app).await;
+
+    // Act
+    let html_page = app.get_subscriber_details_html(subscriber_id).await;
+
+    // Assert
+    assert!(html_page.contains("This is a role address"));
+}
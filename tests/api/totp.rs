@@ -0,0 +1,227 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp};
+use secrecy::Secret;
+use uuid::Uuid;
+use zero2prod::authentication::totp_code;
+
+async fn totp_secret(app: &TestApp) -> Secret<String> {
+    let secret = sqlx::query!(
+        "SELECT totp_secret FROM users WHERE user_id = $1",
+        app.test_user.user_id
+    )
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .totp_secret
+        .unwrap();
+    Secret::new(secret)
+}
+
+// `steps_ahead` picks a code that was not spent yet, since codes are single-use
+fn code(secret: &Secret<String>, steps_ahead: i64) -> String {
+    totp_code(secret, chrono::Utc::now().timestamp() + 30 * steps_ahead).unwrap()
+}
+
+// Turn on TOTP for the logged-in test user and return their recovery codes
+async fn enroll(app: &TestApp) -> (Secret<String>, Vec<String>) {
+    let response = app.post_admin_totp_setup().await;
+    assert_is_redirect_to(&response, "/admin/totp");
+    let secret = totp_secret(app).await;
+    let response = app.post_admin_totp_confirm(&code(&secret, 0)).await;
+    assert_eq!(200, response.status().as_u16());
+    assert_eq!(response.headers()["Cache-Control"], "no-store");
+    let html_page = response.text().await.unwrap();
+    let codes = html_page
+        .split("<p><code>")
+        .nth(1)
+        .unwrap()
+        .split("</code>")
+        .next()
+        .unwrap()
+        .split(' ')
+        .map(String::from)
+        .collect();
+    (secret, codes)
+}
+
+async fn log_in_with_password(app: &TestApp) -> reqwest::Response {
+    app.post_login(&serde_json::json!({
+        "username": &app.test_user.username,
+        "password": &app.test_user.password
+    }))
+    .await
+}
+
+#[tokio::test]
+async fn enrolling_shows_an_otpauth_uri() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    // Act
+    app.post_admin_totp_setup().await;
+
+    // Assert
+    let html_page = app.get_admin_totp_html().await;
+    assert!(html_page.contains("otpauth://totp/zero2prod:"));
+    assert!(html_page.contains("<svg"));
+}
+
+#[tokio::test]
+async fn enrollment_requires_a_valid_code() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    app.post_admin_totp_setup().await;
+
+    // Act
+    app.post_admin_totp_confirm("000000x").await;
+
+    // Assert
+    let html_page = app.get_admin_totp_html().await;
+    assert!(html_page.contains("<p><i>The authentication code is not valid.</i></p>"));
+    app.post_logout().await;
+    let response = log_in_with_password(&app).await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+}
+
+#[tokio::test]
+async fn a_half_authenticated_session_is_not_logged_in() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    enroll(&app).await;
+    app.post_logout().await;
+
+    // Act
+    let response = log_in_with_password(&app).await;
+    assert_is_redirect_to(&response, "/login/totp");
+
+    // Assert
+    let response = app.get_admin_dashboard().await;
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn the_second_step_logs_in_with_a_valid_code() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let (secret, _) = enroll(&app).await;
+    app.post_logout().await;
+    log_in_with_password(&app).await;
+
+    // Act
+    let response = app.post_login_totp(&code(&secret, 1)).await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/dashboard");
+    let response = app.get_admin_dashboard().await;
+    assert_eq!(200, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn an_invalid_code_does_not_log_in() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    enroll(&app).await;
+    app.post_logout().await;
+    log_in_with_password(&app).await;
+
+    // Act
+    let response = app.post_login_totp("123456").await;
+    assert_is_redirect_to(&response, "/login/totp");
+
+    // Assert
+    let html_page = app.get_login_totp_html().await;
+    assert!(html_page.contains("<p><i>The authentication code is not valid.</i></p>"));
+    let response = app.get_admin_dashboard().await;
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn a_code_cannot_be_replayed() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let (secret, _) = enroll(&app).await;
+    app.post_logout().await;
+
+    // Act - the code was already spent to confirm the enrollment
+    log_in_with_password(&app).await;
+    let response = app.post_login_totp(&code(&secret, 0)).await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/login/totp");
+}
+
+#[tokio::test]
+async fn a_recovery_code_can_be_used_once() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let (_, recovery_codes) = enroll(&app).await;
+    assert_eq!(recovery_codes.len(), 10);
+    app.post_logout().await;
+
+    // Act - Part 1 - First use
+    log_in_with_password(&app).await;
+    let response = app.post_login_totp(&recovery_codes[0]).await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+    app.post_logout().await;
+
+    // Act - Part 2 - Second use
+    log_in_with_password(&app).await;
+    let response = app.post_login_totp(&recovery_codes[0]).await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/login/totp");
+}
+
+#[tokio::test]
+async fn basic_auth_is_refused_once_two_factor_is_on() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    enroll(&app).await;
+
+    // Act
+    let response = app
+        .post_newsletters_api(
+            &serde_json::json!({
+                "title": "Newsletter title",
+                "content": {"text": "Plain", "html": "<p>HTML</p>"}
+            }),
+            Some(&Uuid::new_v4().to_string()),
+        )
+        .await;
+
+    // Assert
+    assert_eq!(401, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn a_password_refused_over_basic_auth_counts_as_a_failure() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    enroll(&app).await;
+    let body = serde_json::json!({
+        "title": "Newsletter title",
+        "content": {"text": "Plain", "html": "<p>HTML</p>"}
+    });
+    for _ in 0..5 {
+        let response = app
+            .post_newsletters_api(&body, Some(&Uuid::new_v4().to_string()))
+            .await;
+        assert_eq!(401, response.status().as_u16());
+    }
+
+    // Act
+    let response = app
+        .post_newsletters_api(&body, Some(&Uuid::new_v4().to_string()))
+        .await;
+
+    // Assert
+    assert_eq!(429, response.status().as_u16());
+}
@@ -0,0 +1,228 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app, when_sending_an_email, TestApp, TestUser};
+use uuid::Uuid;
+use wiremock::ResponseTemplate;
+
+// Invite a collaborator as the logged-in owner and return the invite link
+async fn invite(app: &TestApp, email: &str, role: &str) -> reqwest::Url {
+    let _mock_guard = when_sending_an_email()
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount_as_scoped(&app.email_server)
+        .await;
+    let response = app
+        .post_invite_user(&serde_json::json!({"email": email, "role": role}))
+        .await;
+    assert_is_redirect_to(&response, "/admin/users");
+    let email_request = app.email_server.received_requests().await.unwrap().pop().unwrap();
+    app.get_confirmation_links(&email_request).html
+}
+
+fn accept_body(link: &reqwest::Url, username: &str, password: &str) -> Vec<(String, String)> {
+    let mut body: Vec<(String, String)> = link
+        .query_pairs()
+        .map(|(k, v)| (k.into_owned(), v.into_owned()))
+        .collect();
+    body.push(("username".into(), username.into()));
+    body.push(("password".into(), password.into()));
+    body.push(("password_check".into(), password.into()));
+    body
+}
+
+#[tokio::test]
+async fn only_owners_can_manage_users() {
+    // Arrange
+    let app = spawn_app().await;
+    let editor = TestUser::generate_with_role("editor");
+    editor.store(&app.db_pool).await;
+    editor.login(&app).await;
+
+    // Act
+    let response = app.get_users().await;
+
+    // Assert
+    assert_eq!(403, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn viewers_cannot_publish_newsletters() {
+    // Arrange
+    let app = spawn_app().await;
+    let viewer = TestUser::generate_with_role("viewer");
+    viewer.store(&app.db_pool).await;
+    viewer.login(&app).await;
+
+    // Act
+    let response = app
+        .post_publish_newsletter(&serde_json::json!({
+            "title": "Newsletter title",
+            "text_content": "Newsletter body as plain text",
+            "html_content": "<p>Newsletter body as HTML</p>",
+            "idempotency_key": Uuid::new_v4().to_string()
+        }))
+        .await;
+
+    // Assert
+    assert_eq!(403, response.status().as_u16());
+    let response = app.get_admin_dashboard().await;
+    assert_eq!(200, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn viewers_cannot_publish_through_the_api() {
+    // Arrange
+    let app = spawn_app().await;
+    let viewer = TestUser::generate_with_role("viewer");
+    viewer.store(&app.db_pool).await;
+
+    // Act
+    let response = app
+        .api_client
+        .post(format!("{}/newsletters", &app.address))
+        .basic_auth(&viewer.username, Some(&viewer.password))
+        .header("Idempotency-Key", Uuid::new_v4().to_string())
+        .json(&serde_json::json!({
+            "title": "Newsletter title",
+            "content": {"text": "Plain", "html": "<p>HTML</p>"}
+        }))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    // Assert
+    assert_eq!(403, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn an_invited_collaborator_can_set_up_their_account_and_log_in() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let username = Uuid::new_v4().to_string();
+    let password = Uuid::new_v4().to_string();
+
+    // Act - Part 1 - Invite
+    let link = invite(&app, "new.editor@example.com", "editor").await;
+    let html_page = app.get_users_html().await;
+    assert!(html_page.contains("new.editor@example.com as editor"));
+
+    // Act - Part 2 - Accept the invitation
+    let response = app.post_accept_invitation(&accept_body(&link, &username, &password)).await;
+    assert_is_redirect_to(&response, "/login");
+
+    // Act - Part 3 - Log in as the new user
+    let response = app
+        .post_login(&serde_json::json!({"username": &username, "password": &password}))
+        .await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+
+    // Assert
+    let saved = sqlx::query!("SELECT email, role FROM users WHERE username = $1", username)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.email.as_deref(), Some("new.editor@example.com"));
+    assert_eq!(saved.role, "editor");
+}
+
+#[tokio::test]
+async fn invitations_can_only_be_used_once() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let link = invite(&app, "viewer@example.com", "viewer").await;
+    let password = Uuid::new_v4().to_string();
+
+    // Act
+    let response = app
+        .post_accept_invitation(&accept_body(&link, &Uuid::new_v4().to_string(), &password))
+        .await;
+    assert_is_redirect_to(&response, "/login");
+    let response = app
+        .post_accept_invitation(&accept_body(&link, &Uuid::new_v4().to_string(), &password))
+        .await;
+
+    // Assert
+    assert_eq!(410, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn a_second_invitation_to_the_same_address_cannot_create_another_account() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let first_link = invite(&app, "editor@example.com", "editor").await;
+    let second_link = invite(&app, "editor@example.com", "viewer").await;
+    let password = Uuid::new_v4().to_string();
+    let response = app
+        .post_accept_invitation(&accept_body(&first_link, &Uuid::new_v4().to_string(), &password))
+        .await;
+    assert_is_redirect_to(&response, "/login");
+
+    // Act
+    let response = app
+        .post_accept_invitation(&accept_body(&second_link, &Uuid::new_v4().to_string(), &password))
+        .await;
+
+    // Assert
+    let location = response.headers().get("Location").unwrap().to_str().unwrap();
+    let html_page = app
+        .api_client
+        .get(format!("{}{}", &app.address, location))
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    assert!(html_page.contains("There already is an account with this email address."));
+    assert!(!html_page.contains("This username is already taken."));
+}
+
+#[tokio::test]
+async fn invitations_with_a_tampered_signature_are_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let link = invite(&app, "viewer@example.com", "viewer").await;
+    let mut body = accept_body(&link, &Uuid::new_v4().to_string(), "a-password");
+    for (key, value) in body.iter_mut() {
+        if key == "tag" {
+            *value = "00".repeat(32);
+        }
+    }
+
+    // Act
+    let response = app.post_accept_invitation(&body).await;
+
+    // Assert
+    assert_eq!(401, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn the_last_owner_cannot_be_demoted() {
+    // Arrange
+    let app = spawn_app().await;
+    // The seeded admin is an owner as well
+    sqlx::query!(
+        "UPDATE users SET role = 'viewer' WHERE user_id != $1",
+        app.test_user.user_id
+    )
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    app.test_user.login(&app).await;
+
+    // Act
+    let response = app
+        .api_client
+        .post(format!("{}/admin/users/{}/role", &app.address, app.test_user.user_id))
+        .form(&serde_json::json!({"role": "viewer", "csrf_token": app.csrf_token().await}))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_is_redirect_to(&response, "/admin/users");
+
+    // Assert
+    let html_page = app.get_users_html().await;
+    assert!(html_page.contains("<p><i>There must always be at least one owner.</i></p>"));
+}